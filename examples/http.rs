@@ -0,0 +1,338 @@
+extern crate clap;
+extern crate vorbis;
+
+use clap::{Arg, App};
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Read, Write};
+use std::mem;
+use std::net::TcpStream;
+
+use vorbis::{BitReader, Decoder, DecoderBuilder, SetupParser};
+
+// Length of the Ogg page header before the lacing values.
+const PAGE_HEADER_LEN: usize = 27;
+
+const FLAG_CONTINUED: u8 = 1;
+const FLAG_BOS: u8 = 2;
+
+struct Page {
+    flags: u8,
+    serial: u32,
+    seq: u32,
+    lacing: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl Page {
+    // Splits the page data into the packet parts. The last part is continued on the next page
+    // if it's not complete.
+    fn parts(&self) -> Vec<(&[u8], bool)> {
+        let mut r = Vec::new();
+        let mut start = 0;
+        let mut end = 0;
+        for &l in &self.lacing {
+            end += l as usize;
+            if l < 255 {
+                r.push((&self.data[start..end], true));
+                start = end;
+            }
+        }
+        if start < end {
+            r.push((&self.data[start..end], false));
+        }
+        r
+    }
+}
+
+// Splits the bytes received in arbitrary chunks into Ogg pages. The bytes before the capture
+// pattern are skipped: that's how the page boundary is found after joining a stream mid-page or
+// losing a part of it. The page CRC isn't checked for brevity.
+struct PageReader {
+    buf: Vec<u8>,
+    skipped: usize,
+}
+
+impl PageReader {
+    fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    // Returns the next page or `None` if more bytes are needed.
+    fn next(&mut self) -> Option<Page> {
+        loop {
+            let start = match self.buf.windows(4).position(|w| w == b"OggS") {
+                Some(i) => i,
+                // Keeps the bytes that can be the beginning of the capture pattern.
+                None => self.buf.len().saturating_sub(3),
+            };
+            self.skip(start);
+            if self.buf.len() < PAGE_HEADER_LEN {
+                return None;
+            }
+            // Version must be zero, otherwise it's a false match in the page data.
+            if self.buf[4] != 0 {
+                self.skip(1);
+                continue;
+            }
+            let header_len = PAGE_HEADER_LEN + self.buf[26] as usize;
+            if self.buf.len() < header_len {
+                return None;
+            }
+            let data_len = self.buf[PAGE_HEADER_LEN..header_len].iter()
+                .fold(0, |a, &l| a + l as usize);
+            if self.buf.len() < header_len + data_len {
+                return None;
+            }
+            let get_u32 = |b: &[u8]|
+                b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24;
+            let page = Page {
+                flags: self.buf[5],
+                serial: get_u32(&self.buf[14..]),
+                seq: get_u32(&self.buf[18..]),
+                lacing: self.buf[PAGE_HEADER_LEN..header_len].to_vec(),
+                data: self.buf[header_len..header_len + data_len].to_vec(),
+            };
+            self.buf.drain(..header_len + data_len);
+            return Some(page);
+        }
+    }
+
+    fn skip(&mut self, len: usize) {
+        self.skipped += len;
+        self.buf.drain(..len);
+    }
+}
+
+enum State {
+    // Waiting for the first page of a stream.
+    Idle,
+    Ident,
+    Comment(DecoderBuilder),
+    // The setup header is the largest one, its codebooks are read as the pages arrive.
+    Setup(DecoderBuilder, SetupParser),
+    // The decoder and the sample buffer.
+    Audio(Decoder, Vec<i16>),
+}
+
+// Decodes the Vorbis stream from the Ogg pages. A chained stream (e.g. the next track of an
+// internet radio) restarts the decoding with its headers.
+struct Player<W> {
+    output: W,
+    state: State,
+    serial: u32,
+    next_seq: u32,
+    // The beginning of the packet continued on the next page. `None` if the beginning was lost,
+    // the rest of the packet is skipped then.
+    packet: Option<Vec<u8>>,
+    waiting: bool,
+    sample_count: u64,
+}
+
+impl<W: Write> Player<W> {
+    fn push_page(&mut self, page: &Page) {
+        if page.flags & FLAG_BOS != 0 && page.data.starts_with(b"\x01vorbis") {
+            self.state = State::Ident;
+            self.serial = page.serial;
+            self.waiting = false;
+        } else if let State::Idle = self.state {
+            // Joined mid-stream, the headers of the next chained stream are needed.
+            if !self.waiting {
+                println!("Waiting for the beginning of a stream");
+                self.waiting = true;
+            }
+            return;
+        } else if page.serial != self.serial {
+            // Another logical stream multiplexed with this one.
+            return;
+        } else if page.seq != self.next_seq {
+            println!("Lost {} pages", page.seq.wrapping_sub(self.next_seq));
+            self.packet = None;
+            match self.state {
+                // The samples after the gap aren't overlapped with the ones before it.
+                State::Audio(ref mut decoder, _) => decoder.reset(),
+                State::Idle => {}
+                _ => {
+                    println!("Lost the header packets, waiting for the next stream");
+                    self.state = State::Idle;
+                }
+            }
+        }
+        if let State::Idle = self.state {
+            return;
+        }
+        self.next_seq = page.seq.wrapping_add(1);
+        if page.flags & FLAG_CONTINUED == 0 {
+            self.packet = Some(Vec::new());
+        }
+        for (part, complete) in page.parts() {
+            self.push_part(part, complete);
+        }
+    }
+
+    fn push_part(&mut self, part: &[u8], complete: bool) {
+        let mut packet = match self.packet.take() {
+            Some(packet) => packet,
+            None => {
+                if complete {
+                    self.packet = Some(Vec::new());
+                }
+                return;
+            }
+        };
+        self.state = match mem::replace(&mut self.state, State::Idle) {
+            State::Setup(mut builder, mut parser) => match parser.push(part) {
+                Ok(()) if complete => match builder.finish_setup(parser) {
+                    Ok(()) => {
+                        let decoder = builder.build();
+                        let header = decoder.header().clone();
+                        println!("Stream: {} channels, {} Hz", header.channel_count(), header.sample_rate());
+                        let buf = vec![0; header.frame_lens().long() / 2 * header.channel_count()];
+                        State::Audio(decoder, buf)
+                    }
+                    Err(e) => header_error(e),
+                },
+                Ok(()) => State::Setup(builder, parser),
+                Err(e) => header_error(e),
+            },
+            state => {
+                packet.extend_from_slice(part);
+                if complete {
+                    let state = self.decode(state, &packet);
+                    packet.clear();
+                    state
+                } else {
+                    state
+                }
+            }
+        };
+        self.packet = Some(packet);
+    }
+
+    // Decodes the complete packet and returns the next state.
+    fn decode(&mut self, state: State, packet: &[u8]) -> State {
+        let mut reader = BitReader::new(Cursor::new(packet));
+        match state {
+            State::Ident => {
+                let mut builder = Decoder::builder();
+                match builder.read_ident_packet(&mut reader) {
+                    Ok(()) => State::Comment(builder),
+                    Err(e) => header_error(e),
+                }
+            }
+            State::Comment(mut builder) => match builder.read_comment_packet(&mut reader) {
+                Ok(()) => {
+                    let parser = builder.setup_parser();
+                    State::Setup(builder, parser)
+                }
+                Err(e) => header_error(e),
+            },
+            State::Audio(mut decoder, mut buf) => {
+                match decoder.decode_i16(&mut reader, &mut buf) {
+                    Ok(len) => {
+                        let mut bytes = Vec::with_capacity(len * 2);
+                        for &s in &buf[..len] {
+                            bytes.push(s as u8);
+                            bytes.push((s >> 8) as u8);
+                        }
+                        self.output.write_all(&bytes).expect("Couldn't write samples");
+                        self.sample_count += (len / decoder.header().channel_count()) as u64;
+                    }
+                    Err(e) => {
+                        // A corrupted packet is skipped, the next one isn't overlapped with it.
+                        println!("Couldn't decode audio packet: {:?}", e);
+                        decoder.reset();
+                    }
+                }
+                State::Audio(decoder, buf)
+            }
+            state => state,
+        }
+    }
+}
+
+fn header_error(e: vorbis::Error) -> State {
+    println!("Couldn't decode header packet, waiting for the next stream: {:?}", e);
+    State::Idle
+}
+
+// Splits the `http://host[:port]/path` URL into the host, port and path.
+fn parse_url(url: &str) -> (&str, u16, &str) {
+    assert!(url.starts_with("http://"), "Only http:// URLs are supported");
+    let url = &url["http://".len()..];
+    let (authority, path) = match url.find('/') {
+        Some(i) => (&url[..i], &url[i..]),
+        None => (url, "/"),
+    };
+    match authority.rfind(':') {
+        Some(i) => (&authority[..i], authority[i + 1..].parse().expect("Invalid port"), path),
+        None => (authority, 80, path),
+    }
+}
+
+fn main() {
+    let matches = App::new("Pure Vorbis HTTP stream decoder")
+                    .about("Demonstrates incremental decoding with the Pure Vorbis decoder library. \
+                            Decodes OGG Vorbis stream (e.g. internet radio) received over HTTP \
+                            into raw 16-bit little-endian interleaved samples")
+                    .arg(Arg::with_name("URL")
+                        .help("Specifies the http:// URL of the stream")
+                        .required(true))
+                    .arg(Arg::with_name("OUTPUT")
+                        .help("Specifies the file to write the samples to")
+                        .required(true))
+                    .get_matches();
+    let (host, port, path) = parse_url(matches.value_of("URL").unwrap());
+    let output = matches.value_of("OUTPUT").unwrap();
+
+    let mut stream = TcpStream::connect((host, port)).expect("Couldn't connect");
+    // HTTP/1.0 so the response isn't chunked.
+    write!(stream, "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: pure_vorbis\r\n\r\n", path, host)
+        .expect("Couldn't send request");
+
+    let mut player = Player {
+        output: BufWriter::new(File::create(output).expect("Couldn't create output file")),
+        state: State::Idle,
+        serial: 0,
+        next_seq: 0,
+        packet: None,
+        waiting: false,
+        sample_count: 0,
+    };
+    let mut pages = PageReader {
+        buf: Vec::new(),
+        skipped: 0,
+    };
+    let mut response_header = Some(Vec::new());
+    let mut chunk = [0; 4096];
+    loop {
+        let len = stream.read(&mut chunk).expect("Couldn't receive stream");
+        if len == 0 {
+            break;
+        }
+        let mut chunk = &chunk[..len];
+        if let Some(mut header) = response_header.take() {
+            header.extend_from_slice(chunk);
+            match header.windows(4).position(|w| w == b"\r\n\r\n") {
+                Some(i) => {
+                    let status = header.split(|&b| b == b'\r').next().unwrap();
+                    let status = String::from_utf8_lossy(status);
+                    // Icecast and SHOUTcast servers may reply with `ICY 200 OK`.
+                    assert!(status.split(' ').nth(1) == Some("200"), "Request failed: {}", status);
+                    pages.push(&header[i + 4..]);
+                }
+                None => response_header = Some(header),
+            }
+            chunk = &[];
+        }
+        pages.push(chunk);
+
+        let skipped = pages.skipped;
+        while let Some(page) = pages.next() {
+            player.push_page(&page);
+        }
+        if pages.skipped > skipped {
+            println!("Skipped {} bytes to find the next page", pages.skipped - skipped);
+        }
+    }
+    println!("Decoded {} samples", player.sample_count);
+}
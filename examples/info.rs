@@ -0,0 +1,114 @@
+extern crate clap;
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+use clap::{Arg, App};
+use std::fs::File;
+use std::io::Cursor;
+use std::time::Duration;
+
+use ogg_vorbis_ref::OggRefDecoder;
+use vorbis::{BitReader, BitrateKind, Decoder, EncoderInfo, FrameKind};
+
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    format!("{}:{:02}:{:02}.{:03}", secs / 3600, secs / 60 % 60, secs % 60, d.subsec_nanos() / 1_000_000)
+}
+
+fn secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9
+}
+
+fn main() {
+    let matches = App::new("Pure Vorbis OGG info")
+                    .about("Demonstrates usage of the Pure Vorbis inspection APIs. \
+                            Prints the headers, tags and stream summary of OGG Vorbis file")
+                    .arg(Arg::with_name("INPUT")
+                        .help("Specifies the OGG Vorbis file to inspect")
+                        .required(true))
+                    .get_matches();
+    let input = matches.value_of("INPUT").unwrap();
+
+    let file = File::open(input).expect("Couldn't open input file");
+    let file_len = file.metadata().expect("Couldn't read input file metadata").len();
+    let mut ogg = OggRefDecoder::new(file, 4096);
+
+    let mut decoder_builder = Decoder::builder();
+    ogg.next_packet().expect("Couldn't read ident packet");
+    decoder_builder.read_ident_packet(&mut BitReader::new(Cursor::new(ogg.packet_data())))
+            .expect("Couldn't decode ident packet");
+    ogg.next_packet().expect("Couldn't read comment packet");
+    decoder_builder.read_comment_packet(&mut BitReader::new(Cursor::new(ogg.packet_data())))
+            .expect("Couldn't decode comment packet");
+    ogg.next_packet().expect("Couldn't read setup packet");
+    decoder_builder.read_setup_packet(&mut BitReader::new(Cursor::new(ogg.packet_data())))
+            .expect("Couldn't decode setup packet");
+    let decoder = decoder_builder.build();
+
+    let header = decoder.header();
+    println!("File: {}", input);
+    println!("Vorbis version: {}", header.version());
+    println!("Channels: {} {:?}", header.channel_count(), header.channel_layout().positions());
+    println!("Sample rate: {} Hz", header.sample_rate());
+    let bitrates = header.bitrates();
+    let bitrate_kind = match bitrates.kind() {
+        BitrateKind::Fixed => "fixed",
+        BitrateKind::Average => "average",
+        BitrateKind::Bounded => "bounded",
+        BitrateKind::Unset => "unset",
+    };
+    println!("Bitrate: {} (min {}, nominal {}, max {})", bitrate_kind, bitrates.min(), bitrates.nom(),
+        bitrates.max());
+    println!("Frame lengths: {} {}", header.frame_lens().short(), header.frame_lens().long());
+
+    let comments = decoder.comments().expect("No comments");
+    let encoder = EncoderInfo::detect(comments.vendor_raw(), decoder.setup());
+    println!("Vendor: {}", comments.vendor_lossy());
+    println!("Encoder: {:?} {}{}", encoder.kind(), encoder.version().unwrap_or(""),
+        if encoder.is_from_setup() { " (guessed from setup)" } else { "" });
+    println!("Comments:");
+    for (tag, value) in comments.iter() {
+        println!("    {}={}", tag, value);
+    }
+
+    let setup = decoder.setup();
+    println!("Setup: {} codebooks, {} floors, {} residues, {} mappings, {} modes",
+        setup.codebooks().len(), setup.floors().len(), setup.residues().len(),
+        setup.mappings().len(), setup.modes().len());
+
+    // Only the packet headers are read to count the samples: each packet after the first one
+    // produces the overlap of its frame with the previous one. The end trimming by the granule
+    // position of the last page isn't accounted.
+    let frame_lens = header.frame_lens();
+    let mut prev_kind: Option<FrameKind> = None;
+    let mut packet_count = 0;
+    let mut audio_len = 0;
+    let mut sample_count = 0;
+    while ogg.next_packet().expect("Couldn't read audio packet") {
+        let packet = ogg.packet_data();
+        packet_count += 1;
+        audio_len += packet.len() as u64;
+        let kind = match setup.packet_frame_kind(&mut BitReader::new(Cursor::new(packet))) {
+            Ok(kind) => kind,
+            Err(e) => {
+                println!("Invalid audio packet {}: {:?}", packet_count - 1, e);
+                prev_kind = None;
+                continue;
+            }
+        };
+        if let Some(prev_kind) = prev_kind {
+            sample_count += (frame_lens.get(prev_kind) / 4 + frame_lens.get(kind) / 4) as u64;
+        }
+        prev_kind = Some(kind);
+    }
+
+    let duration = header.duration(sample_count);
+    println!("Audio packets: {}", packet_count);
+    println!("Duration: {} ({} samples)", format_duration(duration), sample_count);
+    if let Some(estimate) = header.estimate_duration(file_len) {
+        println!("Duration estimated from bitrate: {}", format_duration(estimate));
+    }
+    if sample_count > 0 {
+        println!("Average bitrate: {:.1} kbit/s", audio_len as f64 * 8.0 / secs(duration) / 1000.0);
+    }
+}
@@ -0,0 +1,53 @@
+extern crate clap;
+extern crate vorbis;
+
+use clap::{Arg, App};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use vorbis::{decode_all, WavFormat, WavWriter};
+
+fn main() {
+    let matches = App::new("Pure Vorbis OGG to WAV converter")
+                    .about("Demonstrates usage of the Pure Vorbis decoder library. \
+                            Converts OGG Vorbis file to WAV")
+                    .arg(Arg::with_name("INPUT")
+                        .help("Specifies the OGG Vorbis file to convert")
+                        .required(true))
+                    .arg(Arg::with_name("OUTPUT")
+                        .help("Specifies the WAV file to write")
+                        .required(true))
+                    .arg(Arg::with_name("format")
+                        .short("f")
+                        .long("format")
+                        .help("Sample format of the WAV file")
+                        .possible_values(&["i16", "i24", "f32"])
+                        .default_value("i16"))
+                    .get_matches();
+    let input = matches.value_of("INPUT").unwrap();
+    let output = matches.value_of("OUTPUT").unwrap();
+    let format = match matches.value_of("format").unwrap() {
+        "i24" => WavFormat::I24,
+        "f32" => WavFormat::F32,
+        _ => WavFormat::I16,
+    };
+
+    let file = BufReader::new(File::open(input).expect("Couldn't open input file"));
+    // `decode_all()` trims the priming and padding samples to the granule positions of the
+    // first and last audio pages, so the WAV file has exactly the samples of the source.
+    let (header, _, samples) = decode_all(file).expect("Couldn't decode input file");
+
+    let out = BufWriter::new(File::create(output).expect("Couldn't create output file"));
+    let mut wav = WavWriter::new(out, header.channel_count(), header.sample_rate(), format)
+            .expect("Couldn't write WAV header");
+
+    println!("Converting {} to {}", input, output);
+
+    wav.write_interleaved(&samples).expect("Couldn't write samples");
+    wav.finish().expect("Couldn't finish WAV file");
+
+    let sample_count = (samples.len() / header.channel_count()) as u64;
+    let duration = header.duration(sample_count);
+    println!("Wrote {} samples ({}.{:03} s)", sample_count, duration.as_secs(),
+        duration.subsec_nanos() / 1_000_000);
+}
@@ -0,0 +1,20 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate pure_vorbis_fuzz;
+
+use pure_vorbis_fuzz::{decoder, reader, AudioPackets};
+
+fuzz_target!(|input: AudioPackets| {
+    let mut decoder = decoder();
+    let mut buf = vec![0; decoder.header().frame_lens().long() / 2 * decoder.header().channel_count()];
+    for (i, packet) in input.packets.iter().enumerate() {
+        if input.reset_at == Some(i as u8) {
+            decoder.reset();
+        }
+        if input.i16 {
+            let _ = decoder.decode_i16(&mut reader(packet), &mut buf);
+        } else if let Ok(samples) = decoder.decode(&mut reader(packet)) {
+            for _ in samples.interleave() {}
+        }
+    }
+});
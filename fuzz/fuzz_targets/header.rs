@@ -0,0 +1,25 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate pure_vorbis_fuzz;
+extern crate vorbis;
+
+use pure_vorbis_fuzz::{reader, Comments, Ident};
+use vorbis::Decoder;
+
+fuzz_target!(|input: (Ident, Comments, bool)| {
+    let (ident, comments, lenient) = input;
+    let mut builder = Decoder::builder();
+    builder.lenient(lenient);
+    if builder.read_ident_packet(&mut reader(&ident.to_packet())).is_err() {
+        return;
+    }
+    if builder.read_comment_packet(&mut reader(&comments.to_packet())).is_ok() {
+        // The typed accessors parse the comment values.
+        let comments = builder.comments().unwrap();
+        for _ in comments.iter() {}
+        let _ = comments.track_number();
+        let _ = comments.date();
+        let _ = comments.replay_gain();
+        let _ = comments.chapters();
+    }
+});
@@ -0,0 +1,18 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate pure_vorbis_fuzz;
+extern crate vorbis;
+
+use pure_vorbis_fuzz::{reader, Setup, IDENT_PACKET};
+use vorbis::Decoder;
+
+fuzz_target!(|input: (Setup, bool)| {
+    let (setup, lenient) = input;
+    let mut builder = Decoder::builder();
+    builder.lenient(lenient);
+    builder.read_ident_packet(&mut reader(IDENT_PACKET)).unwrap();
+    if builder.read_setup_packet(&mut reader(&setup.to_packet())).is_ok() {
+        // Allocates the decoding buffers sized by the setup.
+        builder.build();
+    }
+});
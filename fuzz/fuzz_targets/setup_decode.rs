@@ -0,0 +1,31 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate pure_vorbis_fuzz;
+extern crate vorbis;
+
+use pure_vorbis_fuzz::{reader, AudioPackets, Setup, IDENT_PACKET};
+use vorbis::Decoder;
+
+// Decodes the audio packets with a decoder built from the fuzzed setup header, so the floors,
+// residues and mappings indexed by the packets are arbitrary too.
+fuzz_target!(|input: (Setup, AudioPackets)| {
+    let (setup, input) = input;
+    let mut builder = Decoder::builder();
+    builder.lenient(true);
+    builder.read_ident_packet(&mut reader(IDENT_PACKET)).unwrap();
+    if builder.read_setup_packet(&mut reader(&setup.to_packet())).is_err() {
+        return;
+    }
+    let mut decoder = builder.build();
+    let mut buf = vec![0; decoder.header().frame_lens().long() / 2 * decoder.header().channel_count()];
+    for (i, packet) in input.packets.iter().enumerate() {
+        if input.reset_at == Some(i as u8) {
+            decoder.reset();
+        }
+        if input.i16 {
+            let _ = decoder.decode_i16(&mut reader(packet), &mut buf);
+        } else if let Ok(samples) = decoder.decode(&mut reader(packet)) {
+            for _ in samples.interleave() {}
+        }
+    }
+});
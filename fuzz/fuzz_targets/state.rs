@@ -0,0 +1,24 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate pure_vorbis_fuzz;
+extern crate vorbis;
+
+use pure_vorbis_fuzz::{decoder, reader, AudioPackets};
+use vorbis::DecoderState;
+
+fuzz_target!(|input: (Vec<u8>, AudioPackets)| {
+    let (state, input) = input;
+    let state = match DecoderState::from_bytes(&state) {
+        Ok(state) => state,
+        Err(_) => return,
+    };
+    let mut decoder = decoder();
+    if decoder.restore_state(&state).is_err() {
+        return;
+    }
+    for packet in &input.packets {
+        if let Ok(samples) = decoder.decode(&mut reader(packet)) {
+            for _ in samples.interleave() {}
+        }
+    }
+});
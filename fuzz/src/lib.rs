@@ -0,0 +1,193 @@
+//! Structured inputs for the fuzz targets. Each input serializes into a Vorbis packet that is
+//! valid enough to get past the packet type and magic checks.
+
+#[macro_use] extern crate arbitrary;
+extern crate vorbis;
+
+use std::cmp;
+use std::io::Cursor;
+use vorbis::{BitReader, Decoder};
+
+/// Valid identification header: stereo, 44100 Hz, 256/2048 frames.
+pub const IDENT_PACKET: &'static [u8] = include_bytes!("../data/ident.bin");
+
+/// Valid setup header of the same stream as `IDENT_PACKET`.
+pub const SETUP_PACKET: &'static [u8] = include_bytes!("../data/setup.bin");
+
+pub fn reader(packet: &[u8]) -> BitReader<Cursor<&[u8]>> {
+    BitReader::new(Cursor::new(packet))
+}
+
+/// Builds decoder from `IDENT_PACKET` and `SETUP_PACKET`.
+pub fn decoder() -> Decoder {
+    let mut builder = Decoder::builder();
+    builder.read_ident_packet(&mut reader(IDENT_PACKET)).unwrap();
+    builder.read_setup_packet(&mut reader(SETUP_PACKET)).unwrap();
+    builder.build()
+}
+
+/// Identification header fields. The version is always 0 and the block size exponents are
+/// packed into one byte as in the packet.
+#[derive(Arbitrary, Clone, Debug)]
+pub struct Ident {
+    pub channel_count: u8,
+    pub sample_rate: u32,
+    pub bitrate_max: i32,
+    pub bitrate_nom: i32,
+    pub bitrate_min: i32,
+    pub blocksizes: u8,
+    pub framing: u8,
+    pub trailing: Vec<u8>,
+}
+
+impl Ident {
+    pub fn to_packet(&self) -> Vec<u8> {
+        let mut r = header_packet(1);
+        put_u32(&mut r, 0);
+        r.push(self.channel_count);
+        put_u32(&mut r, self.sample_rate);
+        put_u32(&mut r, self.bitrate_max as u32);
+        put_u32(&mut r, self.bitrate_nom as u32);
+        put_u32(&mut r, self.bitrate_min as u32);
+        r.push(self.blocksizes);
+        r.push(self.framing);
+        r.extend_from_slice(&self.trailing);
+        r
+    }
+}
+
+/// Comment header. The comment count and the lengths can be overridden to not match the data.
+#[derive(Arbitrary, Clone, Debug)]
+pub struct Comments {
+    pub vendor: Vec<u8>,
+    pub comments: Vec<Vec<u8>>,
+    pub count: Option<u32>,
+    /// Adds the value to every length field.
+    pub len_delta: Option<i8>,
+    pub framing: bool,
+    /// Truncates the packet to the length.
+    pub truncate: Option<u16>,
+}
+
+impl Comments {
+    pub fn to_packet(&self) -> Vec<u8> {
+        let mut r = header_packet(3);
+        self.put_bytes(&mut r, &self.vendor);
+        put_u32(&mut r, self.count.unwrap_or(self.comments.len() as u32));
+        for c in &self.comments {
+            self.put_bytes(&mut r, c);
+        }
+        r.push(self.framing as u8);
+        if let Some(len) = self.truncate {
+            r.truncate(len as usize);
+        }
+        r
+    }
+
+    fn put_bytes(&self, buf: &mut Vec<u8>, bytes: &[u8]) {
+        let len = (bytes.len() as u32).wrapping_add(self.len_delta.unwrap_or(0) as u32);
+        put_u32(buf, len);
+        buf.extend_from_slice(bytes);
+    }
+}
+
+/// Modification of a valid packet: the bytes at the offset are XOR'ed with the specified ones
+/// and then the packet is optionally truncated.
+#[derive(Arbitrary, Clone, Debug)]
+pub struct Patch {
+    pub offset: u16,
+    pub xor: Vec<u8>,
+    pub truncate: Option<u16>,
+}
+
+impl Patch {
+    pub fn apply(&self, packet: &[u8]) -> Vec<u8> {
+        let mut r = packet.to_vec();
+        let start = cmp::min(self.offset as usize, r.len());
+        for (b, x) in r[start..].iter_mut().zip(self.xor.iter()) {
+            *b ^= *x;
+        }
+        if let Some(len) = self.truncate {
+            r.truncate(len as usize);
+        }
+        r
+    }
+}
+
+/// Setup header, either arbitrary bytes after the packet type and magic or a patched
+/// `SETUP_PACKET`.
+#[derive(Arbitrary, Clone, Debug)]
+pub enum Setup {
+    Raw(Vec<u8>),
+    Patched(Patch),
+}
+
+impl Setup {
+    pub fn to_packet(&self) -> Vec<u8> {
+        match self {
+            &Setup::Raw(ref data) => {
+                let mut r = header_packet(5);
+                r.extend_from_slice(data);
+                r
+            }
+            &Setup::Patched(ref patch) => patch.apply(SETUP_PACKET),
+        }
+    }
+}
+
+/// Audio packets to decode with `decoder()`.
+#[derive(Arbitrary, Clone, Debug)]
+pub struct AudioPackets {
+    pub packets: Vec<Vec<u8>>,
+    /// Decode with `Decoder::decode_i16()` instead of `Decoder::decode()`.
+    pub i16: bool,
+    /// Resets the decoder before decoding the packet with the index, as done when seeking.
+    pub reset_at: Option<u8>,
+}
+
+// Returns the packet type and the magic of the header packet.
+fn header_packet(kind: u8) -> Vec<u8> {
+    let mut r = vec![kind];
+    r.extend_from_slice(b"vorbis");
+    r
+}
+
+fn put_u32(buf: &mut Vec<u8>, v: u32) {
+    buf.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packets() {
+        assert_eq!(decoder().header().channel_count(), 2);
+
+        let ident = Ident {
+            channel_count: 2,
+            sample_rate: 44100,
+            bitrate_max: 0,
+            bitrate_nom: 112000,
+            bitrate_min: 0,
+            blocksizes: 0xB8,
+            framing: 1,
+            trailing: Vec::new(),
+        };
+        assert_eq!(ident.to_packet(), IDENT_PACKET);
+
+        let comments = Comments {
+            vendor: b"test".to_vec(),
+            comments: vec![b"TITLE=test".to_vec()],
+            count: None,
+            len_delta: None,
+            framing: true,
+            truncate: None,
+        };
+        let mut builder = Decoder::builder();
+        builder.read_comment_packet(&mut reader(&comments.to_packet())).unwrap();
+
+        let patch = Patch { offset: 0, xor: Vec::new(), truncate: None };
+        assert_eq!(Setup::Patched(patch).to_packet(), SETUP_PACKET);
+    }
+}
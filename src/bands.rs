@@ -0,0 +1,111 @@
+#[cfg(not(feature = "std"))]
+use util::Float;
+
+// Band edges of the Bark scale critical bands in Hz as tabulated by Zwicker.
+const BARK_EDGES: [f32; 25] = [
+    0.0, 100.0, 200.0, 300.0, 400.0, 510.0, 630.0, 770.0, 920.0, 1080.0, 1270.0, 1480.0, 1720.0,
+    2000.0, 2320.0, 2700.0, 3150.0, 3700.0, 4400.0, 5300.0, 6400.0, 7700.0, 9500.0, 12000.0,
+    15500.0];
+
+/// Frequency scale of the perceptual bands `FrameSpectrum::band_energies()` aggregates the
+/// spectrum into. The bands are adjacent and don't overlap.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BandScale {
+    /// The 24 critical bands of the Bark scale from 0 to 15500 Hz. The frequencies above it
+    /// aren't included in any band.
+    Bark,
+    /// The given number of bands of equal width on the mel scale
+    /// (`2595 * log10(1 + f / 700)`) from 0 Hz to the Nyquist frequency.
+    Mel(usize),
+}
+
+impl BandScale {
+    pub fn band_count(&self) -> usize {
+        match *self {
+            BandScale::Bark => BARK_EDGES.len() - 1,
+            BandScale::Mel(count) => count,
+        }
+    }
+
+    /// Returns the band that includes the `frequency` in Hz for the stream of the `sample_rate`.
+    /// Returns `None` if the frequency is outside of all bands.
+    pub fn band(&self, frequency: f32, sample_rate: u32) -> Option<usize> {
+        if frequency < 0.0 {
+            return None;
+        }
+        match *self {
+            BandScale::Bark => BARK_EDGES[1..].iter().position(|&e| frequency < e),
+            BandScale::Mel(count) => {
+                let nyquist = sample_rate as f32 / 2.0;
+                if frequency >= nyquist || count == 0 {
+                    return None;
+                }
+                let band = (hz_to_mel(frequency) / hz_to_mel(nyquist) * count as f32) as usize;
+                // Rounding errors may put the frequencies right below Nyquist beyond the last band.
+                Some(if band < count { band } else { count - 1 })
+            }
+        }
+    }
+
+    /// Returns the lower (inclusive) and upper (exclusive) edge of the `band` in Hz for
+    /// the stream of the `sample_rate`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `band` isn't less than `band_count()`.
+    pub fn band_edges(&self, band: usize, sample_rate: u32) -> (f32, f32) {
+        assert!(band < self.band_count(), "Band is out of bounds");
+        match *self {
+            BandScale::Bark => (BARK_EDGES[band], BARK_EDGES[band + 1]),
+            BandScale::Mel(count) => {
+                let max_mel = hz_to_mel(sample_rate as f32 / 2.0);
+                let edge = |i: usize| mel_to_hz(max_mel * i as f32 / count as f32);
+                (edge(band), edge(band + 1))
+            }
+        }
+    }
+}
+
+fn hz_to_mel(frequency: f32) -> f32 {
+    2595.0 * (1.0 + frequency / 700.0).log10()
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * ((10.0 as f32).powf(mel / 2595.0) - 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bark() {
+        let scale = BandScale::Bark;
+        assert_eq!(scale.band_count(), 24);
+        assert_eq!(scale.band(0.0, 44100), Some(0));
+        assert_eq!(scale.band(99.9, 44100), Some(0));
+        assert_eq!(scale.band(100.0, 44100), Some(1));
+        assert_eq!(scale.band(15499.0, 44100), Some(23));
+        assert_eq!(scale.band(15500.0, 44100), None);
+        assert_eq!(scale.band_edges(4, 44100), (400.0, 510.0));
+    }
+
+    #[test]
+    fn mel() {
+        let scale = BandScale::Mel(40);
+        assert_eq!(scale.band_count(), 40);
+        assert_eq!(scale.band(0.0, 44100), Some(0));
+        assert_eq!(scale.band(22049.0, 44100), Some(39));
+        assert_eq!(scale.band(22050.0, 44100), None);
+        assert_eq!(scale.band_edges(0, 44100).0, 0.0);
+        assert!((scale.band_edges(39, 44100).1 - 22050.0).abs() < 0.1);
+        for band in 0..40 {
+            let (low, high) = scale.band_edges(band, 44100);
+            assert!(low < high);
+            assert_eq!(scale.band((low + high) / 2.0, 44100), Some(band));
+        }
+        // 1000 Hz is 1000 mel.
+        assert!((hz_to_mel(1000.0) - 1000.0).abs() < 0.1);
+        assert!((mel_to_hz(1000.0) - 1000.0).abs() < 0.1);
+    }
+}
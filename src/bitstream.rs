@@ -1,23 +1,61 @@
 use std::cmp;
-use std::io::{Error, ErrorKind, Read, Result};
+#[cfg(feature = "std")]
+use std::io::{self, BufRead, Read, Write};
 
 use util::Bits;
+#[cfg(not(feature = "std"))]
+use util::Float;
+
+/// Error of reading from `BitRead`. Unlike `std::io::Error` the end of stream condition doesn't
+/// depend on `std` so the decoder core can work with any bit source.
+#[derive(Debug)]
+pub enum BitstreamError {
+    /// Not enough bits left to read the value.
+    UnexpectedEof,
+    /// I/O error of the underlying reader.
+    #[cfg(feature = "std")]
+    Io(io::Error),
+}
+
+pub type Result<T> = ::std::result::Result<T, BitstreamError>;
+
+#[cfg(feature = "std")]
+impl From<io::Error> for BitstreamError {
+    fn from(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            BitstreamError::UnexpectedEof
+        } else {
+            BitstreamError::Io(e)
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<BitstreamError> for io::Error {
+    fn from(e: BitstreamError) -> Self {
+        match e {
+            BitstreamError::UnexpectedEof =>
+                io::Error::new(io::ErrorKind::UnexpectedEof, "Couldn't read enough bits"),
+            BitstreamError::Io(e) => e,
+        }
+    }
+}
 
 /// A `Read`-like trait that works on a bit level as specified by [Bitpacking Convention].
 /// [Bitpacking Convention]: https://www.xiph.org/vorbis/doc/Vorbis_I_spec.html#x1-360002
-pub trait BitRead: Read {
+pub trait BitRead {
     /// Atempts reading at most `len_bits` and returns the bits read as `u32` value and the number of
     /// bits read as `usize`.
     fn try_read_u32_bits(&mut self, len_bits: usize) -> Result<(u32, usize)>;
 
-    /// Reads exactly `len_bits` and returns the bits read as `u32` value or `ErrorKind::UnexpectedEof`
-    /// if it wasn't possible to read enough bits.
+    /// Reads exactly `len_bits` and returns the bits read as `u32` value or
+    /// `BitstreamError::UnexpectedEof` if it wasn't possible to read enough bits.
     fn read_u32_bits(&mut self, len_bits: usize) -> Result<u32> {
         let (r, r_len) = try!(self.try_read_u32_bits(len_bits));
         if r_len == len_bits {
             Ok(r)
         } else {
-            Err(Error::new(ErrorKind::UnexpectedEof, "Couldn't read enough bits"))
+            Err(BitstreamError::UnexpectedEof)
         }
     }
 
@@ -28,6 +66,17 @@ pub trait BitRead: Read {
     /// Effectively this means it's not possible to unread more than 32 bits.
     fn unread_u32_bits(&mut self, bits: u32, len_bits: usize);
 
+    /// Returns the number of bits read so far (excluding the unread ones) or `None` if the
+    /// position isn't tracked. Used to report the error location, see `Error::Located`.
+    fn bit_pos(&self) -> Option<u64> {
+        None
+    }
+
+    /// Called by the decoder with the name of the field it reads next, the following reads
+    /// belong to the field until the next call. Does nothing by default, see `BitTracer`.
+    #[cfg(feature = "trace")]
+    fn trace_field(&mut self, _name: &'static str) {}
+
     fn read_u8_bits(&mut self, len_bits: usize) -> Result<u8> {
         assert!(len_bits <= 8);
         self.read_u32_bits(len_bits).map(|v| v as u8)
@@ -70,59 +119,154 @@ pub trait BitRead: Read {
         self.read_u8_bits(1).map(|v| v & 1 == 1)
     }
 
+    /// Fills the `buf` with bytes. Unlike `Read::read_exact()` the bytes don't have to be aligned.
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<()> {
+        for b in buf.iter_mut() {
+            *b = try!(self.read_u8());
+        }
+        Ok(())
+    }
+
     /// Reads `f32` value as defined by [float32_unpack](https://www.xiph.org/vorbis/doc/Vorbis_I_spec.html#x1-1200009.2.2).
     fn read_f32(&mut self) -> Result<f32> {
         self.read_u32().map(|v| f32_unpack(v))
     }
 }
 
+/// Source of bytes for `BitReader`. With the `std` feature it's implemented for all `io::Read`
+/// types, otherwise for byte slices.
+pub trait ByteRead {
+    /// Reads at most `buf.len()` bytes and returns the number of bytes read. Zero means the end of
+    /// stream.
+    fn read_bytes_into(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+    /// Reads at most 4 bytes and returns them as little-endian `u32` value and the number of
+    /// bytes read. Zero means the end of stream. This is how `BitReader` refills its buffer,
+    /// the sources that can avoid copying the bytes through a temporary buffer override it.
+    fn read_u32_le(&mut self) -> Result<(u32, usize)> {
+        let mut buf = [0; 4];
+        let read = try!(self.read_bytes_into(&mut buf));
+        Ok((le_u32(&buf[..read]), read))
+    }
+}
+
+/// Adapter of a `BufRead` source for `BitReader` that takes the bytes directly from the
+/// source's buffer instead of copying them through `Read::read()`. Reading from a buffered
+/// file this way has less copy overhead and reading from an unbuffered one wrapped in
+/// `io::BufReader` avoids a read call per 4 bytes. See `BitReader::from_buf_read()`.
+#[cfg(feature = "std")]
+pub struct BufByteReader<R> {
+    inner: R,
+}
+
+#[cfg(feature = "std")]
+impl<R: BufRead> BufByteReader<R> {
+    pub fn new(reader: R) -> Self {
+        BufByteReader {
+            inner: reader,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: BufRead> ByteRead for BufByteReader<R> {
+    fn read_bytes_into(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = {
+            let src = try!(self.inner.fill_buf());
+            let len = cmp::min(buf.len(), src.len());
+            buf[..len].copy_from_slice(&src[..len]);
+            len
+        };
+        self.inner.consume(len);
+        Ok(len)
+    }
+
+    // Continues with the next buffer if the bytes are split between two, so the short reads
+    // only happen at the end of stream.
+    fn read_u32_le(&mut self) -> Result<(u32, usize)> {
+        let mut r = 0;
+        let mut len = 0;
+        while len < 4 {
+            let read = {
+                let src = try!(self.inner.fill_buf());
+                let read = cmp::min(4 - len, src.len());
+                r |= le_u32(&src[..read]) << (len * 8);
+                read
+            };
+            if read == 0 {
+                break;
+            }
+            self.inner.consume(read);
+            len += read;
+        }
+        Ok((r, len))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> ByteRead for R {
+    fn read_bytes_into(&mut self, buf: &mut [u8]) -> Result<usize> {
+        Ok(try!(self.read(buf)))
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<'a> ByteRead for &'a [u8] {
+    fn read_bytes_into(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = cmp::min(buf.len(), self.len());
+        buf[..len].copy_from_slice(&self[..len]);
+        *self = &self[len..];
+        Ok(len)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<'a, R: ByteRead + ?Sized> ByteRead for &'a mut R {
+    fn read_bytes_into(&mut self, buf: &mut [u8]) -> Result<usize> {
+        (**self).read_bytes_into(buf)
+    }
+}
+
 pub struct BitReader<R> {
     inner: R,
     bit_buf: u64,
     bit_buf_left: usize,
+    bit_pos: u64,
+}
+
+#[cfg(feature = "std")]
+impl<R: BufRead> BitReader<BufByteReader<R>> {
+    /// Creates the reader taking the bytes directly from the buffer of the `reader`, see
+    /// `BufByteReader`.
+    pub fn from_buf_read(reader: R) -> Self {
+        BitReader::new(BufByteReader::new(reader))
+    }
 }
 
-impl<R: Read> BitReader<R> {
+impl<R: ByteRead> BitReader<R> {
     pub fn new(reader: R) -> Self {
         BitReader {
             inner: reader,
             bit_buf: 0,
             bit_buf_left: 0,
+            bit_pos: 0,
         }
     }
 
     fn fill_bit_buf(&mut self) -> Result<()> {
         assert_eq!(self.bit_buf_left, 0);
         // Intentionally reading only 32 bits saving another 32 bits for the unread buffer.
-        let mut buf = [0; 4];
-        let read = try!(self.inner.read(&mut buf));
+        let (bits, read) = try!(self.inner.read_u32_le());
+        self.bit_buf = bits as u64;
         self.bit_buf_left = read * 8;
-
-        if read == 0 {
-            return Ok(());
-        }
-
-        let mut bit_buf = buf[0] as u64;
-        if read == 1 {
-            self.bit_buf = bit_buf;
-            return Ok(());
-        }
-
-        bit_buf |= (buf[1] as u64) << 8;
-        if read == 2 {
-            self.bit_buf = bit_buf;
-            return Ok(());
-        }
-
-        bit_buf |= (buf[2] as u64) << 16;
-        if read == 3 {
-            self.bit_buf = bit_buf;
-            return Ok(());
-        }
-
-        bit_buf |= (buf[3] as u64) << 24;
-        self.bit_buf = bit_buf;
-
         Ok(())
     }
 
@@ -149,7 +293,7 @@ impl<R: Read> BitReader<R> {
     }
 }
 
-impl<R: Read> BitRead for BitReader<R> {
+impl<R: ByteRead> BitRead for BitReader<R> {
     fn try_read_u32_bits(&mut self, len_bits: usize) -> Result<(u32, usize)> {
         if len_bits == 0 {
             return Ok((0, 0));
@@ -164,6 +308,7 @@ impl<R: Read> BitRead for BitReader<R> {
             try!(self.fill_bit_buf());
             read_bits += self.read_bit_buf(&mut r, read_bits, len_bits - read_bits);
         }
+        self.bit_pos += read_bits as u64;
         Ok((r, read_bits))
     }
 
@@ -174,11 +319,17 @@ impl<R: Read> BitRead for BitReader<R> {
         assert!(self.bit_buf_left + len_bits <= 64);
         self.bit_buf = (self.bit_buf << len_bits) | bits.ls_bits(len_bits) as u64;
         self.bit_buf_left += len_bits;
+        self.bit_pos = self.bit_pos.saturating_sub(len_bits as u64);
+    }
+
+    fn bit_pos(&self) -> Option<u64> {
+        Some(self.bit_pos)
     }
 }
 
-impl<R: Read> Read for BitReader<R> {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+#[cfg(feature = "std")]
+impl<R: ByteRead> Read for BitReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         if buf.len() == 0 {
             return Ok(0);
         }
@@ -191,6 +342,140 @@ impl<R: Read> Read for BitReader<R> {
     }
 }
 
+/// A `Write`-like trait that works on a bit level as specified by [Bitpacking Convention].
+/// This is the counterpart of `BitRead`.
+/// [Bitpacking Convention]: https://www.xiph.org/vorbis/doc/Vorbis_I_spec.html#x1-360002
+#[cfg(feature = "std")]
+pub trait BitWrite: Write {
+    /// Writes `len_bits` least significant bits of the `bits`.
+    fn write_u32_bits(&mut self, bits: u32, len_bits: usize) -> io::Result<()>;
+
+    /// Writes the buffered bits padding the last incomplete byte with zero bits. Must be called
+    /// after writing the last value, otherwise up to 7 last bits may be lost.
+    fn flush_bits(&mut self) -> io::Result<()>;
+
+    fn write_u8_bits(&mut self, bits: u8, len_bits: usize) -> io::Result<()> {
+        assert!(len_bits <= 8);
+        self.write_u32_bits(bits as u32, len_bits)
+    }
+
+    fn write_u8(&mut self, value: u8) -> io::Result<()> {
+        self.write_u8_bits(value, 8)
+    }
+
+    fn write_u16_bits(&mut self, bits: u16, len_bits: usize) -> io::Result<()> {
+        assert!(len_bits <= 16);
+        self.write_u32_bits(bits as u32, len_bits)
+    }
+
+    fn write_u16(&mut self, value: u16) -> io::Result<()> {
+        self.write_u16_bits(value, 16)
+    }
+
+    /// Writes the value in the same format as read by `BitRead::read_i32_bits()`.
+    fn write_i32_bits(&mut self, value: i32, len_bits: usize) -> io::Result<()> {
+        assert!(len_bits >= 2);
+        let u = (value as i64).abs() as u32;
+        try!(self.write_u32_bits(u, len_bits - 1));
+        self.write_bool(value < 0)
+    }
+
+    fn write_u32(&mut self, value: u32) -> io::Result<()> {
+        self.write_u32_bits(value, 32)
+    }
+
+    fn write_i32(&mut self, value: i32) -> io::Result<()> {
+        self.write_i32_bits(value, 32)
+    }
+
+    fn write_bool(&mut self, value: bool) -> io::Result<()> {
+        self.write_u8_bits(value as u8, 1)
+    }
+
+    /// Writes the value in the Vorbis float format read by `BitRead::read_f32()`. The values
+    /// with more than 21 significant bits are rounded.
+    fn write_f32(&mut self, value: f32) -> io::Result<()> {
+        self.write_u32(f32_pack(value))
+    }
+}
+
+#[cfg(feature = "std")]
+pub struct BitWriter<W> {
+    inner: W,
+    bit_buf: u64,
+    bit_buf_len: usize,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> BitWriter<W> {
+    pub fn new(writer: W) -> Self {
+        BitWriter {
+            inner: writer,
+            bit_buf: 0,
+            bit_buf_len: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Flushes the buffered bits (see `flush_bits()`) and returns the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        try!(self.flush_bits());
+        Ok(self.inner)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> BitWrite for BitWriter<W> {
+    fn write_u32_bits(&mut self, bits: u32, len_bits: usize) -> io::Result<()> {
+        if len_bits == 0 {
+            return Ok(());
+        }
+        assert!(len_bits <= 32);
+        let bits = bits as u64 & ((1 << len_bits) - 1);
+        self.bit_buf |= bits << self.bit_buf_len;
+        self.bit_buf_len += len_bits;
+        while self.bit_buf_len >= 8 {
+            try!(self.inner.write_all(&[self.bit_buf as u8]));
+            self.bit_buf >>= 8;
+            self.bit_buf_len -= 8;
+        }
+        Ok(())
+    }
+
+    fn flush_bits(&mut self) -> io::Result<()> {
+        if self.bit_buf_len > 0 {
+            try!(self.inner.write_all(&[self.bit_buf as u8]));
+            self.bit_buf = 0;
+            self.bit_buf_len = 0;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Write for BitWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &b in buf {
+            try!(self.write_u8(b));
+        }
+        Ok(buf.len())
+    }
+
+    /// Flushes the underlying writer. Note this doesn't flush the buffered bits,
+    /// see `BitWrite::flush_bits()`.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Assembles the little-endian value of at most 4 bytes.
+fn le_u32(bytes: &[u8]) -> u32 {
+    bytes.iter().rev().fold(0, |r, &b| r << 8 | b as u32)
+}
+
 fn f32_unpack(val: u32) -> f32 {
     let mut mantissa = (val & 0x1F_FFFF) as f32;
     let sign = val & 0x8000_0000;
@@ -201,11 +486,34 @@ fn f32_unpack(val: u32) -> f32 {
     mantissa * 2_f32.powf(exponent - 788_f32)
 }
 
+#[cfg(feature = "std")]
+fn f32_pack(val: f32) -> u32 {
+    if val == 0.0 {
+        return 0;
+    }
+    let sign = if val < 0.0 { 0x8000_0000 } else { 0 };
+    let val = val.abs();
+    let mut exponent = val.log2().floor() as i32;
+    let mut mantissa = (val * 2_f32.powi(20 - exponent)).round() as u32;
+    if mantissa >= 1 << 21 {
+        mantissa >>= 1;
+        exponent += 1;
+    }
+    sign | ((exponent + 768) as u32) << 21 | mantissa
+}
+
 #[cfg(test)]
 mod tests {
-    use std::io::{ErrorKind, Cursor, Read};
+    use std::io::{BufReader, Cursor, Read, Write};
 
-    use super::{BitRead, BitReader};
+    use super::{BitRead, BitReader, BitWrite, BitWriter, BitstreamError};
+
+    fn is_eof<T>(r: super::Result<T>) -> bool {
+        match r {
+            Err(BitstreamError::UnexpectedEof) => true,
+            _ => false,
+        }
+    }
 
     #[test]
     fn try_read_u32_bits() {
@@ -221,7 +529,7 @@ mod tests {
         assert_eq!(r.read_u32_bits(5).unwrap(), 0b00110);
         assert_eq!(r.read_u32_bits(4).unwrap(), 0b0111);
         assert_eq!(r.read_u32_bits(4).unwrap(), 0b1001);
-        assert_eq!(r.read_u32_bits(5).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        assert!(is_eof(r.read_u32_bits(5)));
     }
 
     #[test]
@@ -246,7 +554,7 @@ mod tests {
         assert_eq!(r.read_u32_bits(25).unwrap(), 0b1_01000000_01011100_01011101);
         assert_eq!(r.read_u32_bits(9).unwrap(), 0b10_1001011);
         assert_eq!(r.read_u32_bits(6).unwrap(), 0b001001);
-        assert_eq!(r.read_u32_bits(1).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        assert!(is_eof(r.read_u32_bits(1)));
     }
 
     #[test]
@@ -266,6 +574,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn read_slice() {
+        let inp = [0b11111100, 0b01001000, 0b11001110];
+        let mut r = BitReader::new(&inp[..]);
+        assert_eq!(r.read_u16().unwrap(), 0b01001000_11111100);
+        assert_eq!(r.read_u32_bits(4).unwrap(), 0b1110);
+        assert!(is_eof(r.read_u8()));
+    }
+
+    #[test]
+    fn read_buf_read() {
+        let inp: Vec<u8> = (0..100).map(|i| (i * 37 + 11) as u8).collect();
+        let mut expected = BitReader::new(Cursor::new(&inp));
+        // The tiny buffer makes the refills cross the buffer boundaries.
+        let mut r = BitReader::from_buf_read(BufReader::with_capacity(3, Cursor::new(&inp)));
+        for &len in [1, 7, 32, 13, 0, 25, 3, 32, 9].iter().cycle().take(40) {
+            assert_eq!(r.try_read_u32_bits(len).unwrap(), expected.try_read_u32_bits(len).unwrap());
+        }
+        // 541 bits were read.
+        for _ in 0..8 {
+            assert_eq!(r.read_u32().unwrap(), expected.read_u32().unwrap());
+        }
+        assert_eq!(r.try_read_u32_bits(32).unwrap(), (inp[99] as u32 >> 5, 3));
+        assert!(is_eof(r.read_u32_bits(1)));
+    }
+
     #[test]
     fn unread_u32_bits() {
         let inp = [0b01011101, 0b01011100, 0b01000000, 0b10010111,
@@ -297,6 +631,50 @@ mod tests {
 
         assert_eq!(r.read_u32_bits(3).unwrap(), 0b101);
 
-        assert_eq!(r.read_u32_bits(1).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        assert!(is_eof(r.read_u32_bits(1)));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn write_u32_bits() {
+        let mut w = BitWriter::new(Vec::new());
+        w.write_u32_bits(0b0100110, 7).unwrap();
+        w.write_u32_bits(0b00110, 5).unwrap();
+        w.write_u32_bits(0b0111, 4).unwrap();
+        w.write_u32_bits(0b11_1001, 4).unwrap();
+        assert_eq!(w.into_inner().unwrap(), [0b0_0100110, 0b0111_0011, 0b0000_1001]);
+    }
+
+    #[test]
+    fn write_read_round_trip() {
+        let mut w = BitWriter::new(Vec::new());
+        w.write_bool(true).unwrap();
+        w.write_i32_bits(-0b001110001, 9).unwrap();
+        w.write_u32(0xDEAD_BEEF).unwrap();
+        w.write_all(b"vorbis").unwrap();
+        w.write_i32(-1234567).unwrap();
+        w.write_u16_bits(0x1FF, 9).unwrap();
+        w.write_f32(-15.0).unwrap();
+        w.write_f32(0.125).unwrap();
+        let buf = w.into_inner().unwrap();
+
+        let mut r = BitReader::new(Cursor::new(buf));
+        assert_eq!(r.bit_pos(), Some(0));
+        assert_eq!(r.read_bool().unwrap(), true);
+        assert_eq!(r.read_i32_bits(9).unwrap(), -0b001110001);
+        assert_eq!(r.bit_pos(), Some(10));
+        let bits = r.read_u32_bits(3).unwrap();
+        r.unread_u32_bits(bits, 3);
+        assert_eq!(r.bit_pos(), Some(10));
+        assert_eq!(r.read_u32().unwrap(), 0xDEAD_BEEF);
+        let mut magic = [0; 6];
+        r.read_bytes(&mut magic).unwrap();
+        assert_eq!(&magic, b"vorbis");
+        assert_eq!(r.read_i32().unwrap(), -1234567);
+        assert_eq!(r.read_u16_bits(9).unwrap(), 0x1FF);
+        assert_eq!(r.read_f32().unwrap(), -15.0);
+        assert_eq!(r.read_f32().unwrap(), 0.125);
+        // Padding of the last byte.
+        assert_eq!(r.read_u32_bits(5).unwrap(), 0);
+        assert!(is_eof(r.read_u32_bits(1)));
+    }
+}
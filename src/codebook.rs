@@ -1,117 +1,424 @@
-use num::FromPrimitive;
+use std::cmp;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
 
 use bitstream::BitRead;
 use error::{Error, Result};
 use huffman::HuffmanDecoder;
-use util::{Bits, Push};
+use sample::Sample;
+use util::{Bits, FromPrimitive};
+#[cfg(not(feature = "std"))]
+use util::Float;
 
 pub const MAX_CODEWORD_LEN: u32 = 24;
 
+/// Codebook as read from the setup header.
 #[derive(Debug)]
 pub struct Codebook {
-    pub dim_count: usize,
-    pub idx: usize,
+    dim_count: usize,
+    entry_count: usize,
+    idx: usize,
     huffman_decoder: HuffmanDecoder,
     lookup_table: Option<LookupTable>,
 }
 
 const SYNC_PATTERN: [u8; 3] = [0x42, 0x43, 0x56];
 
-impl Codebook {
-    pub fn read<BR: BitRead>(reader: &mut BR) -> Result<Self> {
+// Codebook fields as read from the setup header before building the Huffman decoder and the
+// lookup table. The building doesn't need the reader, so the codebooks can be built in parallel.
+#[derive(Debug)]
+pub struct CodebookDesc {
+    dim_count: usize,
+    entry_count: usize,
+    idx: usize,
+    // First entry, entry count and codeword length of the runs of used entries with the same
+    // codeword length. The ordered codebooks can declare millions of entries in a few bits, so
+    // the runs are kept as is until the Huffman decoder rejects them.
+    codeword_lens: Vec<(u32, u32, u8)>,
+    lookup_table: Option<LookupTableDesc>,
+}
+
+impl CodebookDesc {
+    pub fn read<BR: BitRead>(reader: &mut BR, idx: usize) -> Result<Self> {
         let mut sync_pattern = [0; 3];
-        try!(reader.read_exact(&mut sync_pattern));
+        trace_field!(reader, "codebook.sync");
+        try!(reader.read_bytes(&mut sync_pattern));
         if sync_pattern != SYNC_PATTERN {
-            return Err(Error::Undecodable("Invalid sync pattern"));
+            return Err(Error::InvalidCodebookSync);
         }
 
+        trace_field!(reader, "codebook.dim_count");
         let dim_count = try!(reader.read_u16()) as usize;
+        trace_field!(reader, "codebook.entry_count");
         let entry_count = try!(reader.read_u32_bits(24)) as usize;
+        trace_field!(reader, "codebook.ordered");
         let ordered = try!(reader.read_bool());
 
-        let huffman_decoder = {
-            let mut builder = HuffmanDecoder::builder(9);
-            {
-                let make_codeword = |idx, len|
-                    builder.create_code(idx as u32, len as usize);
-                if ordered {
-                    try!(Self::read_ordered_codeword_lens(reader, entry_count, make_codeword));
-                } else {
-                    try!(Self::read_unordered_codeword_lens(reader, entry_count, make_codeword));
-                }
+        let mut codeword_lens = Vec::new();
+        {
+            let add_codeword_lens = |first, count, len| codeword_lens.push((first, count, len));
+            if ordered {
+                try!(Self::read_ordered_codeword_lens(reader, idx, entry_count, add_codeword_lens));
+            } else {
+                try!(Self::read_unordered_codeword_lens(reader, idx, entry_count, add_codeword_lens));
             }
-            builder.build()
-        };
+        }
 
-        let lookup_table = try!(LookupTable::read(reader, entry_count, dim_count));
+        let lookup_table = try!(LookupTableDesc::read(reader, idx, entry_count, dim_count));
 
-        Ok(Codebook {
+        Ok(CodebookDesc {
             dim_count: dim_count,
-            idx: 0,
-            huffman_decoder: huffman_decoder,
+            entry_count: entry_count,
+            idx: idx,
+            codeword_lens: codeword_lens,
             lookup_table: lookup_table,
         })
     }
 
-    pub fn decode_scalar<R: BitRead>(&self, reader: &mut R) -> Result<u32> {
-        let r = try!(self.huffman_decoder.decode(reader));
-        Ok(r)
+    /// Returns the zero-based index of this codebook in the setup header.
+    #[cfg(feature = "rayon")]
+    pub fn index(&self) -> usize {
+        self.idx
     }
 
-    pub fn decode_vq<'a, R: BitRead, P: Push<f32>>(&self, reader: &mut R, result: &mut P/*, len: usize*/) -> Result<()> {
-        if let Some(ref lookup_table) = self.lookup_table {
-            let lookup_offset = try!(self.decode_scalar(reader));
-            lookup_table.lookup(result, lookup_offset as usize);
-            Ok(())
-        } else {
-            Err(Error::Undecodable("Codebook has no lookup table"))
+    pub fn build(self) -> Result<Codebook> {
+        let mut builder = HuffmanDecoder::builder(9);
+        for &(first, count, len) in &self.codeword_lens {
+            for entry in first..first + count {
+                try!(builder.create_code(entry, len as usize));
+            }
         }
+        Ok(Codebook {
+            dim_count: self.dim_count,
+            entry_count: self.entry_count,
+            idx: self.idx,
+            huffman_decoder: builder.build(),
+            lookup_table: self.lookup_table.map(|lt| lt.build()),
+        })
     }
 
-    fn read_unordered_codeword_lens<R: BitRead, F>(reader: &mut R, count: usize, mut callback: F) -> Result<()>
-            where F: FnMut(usize, u32) -> Result<()> {
+    fn read_unordered_codeword_lens<R: BitRead, F>(reader: &mut R, idx: usize, count: usize,
+            mut callback: F) -> Result<()>
+            where F: FnMut(u32, u32, u8) {
+        trace_field!(reader, "codebook.sparse");
         let sparse = try!(reader.read_bool());
         for i in 0..count {
             if sparse {
+                trace_field!(reader, "codebook.used");
                 let used = try!(reader.read_bool());
                 if !used {
                     continue;
                 }
             }
             let len = try!(Self::read_codeword_len(reader));
-            try!(callback(i, len));
+            try!(Self::check_codeword_len(idx, len));
+            callback(i as u32, 1, len as u8);
         }
         Ok(())
     }
 
-    fn read_ordered_codeword_lens<R: BitRead, F>(reader: &mut R, count: usize, mut callback: F) -> Result<()>
-            where F: FnMut(usize, u32) -> Result<()> {
+    fn read_ordered_codeword_lens<R: BitRead, F>(reader: &mut R, idx: usize, count: usize,
+            mut callback: F) -> Result<()>
+            where F: FnMut(u32, u32, u8) {
         let mut cur_entry = 0;
         let mut cur_len = try!(Self::read_codeword_len(reader));
         while cur_entry < count {
             let num_len_bits = ((count - cur_entry) as u32).ilog() as usize;
+            trace_field!(reader, "codebook.len_count");
             let num = try!(reader.read_u32_bits(num_len_bits)) as usize;
             if cur_entry + num > count {
-                return Err(Error::Undecodable("Codeword length counts mismatch"));
+                return Err(Error::CodewordLenCountMismatch);
             }
-            assert!(cur_len <= MAX_CODEWORD_LEN);
-            for _ in 0..num {
-                try!(callback(cur_entry, cur_len));
-                cur_entry += 1;
+            try!(Self::check_codeword_len(idx, cur_len));
+            if num > 0 {
+                callback(cur_entry as u32, num as u32, cur_len as u8);
             }
+            cur_entry += num;
             cur_len += 1;
         }
         Ok(())
     }
 
     fn read_codeword_len<BR: BitRead>(reader: &mut BR) -> Result<u32> {
+        trace_field!(reader, "codebook.codeword_len");
         Ok(try!(reader.read_u32_bits(5)) + 1)
     }
+
+    fn check_codeword_len(idx: usize, len: u32) -> Result<()> {
+        if len > MAX_CODEWORD_LEN {
+            Err(Error::CodewordTooLong {
+                codebook: idx,
+                len: len,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Codebook {
+    pub fn read<BR: BitRead>(reader: &mut BR, idx: usize) -> Result<Self> {
+        try!(CodebookDesc::read(reader, idx)).build()
+    }
+
+    /// Returns the zero-based index of this codebook in the setup header.
+    pub fn index(&self) -> usize {
+        self.idx
+    }
+
+    /// Returns the number of dimensions (scalars) in each VQ vector.
+    pub fn dim_count(&self) -> usize {
+        self.dim_count
+    }
+
+    /// Returns the number of entries declared by the codebook, including unused ones.
+    pub fn entry_count(&self) -> usize {
+        self.entry_count
+    }
+
+    /// Returns the VQ lookup kind or `None` if the codebook has no lookup table (scalar only).
+    pub fn lookup_kind(&self) -> Option<LookupKind> {
+        self.lookup_table.as_ref().map(|lt| lt.kind)
+    }
+
+    /// Returns the VQ lookup table values (the multiplicands mapped with the minimum and delta
+    /// values), empty if the codebook has no lookup table.
+    pub fn lookup_values(&self) -> &[Sample] {
+        self.lookup_table.as_ref().map(|lt| &lt.mults[..]).unwrap_or(&[])
+    }
+
+    /// Returns whether the VQ vector values are accumulated (the `sequence_p` flag). Always
+    /// `false` if the codebook has no lookup table.
+    pub fn sequence_p(&self) -> bool {
+        self.lookup_table.as_ref().map(|lt| lt.seq_p).unwrap_or(false)
+    }
+
+    /// Returns the number of the lookup type 1 multiplicands for the codebook dimensions: the
+    /// greatest integer whose `dim_count` power doesn't exceed the `entry_count`.
+    pub fn lookup1_value_count(entry_count: usize, dim_count: usize) -> usize {
+        LookupTable::lookup1_value_count(entry_count, dim_count)
+    }
+
+    /// Returns the length in bits of the longest codeword.
+    pub fn max_code_len(&self) -> usize {
+        self.huffman_decoder.max_code_len()
+    }
+
+    pub fn decode_scalar<R: BitRead>(&self, reader: &mut R) -> Result<u32> {
+        let r = try!(self.huffman_decoder.decode(reader));
+        Ok(r)
+    }
+
+    /// Decodes a VQ vector and adds its values to the `result`.
+    /// The `result` length must be equal to the `dim_count()`.
+    pub fn decode_vq_add<R: BitRead>(&self, reader: &mut R, result: &mut [Sample]) -> Result<()> {
+        debug_assert_eq!(result.len(), self.dim_count);
+        if let Some(ref lookup_table) = self.lookup_table {
+            let lookup_offset = try!(self.decode_scalar(reader));
+            lookup_table.lookup_add(result, lookup_offset as usize);
+            Ok(())
+        } else {
+            Err(Error::NoLookupTable)
+        }
+    }
+}
+
+// Scans a codebook of the setup header packet that arrives in parts to find out when all of it
+// has arrived. Each call continues from the bit position reached by the previous one, so the
+// bits are scanned once however the packet is split. Only the fields the codebook length depends
+// on are checked, the codebook is then read and validated with `Codebook::read()`.
+pub struct CodebookSpan {
+    // Bit position of the next field.
+    pos: u64,
+    state: SpanState,
+    dim_count: usize,
+    entry_count: usize,
+}
+
+#[derive(Clone, Copy)]
+enum SpanState {
+    Header,
+    Sparse,
+    Unordered { entry: usize, sparse: bool },
+    FirstLen,
+    Ordered { entry: usize, len: u32 },
+    LookupKind,
+    LookupHeader(LookupKind),
+    // The codebook ends at `pos` or is invalid there.
+    End,
+}
+
+impl CodebookSpan {
+    // Starts scanning the codebook at the bit `pos`.
+    pub fn new(pos: u64) -> Self {
+        CodebookSpan {
+            pos: pos,
+            state: SpanState::Header,
+            dim_count: 0,
+            entry_count: 0,
+        }
+    }
+
+    // Scans the `buf` further and returns `true` if the codebook can be read: either it's
+    // entirely in the `buf` or it's invalid.
+    pub fn advance(&mut self, buf: &[u8]) -> bool {
+        let len = buf.len() as u64 * 8;
+        loop {
+            if let SpanState::End = self.state {
+                return self.pos <= len;
+            }
+            match self.step(buf, len) {
+                Some(state) => self.state = state,
+                None => return false,
+            }
+        }
+    }
+
+    // Reads the fields of the current state or returns `None` if they haven't arrived yet.
+    fn step(&mut self, buf: &[u8], len: u64) -> Option<SpanState> {
+        let has = |pos: u64, bits: u64| pos + bits <= len;
+        match self.state {
+            SpanState::Header => {
+                if !has(self.pos, 65) {
+                    return None;
+                }
+                let sync = SYNC_PATTERN.iter().enumerate()
+                    .all(|(i, &b)| bits_at(buf, self.pos + i as u64 * 8, 8) == b as u32);
+                if !sync {
+                    return Some(SpanState::End);
+                }
+                self.dim_count = bits_at(buf, self.pos + 24, 16) as usize;
+                self.entry_count = bits_at(buf, self.pos + 40, 24) as usize;
+                let ordered = bits_at(buf, self.pos + 64, 1) != 0;
+                self.pos += 65;
+                Some(if ordered { SpanState::FirstLen } else { SpanState::Sparse })
+            }
+            SpanState::Sparse => {
+                if !has(self.pos, 1) {
+                    return None;
+                }
+                let sparse = bits_at(buf, self.pos, 1) != 0;
+                self.pos += 1;
+                Some(SpanState::Unordered { entry: 0, sparse: sparse })
+            }
+            SpanState::Unordered { mut entry, sparse } => {
+                while entry < self.entry_count {
+                    let used = if sparse {
+                        if !has(self.pos, 1) {
+                            break;
+                        }
+                        bits_at(buf, self.pos, 1) != 0
+                    } else {
+                        true
+                    };
+                    let bits = sparse as u64 + if used { 5 } else { 0 };
+                    if !has(self.pos, bits) {
+                        break;
+                    }
+                    if used && bits_at(buf, self.pos + bits - 5, 5) + 1 > MAX_CODEWORD_LEN {
+                        return Some(SpanState::End);
+                    }
+                    self.pos += bits;
+                    entry += 1;
+                }
+                if entry < self.entry_count {
+                    self.state = SpanState::Unordered { entry: entry, sparse: sparse };
+                    None
+                } else {
+                    Some(SpanState::LookupKind)
+                }
+            }
+            SpanState::FirstLen => {
+                if !has(self.pos, 5) {
+                    return None;
+                }
+                let len = bits_at(buf, self.pos, 5) + 1;
+                self.pos += 5;
+                Some(SpanState::Ordered { entry: 0, len: len })
+            }
+            SpanState::Ordered { mut entry, mut len } => {
+                while entry < self.entry_count {
+                    let bits = ((self.entry_count - entry) as u32).ilog();
+                    if !has(self.pos, bits as u64) {
+                        break;
+                    }
+                    let num = bits_at(buf, self.pos, bits) as usize;
+                    if entry + num > self.entry_count || len > MAX_CODEWORD_LEN {
+                        return Some(SpanState::End);
+                    }
+                    self.pos += bits as u64;
+                    entry += num;
+                    len += 1;
+                }
+                if entry < self.entry_count {
+                    self.state = SpanState::Ordered { entry: entry, len: len };
+                    None
+                } else {
+                    Some(SpanState::LookupKind)
+                }
+            }
+            SpanState::LookupKind => {
+                if !has(self.pos, 4) {
+                    return None;
+                }
+                let kind = match bits_at(buf, self.pos, 4) {
+                    0 => {
+                        self.pos += 4;
+                        return Some(SpanState::End);
+                    }
+                    1 => LookupKind::Lookup1,
+                    2 => LookupKind::Lookup2,
+                    _ => return Some(SpanState::End),
+                };
+                self.pos += 4;
+                Some(SpanState::LookupHeader(kind))
+            }
+            SpanState::LookupHeader(kind) => {
+                if !has(self.pos, 69) {
+                    return None;
+                }
+                let value_bits = bits_at(buf, self.pos + 64, 4) as u64 + 1;
+                if self.entry_count == 0 || self.dim_count == 0 {
+                    return Some(SpanState::End);
+                }
+                let mults_len = match kind {
+                    LookupKind::Lookup1 =>
+                        Some(LookupTable::lookup1_value_count(self.entry_count, self.dim_count)),
+                    LookupKind::Lookup2 => self.entry_count.checked_mul(self.dim_count),
+                };
+                // Otherwise the dimensions are invalid, the reading reports it.
+                if let Some(mults_len) = mults_len {
+                    if mults_len > 0 {
+                        self.pos += 69 + mults_len as u64 * value_bits;
+                    }
+                }
+                Some(SpanState::End)
+            }
+            SpanState::End => Some(SpanState::End),
+        }
+    }
+}
+
+// Returns `len` (at most 32) bits of the `buf` starting at the bit `pos` which must be within
+// the `buf`.
+fn bits_at(buf: &[u8], pos: u64, len: usize) -> u32 {
+    let mut r = 0_u64;
+    let mut read = 0;
+    let mut byte = (pos / 8) as usize;
+    let mut shift = (pos % 8) as usize;
+    while read < len {
+        let n = cmp::min(8 - shift, len - read);
+        r |= ((buf[byte] as u64 >> shift) & ((1 << n) - 1)) << read;
+        read += n;
+        byte += 1;
+        shift = 0;
+    }
+    r as u32
 }
 
 enum_from_primitive! {
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
-enum LookupKind {
+pub enum LookupKind {
     Lookup1  = 1,
     Lookup2  = 2,
 }}
@@ -120,14 +427,27 @@ enum LookupKind {
 struct LookupTable {
     kind: LookupKind,
     len: usize,
-    mults: Vec<f32>,
-    //min: f32,
-    //delta: f32,
+    mults: Vec<Sample>,
+    //min: Sample,
+    //delta: Sample,
     seq_p: bool,
 }
 
-impl LookupTable {
-    fn read<R: BitRead>(reader: &mut R, entry_count: usize, dim_count: usize) -> Result<Option<Self>> {
+// Lookup table fields with the multiplicands as read from the setup header.
+#[derive(Debug)]
+struct LookupTableDesc {
+    kind: LookupKind,
+    len: usize,
+    min: f32,
+    delta: f32,
+    mults: Vec<u16>,
+    seq_p: bool,
+}
+
+impl LookupTableDesc {
+    fn read<R: BitRead>(reader: &mut R, idx: usize, entry_count: usize, dim_count: usize)
+            -> Result<Option<Self>> {
+        trace_field!(reader, "codebook.lookup_kind");
         let kind_int = try!(reader.read_u8_bits(4));
         if kind_int == 0 {
             // No lookup table.
@@ -136,47 +456,83 @@ impl LookupTable {
         let kind = match LookupKind::from_u8(kind_int) {
             Some(LookupKind::Lookup1) => LookupKind::Lookup1,
             Some(LookupKind::Lookup2) => LookupKind::Lookup2,
-            None => return Err(Error::Undecodable("Invalid VQ lookup type")),
+            None => return Err(Error::InvalidLookupKind),
         };
+        trace_field!(reader, "codebook.min");
         let min = try!(reader.read_f32());
+        trace_field!(reader, "codebook.delta");
         let delta = try!(reader.read_f32());
+        trace_field!(reader, "codebook.value_bits");
         let value_len_bits = try!(reader.read_u8_bits(4)) as usize + 1;
+        trace_field!(reader, "codebook.sequence_p");
         let seq_p = try!(reader.read_bool());
 
+        let invalid_dims = Error::InvalidLookupDimensions {
+            codebook: idx,
+            entry_count: entry_count,
+            dim_count: dim_count,
+        };
+        if entry_count == 0 || dim_count == 0 {
+            return Err(invalid_dims);
+        }
         let mults_len = match kind {
-            LookupKind::Lookup1 => Self::lookup1_value_count(entry_count, dim_count),
-            LookupKind::Lookup2 => entry_count * dim_count,
+            LookupKind::Lookup1 => LookupTable::lookup1_value_count(entry_count, dim_count),
+            LookupKind::Lookup2 => match entry_count.checked_mul(dim_count) {
+                Some(len) => len,
+                None => return Err(invalid_dims),
+            },
         };
+        if mults_len == 0 {
+            return Err(invalid_dims);
+        }
 
-        let mut mults = Vec::with_capacity(mults_len);
+        // Lookup type 2 can declare up to 2^40 multiplicands. Each one takes at least one bit,
+        // so only preallocate what a packet of reasonable size can have and let the read fail on
+        // the end of packet otherwise.
+        let mut mults = Vec::with_capacity(cmp::min(mults_len, 1 << 16));
         for _ in 0..mults_len {
-            mults.push(try!(reader.read_u16_bits(value_len_bits)) as f32 * delta + min);
+            trace_field!(reader, "codebook.multiplicand");
+            mults.push(try!(reader.read_u16_bits(value_len_bits)));
         }
 
-        Ok(Some(LookupTable {
+        Ok(Some(LookupTableDesc {
             kind: kind,
             len: dim_count,
+            min: min,
+            delta: delta,
             mults: mults,
-            //min: min,
-            //delta: delta,
             seq_p: seq_p,
         }))
     }
 
-    pub fn lookup<P: Push<f32>>(&self, result: &mut P, offset: usize) {
+    fn build(self) -> LookupTable {
+        let (min, delta) = (self.min as Sample, self.delta as Sample);
+        LookupTable {
+            kind: self.kind,
+            len: self.len,
+            mults: self.mults.iter().map(|&m| m as Sample * delta + min).collect(),
+            //min: min,
+            //delta: delta,
+            seq_p: self.seq_p,
+        }
+    }
+}
+
+impl LookupTable {
+    pub fn lookup_add(&self, result: &mut [Sample], offset: usize) {
         match self.kind {
-            LookupKind::Lookup1 => self.lookup1(result, offset),
-            LookupKind::Lookup2 => self.lookup2(result, offset),
+            LookupKind::Lookup1 => self.lookup1_add(result, offset),
+            LookupKind::Lookup2 => self.lookup2_add(result, offset),
         }
     }
 
-    fn lookup1<P: Push<f32>>(&self, result: &mut P, offset: usize) {
-        let mut last = 0_f32;
+    fn lookup1_add(&self, result: &mut [Sample], offset: usize) {
+        let mut last: Sample = 0.0;
         let mut index_divisor = 1_usize;
-        for _ in 0..self.len {
+        for r in result[..self.len].iter_mut() {
             let mult_offset = offset / index_divisor % self.mults.len();
-            let value = self.mults[mult_offset] as f32 + last;
-            result.push(value);
+            let value = self.mults[mult_offset] + last;
+            *r += value;
             if self.seq_p {
                 last = value;
             }
@@ -184,23 +540,192 @@ impl LookupTable {
         }
     }
 
-    fn lookup2<P: Push<f32>>(&self, result: &mut P, offset: usize) {
-        let mut last = 0_f32;
-        let mut mult_it = self.mults.iter().skip(offset * self.len);
-        for _ in 0..self.len {
-            let value = *mult_it.next().unwrap() + last;
-            result.push(value);
+    fn lookup2_add(&self, result: &mut [Sample], offset: usize) {
+        let mut last: Sample = 0.0;
+        let start = offset * self.len;
+        let mults = &self.mults[start..start + self.len];
+        for (r, &mult) in result[..self.len].iter_mut().zip(mults.iter()) {
+            let value = mult + last;
+            *r += value;
             if self.seq_p {
                 last = value;
             }
         }
     }
 
+    /// Returns the greatest `r` such that `r ^ dim_count <= entry_count`.
     fn lookup1_value_count(entry_count: usize, dim_count: usize) -> usize {
-        // x ^ dim_count = entry_count
-        let r = (entry_count as f32).powf(1_f32 / dim_count as f32) as usize;
-        debug_assert!(r.pow(dim_count as u32) <= entry_count);
-        debug_assert!((r + 1).pow(dim_count as u32) > entry_count);
+        let fits = |r: usize| {
+            let mut acc = 1_usize;
+            for _ in 0..dim_count {
+                acc = match acc.checked_mul(r) {
+                    Some(v) if v <= entry_count => v,
+                    _ => return false,
+                };
+            }
+            true
+        };
+        // The float estimate can be off by one in either direction due to rounding.
+        let mut r = (entry_count as f64).powf(1_f64 / dim_count as f64) as usize;
+        while r > 0 && !fits(r) {
+            r -= 1;
+        }
+        while fits(r + 1) {
+            r += 1;
+        }
         r
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use bitstream::BitReader;
+    use error::{Error, ErrorKind};
+
+    fn new_bit_reader(fields: &[(u32, usize)]) -> BitReader<Cursor<Vec<u8>>> {
+        BitReader::new(Cursor::new(pack(fields)))
+    }
+
+    fn pack(fields: &[(u32, usize)]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut acc = 0_u64;
+        let mut acc_len = 0;
+        for &(value, len) in fields {
+            acc |= (value as u64 & ((1 << len) - 1)) << acc_len;
+            acc_len += len;
+            while acc_len >= 8 {
+                buf.push(acc as u8);
+                acc >>= 8;
+                acc_len -= 8;
+            }
+        }
+        if acc_len > 0 {
+            buf.push(acc as u8);
+        }
+        buf
+    }
+
+    #[test]
+    fn lookup1_value_count() {
+        assert_eq!(LookupTable::lookup1_value_count(1, 1), 1);
+        assert_eq!(LookupTable::lookup1_value_count(81, 4), 3);
+        assert_eq!(LookupTable::lookup1_value_count(80, 4), 2);
+        assert_eq!(LookupTable::lookup1_value_count(1000, 3), 10);
+        assert_eq!(LookupTable::lookup1_value_count(999, 3), 9);
+        assert_eq!(LookupTable::lookup1_value_count(5, 8), 1);
+        assert_eq!(LookupTable::lookup1_value_count(0xFF_FFFF, 1), 0xFF_FFFF);
+    }
+
+    #[test]
+    fn unordered_codeword_too_long() {
+        let mut r = new_bit_reader(&[
+            (0x42, 8), (0x43, 8), (0x56, 8),
+            (1, 16),    // dim_count
+            (2, 24),    // entry_count
+            (0, 1),     // ordered
+            (0, 1),     // sparse
+            (0, 5),     // entry 0 len = 1
+            (30, 5),    // entry 1 len = 31
+        ]);
+        match Codebook::read(&mut r, 7) {
+            Err(Error::CodewordTooLong { codebook: 7, len: 31 }) => {},
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn lookup2_huge() {
+        let mut r = new_bit_reader(&[
+            (0x42, 8), (0x43, 8), (0x56, 8),
+            (0xFFFF, 16),       // dim_count
+            (0xFF_FFFF, 24),    // entry_count
+            (1, 1),             // ordered
+            (23, 5),            // len = 24
+            (0xFF_FFFF, 24),    // entry count of len 24
+            (2, 4),             // lookup kind
+            (0, 32),            // min
+            (0, 32),            // delta
+            (0, 4),             // value bits
+            (0, 1),             // sequence_p
+            (0, 8),             // the first multiplicands
+        ]);
+        match Codebook::read(&mut r, 0) {
+            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => {},
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn lookup1_zero_dims() {
+        let mut r = new_bit_reader(&[
+            (0x42, 8), (0x43, 8), (0x56, 8),
+            (0, 16),    // dim_count
+            (2, 24),    // entry_count
+            (0, 1),     // ordered
+            (0, 1),     // sparse
+            (0, 5),     // entry 0 len = 1
+            (0, 5),     // entry 1 len = 1
+            (1, 4),     // lookup kind
+            (0, 32),    // min
+            (0, 32),    // delta
+            (0, 4),     // value bits
+            (0, 1),     // sequence_p
+        ]);
+        match Codebook::read(&mut r, 3) {
+            Err(Error::InvalidLookupDimensions { codebook: 3, entry_count: 2, dim_count: 0 }) => {},
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn span() {
+        let sparse_lookup1: &[(u32, usize)] = &[
+            (0x42, 8), (0x43, 8), (0x56, 8),
+            (2, 16),    // dim_count
+            (4, 24),    // entry_count
+            (0, 1),     // ordered
+            (1, 1),     // sparse
+            (1, 1), (0, 5),     // entry 0 len = 1
+            (0, 1),             // entry 1 unused
+            (1, 1), (1, 5),     // entry 2 len = 2
+            (1, 1), (1, 5),     // entry 3 len = 2
+            (1, 4),     // lookup kind
+            (0, 32),    // min
+            (0, 32),    // delta
+            (2, 4),     // value bits
+            (0, 1),     // sequence_p
+            (5, 3), (2, 3),     // multiplicands
+        ];
+        let ordered: &[(u32, usize)] = &[
+            (0x42, 8), (0x43, 8), (0x56, 8),
+            (1, 16),    // dim_count
+            (4, 24),    // entry_count
+            (1, 1),     // ordered
+            (1, 5),     // len = 2
+            (4, 3),     // entry count of len 2
+            (0, 4),     // lookup kind
+        ];
+        for fields in &[sparse_lookup1, ordered] {
+            // Start in the middle of a byte.
+            let mut fields = fields.to_vec();
+            fields.insert(0, (0, 3));
+            let buf = pack(&fields);
+            let mut span = CodebookSpan::new(3);
+            for len in 0..buf.len() + 1 {
+                let mut r = BitReader::new(&buf[..len]);
+                r.read_u32_bits(3).ok();
+                let complete = Codebook::read(&mut r, 0).is_ok();
+                assert_eq!(span.advance(&buf[..len]), complete, "{}", len);
+            }
+            assert!(span.advance(&buf));
+        }
+
+        // Invalid codebook is read right away to report the error.
+        let buf = pack(&[(0x42, 8), (0x43, 8), (0x57, 8), (1, 16), (4, 24), (1, 1)]);
+        assert!(CodebookSpan::new(0).advance(&buf));
+        assert!(!CodebookSpan::new(0).advance(&buf[..8]));
+    }
+}
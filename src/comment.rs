@@ -0,0 +1,754 @@
+use std::borrow::Cow;
+use std::cmp;
+#[cfg(feature = "tags")]
+use std::collections::BTreeMap;
+#[cfg(feature = "tags")]
+use std::mem;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+use std::str;
+
+use bitstream::BitRead;
+#[cfg(feature = "std")]
+use bitstream::{BitWrite, BitWriter};
+use decoder::PacketKind;
+use error::{Error, Result, Warning};
+#[cfg(feature = "tags")]
+use tag::CommentTag;
+
+/// Comments (tags) from the comment header.
+///
+/// The vendor string and the comments are stored as raw bytes exactly as they were read, so
+/// editing a comment with `set()`, `push()` or `remove()` and writing the comments back leaves
+/// the untouched comments (including the ones that aren't valid UTF-8) and their order intact.
+/// The comments with the same tag (e.g. several artists) can be edited individually by their
+/// index in `raw()` with `insert()`, `replace()` and `remove_at()`.
+#[derive(Clone, Debug)]
+pub struct Comments {
+    vendor: Vec<u8>,
+    comments: Vec<Vec<u8>>,
+}
+
+impl Comments {
+    /// Reads the comment header body using the default `CommentLimits`.
+    pub fn read<R: BitRead>(reader: &mut R) -> Result<Self> {
+        Self::read_with_limits(reader, &CommentLimits::default())
+    }
+
+    /// Reads the comment header body failing with `Error::CommentLimitExceeded` if the header
+    /// exceeds any of the `limits`. The lengths are checked before anything is allocated.
+    pub fn read_with_limits<R: BitRead>(reader: &mut R, limits: &CommentLimits) -> Result<Self> {
+        Self::do_read(reader, limits, None)
+    }
+
+    /// Same as `read_with_limits()` but reports the recoverable spec violations to `warnings`
+    /// instead of failing. The comments of truncated header are kept up to the first
+    /// incomplete one.
+    pub fn read_lenient<R: BitRead>(reader: &mut R, limits: &CommentLimits,
+            warnings: &mut Vec<Warning>) -> Result<Self> {
+        Self::do_read(reader, limits, Some(warnings))
+    }
+
+    fn do_read<R: BitRead>(reader: &mut R, limits: &CommentLimits,
+            mut warnings: Option<&mut Vec<Warning>>) -> Result<Self> {
+        let mut total_len = 0;
+        trace_field!(reader, "comment.vendor");
+        let vendor = try!(Self::read_bytes(reader, limits, &mut total_len));
+
+        trace_field!(reader, "comment.count");
+        let comment_count = try!(reader.read_u32()) as usize;
+        if comment_count > limits.max_count {
+            return Err(Error::CommentLimitExceeded("Too many comments"));
+        }
+        // Don't trust the count for preallocation, the actual comments may be missing.
+        let mut comments = Vec::with_capacity(cmp::min(comment_count, 1024));
+        for i in 0..comment_count {
+            trace_field!(reader, "comment");
+            match Self::read_bytes(reader, limits, &mut total_len) {
+                Ok(c) => comments.push(c),
+                Err(Error::UnexpectedEof) if warnings.is_some() => {
+                    if let Some(ref mut warnings) = warnings {
+                        warnings.push(Warning::DroppedComments(comment_count - i));
+                    }
+                    return Ok(Comments {
+                        vendor: vendor,
+                        comments: comments,
+                    });
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        try!(PacketKind::Comment.read_framing_bit(reader, warnings));
+
+        Ok(Comments {
+            vendor: vendor,
+            comments: comments,
+        })
+    }
+
+    /// Reads the comment header body passing the vendor string and then each comment to `f`
+    /// instead of storing them. Only the first `max_len` bytes of each entry are read into a
+    /// reused buffer and the rest is skipped, so even a pathological header of hundreds of
+    /// megabytes is skimmed with the memory bounded by `max_len`. Returns the number of
+    /// comments.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::io::Cursor;
+    /// use vorbis::{BitReader, Comments};
+    ///
+    /// # fn f(comment_packet_body: &[u8]) {
+    /// // Skim the titles without reading the cover art.
+    /// Comments::read_each(&mut BitReader::new(Cursor::new(comment_packet_body)), 256, |entry| {
+    ///     if entry.index().is_some() && entry.bytes().starts_with(b"TITLE=") {
+    ///         println!("{}", String::from_utf8_lossy(&entry.bytes()[6..]));
+    ///     }
+    /// }).expect("Couldn't read comment header");
+    /// # }
+    /// ```
+    pub fn read_each<R, F>(reader: &mut R, max_len: usize, mut f: F) -> Result<usize>
+            where R: BitRead, F: FnMut(&CommentEntry) {
+        let mut buf = Vec::with_capacity(cmp::min(max_len, 4096));
+        trace_field!(reader, "comment.vendor");
+        let len = try!(Self::read_prefix(reader, max_len, &mut buf));
+        f(&CommentEntry {
+            index: None,
+            bytes: &buf,
+            len: len,
+        });
+
+        trace_field!(reader, "comment.count");
+        let comment_count = try!(reader.read_u32()) as usize;
+        for i in 0..comment_count {
+            trace_field!(reader, "comment");
+            let len = try!(Self::read_prefix(reader, max_len, &mut buf));
+            f(&CommentEntry {
+                index: Some(i),
+                bytes: &buf,
+                len: len,
+            });
+        }
+
+        try!(PacketKind::Comment.read_framing_bit(reader, None));
+        Ok(comment_count)
+    }
+
+    /// Writes the comment header body, the counterpart of `read()`. The packet kind and magic
+    /// value aren't written, see `to_packet()`.
+    #[cfg(feature = "std")]
+    pub fn write<W: BitWrite>(&self, writer: &mut W) -> Result<()> {
+        try!(Self::write_bytes(writer, &self.vendor));
+
+        try!(writer.write_u32(self.comments.len() as u32));
+        for s in self.comments.iter() {
+            try!(Self::write_bytes(writer, s));
+        }
+
+        try!(writer.write_bool(true));
+        try!(writer.flush_bits());
+        Ok(())
+    }
+
+    /// Serializes into a complete Vorbis comment header packet.
+    #[cfg(feature = "std")]
+    pub fn to_packet(&self) -> Vec<u8> {
+        let mut writer = BitWriter::new(Vec::new());
+        PacketKind::Comment.write(&mut writer)
+            .and_then(|_| self.write(&mut writer))
+            .expect("Writing to Vec failed");
+        writer.into_inner().unwrap()
+    }
+
+    /// Returns the vendor string or `None` if it isn't valid UTF-8.
+    pub fn vendor(&self) -> Option<&str> {
+        str::from_utf8(&self.vendor).ok()
+    }
+
+    pub fn vendor_raw(&self) -> &[u8] {
+        &self.vendor
+    }
+
+    /// Returns the vendor string replacing invalid UTF-8 sequences with U+FFFD.
+    pub fn vendor_lossy(&self) -> Cow<str> {
+        String::from_utf8_lossy(&self.vendor)
+    }
+
+    pub fn set_vendor(&mut self, vendor: &str) {
+        self.vendor = vendor.as_bytes().to_vec();
+    }
+
+    /// Returns the number of comments including the ones that aren't valid UTF-8.
+    pub fn len(&self) -> usize {
+        self.comments.len()
+    }
+
+    /// Returns all comments as raw bytes in the stream order.
+    pub fn raw(&self) -> &[Vec<u8>] {
+        &self.comments
+    }
+
+    /// Returns the comment at the specified index replacing invalid UTF-8 sequences with U+FFFD.
+    pub fn get_lossy(&self, index: usize) -> Option<Cow<str>> {
+        self.comments.get(index).map(|c| String::from_utf8_lossy(c))
+    }
+
+    /// Returns iterator over all comments replacing invalid UTF-8 sequences with U+FFFD.
+    pub fn iter_lossy<'a>(&'a self) -> Box<Iterator<Item=Cow<'a, str>> + 'a> {
+        Box::new(self.comments.iter().map(|c| String::from_utf8_lossy(c)))
+    }
+
+    fn read_bytes<R: BitRead>(reader: &mut R, limits: &CommentLimits, total_len: &mut usize)
+            -> Result<Vec<u8>> {
+        let len = try!(reader.read_u32()) as usize;
+        if len > limits.max_len {
+            return Err(Error::CommentLimitExceeded("Comment is too long"));
+        }
+        *total_len += len;
+        if *total_len > limits.max_total_len {
+            return Err(Error::CommentLimitExceeded("Comments are too long in total"));
+        }
+        let mut bytes = vec![0; len];
+        try!(reader.read_bytes(&mut bytes));
+        Ok(bytes)
+    }
+
+    // Reads the length prefixed bytes into the `buf` up to the `max_len`, skipping the rest.
+    // Returns the full length.
+    fn read_prefix<R: BitRead>(reader: &mut R, max_len: usize, buf: &mut Vec<u8>) -> Result<usize> {
+        let len = try!(reader.read_u32()) as usize;
+        buf.clear();
+        buf.resize(cmp::min(len, max_len), 0);
+        try!(reader.read_bytes(buf));
+        let mut skip_buf = [0; 256];
+        let mut left = len - buf.len();
+        while left > 0 {
+            let n = cmp::min(left, skip_buf.len());
+            try!(reader.read_bytes(&mut skip_buf[..n]));
+            left -= n;
+        }
+        Ok(len)
+    }
+
+    #[cfg(feature = "std")]
+    fn write_bytes<W: BitWrite>(writer: &mut W, bytes: &[u8]) -> Result<()> {
+        try!(writer.write_u32(bytes.len() as u32));
+        try!(writer.write_all(bytes));
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tags")]
+impl Comments {
+    pub fn builder() -> CommentsBuilder {
+        CommentsBuilder {
+            vendor: None,
+            comments: Vec::new(),
+        }
+    }
+
+    pub fn iter<'a>(&'a self) -> Box<Iterator<Item=(CommentTag<'a>, &'a str)> + 'a> {
+        let iter = self.comments.iter()
+            .filter_map(|c| str::from_utf8(c).ok())
+            .filter_map(move |s| {
+                let mut split_iter = s.splitn(2, '=');
+                let tag = split_iter.next();
+                let val = split_iter.next();
+                if let (Some(tag), Some(val)) = (tag, val) {
+                    Some((CommentTag::from(tag), val))
+                } else {
+                    None
+                }
+            });
+        Box::new(iter)
+    }
+
+    pub fn by_tag<'a>(&'a self, tag: CommentTag<'a>) -> Box<Iterator<Item=&'a str> + 'a> {
+        let iter = self.iter()
+            .filter_map(move |(t, v)| if t == tag {
+                Some(v)
+            } else {
+                None
+            });
+        Box::new(iter)
+    }
+
+    /// Returns the value of the first comment with the tag.
+    pub fn first<'a>(&'a self, tag: CommentTag<'a>) -> Option<&'a str> {
+        self.by_tag(tag).next()
+    }
+
+    /// Returns the comments grouped by tag. The values of each tag are in the stream order.
+    pub fn to_map(&self) -> CommentMap {
+        let mut map = CommentMap {
+            map: BTreeMap::new(),
+        };
+        for (tag, value) in self.iter() {
+            map.map.entry(tag.as_ref().to_ascii_uppercase()).or_insert_with(Vec::new).push(value);
+        }
+        map
+    }
+
+    /// Same as `by_tag()` but returns raw values including the ones that aren't valid UTF-8.
+    pub fn by_tag_raw<'a>(&'a self, tag: CommentTag<'a>) -> Box<Iterator<Item=&'a [u8]> + 'a> {
+        let iter = self.comments.iter()
+            .filter_map(move |c| if Self::has_tag(c, tag.as_ref().as_bytes()) {
+                Some(&c[tag.as_ref().len() + 1..])
+            } else {
+                None
+            });
+        Box::new(iter)
+    }
+
+    /// Appends a `TAG=value` comment.
+    /// # Panics
+    /// Panics if the tag is invalid, see `CommentsBuilder::add()`.
+    pub fn push(&mut self, tag: CommentTag, value: &str) {
+        self.comments.push(make_comment(tag, value));
+    }
+
+    /// Sets the value of the tag. The first comment with the tag is replaced in place and the
+    /// other comments with the tag are removed. If there's no such comment the new comment is
+    /// appended.
+    /// # Panics
+    /// Panics if the tag is invalid, see `CommentsBuilder::add()`.
+    pub fn set(&mut self, tag: CommentTag, value: &str) {
+        let comment = make_comment(tag, value);
+        let tag_len = comment.iter().position(|&b| b == b'=').unwrap();
+        match self.comments.iter().position(|c| Self::has_tag(c, &comment[..tag_len])) {
+            Some(i) => {
+                self.comments[i] = comment;
+                let mut j = i + 1;
+                while j < self.comments.len() {
+                    if Self::has_tag(&self.comments[j], &self.comments[i][..tag_len]) {
+                        self.comments.remove(j);
+                    } else {
+                        j += 1;
+                    }
+                }
+            }
+            None => self.comments.push(comment),
+        }
+    }
+
+    /// Removes all comments with the tag. Returns the number of comments removed.
+    pub fn remove(&mut self, tag: CommentTag) -> usize {
+        let len = self.comments.len();
+        self.comments.retain(|c| !Self::has_tag(c, tag.as_ref().as_bytes()));
+        len - self.comments.len()
+    }
+
+    /// Inserts a `TAG=value` comment at the index shifting the following comments.
+    /// # Panics
+    /// Panics if the index is greater than `len()` or the tag is invalid, see
+    /// `CommentsBuilder::add()`.
+    pub fn insert(&mut self, index: usize, tag: CommentTag, value: &str) {
+        self.comments.insert(index, make_comment(tag, value));
+    }
+
+    /// Replaces the comment at the index with a `TAG=value` comment. Returns the replaced comment
+    /// as raw bytes.
+    /// # Panics
+    /// Panics if the index is out of bounds or the tag is invalid, see `CommentsBuilder::add()`.
+    pub fn replace(&mut self, index: usize, tag: CommentTag, value: &str) -> Vec<u8> {
+        let comment = make_comment(tag, value);
+        mem::replace(&mut self.comments[index], comment)
+    }
+
+    /// Removes the comment at the index shifting the following comments. Returns the removed
+    /// comment as raw bytes. Unlike `remove()` this leaves the other comments with the same tag
+    /// in place.
+    /// # Panics
+    /// Panics if the index is out of bounds.
+    pub fn remove_at(&mut self, index: usize) -> Vec<u8> {
+        self.comments.remove(index)
+    }
+
+    fn has_tag(comment: &[u8], tag: &[u8]) -> bool {
+        comment.len() > tag.len() && comment[tag.len()] == b'=' &&
+            comment[..tag.len()].eq_ignore_ascii_case(tag)
+    }
+}
+
+/// The vendor string or a comment passed to the `Comments::read_each()` callback.
+#[derive(Clone, Copy, Debug)]
+pub struct CommentEntry<'a> {
+    index: Option<usize>,
+    bytes: &'a [u8],
+    len: usize,
+}
+
+impl<'a> CommentEntry<'a> {
+    /// Returns the index of the comment or `None` for the vendor string.
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
+
+    /// Returns the raw bytes read, at most the `max_len` passed to `read_each()`.
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Returns the full length of the entry in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether only the beginning of the entry was read.
+    pub fn is_truncated(&self) -> bool {
+        self.bytes.len() < self.len
+    }
+}
+
+/// Limits applied when reading the comment header to protect from unbounded allocation caused by
+/// hostile or corrupted streams. The defaults are generous enough for the real-world files
+/// including the ones with embedded cover art.
+#[derive(Clone, Copy, Debug)]
+pub struct CommentLimits {
+    max_count: usize,
+    max_len: usize,
+    max_total_len: usize,
+}
+
+impl CommentLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of comments (65536 by default).
+    pub fn max_count(&mut self, value: usize) -> &mut Self {
+        self.max_count = value;
+        self
+    }
+
+    /// Sets the maximum length in bytes of a single comment or the vendor string
+    /// (16 MiB by default).
+    pub fn max_len(&mut self, value: usize) -> &mut Self {
+        self.max_len = value;
+        self
+    }
+
+    /// Sets the maximum total length in bytes of all comments and the vendor string
+    /// (64 MiB by default).
+    pub fn max_total_len(&mut self, value: usize) -> &mut Self {
+        self.max_total_len = value;
+        self
+    }
+}
+
+impl Default for CommentLimits {
+    fn default() -> Self {
+        CommentLimits {
+            max_count: 1 << 16,
+            max_len: 16 << 20,
+            max_total_len: 64 << 20,
+        }
+    }
+}
+
+// Makes `TAG=value` comment.
+#[cfg(feature = "tags")]
+fn make_comment(tag: CommentTag, value: &str) -> Vec<u8> {
+    let tag = tag.as_ref();
+    assert!(!tag.is_empty() && tag.bytes().all(|b| b >= 0x20 && b <= 0x7D && b != b'='),
+        "Invalid comment tag: {}", tag);
+    format!("{}={}", tag, value).into_bytes()
+}
+
+/// Builds `Comments` programmatically, for example to write tags with `Comments::to_packet()`.
+#[cfg(feature = "tags")]
+pub struct CommentsBuilder {
+    vendor: Option<String>,
+    comments: Vec<Vec<u8>>,
+}
+
+#[cfg(feature = "tags")]
+impl CommentsBuilder {
+    pub fn vendor(&mut self, vendor: &str) -> &mut Self {
+        self.vendor = Some(vendor.to_owned());
+        self
+    }
+
+    /// Adds a `TAG=value` comment. The same tag may be added multiple times.
+    /// # Panics
+    /// Panics if the tag is empty or contains characters not allowed by the spec (only ASCII
+    /// 0x20 through 0x7D excluding `=` are allowed).
+    pub fn add(&mut self, tag: CommentTag, value: &str) -> &mut Self {
+        self.comments.push(make_comment(tag, value));
+        self
+    }
+
+    /// Builds the comments. Missing vendor string is set to empty.
+    pub fn build(&self) -> Comments {
+        Comments {
+            vendor: self.vendor.as_ref().map(|s| s.as_bytes().to_vec()).unwrap_or_default(),
+            comments: self.comments.clone(),
+        }
+    }
+}
+
+/// Case-insensitive multimap of tags to values obtained from `Comments::to_map()`.
+#[cfg(feature = "tags")]
+#[derive(Clone, Debug)]
+pub struct CommentMap<'a> {
+    // Keys are in upper case.
+    map: BTreeMap<String, Vec<&'a str>>,
+}
+
+#[cfg(feature = "tags")]
+impl<'a> CommentMap<'a> {
+    /// Returns all values of the tag or empty slice if there's no such tag.
+    pub fn get(&self, tag: CommentTag) -> &[&'a str] {
+        self.map.get(&tag.as_ref().to_ascii_uppercase()).map(|v| &v[..]).unwrap_or(&[])
+    }
+
+    pub fn first(&self, tag: CommentTag) -> Option<&'a str> {
+        self.get(tag).first().map(|&v| v)
+    }
+
+    pub fn contains(&self, tag: CommentTag) -> bool {
+        !self.get(tag).is_empty()
+    }
+
+    /// Returns the number of distinct tags.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns iterator over the distinct tags (in upper case) and their values sorted by tag.
+    pub fn iter<'b>(&'b self) -> Box<Iterator<Item=(&'b str, &'b [&'a str])> + 'b> {
+        Box::new(self.map.iter().map(|(k, v)| (k.as_str(), &v[..])))
+    }
+}
+
+#[cfg(feature = "tags")]
+impl<'a> IntoIterator for &'a Comments {
+    type Item = (CommentTag<'a>, &'a str);
+    type IntoIter = Box<Iterator<Item=Self::Item> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(all(test, feature = "tags"))]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use bitstream::{BitReader, BitWrite, BitWriter};
+    use decoder::{Decoder, PacketKind};
+
+    use super::*;
+
+    #[test]
+    fn comments_to_packet() {
+        let comments = Comments::builder()
+            .vendor("pure_vorbis")
+            .add(CommentTag::Title, "Test")
+            .add(CommentTag::Artist, "A")
+            .add(CommentTag::Artist, "B")
+            .add(CommentTag::Custom("Custom_Tag"), "x=y")
+            .build();
+
+        let packet = comments.to_packet();
+        assert_eq!(packet[..7], [3, b'v', b'o', b'r', b'b', b'i', b's']);
+        assert_eq!(*packet.last().unwrap(), 1);
+
+        let mut builder = Decoder::builder();
+        builder.read_comment_packet(&mut BitReader::new(Cursor::new(&packet))).unwrap();
+        let read = builder.comments().unwrap();
+        assert_eq!(read.vendor(), Some("pure_vorbis"));
+        assert_eq!(read.raw(), comments.raw());
+        assert_eq!(read.by_tag(CommentTag::Artist).collect::<Vec<_>>(), ["A", "B"]);
+        assert_eq!(read.by_tag(CommentTag::Custom("CUSTOM_TAG")).collect::<Vec<_>>(), ["x=y"]);
+    }
+
+    #[test]
+    fn comments_to_map() {
+        let comments = Comments::builder()
+            .add(CommentTag::Artist, "A")
+            .add(CommentTag::Custom("artist"), "B")
+            .add(CommentTag::Title, "T")
+            .add(CommentTag::Custom("Empty"), "")
+            .build();
+        assert_eq!(comments.first(CommentTag::Artist), Some("A"));
+        assert_eq!(comments.first(CommentTag::Album), None);
+
+        let map = comments.to_map();
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get(CommentTag::Custom("Artist")), ["A", "B"]);
+        assert_eq!(map.first(CommentTag::Artist), Some("A"));
+        assert_eq!(map.first(CommentTag::Custom("EMPTY")), Some(""));
+        assert!(map.get(CommentTag::Album).is_empty());
+        assert!(!map.contains(CommentTag::Album));
+        assert_eq!(map.iter().map(|(t, _)| t).collect::<Vec<_>>(), ["ARTIST", "EMPTY", "TITLE"]);
+    }
+
+    #[test]
+    fn comments_limits() {
+        let packet = Comments::builder()
+            .vendor("vendor")
+            .add(CommentTag::Title, "Title")
+            .add(CommentTag::Artist, "Artist")
+            .build()
+            .to_packet();
+        let read = |limits: &CommentLimits| {
+            Comments::read_with_limits(&mut BitReader::new(Cursor::new(&packet[7..])), limits)
+        };
+
+        assert_eq!(read(&CommentLimits::new()).unwrap().len(), 2);
+        assert_eq!(read(CommentLimits::new().max_count(2).max_len(13).max_total_len(30))
+            .unwrap().len(), 2);
+
+        for limits in &[*CommentLimits::new().max_count(1),
+                        *CommentLimits::new().max_len(12),
+                        *CommentLimits::new().max_total_len(29)] {
+            match read(limits) {
+                Err(Error::CommentLimitExceeded(_)) => {}
+                r => panic!("{:?}", r),
+            }
+        }
+
+        // Huge declared count and length must not be allocated upfront.
+        let mut writer = BitWriter::new(Vec::new());
+        writer.write_u32(0).unwrap();
+        writer.write_u32(0xFFFFFFFF).unwrap();
+        writer.write_u32(0xFFFFFFFF).unwrap();
+        let packet = writer.into_inner().unwrap();
+        match Comments::read(&mut BitReader::new(Cursor::new(&packet))) {
+            Err(Error::CommentLimitExceeded(_)) => {}
+            r => panic!("{:?}", r),
+        }
+        match Comments::read_with_limits(&mut BitReader::new(Cursor::new(&packet)),
+                CommentLimits::new().max_count(usize::max_value())) {
+            Err(Error::CommentLimitExceeded(_)) => {}
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn comments_read_each() {
+        let big: String = (0..100000).map(|_| 'x').collect();
+        let packet = Comments::builder()
+            .vendor("vendor")
+            .add(CommentTag::Title, "T")
+            .add(CommentTag::Custom("COVER"), &big)
+            .add(CommentTag::Artist, "A")
+            .build()
+            .to_packet();
+        let mut entries = Vec::new();
+        let count = Comments::read_each(&mut BitReader::new(Cursor::new(&packet[7..])), 8, |e| {
+            entries.push((e.index(), e.bytes().to_vec(), e.len(), e.is_truncated()));
+        }).unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(entries, vec![
+            (None, b"vendor".to_vec(), 6, false),
+            (Some(0), b"TITLE=T".to_vec(), 7, false),
+            (Some(1), b"COVER=xx".to_vec(), 100006, true),
+            (Some(2), b"ARTIST=A".to_vec(), 8, false),
+        ]);
+
+        // Truncated packet.
+        assert_eq!(Comments::read_each(&mut BitReader::new(Cursor::new(&packet[7..1000])), 8, |_| {})
+            .unwrap_err().kind(), ::error::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn comments_lenient() {
+        let packet = Comments::builder()
+            .add(CommentTag::Title, "Title")
+            .add(CommentTag::Artist, "Artist")
+            .build()
+            .to_packet();
+        // Cut in the middle of the second comment.
+        let truncated = &packet[7..packet.len() - 4];
+
+        match Comments::read(&mut BitReader::new(Cursor::new(truncated))) {
+            Err(Error::UnexpectedEof) => {}
+            r => panic!("{:?}", r),
+        }
+
+        let mut warnings = Vec::new();
+        let comments = Comments::read_lenient(&mut BitReader::new(Cursor::new(truncated)),
+            &CommentLimits::new(), &mut warnings).unwrap();
+        assert_eq!(comments.by_tag(CommentTag::Title).collect::<Vec<_>>(), ["Title"]);
+        assert_eq!(comments.len(), 1);
+        assert_eq!(warnings, [Warning::DroppedComments(1)]);
+
+        // Missing framing bit.
+        let no_framing = &packet[7..packet.len() - 1];
+        let mut warnings = Vec::new();
+        let comments = Comments::read_lenient(&mut BitReader::new(Cursor::new(no_framing)),
+            &CommentLimits::new(), &mut warnings).unwrap();
+        assert_eq!(comments.len(), 2);
+        assert_eq!(warnings, [Warning::InvalidFramingBit(PacketKind::Comment)]);
+    }
+
+    #[test]
+    fn comments_edit_lossless() {
+        let mut writer = BitWriter::new(Vec::new());
+        PacketKind::Comment.write(&mut writer).unwrap();
+        let entries: &[&[u8]] = &[b"TITLE=Old", b"ARTIST=\xC0\xFF", b"no separator", b"title=Old 2",
+                                  b"ALBUM=X"];
+        writer.write_u32(4).unwrap();
+        writer.write_all(b"\xFFven").unwrap();
+        writer.write_u32(entries.len() as u32).unwrap();
+        for e in entries {
+            writer.write_u32(e.len() as u32).unwrap();
+            writer.write_all(e).unwrap();
+        }
+        writer.write_bool(true).unwrap();
+        let packet = writer.into_inner().unwrap();
+
+        let mut builder = Decoder::builder();
+        builder.read_comment_packet(&mut BitReader::new(Cursor::new(&packet))).unwrap();
+        let mut comments = builder.comments().unwrap().clone();
+        assert_eq!(comments.vendor(), None);
+        assert_eq!(comments.vendor_raw(), b"\xFFven");
+        assert_eq!(comments.vendor_lossy(), "\u{FFFD}ven");
+        assert_eq!(comments.len(), 5);
+        assert_eq!(comments.iter().count(), 3);
+        assert_eq!(comments.get_lossy(1).unwrap(), "ARTIST=\u{FFFD}\u{FFFD}");
+        assert_eq!(comments.iter_lossy().nth(2).unwrap(), "no separator");
+        assert_eq!(comments.by_tag_raw(CommentTag::Artist).collect::<Vec<_>>(), [b"\xC0\xFF"]);
+        assert_eq!(comments.to_packet(), packet);
+
+        comments.set(CommentTag::Title, "New");
+        comments.push(CommentTag::Genre, "G");
+        assert_eq!(comments.remove(CommentTag::Album), 1);
+        assert_eq!(comments.remove(CommentTag::Album), 0);
+        assert_eq!(comments.raw(), &[b"TITLE=New".to_vec(), b"ARTIST=\xC0\xFF".to_vec(),
+                                     b"no separator".to_vec(), b"GENRE=G".to_vec()]);
+
+        comments.set(CommentTag::Date, "2016");
+        assert_eq!(comments.raw().last().unwrap(), b"DATE=2016");
+    }
+
+    #[test]
+    fn comments_edit_by_index() {
+        let mut comments = Comments::builder()
+            .add(CommentTag::Artist, "A")
+            .add(CommentTag::Title, "T")
+            .add(CommentTag::Artist, "B")
+            .build();
+        comments.insert(1, CommentTag::Artist, "C");
+        assert_eq!(comments.replace(3, CommentTag::Artist, "D"), b"ARTIST=B");
+        assert_eq!(comments.by_tag(CommentTag::Artist).collect::<Vec<_>>(), ["A", "C", "D"]);
+        assert_eq!(comments.remove_at(0), b"ARTIST=A");
+        assert_eq!(comments.raw(), &[b"ARTIST=C".to_vec(), b"TITLE=T".to_vec(),
+                                     b"ARTIST=D".to_vec()]);
+        comments.insert(3, CommentTag::Genre, "G");
+        assert_eq!(comments.raw().last().unwrap(), b"GENRE=G");
+    }
+
+    #[test]
+    #[should_panic]
+    fn comments_builder_invalid_tag() {
+        Comments::builder().add(CommentTag::Custom("A=B"), "");
+    }
+}
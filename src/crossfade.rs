@@ -0,0 +1,247 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use decoder::Samples;
+use sample::Sample;
+#[cfg(not(feature = "std"))]
+use util::Float;
+
+// The channel count of Vorbis streams is 8-bit.
+const MAX_CHANNEL_COUNT: usize = 255;
+
+/// Crossfade between consecutive streams, e.g. the chained streams of an Ogg file or the tracks
+/// of a playlist, to avoid the click of an abrupt switch between unrelated audio.
+///
+/// The output lags the input by the crossfade length: the last `len` samples of the input are
+/// held back. On `transition()` the held samples of the previous stream are faded out while the
+/// first samples of the next stream are faded in with the equal power (sine) curves, so the
+/// output is shorter than the input by the crossfade length per transition. A few milliseconds
+/// are enough to declick, longer crossfades blend the tracks. Both streams must have the same
+/// channel count and sample rate, resample or remix the next stream otherwise.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::io::Cursor;
+/// use vorbis::{BitReader, Crossfade, Decoder};
+///
+/// # fn f(decoders: Vec<(Decoder, Vec<Vec<u8>>)>) {
+/// // 10 ms at 44.1 kHz.
+/// let mut crossfade = Crossfade::new(2, 441);
+/// let mut output = Vec::new();
+/// for (mut decoder, packets) in decoders {
+///     crossfade.transition();
+///     for packet in &packets {
+///         let samples = decoder.decode(&mut BitReader::new(Cursor::new(&packet[..])))
+///                 .expect("Couldn't decode audio packet");
+///         output.clear();
+///         crossfade.process(&samples, &mut output);
+///         // Play the interleaved samples in output.
+///     }
+/// }
+/// output.clear();
+/// crossfade.flush(&mut output);
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Crossfade {
+    channel_count: usize,
+    // Fade in gains, the fade out ones are the same reversed.
+    curve: Box<[Sample]>,
+    // Interleaved last samples of the input.
+    held: VecDeque<Sample>,
+    // Interleaved held samples of the previous stream being faded out.
+    fade_out: Vec<Sample>,
+    // Number of the fade_out samples (per channel) already mixed.
+    fade_pos: usize,
+}
+
+impl Crossfade {
+    /// Creates a crossfade of audio with `channel_count` channels over `len` samples (per
+    /// channel).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel count is zero or greater than 255.
+    pub fn new(channel_count: usize, len: usize) -> Self {
+        assert!(channel_count > 0 && channel_count <= MAX_CHANNEL_COUNT, "Invalid channel count");
+        let curve: Vec<_> = (0..len)
+            .map(|i| ((i as f64 + 0.5) / len as f64 * PI / 2.0).sin() as Sample)
+            .collect();
+        Crossfade {
+            channel_count: channel_count,
+            curve: curve.into_boxed_slice(),
+            held: VecDeque::with_capacity(len * channel_count),
+            fade_out: Vec::with_capacity(len * channel_count),
+            fade_pos: 0,
+        }
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channel_count
+    }
+
+    /// Returns the crossfade length in samples (per channel).
+    pub fn len(&self) -> usize {
+        self.curve.len()
+    }
+
+    /// Marks the beginning of the next stream: the following input is crossfaded with the held
+    /// end of the previous one. Does nothing before the first input. If the previous stream was
+    /// shorter than the crossfade length, the crossfade is shortened accordingly, and the rest
+    /// of an unfinished crossfade is discarded.
+    pub fn transition(&mut self) {
+        self.fade_out.clear();
+        self.fade_out.extend(self.held.drain(..));
+        self.fade_pos = 0;
+    }
+
+    /// Appends the interleaved samples to the `output` lagging by the crossfade length.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel count of the samples doesn't match.
+    pub fn process(&mut self, samples: &Samples, output: &mut Vec<Sample>) {
+        assert_eq!(samples.channel_count(), self.channel_count, "Channel count mismatch");
+        let channel_count = self.channel_count;
+        let mut frame = [0.0; MAX_CHANNEL_COUNT];
+        for i in 0..samples.len() {
+            for (c, channel) in samples.channels().enumerate() {
+                frame[c] = channel[i];
+            }
+            self.push_frame(&mut frame[..channel_count], output);
+        }
+    }
+
+    /// Same as `process()` but for interleaved input samples.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the input length isn't a multiple of the channel count.
+    pub fn process_interleaved(&mut self, input: &[Sample], output: &mut Vec<Sample>) {
+        assert!(input.len() % self.channel_count == 0,
+            "Input length isn't a multiple of the channel count");
+        let mut frame = [0.0; MAX_CHANNEL_COUNT];
+        for input_frame in input.chunks(self.channel_count) {
+            let frame = &mut frame[..input_frame.len()];
+            frame.copy_from_slice(input_frame);
+            self.push_frame(frame, output);
+        }
+    }
+
+    /// Appends the held samples to the `output`, fading out the rest of an unfinished
+    /// crossfade. The crossfade is reset afterwards.
+    pub fn flush(&mut self, output: &mut Vec<Sample>) {
+        let mut frame = [0.0; MAX_CHANNEL_COUNT];
+        while self.fade_pos < self.fade_out.len() / self.channel_count {
+            let frame = &mut frame[..self.channel_count];
+            for s in frame.iter_mut() {
+                *s = 0.0;
+            }
+            self.push_frame(frame, output);
+        }
+        output.extend(self.held.drain(..));
+        self.reset();
+    }
+
+    /// Discards the held samples as if the crossfade was newly created.
+    pub fn reset(&mut self) {
+        self.held.clear();
+        self.fade_out.clear();
+        self.fade_pos = 0;
+    }
+
+    fn push_frame(&mut self, frame: &mut [Sample], output: &mut Vec<Sample>) {
+        let channel_count = self.channel_count;
+        let fade_len = self.fade_out.len() / channel_count;
+        if self.fade_pos < fade_len {
+            // Stretches the curve if the previous stream was shorter than the crossfade.
+            let len = self.curve.len();
+            let i = self.fade_pos * len / fade_len;
+            let gain_in = self.curve[i];
+            let gain_out = self.curve[len - 1 - i];
+            let old = &self.fade_out[self.fade_pos * channel_count..(self.fade_pos + 1) * channel_count];
+            for (s, &o) in frame.iter_mut().zip(old) {
+                *s = *s * gain_in + o * gain_out;
+            }
+            self.fade_pos += 1;
+        }
+        if self.curve.is_empty() {
+            output.extend_from_slice(frame);
+            return;
+        }
+        if self.held.len() == self.curve.len() * channel_count {
+            output.extend(self.held.drain(..channel_count));
+        }
+        self.held.extend(frame.iter().cloned());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crossfade() {
+        let mut c = Crossfade::new(2, 4);
+        let mut output = Vec::new();
+        // Nothing to crossfade with yet.
+        c.transition();
+        c.process_interleaved(&[1.0, -1.0, 1.0, -1.0, 1.0, -1.0, 1.0, -1.0, 1.0, -1.0, 1.0, -1.0],
+            &mut output);
+        assert_eq!(output, vec![1.0, -1.0, 1.0, -1.0]);
+
+        output.clear();
+        c.transition();
+        c.process_interleaved(&[0.0; 12], &mut output);
+        c.flush(&mut output);
+        assert_eq!(output.len(), 12);
+        // The previous stream fades out.
+        for (i, f) in output[..8].chunks(2).enumerate() {
+            let gain = c.curve[3 - i];
+            assert_eq!(f, &[gain, -gain]);
+        }
+        assert_eq!(&output[8..], &[0.0; 4]);
+
+        // The gains keep the power of uncorrelated streams.
+        for i in 0..4 {
+            let power = c.curve[i] * c.curve[i] + c.curve[3 - i] * c.curve[3 - i];
+            assert!((power - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn crossfade_short_stream() {
+        let mut c = Crossfade::new(1, 4);
+        let mut output = Vec::new();
+        c.process_interleaved(&[1.0, 1.0], &mut output);
+        assert!(output.is_empty());
+        c.transition();
+        c.process_interleaved(&[0.5; 6], &mut output);
+        c.flush(&mut output);
+        assert_eq!(output.len(), 6);
+        // The crossfade is squeezed into the two samples.
+        assert_eq!(output[0], 0.5 * c.curve[0] + c.curve[3]);
+        assert_eq!(output[1], 0.5 * c.curve[2] + c.curve[1]);
+        assert_eq!(&output[2..], &[0.5; 4]);
+    }
+
+    #[test]
+    fn crossfade_flush_fades_out() {
+        let mut c = Crossfade::new(1, 2);
+        let mut output = Vec::new();
+        c.process_interleaved(&[1.0; 3], &mut output);
+        c.transition();
+        c.flush(&mut output);
+        assert_eq!(output, vec![1.0, c.curve[1], c.curve[0]]);
+
+        let mut c = Crossfade::new(1, 0);
+        output.clear();
+        c.process_interleaved(&[1.0; 3], &mut output);
+        c.transition();
+        c.process_interleaved(&[2.0], &mut output);
+        assert_eq!(output, vec![1.0, 1.0, 1.0, 2.0]);
+    }
+}
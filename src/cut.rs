@@ -0,0 +1,182 @@
+use std::io::Cursor;
+use std::ops::Range;
+
+use bitstream::BitReader;
+use decoder::Setup;
+use error::Result;
+use header::Header;
+
+/// Lossless cut of a Vorbis stream: the range of the audio packets to copy into the output
+/// stream and their new granule positions.
+///
+/// The sample positions are counted in the decoder output of the audio packets, i.e. the first
+/// packet starts at zero regardless of the granule positions of the input container. The first
+/// selected packet only primes the decoder (it overlaps with the next one) and the output
+/// starts `start_trim()` samples before the cut start. The granule positions make the decoder
+/// (or Ogg demuxer) discard these samples at the beginning and the extra samples of the last
+/// packet at the end, so the output decodes to exactly the requested sample range.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use vorbis::{Header, PacketCut, Setup};
+///
+/// # fn f(header: &Header, setup: &Setup, audio_packets: Vec<Vec<u8>>) {
+/// // Cut 10..20 seconds of 44.1 kHz stream.
+/// let cut = PacketCut::new(header, setup, &audio_packets, 441000, 882000)
+///         .expect("Couldn't read packet");
+/// for i in cut.packets() {
+///     let granule_pos = cut.granule_pos(i);
+///     // Write the audio_packets[i] with the granule_pos.
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct PacketCut {
+    packets: Range<usize>,
+    granule_positions: Box<[u64]>,
+    start_trim: u64,
+}
+
+impl PacketCut {
+    /// Computes the cut of the `start..end` sample range of the stream with the audio
+    /// `packets` (the header packets excluded). Only the packet kind and mode bits are read
+    /// from each packet. The end is clamped to the stream length, the cut is empty if the
+    /// range is empty then.
+    pub fn new<I, P>(header: &Header, setup: &Setup, packets: I, start: u64, end: u64) -> Result<Self>
+            where I: IntoIterator<Item=P>, P: AsRef<[u8]> {
+        // End sample position of each packet.
+        let mut positions = Vec::new();
+        let mut pos = 0;
+        let mut prev_frame_len = None;
+        for packet in packets {
+            let kind = try!(setup.packet_frame_kind(&mut BitReader::new(Cursor::new(packet.as_ref()))));
+            let frame_len = header.frame_lens().get(kind);
+            if let Some(prev_frame_len) = prev_frame_len {
+                pos += (prev_frame_len / 4 + frame_len / 4) as u64;
+            }
+            positions.push(pos);
+            prev_frame_len = Some(frame_len);
+        }
+
+        let end = if let Some(&last) = positions.last() {
+            if last < end { last } else { end }
+        } else {
+            0
+        };
+        if start >= end {
+            return Ok(PacketCut {
+                packets: 0..0,
+                granule_positions: Box::new([]),
+                start_trim: 0,
+            });
+        }
+
+        // The packet before the first one with output after the start.
+        let first = positions.iter().position(|&p| p > start).unwrap() - 1;
+        let last = positions.iter().position(|&p| p >= end).unwrap();
+        let granule_positions: Vec<_> = positions[first..last + 1].iter()
+            .map(|&p| if p > end { end - start } else { p.saturating_sub(start) })
+            .collect();
+
+        Ok(PacketCut {
+            packets: first..last + 1,
+            granule_positions: granule_positions.into_boxed_slice(),
+            start_trim: start - positions[first],
+        })
+    }
+
+    /// Returns the range of the input audio packet indexes to copy into the output.
+    pub fn packets(&self) -> Range<usize> {
+        self.packets.clone()
+    }
+
+    /// Returns the granule position for the input packet in `packets()` range in the output
+    /// stream. The first packet has zero granule position and the last one has `len()`.
+    pub fn granule_pos(&self, packet: usize) -> u64 {
+        assert!(packet >= self.packets.start && packet < self.packets.end);
+        self.granule_positions[packet - self.packets.start]
+    }
+
+    /// Returns the number of samples decoded from the output packets that precede the cut
+    /// start and must be discarded, as signaled by the granule positions.
+    pub fn start_trim(&self) -> u64 {
+        self.start_trim
+    }
+
+    /// Returns the number of samples in the cut.
+    pub fn len(&self) -> u64 {
+        self.granule_positions.last().cloned().unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use header::{Bitrates, FrameKind, FrameLens};
+    use residue::ResidueKind;
+    use setup_builder::{CodebookConfig, FloorConfig, MappingConfig, ResidueConfig, SetupBuilder};
+    use super::*;
+
+    // Packets of the short (0) and long (1) frames: the packet kind bit and the mode number.
+    fn packets(kinds: &[u8]) -> Vec<Vec<u8>> {
+        kinds.iter().map(|&k| vec![k << 1]).collect()
+    }
+
+    fn cut(kinds: &[u8], start: u64, end: u64) -> PacketCut {
+        let header = Header::new(1, 44100, Bitrates::new(0, 0, 0), FrameLens::new(256, 2048));
+        let mut floor = FloorConfig::new(1, 8);
+        floor.class(1, None, vec![Some(0)]).partition(0, &[128]);
+        let mut residue = ResidueConfig::new(ResidueKind::Residue1, 0, 0, 1, 0);
+        residue.class(&[]);
+        let mut mapping = MappingConfig::new();
+        mapping.submap(0, 0);
+        let packet = SetupBuilder::new()
+            .codebook(CodebookConfig::new(1, vec![1, 1]))
+            .floor(floor)
+            .residue(residue)
+            .mapping(mapping)
+            .mode(FrameKind::Short, 0)
+            .mode(FrameKind::Long, 0)
+            .to_packet(&header)
+            .unwrap();
+        let setup = Setup::read_packet(&mut BitReader::new(Cursor::new(&packet[..])), &header).unwrap();
+        PacketCut::new(&header, &setup, packets(kinds), start, end).unwrap()
+    }
+
+    #[test]
+    fn packet_cut() {
+        // Packet end positions: 0, 1024, 2048, 2624, 2752, 2880, 3456, 4480.
+        let kinds = [1, 1, 1, 0, 0, 0, 1, 1];
+
+        let c = cut(&kinds, 0, 100000);
+        assert_eq!(c.packets(), 0..8);
+        assert_eq!(c.start_trim(), 0);
+        assert_eq!(c.len(), 4480);
+        assert_eq!(c.granule_pos(0), 0);
+        assert_eq!(c.granule_pos(3), 2624);
+
+        let c = cut(&kinds, 2048, 2800);
+        assert_eq!(c.packets(), 2..6);
+        assert_eq!(c.start_trim(), 0);
+        assert_eq!((2..6).map(|i| c.granule_pos(i)).collect::<Vec<_>>(), &[0, 576, 704, 752]);
+
+        let c = cut(&kinds, 2700, 3456);
+        assert_eq!(c.packets(), 3..7);
+        assert_eq!(c.start_trim(), 76);
+        assert_eq!((3..7).map(|i| c.granule_pos(i)).collect::<Vec<_>>(), &[0, 52, 180, 756]);
+        assert_eq!(c.len(), 756);
+    }
+
+    #[test]
+    fn packet_cut_empty() {
+        let kinds = [1, 1, 1];
+        assert!(cut(&kinds, 2048, 3000).is_empty());
+        assert!(cut(&kinds, 1000, 1000).is_empty());
+        assert_eq!(cut(&kinds, 3000, 2000).packets(), 0..0);
+        assert!(cut(&[], 0, 1000).is_empty());
+    }
+}
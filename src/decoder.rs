@@ -1,74 +1,676 @@
-use num::FromPrimitive;
-use std::{mem, str};
-
-use bitstream::BitRead;
-use codebook::Codebook;
-use error::{Error, Result};
+use std::{cmp, fmt, mem};
+#[cfg(feature = "std")]
+use std::io::Cursor;
+use std::ops::{Index, Range};
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+use std::sync::Arc;
+#[cfg(feature = "std")]
+use std::sync::mpsc;
+#[cfg(feature = "std")]
+use std::thread;
+
+use bands::BandScale;
+use bitstream::{BitRead, BitReader, BitstreamError};
+#[cfg(feature = "std")]
+use bitstream::BitWrite;
+use codebook::{Codebook, CodebookSpan};
+#[cfg(feature = "rayon")]
+use codebook::CodebookDesc;
+#[cfg(feature = "comments")]
+use comment::{CommentLimits, Comments};
+use error::{DecodeStage, Error, ErrorKind, Result, Warning};
 use floor::Floor;
-use header::{Comments, FrameKind, Header};
+use frame::Frame;
+use header::{ChannelLayout, FrameKind, FrameLens, Header, SpeakerPosition};
 use mapping::Mapping;
 use mdct::Mdct;
+use mix::DownmixMatrix;
 use mode::Mode;
-use residue::Residue;
-use util::Bits;
+use residue::{Residue, ResidueBuf};
+use sample::{to_i16, Sample};
+use sink::SampleSink;
+use tables::TableCache;
+#[cfg(not(feature = "std"))]
+use util::Float;
+use util::{Bits, FromPrimitive};
 use window::{OverlapTarget, Window, WindowRange, Windows};
 
 const MAGIC_LEN: usize = 6;
 const MAGIC: &'static [u8] = b"vorbis";
 
+// Maximum number of warnings the decoder accumulates until they're taken, further warnings are
+// dropped. Bounded so the warnings buffer can be preallocated.
+const MAX_WARNINGS: usize = 64;
+
+// Serialized `DecoderState`: the magic, frame kind, silent flag, sample size, zero byte, position,
+// channel count and sample count followed by the samples. All numbers are little-endian.
+const STATE_MAGIC: &'static [u8] = b"VbDS";
+const STATE_HEADER_LEN: usize = 24;
+
+// Magnitude of the noise the floor curves are applied to in `DecoderBuilder::floor_only()` mode.
+// The residues of the libvorbis-encoded streams average about twice the floor, so the loudness
+// of the output roughly matches the decoded audio.
+const NOISE_MAGNITUDE: Sample = 2.0;
+
+// Lowest value of `FrameSpectrum`, the magnitudes below it (including zero) are clamped.
+const SPECTRUM_MIN_DB: Sample = -140.0;
+
 /// Low-level Vorbis decoder.
 ///
 /// Decodes Vorbis audio packets into audio samples. Note the decoder works directly with
 /// Vorbis packet data extracted from container (like Ogg).
 ///
+/// The decoder is `Send` and `Sync`: the tables shared between decoders (see `TableCache`) are
+/// reference counted atomically, so the decoder can be built on one thread and moved to an audio
+/// thread or a thread pool.
+///
 /// # Example
 /// See [module reference](index.html).
 pub struct Decoder {
-    header: Header,
+    #[cfg(feature = "comments")]
     comments: Option<Comments>,
-    setup: Setup,
-    windows: Windows,
-    mdct: [Mdct; 2],
-
-    floor_y_list: Box<[Vec<(u16, bool)>]>,
-    prev_frame: Box<[Box<[f32]>]>,
-    prev_frame_kind: Option<FrameKind>,
-    frame: Box<[Box<[f32]>]>,
-    frame_kind: Option<FrameKind>,
-    pos: u64,
+    warnings: Vec<Warning>,
+    info: Option<PacketInfo>,
+    stats: Option<PacketStats>,
+    packet_count: u64,
+    error_location: Option<ErrorLocation>,
+    spectrum: SpectrumDecoder,
+    synth: Synthesizer,
 }
 
 impl Decoder {
     pub fn builder() -> DecoderBuilder {
         DecoderBuilder {
             header: None,
+            #[cfg(feature = "comments")]
             comments: None,
             setup: None,
+            decouple_channels: true,
+            table_cache: None,
+            #[cfg(feature = "comments")]
+            comment_limits: CommentLimits::default(),
+            lenient: false,
+            decode_limits: DecodeLimits::default(),
+            spectrum_analysis: false,
+            rate_divisor: 1,
+            floor_only: false,
+            warnings: Vec::new(),
         }
     }
 
     /// Decodes an audio packet. Note if this is the first audio packet (either for a newly initialized
     /// decoder instance or after a call to `reset()`) the returned samples will
-    /// be empty.
+    /// be empty, see `prime()`.
+    ///
+    /// Decoding doesn't allocate, even if it fails: all the buffers are preallocated in
+    /// `DecoderBuilder::build()`. At most 64 warnings are accumulated until `take_warnings()` is
+    /// called, the further ones are dropped.
+    ///
+    /// The location of the error is reported by `error_location()`.
     pub fn decode<R: BitRead>(&mut self, reader: &mut R) -> Result<Samples> {
-        self.swap_frames();
+        let (info, silent) = try!(self.decode_spectrum(reader));
+        self.synth.synthesize(info.frame_kind, silent);
+        Ok(self.synth.samples())
+    }
 
-        let packet_kind = try!(reader.read_u8_bits(1));
-        if packet_kind != PacketKind::Audio as u8 {
-            return Err(Error::WrongPacketKind("Expected audio packet"));
+    /// Same as `decode()` but tells the priming packet, which has no output, and the packets
+    /// the decoder recovered from apart from the regular ones.
+    pub fn decode_outcome<R: BitRead>(&mut self, reader: &mut R) -> Result<DecodeOutcome> {
+        try!(self.decode(reader));
+        Ok(if self.synth.prev_frame_kind.is_none() {
+            DecodeOutcome::Primed
+        } else if self.spectrum.recovered {
+            DecodeOutcome::Recovered(self.synth.samples())
+        } else {
+            DecodeOutcome::Frame(self.synth.samples())
+        })
+    }
+
+    /// Decodes an audio packet into 16-bit interleaved samples written to the `buf`. Returns
+    /// the number of values written (the sample count times the channel count). The window
+    /// overlap-add and the conversion are done in a single pass which is faster than converting
+    /// `samples()` afterwards. The `samples()` are still available after the call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `buf` is shorter than `header().frame_lens().long() / 2 *
+    /// header().channel_count()`.
+    pub fn decode_i16<R: BitRead>(&mut self, reader: &mut R, buf: &mut [i16]) -> Result<usize> {
+        let header = &self.spectrum.header;
+        assert!(buf.len() >= header.frame_lens().long() / 2 * header.channel_count(),
+            "Buffer is too short");
+        let (info, silent) = try!(self.decode_spectrum(reader));
+        Ok(self.synth.synthesize_i16(info.frame_kind, silent, buf))
+    }
+
+    /// Decodes an audio packet writing the samples of each channel into the corresponding buffer
+    /// of the `out`. Returns the number of samples written to each buffer. Suits the audio
+    /// engines consuming planar (non-interleaved) buffers they own. The `samples()` are still
+    /// available after the call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of the buffers isn't `header().channel_count()` or any of them is
+    /// shorter than `header().frame_lens().long() / 2`.
+    pub fn decode_planar_into<R: BitRead>(&mut self, reader: &mut R, out: &mut [&mut [Sample]])
+            -> Result<usize> {
+        {
+            let header = &self.spectrum.header;
+            assert_eq!(out.len(), header.channel_count(), "Channel count mismatch");
+            let min_len = header.frame_lens().long() / 2;
+            assert!(out.iter().all(|buf| buf.len() >= min_len), "Buffer is too short");
         }
-        let mode_count = self.setup.modes.len();
-        let mode_idx = try!(reader.read_u8_bits((mode_count as u8).ilog() as usize - 1)) as usize;
-        if mode_idx >= mode_count {
-            return Err(Error::Undecodable("Invalid packet mode number"));
+        let samples = try!(self.decode(reader));
+        for (buf, channel) in out.iter_mut().zip(samples.channels()) {
+            buf[..channel.len()].copy_from_slice(channel);
+        }
+        Ok(samples.len())
+    }
+
+    /// Decodes an audio packet mixing the samples into the interleaved `buf` with the `matrix`,
+    /// e.g. to fold down a surround stream to stereo. Returns the number of samples (per
+    /// channel) written, each has `matrix.output_count()` values. The `samples()` are still
+    /// available after the call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the matrix input count isn't `header().channel_count()` or the `buf` is shorter
+    /// than `header().frame_lens().long() / 2 * matrix.output_count()`.
+    pub fn decode_downmixed<R: BitRead>(&mut self, reader: &mut R, matrix: &DownmixMatrix,
+            buf: &mut [Sample]) -> Result<usize> {
+        {
+            let header = &self.spectrum.header;
+            assert_eq!(matrix.input_count(), header.channel_count(), "Channel count mismatch");
+            assert!(buf.len() >= header.frame_lens().long() / 2 * matrix.output_count(),
+                "Buffer is too short");
+        }
+        let samples = try!(self.decode(reader));
+        Ok(matrix.apply(&samples, buf))
+    }
+
+    /// Decodes an audio packet passing the samples to the `sink`. Returns the number of samples
+    /// (per channel) written. The samples go from the decoder buffers straight to the sink, so
+    /// it can write them to a ring buffer, a file or an FFI buffer without intermediate copies.
+    /// The `samples()` are still available after the call.
+    pub fn decode_to<R: BitRead, S: SampleSink>(&mut self, reader: &mut R, mut sink: S)
+            -> Result<usize> {
+        let samples = try!(self.decode(reader));
+        if !samples.is_empty() {
+            try!(sink.write_planar(&samples));
+        }
+        Ok(samples.len())
+    }
+
+    // Decodes the audio packet into the current frame counting the packet.
+    fn decode_spectrum<R: BitRead>(&mut self, reader: &mut R) -> Result<(PacketInfo, bool)> {
+        self.synth.swap_frames();
+        self.info = None;
+        let packet = self.packet_count;
+        self.packet_count += 1;
+        self.error_location = None;
+        let (info, silent) = match self.spectrum.decode(reader, &mut self.synth.frame,
+                &mut self.warnings, &mut self.stats) {
+            Ok(r) => r,
+            Err(e) => {
+                self.error_location = Some(ErrorLocation {
+                    packet: packet,
+                    sample_pos: self.synth.pos,
+                    bit_pos: reader.bit_pos(),
+                    stage: self.spectrum.stage,
+                });
+                return Err(e);
+            }
+        };
+        self.info = Some(info);
+        Ok((info, silent))
+    }
+
+    // Decodes the audio packet without the synthesis (the inverse MDCT and overlap-add) for
+    // `validate()`. The decoder is reset, so the `samples()` are empty afterwards. The packet
+    // isn't counted and the `error_location()` isn't set, the validation report has the packet
+    // index and the bit position.
+    pub(crate) fn decode_spectrum_only<R: BitRead>(&mut self, reader: &mut R) -> Result<PacketInfo> {
+        self.synth.reset();
+        self.info = None;
+        let (info, _) = try!(self.spectrum.decode(reader, &mut self.synth.frame,
+            &mut self.warnings, &mut self.stats));
+        self.info = Some(info);
+        Ok(info)
+    }
+
+    /// Converts this decoder into a `PipelinedDecoder` that continues decoding the same stream.
+    #[cfg(feature = "std")]
+    pub fn into_pipelined(self) -> PipelinedDecoder {
+        PipelinedDecoder::new(self)
+    }
+
+    /// Resets this decoder's state as it would be after a newly initialized decoder instance,
+    /// e.g. after seeking to another packet. The sample position is reset to zero.
+    pub fn reset(&mut self) {
+        self.synth.reset();
+    }
+
+    /// Decodes the audio packet only to establish the left window of the next packet, the
+    /// previous state of the decoder is discarded as with `reset()`. This is what `decode()`
+    /// does implicitly for the first packet, so the output is empty (`samples()` are empty
+    /// too) and the sample position is zero afterwards.
+    ///
+    /// After seeking to a packet, priming with the packet preceding it makes the following
+    /// `decode()` calls return bit-identical samples to the ones of linear decoding of the
+    /// stream: the right half of a frame doesn't depend on the previous frames.
+    pub fn prime<R: BitRead>(&mut self, reader: &mut R) -> Result<()> {
+        self.synth.reset();
+        try!(self.decode(reader));
+        Ok(())
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.spectrum.header
+    }
+
+    /// Returns the sample rate of the output, which is the stream's sample rate divided by
+    /// the `DecoderBuilder::rate_divisor()`.
+    pub fn sample_rate(&self) -> u32 {
+        self.spectrum.header.sample_rate() / self.synth.rate_divisor as u32
+    }
+
+    /// Returns the divisor of the output sample rate set with `DecoderBuilder::rate_divisor()`.
+    pub fn rate_divisor(&self) -> usize {
+        self.synth.rate_divisor
+    }
+
+    /// Returns the warnings reported since the decoder was built or the last call of this method
+    /// (including the ones reported while reading the header packets). The warnings are
+    /// accumulated until taken.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        mem::replace(&mut self.warnings, Vec::with_capacity(MAX_WARNINGS))
+    }
+
+    #[cfg(feature = "comments")]
+    pub fn comments(&self) -> Option<&Comments> {
+        self.comments.as_ref()
+    }
+
+    pub fn setup(&self) -> &Setup {
+        &self.spectrum.setup
+    }
+
+    pub fn samples(&self) -> Samples {
+        self.synth.samples()
+    }
+
+    /// Returns the magnitude spectrum of the frame of the last decoded packet. Returns `None`
+    /// if not enabled with `DecoderBuilder::spectrum_analysis()` or no packet was decoded yet.
+    pub fn spectrum(&self) -> Option<FrameSpectrum> {
+        self.synth.spectrum()
+    }
+
+    /// Returns the number of bits each decoding stage consumed from the last successfully
+    /// decoded packet. Returns `None` if no packet was decoded yet or the reader doesn't track
+    /// the bit position (`BitRead::bit_pos()`).
+    pub fn packet_stats(&self) -> Option<PacketStats> {
+        self.stats
+    }
+
+    /// Returns the mode and window flags of the last successfully decoded packet. Returns `None`
+    /// if no packet was decoded yet or decoding of the last one failed.
+    pub fn packet_info(&self) -> Option<PacketInfo> {
+        self.info
+    }
+
+    // Returns sample position - the number of sample this decoder produced so far.
+    pub fn pos(&self) -> u64 {
+        self.synth.pos
+    }
+
+    /// Returns the number of audio packets passed to the decoding methods since the decoder was
+    /// built, including the failed ones and the ones passed to `prime()`. Unlike `pos()` it
+    /// isn't reset by `reset()`. This is the packet index of the errors, see
+    /// `error_location()`.
+    pub fn packet_count(&self) -> u64 {
+        self.packet_count
+    }
+
+    /// Returns the location of the error if decoding of the last audio packet failed, `None` if
+    /// it succeeded or no packet was decoded yet. The location is kept by the decoder rather
+    /// than attached to the error so that decoding doesn't allocate.
+    pub fn error_location(&self) -> Option<ErrorLocation> {
+        self.error_location
+    }
+
+    /// Returns the algorithmic latency: the number of samples of the last decoded frame held
+    /// back for the overlap with the next one, i.e. half of the frame length. The end of the
+    /// audio decoded so far is this far ahead of `pos()`, so the real-time users aligning the
+    /// output with other media can compensate for it. Zero if no packet was decoded yet.
+    pub fn latency_samples(&self) -> usize {
+        self.synth.latency_samples()
+    }
+
+    /// Returns the snapshot of the decoding state to continue decoding from later with
+    /// `restore_state()`.
+    pub fn state(&self) -> DecoderState {
+        self.synth.state()
+    }
+
+    /// Restores the decoding state returned by `state()` of a decoder of the same stream. The
+    /// next decoded packet must be the one following the last packet decoded before the
+    /// snapshot. Until then `samples()` are empty and `spectrum()` and `packet_stats()` return
+    /// `None`. Fails with `Error::InvalidDecoderState` if the state doesn't match the stream's
+    /// channel count or frame lengths, the decoder is unchanged then.
+    pub fn restore_state(&mut self, state: &DecoderState) -> Result<()> {
+        try!(self.synth.restore_state(state));
+        self.info = None;
+        self.stats = None;
+        Ok(())
+    }
+}
+
+/// Summarizes the stream parameters and the decoding options and position, e.g. for bug reports.
+/// The sample data and the setup components aren't included (see `DebugDump` for the latter).
+impl fmt::Debug for Decoder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut d = f.debug_struct("Decoder");
+        d.field("header", self.header());
+        #[cfg(feature = "comments")]
+        d.field("comments", &self.comments.as_ref().map(|c| c.len()));
+        d.field("setup", self.setup())
+            .field("decouple_channels", &self.spectrum.decouple_channels)
+            .field("lenient", &self.spectrum.lenient)
+            .field("decode_limits", &self.spectrum.limits)
+            .field("floor_only", &self.spectrum.floor_only)
+            .field("rate_divisor", &self.synth.rate_divisor)
+            .field("spectrum_analysis", &self.synth.analysis.is_some())
+            .field("pos", &self.pos())
+            .field("packet_count", &self.packet_count())
+            .field("prev_frame_kind", &self.synth.prev_frame_kind)
+            .field("warnings", &self.warnings)
+            .finish()
+    }
+}
+
+/// Outcome of decoding an audio packet returned by `Decoder::decode_outcome()`.
+pub enum DecodeOutcome<'a> {
+    /// The packet is the first one after the decoder was built or reset: it only establishes
+    /// the left window of the next packet, so there's no output (see `Decoder::prime()`).
+    Primed,
+    /// The samples decoded from the packet.
+    Frame(Samples<'a>),
+    /// The samples decoded from the damaged packet or the one exceeding the `DecodeLimits` in
+    /// lenient mode. The decoder recovered reporting `Warning::TruncatedFloor` or
+    /// `Warning::ResiduePassesSkipped`, so the samples are the best effort approximation of
+    /// the original audio.
+    Recovered(Samples<'a>),
+}
+
+impl<'a> DecodeOutcome<'a> {
+    /// Returns the samples or `None` if `Primed`.
+    pub fn samples(self) -> Option<Samples<'a>> {
+        match self {
+            DecodeOutcome::Primed => None,
+            DecodeOutcome::Frame(s) | DecodeOutcome::Recovered(s) => Some(s),
+        }
+    }
+
+    pub fn is_primed(&self) -> bool {
+        match *self {
+            DecodeOutcome::Primed => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_recovered(&self) -> bool {
+        match *self {
+            DecodeOutcome::Recovered(_) => true,
+            _ => false,
         }
-        let mode = &self.setup.modes[mode_idx];
+    }
+}
+
+/// Mode and window flags read from the beginning of an audio packet, returned by
+/// `Decoder::packet_info()` and `Setup::packet_info()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PacketInfo {
+    mode: usize,
+    frame_kind: FrameKind,
+    // Long frames only: the previous and next window flags.
+    window_flags: Option<(bool, bool)>,
+}
+
+impl PacketInfo {
+    /// Returns the index of the packet's mode in `Setup::modes()`.
+    pub fn mode(&self) -> usize {
+        self.mode
+    }
+
+    pub fn frame_kind(&self) -> FrameKind {
+        self.frame_kind
+    }
+
+    /// Returns whether the previous frame is long as signaled by the packet's window flag.
+    /// Returns `None` for short frames which don't have the flag.
+    pub fn prev_window_long(&self) -> Option<bool> {
+        self.window_flags.map(|f| f.0)
+    }
+
+    /// Returns whether the next frame is long as signaled by the packet's window flag.
+    /// Returns `None` for short frames which don't have the flag.
+    pub fn next_window_long(&self) -> Option<bool> {
+        self.window_flags.map(|f| f.1)
+    }
+}
+
+/// Location of the error of an audio packet, see `Decoder::error_location()`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ErrorLocation {
+    packet: u64,
+    sample_pos: u64,
+    bit_pos: Option<u64>,
+    stage: Option<DecodeStage>,
+}
+
+impl ErrorLocation {
+    /// Returns the index of the packet among the audio packets passed to the decoder since it
+    /// was built (see `Decoder::packet_count()`).
+    pub fn packet(&self) -> u64 {
+        self.packet
+    }
+
+    /// Returns the sample position of the decoder (`Decoder::pos()`) when the packet was
+    /// decoded.
+    pub fn sample_pos(&self) -> u64 {
+        self.sample_pos
+    }
 
-        if mode.frame_kind == FrameKind::Long {
-            /* let is_prev_long_frame = */ try!(reader.read_bool());
-            /* let is_next_long_frame = */ try!(reader.read_bool());
+    /// Returns the offset in bits from the beginning of the packet where decoding stopped if
+    /// the reader reports the position (see `BitRead::bit_pos()`).
+    pub fn bit_pos(&self) -> Option<u64> {
+        self.bit_pos
+    }
+
+    /// Returns the component of the packet that was being decoded, `None` for the packet kind
+    /// and mode number.
+    pub fn stage(&self) -> Option<DecodeStage> {
+        self.stage
+    }
+}
+
+/// Number of bits consumed by each stage of decoding an audio packet, returned by
+/// `Decoder::packet_stats()`.
+///
+/// The packet length minus `total_bits()` is the padding at the end of the packet (or the
+/// residue data that was cut off). Together with the packet sample counts these give the
+/// bitrate over time and show where the bits are spent.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PacketStats {
+    header_bits: u64,
+    floor_bits: u64,
+    residue_pass_bits: [u64; 8],
+}
+
+impl PacketStats {
+    /// Returns the number of bits of the packet kind, mode number and window flags.
+    pub fn header_bits(&self) -> u64 {
+        self.header_bits
+    }
+
+    /// Returns the number of bits of the floors of all channels.
+    pub fn floor_bits(&self) -> u64 {
+        self.floor_bits
+    }
+
+    /// Returns the number of bits of the residues of all submaps.
+    pub fn residue_bits(&self) -> u64 {
+        self.residue_pass_bits.iter().sum()
+    }
+
+    /// Returns the number of bits of each of the eight residue passes summed over all submaps.
+    /// The pass zero includes the partition classifications.
+    pub fn residue_pass_bits(&self) -> &[u64; 8] {
+        &self.residue_pass_bits
+    }
+
+    /// Returns the number of bits of all the stages.
+    pub fn total_bits(&self) -> u64 {
+        self.header_bits + self.floor_bits + self.residue_bits()
+    }
+}
+
+/// Snapshot of the decoding state returned by `Decoder::state()`: the part of the last decoded
+/// frame that overlaps with the next one and the sample position.
+///
+/// Restoring the state with `Decoder::restore_state()` on a decoder built from the same header
+/// packets makes it continue exactly where the snapshot was taken, so a long decode can be
+/// suspended and resumed later, even in another process with `to_bytes()` and `from_bytes()`.
+/// The state doesn't include the headers, the spectrum analysis or the packet stats.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecoderState {
+    frame_kind: Option<FrameKind>,
+    silent: bool,
+    pos: u64,
+    channel_count: usize,
+    // The right halves of the frame channels, channel after channel.
+    samples: Box<[Sample]>,
+}
+
+impl DecoderState {
+    /// Returns the kind of the last decoded frame or `None` if no packet was decoded yet.
+    pub fn frame_kind(&self) -> Option<FrameKind> {
+        self.frame_kind
+    }
+
+    /// Returns the sample position of the decoder, see `Decoder::pos()`.
+    pub fn pos(&self) -> u64 {
+        self.pos
+    }
+
+    /// Serializes the state. The samples are stored with the precision of `Sample`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let sample_len = mem::size_of::<Sample>();
+        let mut r = Vec::with_capacity(STATE_HEADER_LEN + self.samples.len() * sample_len);
+        r.extend_from_slice(STATE_MAGIC);
+        r.push(match self.frame_kind {
+            None => 0,
+            Some(FrameKind::Short) => 1,
+            Some(FrameKind::Long) => 2,
+        });
+        r.push(self.silent as u8);
+        r.push(sample_len as u8);
+        r.push(0);
+        for i in 0..8 {
+            r.push((self.pos >> (i * 8)) as u8);
+        }
+        for &v in &[self.channel_count as u32, self.samples.len() as u32] {
+            r.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]);
+        }
+        for &s in self.samples.iter() {
+            if sample_len == 4 {
+                let v = (s as f32).to_bits();
+                r.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]);
+            } else {
+                let v = (s as f64).to_bits();
+                for i in 0..8 {
+                    r.push((v >> (i * 8)) as u8);
+                }
+            }
+        }
+        r
+    }
+
+    /// Deserializes the state written by `to_bytes()`. The samples of either precision are
+    /// accepted. Fails with `Error::InvalidDecoderState` if the data is malformed.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        fn get(b: &[u8], len: usize) -> u64 {
+            b[..len].iter().rev().fold(0, |a, &v| a << 8 | v as u64)
         }
 
+        if bytes.len() < STATE_HEADER_LEN || &bytes[..STATE_MAGIC.len()] != STATE_MAGIC {
+            return Err(Error::InvalidDecoderState);
+        }
+        let b = &bytes[STATE_MAGIC.len()..];
+        let frame_kind = match b[0] {
+            0 => None,
+            1 => Some(FrameKind::Short),
+            2 => Some(FrameKind::Long),
+            _ => return Err(Error::InvalidDecoderState),
+        };
+        let sample_len = b[2] as usize;
+        if b[1] > 1 || (sample_len != 4 && sample_len != 8) || b[3] != 0 {
+            return Err(Error::InvalidDecoderState);
+        }
+        let pos = get(&b[4..], 8);
+        let channel_count = get(&b[12..], 4) as usize;
+        let len = get(&b[16..], 4) as usize;
+        let data = &bytes[STATE_HEADER_LEN..];
+        if len.checked_mul(sample_len) != Some(data.len()) {
+            return Err(Error::InvalidDecoderState);
+        }
+        let samples: Vec<_> = data.chunks(sample_len).map(|c| if sample_len == 4 {
+            f32::from_bits(get(c, 4) as u32) as Sample
+        } else {
+            f64::from_bits(get(c, 8)) as Sample
+        }).collect();
+        Ok(DecoderState {
+            frame_kind: frame_kind,
+            silent: b[1] != 0,
+            pos: pos,
+            channel_count: channel_count,
+            samples: samples.into_boxed_slice(),
+        })
+    }
+}
+
+// The first half of audio packet decoding: reads the packet and produces the spectrum of the
+// frame (i.e. everything up to the inverse MDCT).
+struct SpectrumDecoder {
+    header: Header,
+    setup: Setup,
+    decouple_channels: bool,
+    limits: DecodeLimits,
+    lenient: bool,
+    // The residues are skipped, see `DecoderBuilder::floor_only()`.
+    floor_only: bool,
+    scratch: DecodeScratch,
+    // The decoder recovered from a problem in the last successfully decoded packet.
+    recovered: bool,
+    // Component of the packet being decoded, where decoding stopped if it failed.
+    stage: Option<DecodeStage>,
+}
+
+impl SpectrumDecoder {
+    // Decodes the packet into the first half of the `frame` channels and returns the packet info
+    // and whether the floors of all channels are unused (the spectrum is all zeros).
+    // The `stats` are set if decoding succeeded and the reader tracks bit position or reset
+    // otherwise.
+    fn decode<R: BitRead>(&mut self, reader: &mut R, frame: &mut Frame, warnings: &mut Vec<Warning>,
+            stats: &mut Option<PacketStats>) -> Result<(PacketInfo, bool)> {
+        *stats = None;
+        self.stage = None;
+        let start_pos = reader.bit_pos();
+        let info = try!(self.setup.packet_info(reader));
+        let mode = &self.setup.modes[info.mode];
+        let floor_pos = reader.bit_pos();
+
         let frame_lens = self.header.frame_lens();
         let frame_len = frame_lens.get(mode.frame_kind);
         let frame_half_len = frame_len / 2;
@@ -76,40 +678,93 @@ impl Decoder {
         let mapping = &self.setup.mappings[mode.mapping as usize];
 
         // Begin decoding floors.
-        for (channel, floor_y_list) in self.floor_y_list.iter_mut().enumerate() {
-            let submap_idx = mapping.channel_to_submap[channel];
-            let floor_idx = mapping.submaps[submap_idx].floor;
-            let floor = &self.setup.floors[floor_idx];
-            try!(floor.begin_decode(floor_y_list, reader, &self.setup.codebooks));
+        let scratch = &mut self.scratch;
+        let mut floor_segments = 0;
+        let mut recovered = false;
+        for (channel, floor_y_list) in scratch.floor_y_list.iter_mut().enumerate() {
+            let floor_idx = mapping.channel_submap(channel).floor();
+            self.stage = Some(DecodeStage::Floor(floor_idx));
+            let complete = try!(self.setup.floors[floor_idx]
+                .begin_decode(floor_y_list, reader, &self.setup.codebooks));
+            if !complete {
+                recovered = true;
+                if warnings.len() < MAX_WARNINGS {
+                    warnings.push(Warning::TruncatedFloor { channel: channel });
+                }
+            }
+            floor_segments += floor_y_list.len();
+            if floor_segments > self.limits.max_floor_segments {
+                return Err(Error::DecodeLimitExceeded("Too many floor segments"));
+            }
         }
 
         // Decode residues.
-        {
-            let mut zero_channels: Vec<_> = self.floor_y_list.iter().map(|f| f.is_empty()).collect();
-
-            mapping.unzero_coupled_channels(&mut zero_channels);
+        let residue_pos = reader.bit_pos();
+        let mut residue_pass_bits = [0; 8];
+        if self.floor_only {
+            // The floor curves are applied to noise instead, the coupled channels are left as
+            // is since the residues carry the coupling.
+            for channel in frame.channels_mut() {
+                fill_noise(&mut channel[..frame_half_len]);
+            }
+        } else {
+            for (zero, floor_y_list) in scratch.zero_channels.iter_mut()
+                                                .zip(scratch.floor_y_list.iter()) {
+                *zero = floor_y_list.is_empty();
+            }
 
-            for submap in mapping.submaps.iter() {
-                let residue_idx = submap.residue;
-                let residue = &self.setup.residues[residue_idx];
-                try!(residue.decode(reader,
-                            &mut self.frame,
+            mapping.unzero_coupled_channels(&mut scratch.zero_channels);
+
+            let mut vq_reads_left = self.limits.max_vq_reads;
+            for submap in mapping.submaps().iter() {
+                let residue = &self.setup.residues[submap.residue()];
+                let mut pass_count = residue.pass_count();
+                if pass_count > self.limits.max_residue_passes {
+                    if !self.lenient {
+                        self.stage = Some(DecodeStage::Residue {
+                            residue: submap.residue(),
+                            pass: Some(self.limits.max_residue_passes),
+                        });
+                        return Err(Error::DecodeLimitExceeded("Too many residue passes"));
+                    }
+                    recovered = true;
+                    if warnings.len() < MAX_WARNINGS {
+                        warnings.push(Warning::ResiduePassesSkipped(
+                            pass_count - self.limits.max_residue_passes));
+                    }
+                    pass_count = self.limits.max_residue_passes;
+                }
+                let mut pass = None;
+                let r = residue.decode(reader,
+                            frame,
                             frame_half_len,
-                            &submap.channels,
-                            &zero_channels,
-                            &self.setup.codebooks));
+                            submap.channels(),
+                            &scratch.zero_channels,
+                            &self.setup.codebooks,
+                            &mut scratch.residue_buf,
+                            &mut residue_pass_bits,
+                            pass_count,
+                            &mut vq_reads_left,
+                            &mut pass);
+                if r.is_err() {
+                    self.stage = Some(DecodeStage::Residue {
+                        residue: submap.residue(),
+                        pass: pass,
+                    });
+                }
+                try!(r);
             }
         }
 
-        mapping.decouple_channels(&mut self.frame, frame_half_len);
+        if self.decouple_channels && !self.floor_only {
+            mapping.decouple_channels(frame, frame_half_len);
+        }
 
         // Finish decoding floors (synthesize and perform dot product with residues).
-        for ((channel, result), floor_y_list) in self.frame.iter_mut().enumerate()
-                                                        .zip(self.floor_y_list.iter()) {
+        for ((channel, result), floor_y_list) in frame.channels_mut().enumerate()
+                                                      .zip(scratch.floor_y_list.iter()) {
             if !floor_y_list.is_empty() {
-                let submap_idx = mapping.channel_to_submap[channel];
-                let floor_idx = mapping.submaps[submap_idx].floor;
-                let floor = &self.setup.floors[floor_idx];
+                let floor = &self.setup.floors[mapping.channel_submap(channel).floor()];
                 floor.finish_decode(result, floor_y_list);
             } else {
                 for r in result[..frame_half_len].as_mut().iter_mut() {
@@ -118,78 +773,732 @@ impl Decoder {
             }
         }
 
-        for channel in self.frame.iter_mut() {
-            self.mdct[mode.frame_kind as usize].inverse(&mut channel[..frame_len]);
+        if let (Some(start_pos), Some(floor_pos), Some(residue_pos)) = (start_pos, floor_pos, residue_pos) {
+            *stats = Some(PacketStats {
+                header_bits: floor_pos - start_pos,
+                floor_bits: residue_pos - floor_pos,
+                residue_pass_bits: residue_pass_bits,
+            });
         }
 
-        if let Some(prev_frame_kind) = self.prev_frame_kind {
-            let window = self.windows.get(prev_frame_kind, mode.frame_kind);
-            for (mut l, mut r) in self.prev_frame.iter_mut().zip(self.frame.iter_mut()) {
-                window.overlap(&mut l, &mut r);
+        let silent = scratch.floor_y_list.iter().all(|l| l.is_empty());
+        self.recovered = recovered;
+        Ok((info, silent))
+    }
+}
+
+// Fills the `buf` with a pseudo-random sequence of `NOISE_MAGNITUDE` with random signs, which
+// is the same for every call.
+fn fill_noise(buf: &mut [Sample]) {
+    let mut seed = 1_u32;
+    for v in buf {
+        seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+        *v = if seed & 0x8000_0000 == 0 { NOISE_MAGNITUDE } else { -NOISE_MAGNITUDE };
+    }
+}
+
+// The second half of audio packet decoding: the inverse MDCT of the decoded spectrum and
+// overlap-add with the previous frame.
+struct Synthesizer {
+    // The frame lengths as synthesized: the stream's ones divided by the `rate_divisor`.
+    frame_lens: FrameLens,
+    rate_divisor: usize,
+    windows: Windows,
+    mdct: [Option<Arc<Mdct>>; 2],
+
+    prev_frame: Frame,
+    prev_frame_kind: Option<FrameKind>,
+    prev_frame_silent: bool,
+    frame: Frame,
+    frame_kind: Option<FrameKind>,
+    // All channels of the frame are zero, so the inverse MDCT is skipped.
+    frame_silent: bool,
+    pos: u64,
+
+    analysis: Option<SpectrumAnalysis>,
+}
+
+impl Synthesizer {
+    // Performs the inverse MDCT of the current frame which must contain the decoded spectrum.
+    // If the frame is `silent` (the spectrum is all zeros) the output is zeroed instead.
+    fn synthesize(&mut self, frame_kind: FrameKind, silent: bool) {
+        let frame_len = self.frame_lens.get(frame_kind);
+        if let Some(ref mut analysis) = self.analysis {
+            analysis.analyze(&self.frame, frame_len / 2);
+        }
+        let mdct = self.mdct[frame_kind as usize].as_ref().unwrap();
+        let window = match self.prev_frame_kind {
+            Some(prev_frame_kind) => Some(self.windows.get(prev_frame_kind, frame_kind)),
+            None => None,
+        };
+        // Overlapping two zero frames gives zeros.
+        let skip_overlap = silent && self.prev_frame_silent;
+        for_each_channel(&mut self.prev_frame, &mut self.frame, |prev, cur| {
+            inverse_mdct(mdct, &mut cur[..frame_len], silent);
+            if let Some(window) = window {
+                if !skip_overlap {
+                    window.overlap(prev, cur);
+                }
             }
+        });
+        if let Some(window) = window {
             self.pos += window.len() as u64;
         }
 
-        self.frame_kind = Some(mode.frame_kind);
+        self.frame_kind = Some(frame_kind);
+        self.frame_silent = silent;
+    }
 
-        Ok(self.samples())
+    // Same as `synthesize()` but also writes the output samples converted to `i16` and interleaved
+    // into the `buf`. Returns the number of values written.
+    fn synthesize_i16(&mut self, frame_kind: FrameKind, silent: bool, buf: &mut [i16]) -> usize {
+        let frame_len = self.frame_lens.get(frame_kind);
+        if let Some(ref mut analysis) = self.analysis {
+            analysis.analyze(&self.frame, frame_len / 2);
+        }
+        {
+            let mdct = self.mdct[frame_kind as usize].as_ref().unwrap();
+            for_each_channel(&mut self.prev_frame, &mut self.frame, |_, cur| {
+                inverse_mdct(mdct, &mut cur[..frame_len], silent);
+            });
+        }
+        self.frame_kind = Some(frame_kind);
+        self.frame_silent = silent;
+
+        let window = match self.prev_frame_kind {
+            Some(prev_frame_kind) => self.windows.get(prev_frame_kind, frame_kind),
+            None => return 0,
+        };
+        let channel_count = self.frame.channel_count();
+        for (channel, (prev, cur)) in self.prev_frame.channels_mut()
+                                          .zip(self.frame.channels_mut()).enumerate() {
+            window.overlap_output(prev, cur, |i, v| buf[i * channel_count + channel] = to_i16(v));
+        }
+        self.pos += window.len() as u64;
+        window.len() * channel_count
+    }
+
+    fn samples(&self) -> Samples {
+        let silent = self.prev_frame_silent && self.frame_silent;
+        self.window().map(|w| match w.overlap_target {
+            OverlapTarget::Left => Samples { frame: &self.prev_frame, range: w.left, silent: silent },
+            OverlapTarget::Right => Samples { frame: &self.frame, range: w.right, silent: silent },
+        }).unwrap_or_else(|| Samples {
+            frame: &self.frame,
+            range: WindowRange { start: 0, end: 0 },
+            silent: true,
+        })
+    }
+
+    fn spectrum(&self) -> Option<FrameSpectrum> {
+        self.analysis.as_ref().and_then(|a| if a.len > 0 {
+            Some(FrameSpectrum {
+                frame: &a.buf,
+                len: a.len,
+                sample_rate: a.sample_rate,
+            })
+        } else {
+            None
+        })
+    }
+
+    fn reset(&mut self) {
+        self.prev_frame_kind = None;
+        self.prev_frame_silent = false;
+        self.frame_kind = None;
+        self.frame_silent = false;
+        self.pos = 0;
+        if let Some(ref mut analysis) = self.analysis {
+            analysis.len = 0;
+        }
+    }
+
+    fn latency_samples(&self) -> usize {
+        // The frame of a failed decode is never used.
+        match self.frame_kind.or(self.prev_frame_kind) {
+            Some(frame_kind) => self.frame_lens.get(frame_kind) / 2,
+            None => 0,
+        }
+    }
+
+    fn state(&self) -> DecoderState {
+        // The frame of a failed decode is never used, the last one stays the previous frame.
+        let (frame, frame_kind, silent) = if self.frame_kind.is_some() {
+            (&self.frame, self.frame_kind, self.frame_silent)
+        } else {
+            (&self.prev_frame, self.prev_frame_kind, self.prev_frame_silent)
+        };
+        let mut samples = Vec::new();
+        if let Some(frame_kind) = frame_kind {
+            let frame_len = self.frame_lens.get(frame_kind);
+            samples.reserve(frame_len / 2 * frame.channel_count());
+            for channel in frame.channels() {
+                samples.extend_from_slice(&channel[frame_len / 2..frame_len]);
+            }
+        }
+        DecoderState {
+            frame_kind: frame_kind,
+            silent: silent,
+            pos: self.pos,
+            channel_count: frame.channel_count(),
+            samples: samples.into_boxed_slice(),
+        }
+    }
+
+    // Only the right half of the restored frame is overlapped with the next one. The restored
+    // frame becomes the only one so there are no output samples until the next decode.
+    fn restore_state(&mut self, state: &DecoderState) -> Result<()> {
+        let half_len = match state.frame_kind {
+            Some(kind) if self.mdct[kind as usize].is_some() => self.frame_lens.get(kind) / 2,
+            Some(_) => return Err(Error::InvalidDecoderState),
+            None => 0,
+        };
+        if state.channel_count != self.frame.channel_count() ||
+                state.samples.len() != half_len * state.channel_count {
+            return Err(Error::InvalidDecoderState);
+        }
+        self.reset();
+        if half_len > 0 {
+            for (channel, samples) in self.frame.channels_mut().zip(state.samples.chunks(half_len)) {
+                channel[half_len..half_len * 2].copy_from_slice(samples);
+            }
+        }
+        self.frame_kind = state.frame_kind;
+        self.frame_silent = state.silent;
+        self.pos = state.pos;
+        Ok(())
+    }
+
+    fn window(&self) -> Option<&Window> {
+        if let (Some(prev_frame_kind), Some(frame_kind)) = (self.prev_frame_kind, self.frame_kind) {
+            Some(self.windows.get(prev_frame_kind, frame_kind))
+        } else {
+            None
+        }
+    }
+
+    fn swap_frames(&mut self) {
+        if self.frame_kind.is_some() {
+            mem::swap(&mut self.frame, &mut self.prev_frame);
+            self.prev_frame_kind = self.frame_kind;
+            self.prev_frame_silent = self.frame_silent;
+            self.frame_kind = None;
+        }
+    }
+
+    // Makes the `frame` containing the decoded spectrum current, the current frame becomes
+    // the previous one. Returns the buffer that's no longer used.
+    #[cfg(feature = "std")]
+    fn push_frame(&mut self, frame: Frame) -> Frame {
+        let cur = mem::replace(&mut self.frame, frame);
+        if self.frame_kind.is_some() {
+            self.prev_frame_kind = self.frame_kind;
+            self.prev_frame_silent = self.frame_silent;
+            self.frame_kind = None;
+            mem::replace(&mut self.prev_frame, cur)
+        } else {
+            cur
+        }
+    }
+}
+
+// Computes the inverse MDCT of the `buf` or zeroes it if the spectrum is known to be `silent`
+// (the inverse MDCT of zeros).
+fn inverse_mdct(mdct: &Mdct, buf: &mut [Sample], silent: bool) {
+    if silent {
+        for s in buf {
+            *s = 0.0;
+        }
+    } else {
+        mdct.inverse(buf);
+    }
+}
+
+// Magnitude spectrum of the last synthesized frame, enabled by
+// `DecoderBuilder::spectrum_analysis()`.
+struct SpectrumAnalysis {
+    buf: Frame,
+    // Number of values in each channel, zero if no frame was analyzed.
+    len: usize,
+    sample_rate: u32,
+}
+
+impl SpectrumAnalysis {
+    // Converts the first `len` coefficients of the `frame` channels to dB.
+    fn analyze(&mut self, frame: &Frame, len: usize) {
+        let min_magnitude = (10.0 as Sample).powf(SPECTRUM_MIN_DB / 20.0);
+        for (dst, src) in self.buf.channels_mut().zip(frame.channels()) {
+            for (d, &s) in dst[..len].iter_mut().zip(&src[..len]) {
+                let m = if s < 0.0 { -s } else { s };
+                *d = if m > min_magnitude { 20.0 * m.log10() } else { SPECTRUM_MIN_DB };
+            }
+        }
+        self.len = len;
+    }
+}
+
+// Number of frames the worker thread of `PipelinedDecoder` can decode ahead.
+#[cfg(feature = "std")]
+const PIPELINE_DEPTH: usize = 2;
+
+/// Vorbis decoder that decodes audio packets on two threads.
+///
+/// The packet data, floors and residues are decoded on a worker thread while the inverse MDCT
+/// and overlap-add of the previously decoded packet run on the caller thread. This improves
+/// throughput when the packets are queued ahead with `push()`, e.g. for offline transcoding.
+/// The samples are returned in the packet order.
+///
+/// Created by `Decoder::into_pipelined()`. Requires thread support, so it's not usable on
+/// `wasm32-unknown-unknown` where the rest of the decoder works, and needs the `std` feature.
+#[cfg(feature = "std")]
+pub struct PipelinedDecoder {
+    header: Header,
+    #[cfg(feature = "comments")]
+    comments: Option<Comments>,
+    warnings: Vec<Warning>,
+    info: Option<PacketInfo>,
+    stats: Option<PacketStats>,
+    packet_count: u64,
+    error_location: Option<ErrorLocation>,
+    synth: Synthesizer,
+    queued: usize,
+
+    packets: Option<mpsc::Sender<Vec<u8>>>,
+    free_frames: Option<mpsc::Sender<Frame>>,
+    spectra: mpsc::Receiver<SpectrumResult>,
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+// Decoded spectrum of a packet sent from the worker thread.
+#[cfg(feature = "std")]
+struct Spectrum {
+    frame: Frame,
+    info: PacketInfo,
+    silent: bool,
+    warnings: Vec<Warning>,
+    stats: Option<PacketStats>,
+}
+
+// Spectrum of the packet or the error with the bit position and stage where decoding stopped.
+#[cfg(feature = "std")]
+type SpectrumResult = ::std::result::Result<Spectrum, (Error, Option<u64>, Option<DecodeStage>)>;
+
+#[cfg(feature = "std")]
+impl PipelinedDecoder {
+    fn new(decoder: Decoder) -> Self {
+        #[cfg(feature = "comments")]
+        let comments = decoder.comments;
+        let Decoder { warnings, info, stats, packet_count, error_location, spectrum, synth, .. } = decoder;
+        let header = spectrum.header.clone();
+
+        let (packets_tx, packets_rx) = mpsc::channel();
+        let (free_frames_tx, free_frames_rx) = mpsc::channel();
+        let (spectra_tx, spectra_rx) = mpsc::channel();
+        for _ in 0..PIPELINE_DEPTH {
+            free_frames_tx.send(Frame::new(synth.frame.channel_count(), synth.frame.stride())).unwrap();
+        }
+        let worker = thread::spawn(move || run_worker(spectrum, packets_rx, free_frames_rx, spectra_tx));
+
+        PipelinedDecoder {
+            header: header,
+            #[cfg(feature = "comments")]
+            comments: comments,
+            warnings: warnings,
+            info: info,
+            stats: stats,
+            packet_count: packet_count,
+            error_location: error_location,
+            synth: synth,
+            queued: 0,
+
+            packets: Some(packets_tx),
+            free_frames: Some(free_frames_tx),
+            spectra: spectra_rx,
+            worker: Some(worker),
+        }
+    }
+
+    /// Queues the audio packet for decoding.
+    pub fn push(&mut self, packet: Vec<u8>) {
+        self.packets.as_ref().unwrap().send(packet).expect("Decoder worker thread terminated");
+        self.queued += 1;
+    }
+
+    /// Finishes decoding of the oldest queued packet, waiting for the worker thread if needed.
+    /// Returns `None` if there are no queued packets. The returned samples are the same as
+    /// `Decoder::decode()` would return for the packet.
+    pub fn next(&mut self) -> Option<Result<Samples>> {
+        if self.queued == 0 {
+            return None;
+        }
+        self.queued -= 1;
+        self.info = None;
+        let packet = self.packet_count;
+        self.packet_count += 1;
+        self.error_location = None;
+        let spectrum = match self.spectra.recv().expect("Decoder worker thread terminated") {
+            Ok(s) => s,
+            Err((e, bit_pos, stage)) => {
+                self.error_location = Some(ErrorLocation {
+                    packet: packet,
+                    sample_pos: self.synth.pos,
+                    bit_pos: bit_pos,
+                    stage: stage,
+                });
+                return Some(Err(e));
+            }
+        };
+        for w in spectrum.warnings {
+            if self.warnings.len() < MAX_WARNINGS {
+                self.warnings.push(w);
+            }
+        }
+        self.info = Some(spectrum.info);
+        self.stats = spectrum.stats;
+        let unused = self.synth.push_frame(spectrum.frame);
+        // The worker only terminates when the channels are closed.
+        let _ = self.free_frames.as_ref().unwrap().send(unused);
+        self.synth.synthesize(spectrum.info.frame_kind, spectrum.silent);
+        Some(Ok(self.synth.samples()))
+    }
+
+    /// Returns the number of packets queued with `push()` and not yet returned by `next()`.
+    pub fn queued(&self) -> usize {
+        self.queued
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    #[cfg(feature = "comments")]
+    pub fn comments(&self) -> Option<&Comments> {
+        self.comments.as_ref()
+    }
+
+    /// Returns the warnings reported since the decoder was built or the last call of this method.
+    /// See `Decoder::take_warnings()`.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        mem::replace(&mut self.warnings, Vec::with_capacity(MAX_WARNINGS))
+    }
+
+    /// Returns the number of bits each decoding stage consumed from the packet last returned
+    /// by `next()`. See `Decoder::packet_stats()`.
+    pub fn packet_stats(&self) -> Option<PacketStats> {
+        self.stats
+    }
+
+    /// Returns the mode and window flags of the packet last returned by `next()`. See
+    /// `Decoder::packet_info()`.
+    pub fn packet_info(&self) -> Option<PacketInfo> {
+        self.info
+    }
+
+    /// Returns the magnitude spectrum of the frame of the packet last returned by `next()`.
+    /// See `Decoder::spectrum()`.
+    pub fn spectrum(&self) -> Option<FrameSpectrum> {
+        self.synth.spectrum()
+    }
+
+    // Returns sample position - the number of sample this decoder produced so far.
+    pub fn pos(&self) -> u64 {
+        self.synth.pos
+    }
+
+    /// Returns the number of audio packets returned by `next()` including the ones the decoder
+    /// decoded before it was converted. See `Decoder::packet_count()`.
+    pub fn packet_count(&self) -> u64 {
+        self.packet_count
+    }
+
+    /// Returns the location of the error if decoding of the packet last returned by `next()`
+    /// failed. See `Decoder::error_location()`.
+    pub fn error_location(&self) -> Option<ErrorLocation> {
+        self.error_location
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for PipelinedDecoder {
+    fn drop(&mut self) {
+        // Closing the channels makes the worker terminate.
+        self.packets = None;
+        self.free_frames = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn run_worker(mut spectrum: SpectrumDecoder,
+        packets: mpsc::Receiver<Vec<u8>>,
+        free_frames: mpsc::Receiver<Frame>,
+        spectra: mpsc::Sender<SpectrumResult>) {
+    // The frame is kept if decoding failed.
+    let mut spare_frame = None;
+    for packet in packets.iter() {
+        let mut frame = match spare_frame.take().map(Ok).unwrap_or_else(|| free_frames.recv()) {
+            Ok(frame) => frame,
+            Err(_) => return,
+        };
+        let mut warnings = Vec::new();
+        let mut stats = None;
+        let mut reader = BitReader::new(Cursor::new(&packet[..]));
+        let r = match spectrum.decode(&mut reader, &mut frame, &mut warnings, &mut stats) {
+            Ok((info, silent)) => Ok(Spectrum {
+                frame: frame,
+                info: info,
+                silent: silent,
+                warnings: warnings,
+                stats: stats,
+            }),
+            Err(e) => {
+                spare_frame = Some(frame);
+                Err((e, reader.bit_pos(), spectrum.stage))
+            }
+        };
+        if spectra.send(r).is_err() {
+            return;
+        }
+    }
+}
+
+// Calls `f` with the previous and current frame buffers of each channel.
+#[cfg(not(feature = "rayon"))]
+fn for_each_channel<F>(prev_frame: &mut Frame, frame: &mut Frame, f: F)
+        where F: Fn(&mut [Sample], &mut [Sample]) + Sync {
+    for (prev, cur) in prev_frame.channels_mut().zip(frame.channels_mut()) {
+        f(prev, cur);
+    }
+}
+
+// Same as above but processes the channels in parallel.
+#[cfg(feature = "rayon")]
+fn for_each_channel<F>(prev_frame: &mut Frame, frame: &mut Frame, f: F)
+        where F: Fn(&mut [Sample], &mut [Sample]) + Sync {
+    use rayon::prelude::*;
+
+    let stride = frame.stride();
+    prev_frame.as_mut_slice().par_chunks_mut(stride).zip(frame.as_mut_slice().par_chunks_mut(stride))
+            .for_each(|(prev, cur)| f(prev, cur));
+}
+
+// Per-packet temporaries of `Decoder::decode()`, sized once when the decoder is built so they
+// can be reused for every packet.
+struct DecodeScratch {
+    floor_y_list: Box<[Vec<(u16, bool)>]>,
+    // Whether the channel's floor is unused in the current packet.
+    zero_channels: Box<[bool]>,
+    residue_buf: ResidueBuf,
+}
+
+impl DecodeScratch {
+    fn new(header: &Header, setup: &Setup, max_frame_len: usize) -> Self {
+        let channel_count = header.channel_count();
+        let max_floor_len = setup.floors.iter().map(|f| f.x_list().len()).max().unwrap();
+        let mut floor_y_list = Vec::with_capacity(channel_count);
+        for _ in 0..channel_count {
+            floor_y_list.push(Vec::with_capacity(max_floor_len));
+        }
+        DecodeScratch {
+            floor_y_list: floor_y_list.into_boxed_slice(),
+            zero_channels: vec![false; channel_count].into_boxed_slice(),
+            residue_buf: ResidueBuf::new(&setup.residues, &setup.codebooks, channel_count,
+                                         max_frame_len / 2),
+        }
+    }
+}
+
+/// Contains decoded sample data for all channels returned by the [Decoder::decode()] method.
+/// [Decoder::decode()]: struct.Decoder.html#method.decode
+pub struct Samples<'a> {
+    frame: &'a Frame,
+    range: WindowRange,
+    silent: bool,
+}
+
+impl<'a> Samples<'a> {
+    /// Returns the number of samples each channel has.
+    pub fn len(&self) -> usize {
+        self.range.len()
+    }
+
+    /// Returns `true` if the `len() == 0`.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the samples are known to be all zeros: the floors of all channels are
+    /// unused in both frames the samples are overlapped from. Encoders mark the floors unused
+    /// for the digital silence and the content below the masking threshold, so this detects
+    /// near-silence too. The inverse MDCT of such frames is skipped. Always `true` if
+    /// `is_empty()`.
+    pub fn is_silent(&self) -> bool {
+        self.silent
+    }
+
+    /// Returns iterator over the samples in all channels interleaved.
+    pub fn interleave(&self) -> InterleavedSamplesIter<'a> {
+        InterleavedSamplesIter {
+            frame: self.frame,
+            range: self.range,
+            pos: (0, self.range.start),
+        }
+    }
+
+    /// Writes the samples in all channels interleaved into the `buf` which must be at least
+    /// `len() * channel_count()` long. Returns the number of samples written.
+    pub fn interleave_into(&self, buf: &mut [Sample]) -> usize {
+        let channel_count = self.channel_count();
+        let len = self.len() * channel_count;
+        let buf = &mut buf[..len];
+        if channel_count == 1 {
+            buf.copy_from_slice(self.channel(0));
+        } else {
+            for (channel, samples) in self.channels().enumerate() {
+                for (dst, &src) in buf[channel..].iter_mut().step_by(channel_count).zip(samples) {
+                    *dst = src;
+                }
+            }
+        }
+        len
+    }
+
+    /// Returns the `range` of the samples of each channel, e.g. the one returned by
+    /// `GaplessTrim::trim()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of `0..len()`.
+    pub fn slice(&self, range: Range<usize>) -> Samples<'a> {
+        assert!(range.start <= range.end && range.end <= self.len(), "Range is out of bounds");
+        Samples {
+            frame: self.frame,
+            range: WindowRange {
+                start: self.range.start + range.start,
+                end: self.range.start + range.end,
+            },
+            silent: self.silent,
+        }
+    }
+
+    /// Returns the number of channels. This is the same as `Header::channel_count()`.
+    pub fn channel_count(&self) -> usize {
+        self.frame.channel_count()
+    }
+
+    /// Returns the channel layout. This is the same as `Header::channel_layout()`.
+    pub fn channel_layout(&self) -> ChannelLayout {
+        ChannelLayout::from_channel_count(self.channel_count())
+    }
+
+    /// Returns iterator over the samples for each channel in order.
+    pub fn channels(&self) -> ChannelIter<'a> {
+        ChannelIter {
+            frame_iter: self.frame.channels(),
+            range: self.range,
+        }
+    }
+
+    /// Returns samples slice for the specified zero-based channel index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of range, see `get_channel()` for the checked variant.
+    pub fn channel(&self, index: usize) -> &[Sample] {
+        &self.frame[index][self.range.start..self.range.end]
+    }
+
+    /// Returns samples slice for the specified zero-based channel index or `None` if the index
+    /// is out of range.
+    pub fn get_channel(&self, index: usize) -> Option<&[Sample]> {
+        if index < self.channel_count() {
+            Some(self.channel(index))
+        } else {
+            None
+        }
     }
 
-    // Resets this decoder's state as it would be after a newly initialized decoder instance.
-    pub fn reset(&mut self) {
-        self.prev_frame_kind = None;
-        self.frame_kind = None;
-        self.pos = 0;
+    /// Returns samples of the channel with the speaker position in the Vorbis channel order or
+    /// `None` if the layout has no such channel (always for the `Custom` layouts).
+    pub fn by_position(&self, position: SpeakerPosition) -> Option<&[Sample]> {
+        self.channel_layout().channel(position).map(|i| self.channel(i))
     }
 
-    pub fn header(&self) -> &Header {
-        &self.header
+    /// Returns samples of the front left channel or `None` if there's no such channel (mono and
+    /// `Custom` layouts).
+    pub fn left(&self) -> Option<&[Sample]> {
+        self.by_position(SpeakerPosition::FrontLeft)
     }
 
-    pub fn comments(&self) -> Option<&Comments> {
-        self.comments.as_ref()
+    /// Returns samples of the front right channel or `None` if there's no such channel (mono
+    /// and `Custom` layouts).
+    pub fn right(&self) -> Option<&[Sample]> {
+        self.by_position(SpeakerPosition::FrontRight)
     }
 
-    pub fn samples(&self) -> Samples {
-        self.window().map(|w| match w.overlap_target {
-            OverlapTarget::Left => Samples { frame: &self.prev_frame, range: w.left },
-            OverlapTarget::Right => Samples { frame: &self.frame, range: w.right },
-        }).unwrap_or_else(|| Samples { frame: &self.frame, range: WindowRange { start: 0, end: 0 } })
+    /// Returns samples of the front center channel, or the only channel of mono streams. Returns
+    /// `None` if there's no such channel (stereo, quadraphonic and `Custom` layouts).
+    pub fn center(&self) -> Option<&[Sample]> {
+        match self.channel_layout() {
+            ChannelLayout::Mono => Some(self.channel(0)),
+            layout => layout.channel(SpeakerPosition::FrontCenter).map(|i| self.channel(i)),
+        }
     }
+}
 
-    // Returns sample position - the number of sample this decoder produced so far.
-    pub fn pos(&self) -> u64 {
-        self.pos
+/// Returns samples slice of the channel like `Samples::channel()`.
+impl<'a> Index<usize> for Samples<'a> {
+    type Output = [Sample];
+
+    fn index(&self, index: usize) -> &[Sample] {
+        self.channel(index)
     }
+}
 
-    fn window(&self) -> Option<&Window> {
-        if let (Some(prev_frame_kind), Some(frame_kind)) = (self.prev_frame_kind, self.frame_kind) {
-            Some(self.windows.get(prev_frame_kind, frame_kind))
-        } else {
-            None
-        }
+/// Iterates over the samples of each channel in order like `Samples::channels()`.
+impl<'a> IntoIterator for Samples<'a> {
+    type Item = &'a [Sample];
+    type IntoIter = ChannelIter<'a>;
+
+    fn into_iter(self) -> ChannelIter<'a> {
+        self.channels()
     }
+}
 
-    fn swap_frames(&mut self) {
-        if self.frame_kind.is_some() {
-            mem::swap(&mut self.frame, &mut self.prev_frame);
-            self.prev_frame_kind = self.frame_kind;
-            self.frame_kind = None;
-        }
+impl<'a, 'b> IntoIterator for &'b Samples<'a> {
+    type Item = &'a [Sample];
+    type IntoIter = ChannelIter<'a>;
+
+    fn into_iter(self) -> ChannelIter<'a> {
+        self.channels()
     }
 }
 
-/// Contains decoded sample data for all channels returned by the [Decoder::decode()] method.
-/// [Decoder::decode()]: struct.Decoder.html#method.decode
-pub struct Samples<'a> {
-    frame: &'a [Box<[f32]>],
-    range: WindowRange,
+/// Magnitude spectrum of a decoded frame for each channel returned by the [Decoder::spectrum()]
+/// method.
+///
+/// The values are the magnitudes of the MDCT coefficients decoded from the packet in dB
+/// (`20 * log10(|X[k]|)`) clamped to -140 dB, so no extra transform of the decoded samples is
+/// done. The coefficients are those of the windowed frame: a full scale sine peaks at about
+/// 0 dB. Since MDCT is a real transform the values of a steady tone fluctuate from frame to
+/// frame with its phase, averaging over a few frames gives a smoother picture.
+///
+/// The frame is the one overlapped by the next decoded packet, its samples are output partly
+/// by this and partly by the next `decode()` call.
+/// [Decoder::spectrum()]: struct.Decoder.html#method.spectrum
+pub struct FrameSpectrum<'a> {
+    frame: &'a Frame,
+    len: usize,
+    sample_rate: u32,
 }
 
-impl<'a> Samples<'a> {
-    /// Returns the number of samples each channel has.
+impl<'a> FrameSpectrum<'a> {
+    /// Returns the number of frequency bins (values in each channel), this is half of
+    /// the frame length.
     pub fn len(&self) -> usize {
-        self.range.len()
+        self.len
     }
 
     /// Returns `true` if the `len() == 0`.
@@ -197,55 +1506,89 @@ impl<'a> Samples<'a> {
         self.len() == 0
     }
 
-    /// Returns iterator over the samples in all channels interleaved.
-    pub fn interleave(&self) -> InterleavedSamplesIter<'a> {
-        InterleavedSamplesIter {
-            frame: self.frame,
-            range: self.range,
-            pos: (0, self.range.start),
-        }
+    /// Returns the center frequency of the `bin` in Hz.
+    pub fn frequency(&self, bin: usize) -> f32 {
+        (bin as f32 + 0.5) * self.sample_rate as f32 / (2 * self.len) as f32
     }
 
     /// Returns the number of channels. This is the same as `Header::channel_count()`.
     pub fn channel_count(&self) -> usize {
-        self.frame.len()
+        self.frame.channel_count()
     }
 
-    /// Returns iterator over the samples for each channel in order.
+    /// Returns iterator over the spectrum of each channel in order.
     pub fn channels(&self) -> ChannelIter<'a> {
         ChannelIter {
-            frame_iter: self.frame.iter(),
-            range: self.range,
+            frame_iter: self.frame.channels(),
+            range: WindowRange { start: 0, end: self.len },
         }
     }
 
-    // Returns samples slice for the specified zero-based channel index.
-    pub fn channel(&self, index: usize) -> &[f32] {
-        &self.frame[index][self.range.start..self.range.end]
+    /// Returns the spectrum of the specified zero-based channel index.
+    pub fn channel(&self, index: usize) -> &[Sample] {
+        &self.frame[index][..self.len]
+    }
+
+    /// Writes the energy of the channel's spectrum in each band of the `scale` to the `out`.
+    /// The energy is the sum of the squared magnitudes of the bins with the center frequency in
+    /// the band, in dB and clamped to -140 dB like the spectrum values. The bands without bins
+    /// (the narrow low frequency bands of short frames or the ones above the Nyquist frequency)
+    /// are -140 dB. Doesn't allocate, so the bands can be computed for every frame in the
+    /// feature extraction pipelines instead of running their own FFT.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `out` length isn't `scale.band_count()`.
+    pub fn band_energies(&self, channel: usize, scale: BandScale, out: &mut [Sample]) {
+        assert_eq!(out.len(), scale.band_count(), "Band count mismatch");
+        for e in out.iter_mut() {
+            *e = 0.0;
+        }
+        for (bin, &db) in self.channel(channel).iter().enumerate() {
+            if let Some(band) = scale.band(self.frequency(bin), self.sample_rate) {
+                out[band] += (10.0 as Sample).powf(db / 10.0);
+            }
+        }
+        let min_energy = (10.0 as Sample).powf(SPECTRUM_MIN_DB / 10.0);
+        for e in out.iter_mut() {
+            *e = if *e > min_energy { 10.0 * e.log10() } else { SPECTRUM_MIN_DB };
+        }
     }
 }
 
 pub struct ChannelIter<'a> {
-    frame_iter: ::std::slice::Iter<'a, Box<[f32]>>,
+    frame_iter: ::std::slice::Chunks<'a, Sample>,
     range: WindowRange,
 }
 
 impl<'a> Iterator for ChannelIter<'a> {
-    type Item = &'a [f32];
+    type Item = &'a [Sample];
 
     fn next(&mut self) -> Option<Self::Item> {
         self.frame_iter.next().map(|c| &c[self.range.start..self.range.end])
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.frame_iter.size_hint()
+    }
 }
 
+impl<'a> DoubleEndedIterator for ChannelIter<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.frame_iter.next_back().map(|c| &c[self.range.start..self.range.end])
+    }
+}
+
+impl<'a> ExactSizeIterator for ChannelIter<'a> {}
+
 pub struct InterleavedSamplesIter<'a> {
-    frame: &'a [Box<[f32]>],
+    frame: &'a Frame,
     range: WindowRange,
     pos: (usize, usize),
 }
 
 impl<'a> Iterator for InterleavedSamplesIter<'a> {
-    type Item = f32;
+    type Item = Sample;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.pos.1 == self.range.end {
@@ -253,73 +1596,267 @@ impl<'a> Iterator for InterleavedSamplesIter<'a> {
         }
         let r = self.frame[self.pos.0][self.pos.1];
         self.pos.0 += 1;
-        if self.pos.0 >= self.frame.len() {
+        if self.pos.0 >= self.frame.channel_count() {
             self.pos.0 = 0;
             self.pos.1 += 1;
         }
         Some(r)
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.range.end - self.pos.1) * self.frame.channel_count() - self.pos.0;
+        (len, Some(len))
+    }
 }
 
+impl<'a> ExactSizeIterator for InterleavedSamplesIter<'a> {}
+
 pub struct DecoderBuilder {
     header: Option<Header>,
+    #[cfg(feature = "comments")]
     comments: Option<Comments>,
     setup: Option<Setup>,
+    decouple_channels: bool,
+    table_cache: Option<TableCache>,
+    #[cfg(feature = "comments")]
+    comment_limits: CommentLimits,
+    lenient: bool,
+    decode_limits: DecodeLimits,
+    spectrum_analysis: bool,
+    rate_divisor: usize,
+    floor_only: bool,
+    warnings: Vec<Warning>,
 }
 
 impl DecoderBuilder {
+    /// Sets whether the square polar channel decoupling should be performed (enabled by default).
+    /// When disabled the coupled channels are output as raw magnitude/angle pairs as they
+    /// were encoded, which is only useful for stream analysis.
+    pub fn decouple_channels(&mut self, value: bool) -> &mut Self {
+        self.decouple_channels = value;
+        self
+    }
+
+    /// Sets the cache to take the MDCT and window tables from. By default the decoder computes
+    /// its own tables. See `TableCache` for details.
+    pub fn table_cache(&mut self, cache: &TableCache) -> &mut Self {
+        self.table_cache = Some(cache.clone());
+        self
+    }
+
+    /// Sets the limits applied when reading the comment header. See `CommentLimits` for
+    /// the defaults.
+    #[cfg(feature = "comments")]
+    pub fn comment_limits(&mut self, limits: &CommentLimits) -> &mut Self {
+        self.comment_limits = *limits;
+        self
+    }
+
+    /// Sets whether the recoverable spec violations in the header packets (see `Warning`) should
+    /// be reported as warnings instead of errors (disabled by default).
+    pub fn lenient(&mut self, value: bool) -> &mut Self {
+        self.lenient = value;
+        self
+    }
+
+    /// Sets the caps on the decoding work per audio packet. See `DecodeLimits` for the
+    /// defaults.
+    pub fn decode_limits(&mut self, limits: &DecodeLimits) -> &mut Self {
+        self.decode_limits = *limits;
+        self
+    }
+
+    /// Sets whether the decoder should compute the magnitude spectrum of each decoded frame
+    /// returned by `Decoder::spectrum()` (disabled by default). The spectrum is taken from
+    /// the decoded MDCT coefficients, so it's cheap compared to running a separate transform.
+    /// See `FrameSpectrum::band_energies()` for the Bark and mel band energies.
+    pub fn spectrum_analysis(&mut self, value: bool) -> &mut Self {
+        self.spectrum_analysis = value;
+        self
+    }
+
+    /// Sets the divisor of the output sample rate: 1 (the default) for full-rate decoding, 2 or
+    /// 4 for decoding at half or quarter of the stream's sample rate. The reduced rate output
+    /// is synthesized from the low part of the spectrum with a shorter inverse MDCT, which takes
+    /// roughly 2 (or 4) times less CPU time for the synthesis and is enough for previews or
+    /// very weak devices. The frequencies above the reduced Nyquist frequency are dropped and
+    /// the quality is lower than resampling the full-rate output.
+    ///
+    /// The samples and sample positions (`Decoder::pos()`, `Decoder::latency_samples()`) are at
+    /// the output rate, see `Decoder::sample_rate()`. The packets still have to be read
+    /// entirely, so the spectrum decoding takes the same time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `value` isn't 1, 2 or 4.
+    pub fn rate_divisor(&mut self, value: usize) -> &mut Self {
+        assert!(value == 1 || value == 2 || value == 4, "Rate divisor must be 1, 2 or 4");
+        self.rate_divisor = value;
+        self
+    }
+
+    /// Sets whether only the floors of the audio packets should be decoded (disabled by
+    /// default). The residues, which take most of the packet and of the decoding time, are
+    /// skipped and the floor curves (the spectral envelopes of the channels) are rendered as
+    /// shaped noise. The output doesn't resemble the audio closely, but the energy of each
+    /// frame and its distribution over the spectrum are roughly preserved, which is enough for
+    /// the waveform and loudness overviews. Combined with `rate_divisor()` the decoding is even
+    /// cheaper.
+    ///
+    /// The coupled channels aren't decoupled and `PacketStats::residue_bits()` is zero for the
+    /// packets decoded this way.
+    pub fn floor_only(&mut self, value: bool) -> &mut Self {
+        self.floor_only = value;
+        self
+    }
+
     pub fn read_ident_packet<R: BitRead>(&mut self, reader: &mut R) -> Result<()> {
-        self.header = Some(try!(PacketKind::Ident.read(reader, |r| Header::read(r))));
+        let warnings = if self.lenient { Some(&mut self.warnings) } else { None };
+        self.header = Some(try!(PacketKind::Ident.read(reader, |r| match warnings {
+            Some(warnings) => Header::read_lenient(r, warnings),
+            None => Header::read(r),
+        })));
         Ok(())
     }
 
+    #[cfg(feature = "comments")]
     pub fn read_comment_packet<R: BitRead>(&mut self, reader: &mut R) -> Result<()> {
-        self.comments = Some(try!(PacketKind::Comment.read(reader, |r| Comments::read(r))));
+        let limits = self.comment_limits;
+        let warnings = if self.lenient { Some(&mut self.warnings) } else { None };
+        self.comments = Some(try!(PacketKind::Comment.read(reader, |r| match warnings {
+            Some(warnings) => Comments::read_lenient(r, &limits, warnings),
+            None => Comments::read_with_limits(r, &limits),
+        })));
         Ok(())
     }
 
+    /// Without the `comments` feature only the packet kind and the magic value of the comment
+    /// header are checked, the comments are skipped.
+    #[cfg(not(feature = "comments"))]
+    pub fn read_comment_packet<R: BitRead>(&mut self, reader: &mut R) -> Result<()> {
+        PacketKind::Comment.read(reader, |_| Ok(()))
+    }
+
     pub fn read_setup_packet<R: BitRead>(&mut self, reader: &mut R) -> Result<()> {
         let header = self.header.as_ref()
                 .expect("You need to call read_ident_packet() before read_setup_packet()");
-        self.setup = Some(try!(PacketKind::Setup.read(reader, |r| Setup::read(r, header))));
+        let warnings = if self.lenient { Some(&mut self.warnings) } else { None };
+        self.setup = Some(try!(PacketKind::Setup.read(reader, |r| Setup::read(r, header, warnings))));
+        Ok(())
+    }
+
+    /// Returns the parser to read the setup header packet in parts as it arrives instead of
+    /// `read_setup_packet()`. Pass it to `finish_setup()` once the whole packet is pushed.
+    pub fn setup_parser(&self) -> SetupParser {
+        let header = self.header.as_ref()
+                .expect("You need to call read_ident_packet() before setup_parser()");
+        SetupParser::new(header)
+    }
+
+    /// Finishes reading the setup header packet pushed to the `parser`. Same as
+    /// `read_setup_packet()` otherwise.
+    pub fn finish_setup(&mut self, parser: SetupParser) -> Result<()> {
+        let warnings = if self.lenient { Some(&mut self.warnings) } else { None };
+        self.setup = Some(try!(parser.finish_with(warnings)));
         Ok(())
     }
 
-    pub fn build(mut self) -> Decoder {
+    /// Returns the warnings reported while reading the header packets.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn build(self) -> Decoder {
+        self.build_with_frames(&mut Vec::new())
+    }
+
+    // Builds the decoder taking the frame buffers from `frames` if there are ones of the right
+    // size.
+    fn build_with_frames(mut self, frames: &mut Vec<(Frame, Frame)>) -> Decoder {
         assert!(self.setup.is_some(),
             "You need to call read_ident_packet() and read_setup_packet() first");
         let header = self.header.take().unwrap();
         let setup = self.setup.take().unwrap();
 
-        let max_floor_len = setup.floors.iter().max_by_key(|f| f.x_list.len()).unwrap().x_list.len();
+        let table_cache = self.table_cache.take().unwrap_or_else(TableCache::new);
 
-        let windows = Windows::new(header.frame_lens());
+        // Only build the tables for the frame kinds the stream's modes actually use.
+        let frame_lens = header.frame_lens();
+        let synth_frame_lens = FrameLens::new(frame_lens.short() / self.rate_divisor,
+            frame_lens.long() / self.rate_divisor);
+        let used_kinds = [setup.uses_frame_kind(FrameKind::Short),
+                          setup.uses_frame_kind(FrameKind::Long)];
+        let mdct_for = |kind: FrameKind| if used_kinds[kind as usize] {
+            Some(table_cache.mdct(synth_frame_lens.get(kind)))
+        } else {
+            None
+        };
+        let mdct = [mdct_for(FrameKind::Short), mdct_for(FrameKind::Long)];
 
-        let mdct = [Mdct::new(header.frame_lens().short()),
-                    Mdct::new(header.frame_lens().long())];
+        let windows = Windows::new(synth_frame_lens, used_kinds, &table_cache);
 
-        let mut floor_y_list = Vec::with_capacity(header.channel_count());
-        let mut prev_frame = Vec::with_capacity(header.channel_count());
-        let mut frame = Vec::with_capacity(header.channel_count());
-        for _ in 0..header.channel_count() {
-            floor_y_list.push(Vec::with_capacity(max_floor_len));
-            prev_frame.push(vec![0_f32; header.frame_lens().long()].into_boxed_slice());
-            frame.push(vec![0_f32; header.frame_lens().long()].into_boxed_slice());
-        }
+        let max_frame_len = if used_kinds[FrameKind::Long as usize] {
+            frame_lens.long()
+        } else {
+            frame_lens.short()
+        };
+
+        let channel_count = header.channel_count();
+        let (prev_frame, frame) = match frames.iter().position(|f|
+                f.0.channel_count() == channel_count && f.0.stride() == max_frame_len) {
+            Some(i) => frames.swap_remove(i),
+            None => (Frame::new(channel_count, max_frame_len), Frame::new(channel_count, max_frame_len)),
+        };
+        let scratch = DecodeScratch::new(&header, &setup, max_frame_len);
+        let analysis = if self.spectrum_analysis {
+            Some(SpectrumAnalysis {
+                buf: Frame::new(channel_count, max_frame_len / 2),
+                len: 0,
+                sample_rate: header.sample_rate() / self.rate_divisor as u32,
+            })
+        } else {
+            None
+        };
+
+        let mut warnings = self.warnings;
+        let reserve = MAX_WARNINGS.saturating_sub(warnings.len());
+        warnings.reserve(reserve);
 
         Decoder {
-            header: header,
+            #[cfg(feature = "comments")]
             comments: self.comments,
-            setup: setup,
-            windows: windows,
-            mdct: mdct,
-
-            floor_y_list: floor_y_list.into_boxed_slice(),
-            prev_frame: prev_frame.into_boxed_slice(),
-            prev_frame_kind: None,
-            frame: frame.into_boxed_slice(),
-            frame_kind: None,
-            pos: 0,
+            warnings: warnings,
+            info: None,
+            stats: None,
+            packet_count: 0,
+            error_location: None,
+            spectrum: SpectrumDecoder {
+                header: header,
+                setup: setup,
+                decouple_channels: self.decouple_channels,
+                limits: self.decode_limits,
+                lenient: self.lenient,
+                floor_only: self.floor_only,
+                scratch: scratch,
+                recovered: false,
+                stage: None,
+            },
+            synth: Synthesizer {
+                frame_lens: synth_frame_lens,
+                rate_divisor: self.rate_divisor,
+                windows: windows,
+                mdct: mdct,
+
+                prev_frame: prev_frame,
+                prev_frame_kind: None,
+                prev_frame_silent: false,
+                frame: frame,
+                frame_kind: None,
+                frame_silent: false,
+                pos: 0,
+
+                analysis: analysis,
+            },
         }
     }
 
@@ -327,12 +1864,155 @@ impl DecoderBuilder {
         self.header.as_ref()
     }
 
+    #[cfg(feature = "comments")]
     pub fn comments(&self) -> Option<&Comments> {
         self.comments.as_ref()
     }
+
+    pub fn setup(&self) -> Option<&Setup> {
+        self.setup.as_ref()
+    }
+}
+
+/// Summarizes which header packets were read and the options set. The comments are shown by
+/// their count.
+impl fmt::Debug for DecoderBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut d = f.debug_struct("DecoderBuilder");
+        d.field("header", &self.header);
+        #[cfg(feature = "comments")]
+        d.field("comments", &self.comments.as_ref().map(|c| c.len()));
+        d.field("setup", &self.setup)
+            .field("decouple_channels", &self.decouple_channels)
+            .field("lenient", &self.lenient)
+            .field("decode_limits", &self.decode_limits)
+            .field("floor_only", &self.floor_only)
+            .field("rate_divisor", &self.rate_divisor)
+            .field("spectrum_analysis", &self.spectrum_analysis)
+            .field("table_cache", &self.table_cache.is_some())
+            .field("warnings", &self.warnings)
+            .finish()
+    }
+}
+
+/// Caps on the decoding work per audio packet for the services decoding untrusted streams. The
+/// work of the real-world packets is bounded by the frame length and channel count, but a crafted
+/// stream can make every packet as expensive as the worst case allows. The packets exceeding a
+/// limit fail with `Error::DecodeLimitExceeded`. Nothing is limited by default.
+#[derive(Clone, Copy, Debug)]
+pub struct DecodeLimits {
+    max_residue_passes: usize,
+    max_vq_reads: usize,
+    max_floor_segments: usize,
+}
+
+impl DecodeLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of residue decode passes (8, i.e. all, by default). In lenient
+    /// mode (see `DecoderBuilder::lenient()`) the further passes are skipped reporting
+    /// `Warning::ResiduePassesSkipped`, the packets are decoded at lower quality then.
+    pub fn max_residue_passes(&mut self, value: usize) -> &mut Self {
+        self.max_residue_passes = value;
+        self
+    }
+
+    /// Sets the maximum number of VQ vectors read in the residue decoding of a packet
+    /// (unlimited by default). The libvorbis encoder reads at most a few per sample and
+    /// channel.
+    pub fn max_vq_reads(&mut self, value: usize) -> &mut Self {
+        self.max_vq_reads = value;
+        self
+    }
+
+    /// Sets the maximum number of floor segments, the Y values of the floor curves of all
+    /// channels, decoded from a packet (unlimited by default). A floor curve has at most 65
+    /// Y values.
+    pub fn max_floor_segments(&mut self, value: usize) -> &mut Self {
+        self.max_floor_segments = value;
+        self
+    }
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_residue_passes: 8,
+            max_vq_reads: usize::max_value(),
+            max_floor_segments: usize::max_value(),
+        }
+    }
 }
 
-struct Setup {
+// Maximum number of frame buffer pairs `DecoderPool` keeps.
+const MAX_POOLED_FRAMES: usize = 8;
+
+/// Pool of reusable decoder resources for applications that open many streams, like jukeboxes.
+///
+/// The decoders built by the pool share the MDCT tables and window slopes (see `TableCache`).
+/// The frame buffers of the decoders returned with `recycle()` are reused for the new streams
+/// with the same channel count and block sizes.
+pub struct DecoderPool {
+    table_cache: TableCache,
+    frames: Vec<(Frame, Frame)>,
+}
+
+impl DecoderPool {
+    pub fn new() -> Self {
+        DecoderPool {
+            table_cache: TableCache::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn table_cache(&self) -> &TableCache {
+        &self.table_cache
+    }
+
+    /// Returns a new builder that uses the table cache of this pool.
+    pub fn builder(&self) -> DecoderBuilder {
+        let mut builder = Decoder::builder();
+        builder.table_cache(&self.table_cache);
+        builder
+    }
+
+    /// Builds the decoder reusing the recycled frame buffers if there are suitable ones.
+    /// See `DecoderBuilder::build()`.
+    pub fn build(&mut self, builder: DecoderBuilder) -> Decoder {
+        builder.build_with_frames(&mut self.frames)
+    }
+
+    /// Takes the frame buffers of the `decoder` for reuse. The oldest buffers are dropped when
+    /// the pool is full.
+    pub fn recycle(&mut self, decoder: Decoder) {
+        if self.frames.len() == MAX_POOLED_FRAMES {
+            self.frames.remove(0);
+        }
+        let synth = decoder.synth;
+        self.frames.push((synth.prev_frame, synth.frame));
+    }
+
+    /// Returns the number of frame buffer pairs available for reuse.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+impl Default for DecoderPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decoder configuration read from the setup header. Provides read-only access to the parsed
+/// setup components for stream analysis.
+pub struct Setup {
     codebooks: Box<[Codebook]>,
     floors: Box<[Floor]>,
     residues: Box<[Residue]>,
@@ -341,9 +2021,88 @@ struct Setup {
 }
 
 impl Setup {
-    fn read<R: BitRead>(reader: &mut R, header: &Header) -> Result<Self> {
+    pub fn codebooks(&self) -> &[Codebook] {
+        &self.codebooks
+    }
+
+    pub fn floors(&self) -> &[Floor] {
+        &self.floors
+    }
+
+    pub fn residues(&self) -> &[Residue] {
+        &self.residues
+    }
+
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
+    pub fn modes(&self) -> &[Mode] {
+        &self.modes
+    }
+
+    fn uses_frame_kind(&self, kind: FrameKind) -> bool {
+        self.modes.iter().any(|m| m.frame_kind == kind)
+    }
+
+    /// Reads the frame kind from the beginning of the audio packet. The frame length is
+    /// `Header::frame_lens().get(kind)`.
+    pub fn packet_frame_kind<R: BitRead>(&self, reader: &mut R) -> Result<FrameKind> {
+        self.read_mode(reader).map(|m| self.modes[m].frame_kind)
+    }
+
+    /// Reads the packet kind, mode number and window flags from the beginning of the audio
+    /// packet without decoding the rest of it.
+    pub fn packet_info<R: BitRead>(&self, reader: &mut R) -> Result<PacketInfo> {
+        let mode = try!(self.read_mode(reader));
+        let frame_kind = self.modes[mode].frame_kind;
+        let window_flags = if frame_kind == FrameKind::Long {
+            trace_field!(reader, "audio.prev_window_flag");
+            let prev_long = try!(reader.read_bool());
+            trace_field!(reader, "audio.next_window_flag");
+            let next_long = try!(reader.read_bool());
+            Some((prev_long, next_long))
+        } else {
+            None
+        };
+        Ok(PacketInfo {
+            mode: mode,
+            frame_kind: frame_kind,
+            window_flags: window_flags,
+        })
+    }
+
+    // Reads the packet kind and the mode number from the beginning of the audio packet. Returns
+    // the mode index.
+    fn read_mode<R: BitRead>(&self, reader: &mut R) -> Result<usize> {
+        trace_field!(reader, "audio.packet_kind");
+        let packet_kind = try!(reader.read_u8_bits(1));
+        if packet_kind != PacketKind::Audio as u8 {
+            return Err(Error::WrongPacketKind("Expected audio packet"));
+        }
+        let mode_count = self.modes.len();
+        trace_field!(reader, "audio.mode");
+        let mode_idx = try!(reader.read_u8_bits((mode_count as u8).ilog() as usize - 1)) as usize;
+        if mode_idx >= mode_count {
+            return Err(Error::InvalidModeNumber);
+        }
+        Ok(mode_idx)
+    }
+
+    /// Reads the setup header packet of the stream with the `header`.
+    pub fn read_packet<R: BitRead>(reader: &mut R, header: &Header) -> Result<Self> {
+        PacketKind::Setup.read(reader, |r| Self::read(r, header, None))
+    }
+
+    fn read<R: BitRead>(reader: &mut R, header: &Header, warnings: Option<&mut Vec<Warning>>)
+            -> Result<Self> {
         let codebooks = try!(Self::read_codebooks(reader));
+        Self::read_rest(reader, header, codebooks, warnings)
+    }
 
+    // Reads the setup header after the codebooks.
+    fn read_rest<R: BitRead>(reader: &mut R, header: &Header, codebooks: Vec<Codebook>,
+            warnings: Option<&mut Vec<Warning>>) -> Result<Self> {
         try!(Self::skip_time_domain_trans(reader));
 
         let floors = try!(Self::read_floors(reader, codebooks.len()));
@@ -353,7 +2112,7 @@ impl Setup {
         let mappings = try!(Self::read_mappings(reader, header.channel_count(),
                                                 floors.len(), residues.len()));
 
-        let modes = try!(Self::read_modes(reader, mappings.len()));
+        let modes = try!(Self::read_modes(reader, mappings.len(), warnings));
 
         Ok(Setup {
             codebooks: codebooks.into_boxed_slice(),
@@ -364,43 +2123,94 @@ impl Setup {
         })
     }
 
+    fn read_codebook_count<R: BitRead>(reader: &mut R) -> Result<usize> {
+        trace_field!(reader, "setup.codebook_count");
+        Ok(try!(reader.read_u8()) as usize + 1)
+    }
+
+    #[cfg(not(feature = "rayon"))]
     fn read_codebooks<R: BitRead>(reader: &mut R) -> Result<Vec<Codebook>> {
-        let count = try!(reader.read_u8()) as usize + 1;
+        let count = try!(Self::read_codebook_count(reader));
         let mut r = Vec::with_capacity(count);
-        for _ in 0..count {
-            let mut codebook = try!(Codebook::read(reader));
-            codebook.idx = r.len();
+        for idx in 0..count {
+            let codebook = try!(Codebook::read(reader, idx)
+                .map_err(|e| e.located(reader.bit_pos(), Some(DecodeStage::Codebook(idx)))));
             r.push(codebook);
         }
         Ok(r)
     }
 
+    // Same as above but builds the Huffman decoders and lookup tables in parallel after reading
+    // all the codebooks. The errors are the same as of the sequential version: the first failed
+    // codebook wins whether it failed to read or to build.
+    #[cfg(feature = "rayon")]
+    fn read_codebooks<R: BitRead>(reader: &mut R) -> Result<Vec<Codebook>> {
+        use rayon::prelude::*;
+
+        let count = try!(Self::read_codebook_count(reader));
+        // The descriptions with the bit position of their end for the error location.
+        let mut descs = Vec::with_capacity(count);
+        let mut read_err = None;
+        for idx in 0..count {
+            match CodebookDesc::read(reader, idx) {
+                Ok(desc) => descs.push((desc, reader.bit_pos())),
+                Err(e) => {
+                    read_err = Some(e.located(reader.bit_pos(), Some(DecodeStage::Codebook(idx))));
+                    break;
+                }
+            }
+        }
+        let built: Vec<_> = descs.into_par_iter()
+            .map(|(desc, bit_pos)| {
+                let idx = desc.index();
+                desc.build().map_err(|e| e.located(bit_pos, Some(DecodeStage::Codebook(idx))))
+            })
+            .collect();
+        let mut r = Vec::with_capacity(count);
+        for codebook in built {
+            r.push(try!(codebook));
+        }
+        match read_err {
+            Some(e) => Err(e),
+            None => Ok(r),
+        }
+    }
+
     fn skip_time_domain_trans<R: BitRead>(reader: &mut R) -> Result<()> {
+        trace_field!(reader, "setup.time_domain_count");
         let len = try!(reader.read_u8_bits(6)) as usize + 1;
         for _ in 0..len {
+            trace_field!(reader, "setup.time_domain_transform");
             let value = try!(reader.read_u32_bits(16));
             if value != 0 {
-                return Err(Error::Undecodable("Non-zero value in time domain transforms"));
+                return Err(Error::NonZeroTimeDomainTransform);
             }
         }
         Ok(())
     }
 
     fn read_floors<R: BitRead>(reader: &mut R, codebook_count: usize) -> Result<Vec<Floor>> {
+        trace_field!(reader, "setup.floor_count");
         let count = try!(reader.read_u8_bits(6)) as usize + 1;
         let mut floors = Vec::with_capacity(count);
-        for _ in 0..count {
-            let floor = try!(Floor::read(reader, codebook_count));
+        for idx in 0..count {
+            let floor = try!(Floor::read(reader, codebook_count)
+                .map_err(|e| e.located(reader.bit_pos(), Some(DecodeStage::Floor(idx)))));
             floors.push(floor);
         }
         Ok(floors)
     }
 
     fn read_residues<R: BitRead>(reader: &mut R, codebook_count: usize) -> Result<Vec<Residue>> {
+        trace_field!(reader, "setup.residue_count");
         let count = try!(reader.read_u8_bits(6)) as usize + 1;
         let mut residues = Vec::with_capacity(count);
-        for _ in 0..count {
-            let residue = try!(Residue::read(reader, codebook_count));
+        for idx in 0..count {
+            let residue = try!(Residue::read(reader, codebook_count)
+                .map_err(|e| e.located(reader.bit_pos(), Some(DecodeStage::Residue {
+                    residue: idx,
+                    pass: None,
+                }))));
             residues.push(residue);
         }
         Ok(residues)
@@ -408,32 +2218,170 @@ impl Setup {
 
     fn read_mappings<R: BitRead>(reader: &mut R, channel_count: usize,
             floor_count: usize, residue_count: usize) -> Result<Vec<Mapping>> {
+        trace_field!(reader, "setup.mapping_count");
         let count = try!(reader.read_u8_bits(6)) as usize + 1;
         let mut mappings = Vec::with_capacity(count);
-        for _ in 0..count {
-            let mapping = try!(Mapping::read(reader, channel_count, floor_count, residue_count));
+        for idx in 0..count {
+            let mapping = try!(Mapping::read(reader, channel_count, floor_count, residue_count)
+                .map_err(|e| e.located(reader.bit_pos(), Some(DecodeStage::Mapping(idx)))));
             mappings.push(mapping);
         }
         Ok(mappings)
     }
 
-    fn read_modes<R: BitRead>(reader: &mut R, mapping_count: usize) -> Result<Vec<Mode>> {
+    fn read_modes<R: BitRead>(reader: &mut R, mapping_count: usize,
+            warnings: Option<&mut Vec<Warning>>) -> Result<Vec<Mode>> {
+        trace_field!(reader, "setup.mode_count");
         let count = try!(reader.read_u8_bits(6)) as usize + 1;
         let mut modes = Vec::with_capacity(count);
-        for _ in 0..count {
-            let mode = try!(Mode::read(reader, mapping_count));
+        for idx in 0..count {
+            let mode = try!(Mode::read(reader, mapping_count)
+                .map_err(|e| e.located(reader.bit_pos(), Some(DecodeStage::Mode(idx)))));
             modes.push(mode);
         }
-        if !try!(reader.read_bool()) {
-            return Err(Error::Undecodable("Invalid framing bit"));
-        }
+        try!(PacketKind::Setup.read_framing_bit(reader, warnings));
         Ok(modes)
     }
 }
 
+/// Shows the number of each kind of setup components and the frame kinds of the modes.
+impl fmt::Debug for Setup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let modes: Vec<FrameKind> = self.modes.iter().map(|m| m.frame_kind).collect();
+        f.debug_struct("Setup")
+            .field("codebooks", &self.codebooks.len())
+            .field("floors", &self.floors.len())
+            .field("residues", &self.residues.len())
+            .field("mappings", &self.mappings.len())
+            .field("modes", &modes)
+            .finish()
+    }
+}
+
+/// Parser of the setup header packet that receives the packet in parts as they arrive, e.g. from
+/// the network. The codebooks, which make up most of the packet, are read and built as soon as
+/// their bytes are available, so little work is left when the last part arrives.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::io::Cursor;
+/// use vorbis::{BitReader, Decoder};
+///
+/// # fn f(ident_packet: &[u8], comment_packet: &[u8], setup_packet_parts: Vec<Vec<u8>>) {
+/// let mut builder = Decoder::builder();
+/// builder.read_ident_packet(&mut BitReader::new(Cursor::new(ident_packet))).unwrap();
+/// builder.read_comment_packet(&mut BitReader::new(Cursor::new(comment_packet))).unwrap();
+/// let mut parser = builder.setup_parser();
+/// for part in &setup_packet_parts {
+///     parser.push(part).expect("Invalid setup header");
+/// }
+/// builder.finish_setup(parser).expect("Invalid setup header");
+/// let decoder = builder.build();
+/// # }
+/// ```
+pub struct SetupParser {
+    header: Header,
+    buf: Vec<u8>,
+    // Bit position in `buf` of the next codebook, zero until the codebook count is read.
+    pos: u64,
+    // Scan of the next codebook, it's read once all of it has arrived.
+    span: CodebookSpan,
+    codebook_count: usize,
+    codebooks: Vec<Codebook>,
+}
+
+impl SetupParser {
+    /// Creates the parser of the setup header packet of the stream with the `header`.
+    pub fn new(header: &Header) -> Self {
+        SetupParser {
+            header: header.clone(),
+            buf: Vec::new(),
+            pos: 0,
+            span: CodebookSpan::new(0),
+            codebook_count: 0,
+            codebooks: Vec::new(),
+        }
+    }
+
+    /// Appends the next `bytes` of the packet and reads the codebooks that are complete. Fails
+    /// if the packet received so far is invalid.
+    pub fn push(&mut self, bytes: &[u8]) -> Result<()> {
+        self.buf.extend_from_slice(bytes);
+        if self.pos == 0 {
+            if self.buf.len() < 1 + MAGIC_LEN + 1 {
+                return Ok(());
+            }
+            let mut reader = BitReader::new(&self.buf[..]);
+            self.codebook_count = try!(PacketKind::Setup.read(&mut reader, Setup::read_codebook_count));
+            self.pos = reader.bit_pos().unwrap();
+            self.span = CodebookSpan::new(self.pos);
+        }
+        while self.codebooks.len() < self.codebook_count && self.span.advance(&self.buf) {
+            let idx = self.codebooks.len();
+            // The reader starts at the byte of the codebook start.
+            let start = self.pos / 8 * 8;
+            let mut reader = BitReader::new(&self.buf[start as usize / 8..]);
+            reader.read_u32_bits((self.pos - start) as usize).unwrap();
+            match Codebook::read(&mut reader, idx) {
+                Ok(codebook) => {
+                    self.codebooks.push(codebook);
+                    self.pos = start + reader.bit_pos().unwrap();
+                    self.span = CodebookSpan::new(self.pos);
+                }
+                // Incomplete, retried when more bytes arrive.
+                Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.located(reader.bit_pos().map(|p| start + p),
+                    Some(DecodeStage::Codebook(idx)))),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of codebooks read so far.
+    pub fn codebooks_read(&self) -> usize {
+        self.codebooks.len()
+    }
+
+    /// Reads the rest of the packet once the whole packet has been pushed.
+    pub fn finish(self) -> Result<Setup> {
+        self.finish_with(None)
+    }
+
+    fn finish_with(self, warnings: Option<&mut Vec<Warning>>) -> Result<Setup> {
+        if self.pos == 0 {
+            let mut reader = BitReader::new(&self.buf[..]);
+            return PacketKind::Setup.read(&mut reader, |r| Setup::read(r, &self.header, warnings));
+        }
+        let SetupParser { header, buf, pos, codebook_count, mut codebooks, .. } = self;
+        let mut reader = reader_at(&buf, pos);
+        let r = (|| {
+            for idx in codebooks.len()..codebook_count {
+                let codebook = try!(Codebook::read(&mut reader, idx)
+                    .map_err(|e| e.located(reader.bit_pos(), Some(DecodeStage::Codebook(idx)))));
+                codebooks.push(codebook);
+            }
+            Setup::read_rest(&mut reader, &header, codebooks, warnings)
+        })();
+        r.map_err(|e| e.located(reader.bit_pos(), None))
+    }
+}
+
+// Returns the reader of the `buf` positioned at the bit `pos`.
+fn reader_at(buf: &[u8], pos: u64) -> BitReader<&[u8]> {
+    let mut reader = BitReader::new(buf);
+    let mut left = pos as usize;
+    while left > 0 {
+        let len = cmp::min(left, 32);
+        reader.read_u32_bits(len).unwrap();
+        left -= len;
+    }
+    reader
+}
+
 enum_from_primitive! {
-#[derive(Clone, Copy, Debug, PartialEq)]
-enum PacketKind {
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PacketKind {
     Audio   = 0,
     Ident   = 1,
     Comment = 3,
@@ -443,18 +2391,149 @@ enum PacketKind {
 impl PacketKind {
     fn read<BR: BitRead, R, F>(self, reader: &mut BR, f: F) -> Result<R>
             where F: FnOnce(&mut BR) -> Result<R> {
+        trace_field!(reader, "packet_kind");
         let packet_kind = try!(PacketKind::from_u8(try!(reader.read_u8()))
-                    .ok_or(Error::Undecodable("Invalid packet kind")));
+                    .ok_or(Error::InvalidPacketKind));
         if packet_kind != self {
             return Err(Error::WrongPacketKind("Unexpected packet kind"));
         }
 
         let mut magic = [0; MAGIC_LEN];
-        try!(reader.read_exact(&mut magic));
+        trace_field!(reader, "magic");
+        try!(reader.read_bytes(&mut magic));
         if magic != MAGIC {
-            return Err(Error::Undecodable("Invalid packet magic value"));
+            return Err(Error::InvalidMagic);
+        }
+
+        f(reader).map_err(|e| e.located(reader.bit_pos(), None))
+    }
+
+    // Reads the framing bit at the end of the header packet. In lenient mode (`warnings` is
+    // `Some`) unset or missing framing bit is reported as a warning.
+    pub fn read_framing_bit<R: BitRead>(self, reader: &mut R, warnings: Option<&mut Vec<Warning>>)
+            -> Result<()> {
+        trace_field!(reader, "framing_bit");
+        let r = reader.read_bool();
+        match (r, warnings) {
+            (Ok(true), _) => Ok(()),
+            (Ok(false), None) => Err(Error::InvalidFramingBit),
+            (Ok(false), Some(warnings)) => {
+                warnings.push(Warning::InvalidFramingBit(self));
+                Ok(())
+            }
+            (Err(BitstreamError::UnexpectedEof), Some(warnings)) => {
+                warnings.push(Warning::InvalidFramingBit(self));
+                Ok(())
+            }
+            (Err(e), _) => Err(e.into()),
+        }
+    }
+
+    // Writes the packet kind and the magic value.
+    #[cfg(feature = "std")]
+    pub fn write<W: BitWrite>(self, writer: &mut W) -> Result<()> {
+        try!(writer.write_u8(self as u8));
+        try!(writer.write_all(MAGIC));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use error::{DecodeStage, Error, ErrorKind, Warning};
+
+    use super::*;
+
+    const IDENT: [u8; 30] = [
+        1, b'v', b'o', b'r', b'b', b'i', b's',
+        0, 0, 0, 0, 2, 0x44, 0xAC, 0, 0,
+        0, 0, 0, 0, 0x00, 0xF4, 0x01, 0, 0, 0, 0, 0,
+        0xB8, 1,
+    ];
+
+    #[test]
+    fn error_location() {
+        let mut builder = Decoder::builder();
+        builder.read_ident_packet(&mut BitReader::new(Cursor::new(&IDENT[..]))).unwrap();
+
+        // Two codebooks, the second one has invalid sync pattern.
+        let setup = [
+            5, b'v', b'o', b'r', b'b', b'i', b's',
+            1,
+            0x42, 0x43, 0x56, 1, 0, 2, 0, 0, 0, 0,
+            0x42, 0x43, 0x57,
+        ];
+        let err = builder.read_setup_packet(&mut BitReader::new(Cursor::new(&setup[..])))
+            .err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::Undecodable);
+        assert_eq!(err.stage(), Some(DecodeStage::Codebook(1)));
+        assert_eq!(err.bit_pos(), Some(21 * 8));
+        match err.inner() {
+            &Error::InvalidCodebookSync => {}
+            e => panic!("{:?}", e),
+        }
+
+        // EOF errors don't get location.
+        let err = builder.read_setup_packet(&mut BitReader::new(Cursor::new(&setup[..12])))
+            .err().unwrap();
+        assert_eq!(err.stage(), None);
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+        // The first codebook has three codewords of length 1, the second one has invalid sync
+        // pattern. The first error is reported even if the codebooks are built in parallel.
+        let setup = [
+            5, b'v', b'o', b'r', b'b', b'i', b's',
+            1,
+            0x42, 0x43, 0x56, 1, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let err = builder.read_setup_packet(&mut BitReader::new(Cursor::new(&setup[..])))
+            .err().unwrap();
+        assert_eq!(err.stage(), Some(DecodeStage::Codebook(0)));
+        assert_eq!(err.bit_pos(), Some(16 * 8 + 21));
+        match err.inner() {
+            &Error::CodebookOverspecified => {}
+            e => panic!("{:?}", e),
         }
+    }
+
+    #[test]
+    fn lenient_framing_bit() {
+        let mut ident = IDENT;
+        ident[29] = 0;
+
+        let mut builder = Decoder::builder();
+        let err = builder.read_ident_packet(&mut BitReader::new(Cursor::new(&ident[..])))
+            .err().unwrap();
+        match err.inner() {
+            &Error::InvalidFramingBit => {}
+            e => panic!("{:?}", e),
+        }
+        assert!(builder.warnings().is_empty());
+
+        let mut builder = Decoder::builder();
+        builder.lenient(true);
+        builder.read_ident_packet(&mut BitReader::new(Cursor::new(&ident[..]))).unwrap();
+        assert_eq!(builder.header().unwrap().channel_count(), 2);
+        assert_eq!(builder.warnings(), [Warning::InvalidFramingBit(PacketKind::Ident)]);
+    }
 
-        f(reader)
+    #[test]
+    fn samples_interleave_into() {
+        let mut frame = Frame::new(2, 4);
+        for (channel, samples) in frame.channels_mut().enumerate() {
+            for (i, s) in samples.iter_mut().enumerate() {
+                *s = (channel * 10 + i) as Sample;
+            }
+        }
+        let samples = Samples { frame: &frame, range: WindowRange { start: 1, end: 3 }, silent: false };
+        let mut buf = [0.0; 5];
+        assert_eq!(samples.interleave_into(&mut buf), 4);
+        assert_eq!(buf, [1.0, 11.0, 2.0, 12.0, 0.0]);
+        assert_eq!(samples.interleave().collect::<Vec<_>>(), &buf[..4]);
+
+        let slice = samples.slice(1..2);
+        assert_eq!(slice.len(), 1);
+        assert_eq!(slice.interleave().collect::<Vec<_>>(), &[2.0, 12.0]);
+        assert!(samples.slice(2..2).is_empty());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,208 @@
+//! Human readable dump of the parsed stream headers.
+
+use std::fmt;
+
+use decoder::Setup;
+use header::{FrameKind, Header};
+
+/// Structured description of the parsed identification and setup headers with all the
+/// parameters of the codebooks, floors, residues, mappings and modes, one item per line.
+/// Intended for encoder developers and bug reports. The output is produced by the `Display`
+/// implementation, its format may change between versions.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use vorbis::{DebugDump, Decoder};
+///
+/// # fn f(decoder: &Decoder) {
+/// println!("{}", DebugDump::new(decoder.header(), decoder.setup()));
+/// # }
+/// ```
+pub struct DebugDump<'a> {
+    header: &'a Header,
+    setup: &'a Setup,
+}
+
+impl<'a> DebugDump<'a> {
+    pub fn new(header: &'a Header, setup: &'a Setup) -> Self {
+        DebugDump {
+            header: header,
+            setup: setup,
+        }
+    }
+}
+
+impl<'a> fmt::Display for DebugDump<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let header = self.header;
+        let bitrates = header.bitrates();
+        try!(writeln!(f, "Header: version {}, {} channels, {} Hz, frame lengths {}/{}",
+            header.version(), header.channel_count(), header.sample_rate(),
+            header.frame_lens().short(), header.frame_lens().long()));
+        try!(writeln!(f, "Bitrates: min {}, nominal {}, max {}",
+            bitrates.min(), bitrates.nom(), bitrates.max()));
+
+        let setup = self.setup;
+        try!(writeln!(f, "Codebooks: {}", setup.codebooks().len()));
+        for (i, codebook) in setup.codebooks().iter().enumerate() {
+            try!(write!(f, "  {}: {} dims, {} entries, max codeword length {}, ",
+                i, codebook.dim_count(), codebook.entry_count(), codebook.max_code_len()));
+            match codebook.lookup_kind() {
+                Some(kind) => try!(writeln!(f, "lookup {} with {} values{}",
+                    kind as u8, codebook.lookup_values().len(),
+                    if codebook.sequence_p() { ", sequence_p" } else { "" })),
+                None => try!(writeln!(f, "no lookup")),
+            }
+        }
+
+        try!(writeln!(f, "Floors: {}", setup.floors().len()));
+        for (i, floor) in setup.floors().iter().enumerate() {
+            try!(writeln!(f, "  {}: type 1, multiplier {}, range {}, {} X values",
+                i, floor.mult(), floor.range(), floor.x_list().len()));
+            try!(writeln!(f, "    partition classes: {}", List(floor.part_classes())));
+            for (j, class) in floor.classes().iter().enumerate() {
+                try!(writeln!(f, "    class {}: {} dims, {} subclass bits, master book {}, subclass books {}",
+                    j, class.dim_count(), class.subclass_bit_count(), Book(class.master_book()),
+                    Books(class.subclass_books())));
+            }
+            try!(writeln!(f, "    X list: {}", List(floor.x_list())));
+        }
+
+        try!(writeln!(f, "Residues: {}", setup.residues().len()));
+        for (i, residue) in setup.residues().iter().enumerate() {
+            try!(writeln!(f, "  {}: type {}, range {}..{}, partition length {}, classbook {}",
+                i, residue.kind() as u8, residue.start(), residue.end(), residue.part_len(),
+                residue.classbook()));
+            for (j, books) in residue.class_books().iter().enumerate() {
+                try!(writeln!(f, "    class {}: books {}", j, Books(books)));
+            }
+        }
+
+        try!(writeln!(f, "Mappings: {}", setup.mappings().len()));
+        for (i, mapping) in setup.mappings().iter().enumerate() {
+            try!(writeln!(f, "  {}: {} couplings, {} submaps",
+                i, mapping.channel_couplings().len(), mapping.submaps().len()));
+            for coupling in mapping.channel_couplings() {
+                try!(writeln!(f, "    coupling: magnitude {}, angle {}",
+                    coupling.mag_channel(), coupling.ang_channel()));
+            }
+            for (j, submap) in mapping.submaps().iter().enumerate() {
+                try!(writeln!(f, "    submap {}: channels {}, floor {}, residue {}",
+                    j, List(submap.channels()), submap.floor(), submap.residue()));
+            }
+        }
+
+        try!(writeln!(f, "Modes: {}", setup.modes().len()));
+        for (i, mode) in setup.modes().iter().enumerate() {
+            try!(writeln!(f, "  {}: {}, mapping {}", i, match mode.frame_kind {
+                FrameKind::Short => "short",
+                FrameKind::Long => "long",
+            }, mode.mapping));
+        }
+        Ok(())
+    }
+}
+
+// Space separated list of values.
+struct List<'a, T: 'a>(&'a [T]);
+
+impl<'a, T: fmt::Display> fmt::Display for List<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, v) in self.0.iter().enumerate() {
+            if i > 0 {
+                try!(write!(f, " "));
+            }
+            try!(write!(f, "{}", v));
+        }
+        Ok(())
+    }
+}
+
+// Codebook index or `-` if there's none.
+struct Book(Option<usize>);
+
+impl fmt::Display for Book {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(book) => write!(f, "{}", book),
+            None => write!(f, "-"),
+        }
+    }
+}
+
+// Space separated list of `Book`s.
+struct Books<'a>(&'a [Option<usize>]);
+
+impl<'a> fmt::Display for Books<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, &book) in self.0.iter().enumerate() {
+            if i > 0 {
+                try!(write!(f, " "));
+            }
+            try!(write!(f, "{}", Book(book)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use bitstream::BitReader;
+    use codebook::LookupKind;
+    use header::{Bitrates, FrameLens};
+    use residue::ResidueKind;
+    use setup_builder::{CodebookConfig, FloorConfig, MappingConfig, ResidueConfig, SetupBuilder};
+    use super::*;
+
+    #[test]
+    fn dump() {
+        let header = Header::new(2, 44100, Bitrates::new(0, 128000, 0), FrameLens::new(256, 2048));
+        let mut floor = FloorConfig::new(2, 7);
+        floor.class(2, Some(1), vec![None, Some(0)]).partition(0, &[32, 64]);
+        let mut residue = ResidueConfig::new(ResidueKind::Residue2, 0, 64, 16, 1);
+        residue.class(&[Some(2), None, Some(2)]);
+        let mut mapping = MappingConfig::new();
+        mapping.coupling(0, 1).submap(0, 0);
+        let mut vq_book = CodebookConfig::new(2, vec![2; 4]);
+        vq_book.lookup(LookupKind::Lookup1, -1.0, 2.0, 1, vec![0, 1]);
+        let packet = SetupBuilder::new()
+            .codebook(CodebookConfig::new(1, vec![1, 1]))
+            .codebook(CodebookConfig::new(1, vec![2, 2, 1]))
+            .codebook(vq_book)
+            .floor(floor)
+            .residue(residue)
+            .mapping(mapping)
+            .mode(FrameKind::Short, 0)
+            .mode(FrameKind::Long, 0)
+            .to_packet(&header)
+            .unwrap();
+        let setup = Setup::read_packet(&mut BitReader::new(Cursor::new(&packet[..])), &header).unwrap();
+
+        assert_eq!(DebugDump::new(&header, &setup).to_string(), "\
+Header: version 0, 2 channels, 44100 Hz, frame lengths 256/2048
+Bitrates: min 0, nominal 128000, max 0
+Codebooks: 3
+  0: 1 dims, 2 entries, max codeword length 1, no lookup
+  1: 1 dims, 3 entries, max codeword length 2, no lookup
+  2: 2 dims, 4 entries, max codeword length 2, lookup 1 with 2 values
+Floors: 1
+  0: type 1, multiplier 2, range 128, 4 X values
+    partition classes: 0
+    class 0: 2 dims, 1 subclass bits, master book 1, subclass books - 0
+    X list: 0 128 32 64
+Residues: 1
+  0: type 2, range 0..64, partition length 16, classbook 1
+    class 0: books 2 - 2 - - - - -
+Mappings: 1
+  0: 1 couplings, 1 submaps
+    coupling: magnitude 0, angle 1
+    submap 0: channels 0 1, floor 0, residue 0
+Modes: 2
+  0: short, mapping 0
+  1: long, mapping 0
+");
+    }
+}
@@ -0,0 +1,590 @@
+//! Simple constant quality Vorbis encoder.
+
+use std::cmp;
+use std::io::Cursor;
+
+use bitstream::{BitReader, BitWrite, BitWriter};
+use codebook::LookupKind;
+use decoder::Setup;
+use error::Result;
+use header::{Bitrates, FrameKind, FrameLens, Header};
+use mdct::Mdct;
+use residue::ResidueKind;
+use sample::Sample;
+use setup_builder::{CodebookConfig, FloorConfig, MappingConfig, ResidueConfig, SetupBuilder};
+use util::Bits;
+use window::Windows;
+
+// Only the long frames are used, the short frame length is needed for the ident header only.
+const SHORT_FRAME_LEN: usize = 256;
+const FRAME_LEN: usize = 2048;
+// Number of the new samples in each packet which is also the spectrum length.
+const HOP_LEN: usize = FRAME_LEN / 2;
+
+// Floor X list without the implicit 0 and 1 << FLOOR_RANGE_BITS values. Each value is in the
+// middle of the values before it so it's predicted well from the neighbors and is coded with
+// smaller numbers. The floor is denser at the low frequencies.
+const FLOOR_X_LIST: [u16; 32] = [
+    48, 10, 238, 6, 22, 107, 528, 4, 8, 15, 33, 72, 160, 355, 787, 3,
+    5, 7, 9, 12, 18, 27, 40, 59, 88, 131, 195, 291, 433, 645, 960, 2];
+const FLOOR_RANGE_BITS: usize = 10;
+// Gives floor Y values in 0..128 range with the step of ~1.1 dB.
+const FLOOR_MULT: u8 = 2;
+const FLOOR_PART_DIM: usize = 4;
+
+const RESIDUE_PART_LEN: usize = 32;
+// Maximum magnitude of the quantized residue values for each partition class. The first class
+// is all zeros.
+const RESIDUE_CLASS_MAX: [i32; 5] = [0, 1, 4, 15, 48];
+// Number of partition classes coded in one class book entry.
+const RESIDUE_CLASSWORD_DIM: usize = 2;
+
+// Codebook indexes.
+const FLOOR_BOOK: usize = 0;
+const CLASS_BOOK: usize = 1;
+// VQ books of the RESIDUE_CLASS_MAX classes except the first one.
+const RESIDUE_BOOKS: [usize; 4] = [2, 3, 4, 5];
+
+/// Encodes PCM audio into Vorbis packets with constant quality (variable bitrate).
+///
+/// The encoder is intentionally simple: it uses long frames only, a fixed floor 1 layout and
+/// residue type 1 with no channel coupling, so the output is notably bigger than the one of
+/// libvorbis at the comparable quality. The packets are not wrapped into any container, the
+/// header and audio packets must be written into Ogg (or other) stream by the caller. The
+/// comment header isn't produced by the encoder, use `Comments::to_packet()` to make it.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use vorbis::{Comments, Encoder};
+///
+/// # fn f(left: &[f32], right: &[f32]) {
+/// let mut encoder = Encoder::builder(2, 44100).quality(0.6).build();
+/// let ident_packet = encoder.ident_packet();
+/// let comment_packet = Comments::builder().vendor("my encoder").build().to_packet();
+/// let setup_packet = encoder.setup_packet();
+///
+/// let mut packets = encoder.encode(&[left, right]);
+/// packets.extend(encoder.finish());
+/// for packet in &packets {
+///     // Write packet.data() with packet.granule_pos().
+/// }
+/// # }
+/// ```
+pub struct Encoder {
+    header: Header,
+    setup_packet: Vec<u8>,
+    setup: Setup,
+    books: Vec<Book>,
+    // Spectrum range of each floor X list value where the maximum of the spectrum magnitude is
+    // taken to get the floor Y value.
+    floor_regions: Vec<(usize, usize)>,
+    mdct: Mdct,
+    window: Box<[Sample]>,
+    // Ratio of the spectrum peaks to the floor. The residue quantization step is the floor so
+    // the peaks are quantized to about this value.
+    peak_to_floor: Sample,
+    // Minimum floor value. The spectrum values under half of it are quantized to zero.
+    min_floor: Sample,
+    // Input samples of each channel starting at the beginning of the next frame.
+    input: Vec<Vec<Sample>>,
+    sample_count: u64,
+    packet_count: u64,
+    // Buffers reused between the frames.
+    frame: Vec<Sample>,
+    spectrum: Vec<Sample>,
+    curve: Vec<Sample>,
+    y_list: Vec<(u16, bool)>,
+    coded: Vec<u16>,
+    // Quantized residue of each channel, `None` for the unused channels.
+    residue: Vec<Option<Vec<i32>>>,
+}
+
+impl Encoder {
+    pub fn builder(channel_count: usize, sample_rate: u32) -> EncoderBuilder {
+        EncoderBuilder::new(channel_count, sample_rate)
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Returns the identification header packet.
+    pub fn ident_packet(&self) -> Vec<u8> {
+        self.header.to_packet()
+    }
+
+    /// Returns the setup header packet.
+    pub fn setup_packet(&self) -> Vec<u8> {
+        self.setup_packet.clone()
+    }
+
+    /// Encodes the samples with a slice for each channel (all of the same length). Returns the
+    /// packets completed so far.
+    pub fn encode(&mut self, channels: &[&[Sample]]) -> Vec<EncodedPacket> {
+        assert_eq!(channels.len(), self.input.len());
+        let len = channels[0].len();
+        assert!(channels.iter().all(|c| c.len() == len));
+        for (input, channel) in self.input.iter_mut().zip(channels.iter()) {
+            input.extend_from_slice(channel);
+        }
+        self.sample_count += len as u64;
+
+        let mut r = Vec::new();
+        while self.input[0].len() >= FRAME_LEN {
+            r.push(self.encode_frame());
+        }
+        r
+    }
+
+    /// Encodes the remaining samples padded with silence. The granule position of the last
+    /// packet is the total sample count so the decoder can discard the padding.
+    pub fn finish(mut self) -> Vec<EncodedPacket> {
+        let mut r = Vec::new();
+        // Each packet completes HOP_LEN samples of the previous one.
+        while (self.packet_count as i64 - 1) * (HOP_LEN as i64) < self.sample_count as i64 {
+            for input in &mut self.input {
+                input.resize(FRAME_LEN, 0.0);
+            }
+            r.push(self.encode_frame());
+        }
+        if let Some(last) = r.last_mut() {
+            last.granule_pos = self.sample_count;
+        }
+        r
+    }
+
+    fn encode_frame(&mut self) -> EncodedPacket {
+        let mut writer = BitWriter::new(Vec::new());
+        self.write_audio(&mut writer).expect("Writing to Vec failed");
+        for input in &mut self.input {
+            input.drain(..HOP_LEN);
+        }
+
+        let granule_pos = cmp::min(self.packet_count * HOP_LEN as u64, self.sample_count);
+        self.packet_count += 1;
+        EncodedPacket {
+            data: writer.into_inner().unwrap(),
+            granule_pos: granule_pos,
+        }
+    }
+
+    fn write_audio<W: BitWrite>(&mut self, writer: &mut W) -> Result<()> {
+        try!(writer.write_bool(false));
+        // There's only one mode so no mode number, just the previous and next window flags.
+        try!(writer.write_bool(true));
+        try!(writer.write_bool(true));
+
+        for channel in 0..self.input.len() {
+            for (i, f) in self.frame.iter_mut().enumerate() {
+                *f = self.input[channel][i] * self.window[i];
+            }
+            self.mdct.forward(&self.frame, &mut self.spectrum);
+
+            if !self.fit_floor() {
+                try!(writer.write_bool(false));
+                self.residue[channel] = None;
+                continue;
+            }
+            let floor = &self.setup.floors()[0];
+            floor.encode_amplitude(&mut self.y_list, &mut self.coded);
+            try!(writer.write_bool(true));
+            let y_bits = (floor.range() - 1).ilog();
+            try!(writer.write_u16_bits(self.coded[0], y_bits));
+            try!(writer.write_u16_bits(self.coded[1], y_bits));
+            for &c in &self.coded[2..] {
+                try!(self.books[FLOOR_BOOK].write_entry(writer, c as usize));
+            }
+
+            for c in self.curve.iter_mut() {
+                *c = 1.0;
+            }
+            floor.finish_decode(&mut self.curve, &self.y_list);
+            let max = RESIDUE_CLASS_MAX[RESIDUE_CLASS_MAX.len() - 1];
+            let residue = self.residue[channel].get_or_insert_with(|| vec![0; HOP_LEN]);
+            for ((r, &s), &c) in residue.iter_mut().zip(self.spectrum.iter()).zip(self.curve.iter()) {
+                *r = cmp::max(-max, cmp::min((s / c).round() as i32, max));
+            }
+        }
+
+        self.write_residue(writer)
+    }
+
+    // Computes the floor Y list from the spectrum. Returns false if the whole spectrum is
+    // quantized to zero, the channel is unused then.
+    fn fit_floor(&mut self) -> bool {
+        let floor = &self.setup.floors()[0];
+        let mut used = false;
+        self.y_list.truncate(0);
+        for &(start, end) in &self.floor_regions {
+            let peak = self.spectrum[start..end].iter().fold(0.0, |m: Sample, &s| m.max(s.abs()));
+            used |= peak >= self.min_floor * 0.5;
+            let y = floor.amplitude_to_y((peak / self.peak_to_floor).max(self.min_floor));
+            self.y_list.push((y, true));
+        }
+        used
+    }
+
+    // Writes the residue of the used channels in the order the residue type 1 decoder
+    // reads it.
+    fn write_residue<W: BitWrite>(&self, writer: &mut W) -> Result<()> {
+        let part_count = HOP_LEN / RESIDUE_PART_LEN;
+        let channels: Vec<_> = self.residue.iter().filter_map(|r| r.as_ref()).collect();
+        let classes: Vec<Vec<usize>> = channels.iter()
+            .map(|r| r.chunks(RESIDUE_PART_LEN)
+                .map(|part| {
+                    let max = part.iter().fold(0, |m, &v| cmp::max(m, v.abs()));
+                    RESIDUE_CLASS_MAX.iter().position(|&c| max <= c).unwrap()
+                })
+                .collect())
+            .collect();
+
+        let mut start = 0;
+        while start < part_count {
+            let end = cmp::min(start + RESIDUE_CLASSWORD_DIM, part_count);
+            for classes in &classes {
+                let entry = (start..start + RESIDUE_CLASSWORD_DIM)
+                    .fold(0, |e, part| e * RESIDUE_CLASS_MAX.len() + classes.get(part).cloned().unwrap_or(0));
+                try!(self.books[CLASS_BOOK].write_entry(writer, entry));
+            }
+            for part in start..end {
+                for (residue, classes) in channels.iter().zip(classes.iter()) {
+                    if classes[part] == 0 {
+                        continue;
+                    }
+                    let book = &self.books[RESIDUE_BOOKS[classes[part] - 1]];
+                    let values = &residue[part * RESIDUE_PART_LEN..(part + 1) * RESIDUE_PART_LEN];
+                    for v in values.chunks(book.dim_count()) {
+                        try!(book.write_vector(writer, v));
+                    }
+                }
+            }
+            start = end;
+        }
+        Ok(())
+    }
+}
+
+/// Builder of `Encoder`.
+pub struct EncoderBuilder {
+    channel_count: usize,
+    sample_rate: u32,
+    quality: f32,
+}
+
+impl EncoderBuilder {
+    pub fn new(channel_count: usize, sample_rate: u32) -> Self {
+        assert!(channel_count > 0 && channel_count <= 255);
+        assert!(sample_rate > 0);
+        EncoderBuilder {
+            channel_count: channel_count,
+            sample_rate: sample_rate,
+            quality: 0.5,
+        }
+    }
+
+    /// Sets the quality in `0.0..1.0` range, `0.5` by default. Higher quality gives bigger
+    /// output.
+    pub fn quality(&mut self, value: f32) -> &mut Self {
+        assert!(value >= 0.0 && value <= 1.0);
+        self.quality = value;
+        self
+    }
+
+    pub fn build(&self) -> Encoder {
+        let header = Header::new(self.channel_count, self.sample_rate, Bitrates::new(0, 0, 0),
+                FrameLens::new(SHORT_FRAME_LEN, FRAME_LEN));
+
+        let books = make_books();
+        let setup_packet = setup_builder(&books).to_packet(&header).expect("Invalid setup");
+        let setup = Setup::read_packet(&mut BitReader::new(Cursor::new(&setup_packet[..])), &header)
+            .unwrap();
+
+        let mut window = Windows::make_slope(HOP_LEN).into_vec();
+        let falling: Vec<_> = window.iter().rev().cloned().collect();
+        window.extend(falling);
+
+        let quality = self.quality as Sample;
+        Encoder {
+            header: header,
+            setup_packet: setup_packet,
+            floor_regions: floor_regions(setup.floors()[0].x_list()),
+            setup: setup,
+            books: books,
+            mdct: Mdct::new(FRAME_LEN),
+            window: window.into_boxed_slice(),
+            peak_to_floor: 2.0 * (16.0 as Sample).powf(quality),
+            min_floor: (10.0 as Sample).powf(-(70.0 + 40.0 * quality) / 20.0),
+            input: vec![vec![0.0; HOP_LEN]; self.channel_count],
+            sample_count: 0,
+            packet_count: 0,
+            frame: vec![0.0; FRAME_LEN],
+            spectrum: vec![0.0; HOP_LEN],
+            curve: vec![0.0; HOP_LEN],
+            y_list: Vec::with_capacity(FLOOR_X_LIST.len() + 2),
+            coded: Vec::new(),
+            residue: vec![None; self.channel_count],
+        }
+    }
+}
+
+/// Audio packet produced by `Encoder`.
+#[derive(Clone, Debug)]
+pub struct EncodedPacket {
+    data: Vec<u8>,
+    granule_pos: u64,
+}
+
+impl EncodedPacket {
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Returns the number of samples (per channel) decoded after this packet, as stored in the
+    /// granule position of the Ogg page. For the last packet this is the total input length
+    /// which can be less than the decoded length.
+    pub fn granule_pos(&self) -> u64 {
+        self.granule_pos
+    }
+}
+
+// Codebook of the encoder setup with the codewords for writing. The VQ books use the lattice
+// lookup of the integers in `-max..max + 1` range, the others have no lookup.
+struct Book {
+    config: CodebookConfig,
+    codewords: Vec<u32>,
+    // Zero for the books without lookup.
+    max: i32,
+}
+
+impl Book {
+    // Creates VQ book with the weight of each value in `-max..max + 1` range. The entry weight
+    // is the product of the weights of its values.
+    fn lattice<F: Fn(i32) -> f64>(dim_count: usize, max: i32, weight: F) -> Self {
+        let value_count = (max * 2 + 1) as usize;
+        let mut weights = vec![1.0; value_count.pow(dim_count as u32)];
+        for (entry, w) in weights.iter_mut().enumerate() {
+            let mut e = entry;
+            for _ in 0..dim_count {
+                *w *= weight((e % value_count) as i32 - max);
+                e /= value_count;
+            }
+        }
+        Self::new(dim_count, max, &weights)
+    }
+
+    fn new(dim_count: usize, max: i32, weights: &[f64]) -> Self {
+        let mut config = CodebookConfig::with_weights(dim_count, weights);
+        if max > 0 {
+            let value_count = max as u32 * 2 + 1;
+            config.lookup(LookupKind::Lookup1, -max as f32, 1.0, (value_count - 1).ilog(),
+                (0..value_count).collect());
+        }
+        let codewords = config.codewords().unwrap();
+        Book {
+            config: config,
+            codewords: codewords,
+            max: max,
+        }
+    }
+
+    fn dim_count(&self) -> usize {
+        self.config.dim_count()
+    }
+
+    fn write_entry<W: BitWrite>(&self, writer: &mut W, entry: usize) -> Result<()> {
+        let len = self.config.codeword_lens()[entry] as usize;
+        try!(writer.write_u32_bits(self.codewords[entry], len));
+        Ok(())
+    }
+
+    // Writes the entry of the lattice vector. The first value is the least significant.
+    fn write_vector<W: BitWrite>(&self, writer: &mut W, values: &[i32]) -> Result<()> {
+        let value_count = (self.max * 2 + 1) as usize;
+        let entry = values.iter().rev().fold(0, |e, &v| e * value_count + (v + self.max) as usize);
+        self.write_entry(writer, entry)
+    }
+}
+
+// Makes the books in the order of the *_BOOK constants. The weights roughly follow the value
+// distribution of the music to get shorter codewords for the likely values.
+fn make_books() -> Vec<Book> {
+    let floor_weights: Vec<_> = (0..128).map(|c| 1.0 / (c as f64 + 2.0).powf(1.5)).collect();
+
+    let class_count = RESIDUE_CLASS_MAX.len();
+    let class_weights = [4.0, 3.0, 3.0, 2.0, 1.0];
+    let classword_weights: Vec<_> = (0..class_count.pow(RESIDUE_CLASSWORD_DIM as u32))
+        .map(|entry| {
+            (0..RESIDUE_CLASSWORD_DIM).fold((1.0, entry), |(w, e), _| {
+                (w * class_weights[e % class_count], e / class_count)
+            }).0
+        })
+        .collect();
+
+    vec![
+        Book::new(1, 0, &floor_weights),
+        // The class book has no lookup but its dimension is the number of classes per entry.
+        Book::new(RESIDUE_CLASSWORD_DIM, 0, &classword_weights),
+        Book::lattice(4, 1, |v| if v == 0 { 4.0 } else { 1.0 }),
+        Book::lattice(2, 4, |v| 1.0 / (1.0 + v.abs() as f64)),
+        Book::lattice(2, 15, |v| 1.0 / (1.0 + v.abs() as f64)),
+        Book::lattice(1, 48, |v| 1.0 / (1.0 + v.abs() as f64)),
+    ]
+}
+
+// Returns the spectrum range between the neighbors of each floor X value. The ranges of the
+// neighbors overlap so the floor curve interpolated between them isn't under the both values.
+fn floor_regions(x_list: &[u16]) -> Vec<(usize, usize)> {
+    let mut sorted: Vec<_> = x_list.iter().map(|&x| cmp::min(x as usize, HOP_LEN)).collect();
+    sorted.sort();
+    x_list.iter().map(|&x| {
+        let i = sorted.binary_search(&cmp::min(x as usize, HOP_LEN)).unwrap();
+        let start = if i == 0 { 0 } else { sorted[i - 1] };
+        let end = if i + 1 == sorted.len() { HOP_LEN } else { sorted[i + 1] };
+        (cmp::min(start, HOP_LEN - 1), end)
+    }).collect()
+}
+
+// Makes the setup with the floor 1 of one partition class without subclasses, residue type 1
+// and one long frame mode.
+fn setup_builder(books: &[Book]) -> SetupBuilder {
+    let mut floor = FloorConfig::new(FLOOR_MULT, FLOOR_RANGE_BITS);
+    floor.class(FLOOR_PART_DIM, None, vec![Some(FLOOR_BOOK)]);
+    for x_values in FLOOR_X_LIST.chunks(FLOOR_PART_DIM) {
+        floor.partition(0, x_values);
+    }
+
+    let mut residue = ResidueConfig::new(ResidueKind::Residue1, 0, HOP_LEN, RESIDUE_PART_LEN, CLASS_BOOK);
+    residue.class(&[]);
+    for &book in &RESIDUE_BOOKS {
+        residue.class(&[Some(book)]);
+    }
+
+    let mut mapping = MappingConfig::new();
+    mapping.submap(0, 0);
+
+    let mut r = SetupBuilder::new();
+    for book in books {
+        r.codebook(book.config.clone());
+    }
+    r.floor(floor)
+        .residue(residue)
+        .mapping(mapping)
+        .mode(FrameKind::Long, 0);
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp;
+    use std::f64::consts::PI;
+    use std::io::Cursor;
+
+    use bitstream::BitReader;
+    use decoder::Decoder;
+    use sample::Sample;
+    use super::*;
+
+    fn encode(encoder: &mut Encoder, channels: &[Vec<Sample>]) -> Vec<EncodedPacket> {
+        let mut packets = Vec::new();
+        let len = channels[0].len();
+        let mut start = 0;
+        while start < len {
+            let end = cmp::min(start + 1000, len);
+            let chunk: Vec<_> = channels.iter().map(|c| &c[start..end]).collect();
+            packets.extend(encoder.encode(&chunk));
+            start = end;
+        }
+        packets
+    }
+
+    // Returns the decoded samples and the number of non-empty `Samples` reported silent.
+    fn decode(ident_packet: &[u8], setup_packet: &[u8], packets: &[EncodedPacket])
+            -> (Vec<Vec<Sample>>, usize) {
+        let mut builder = Decoder::builder();
+        builder.read_ident_packet(&mut BitReader::new(Cursor::new(ident_packet))).unwrap();
+        builder.read_setup_packet(&mut BitReader::new(Cursor::new(setup_packet))).unwrap();
+        let mut decoder = builder.build();
+        let mut r = vec![Vec::new(); decoder.header().channel_count()];
+        let mut silent_count = 0;
+        for packet in packets {
+            let samples = decoder.decode(&mut BitReader::new(Cursor::new(packet.data()))).unwrap();
+            if samples.is_silent() && !samples.is_empty() {
+                assert!(samples.channels().all(|c| c.iter().all(|&s| s == 0.0)));
+                silent_count += 1;
+            }
+            for (i, c) in r.iter_mut().enumerate() {
+                c.extend_from_slice(samples.channel(i));
+            }
+        }
+        let len = packets.last().unwrap().granule_pos() as usize;
+        for c in &mut r {
+            c.truncate(len);
+        }
+        (r, silent_count)
+    }
+
+    fn snr(expected: &[Sample], actual: &[Sample]) -> f64 {
+        let signal = expected.iter().fold(0.0, |a, &s| a + s as f64 * s as f64);
+        let noise = expected.iter().zip(actual.iter())
+            .fold(0.0, |a, (&e, &s)| a + (e - s) as f64 * (e - s) as f64);
+        10.0 * (signal / noise).log10()
+    }
+
+    #[test]
+    fn encode_decode() {
+        let len = 30000;
+        let mut noise = 1_u32;
+        let input: Vec<Vec<Sample>> = vec![
+            (0..len).map(|i| {
+                let t = i as f64 / 44100.0;
+                (0.5 * (2.0 * PI * 440.0 * t).sin() + 0.2 * (2.0 * PI * 3000.0 * t).sin()) as Sample
+            }).collect(),
+            (0..len).map(|i| {
+                noise = noise.wrapping_mul(1103515245).wrapping_add(12345);
+                let t = i as f64 / 44100.0;
+                (0.3 * (2.0 * PI * 1000.0 * t).sin() + 0.01 * ((noise >> 16) as f64 / 32768.0 - 1.0))
+                    as Sample
+            }).collect(),
+        ];
+
+        let mut prev_len = 0;
+        for &quality in &[0.0, 0.5, 1.0] {
+            let mut encoder = Encoder::builder(2, 44100).quality(quality).build();
+            let mut packets = encode(&mut encoder, &input);
+            let ident_packet = encoder.ident_packet();
+            let setup_packet = encoder.setup_packet();
+            packets.extend(encoder.finish());
+            assert_eq!(packets.last().unwrap().granule_pos(), len as u64);
+            let encoded_len = packets.iter().fold(0, |a, p| a + p.data().len());
+            assert!(encoded_len > prev_len);
+            prev_len = encoded_len;
+
+            let (output, silent_count) = decode(&ident_packet, &setup_packet, &packets);
+            assert_eq!(silent_count, 0);
+            for (expected, actual) in input.iter().zip(output.iter()) {
+                assert_eq!(actual.len(), len);
+                let snr = snr(expected, actual);
+                assert!(snr > 10.0 + 20.0 * quality as f64, "quality {}: SNR {}", quality, snr);
+            }
+        }
+    }
+
+    #[test]
+    fn silence() {
+        let mut encoder = Encoder::builder(1, 8000).build();
+        let mut packets = encoder.encode(&[&[0.0; 5000]]);
+        let ident_packet = encoder.ident_packet();
+        let setup_packet = encoder.setup_packet();
+        packets.extend(encoder.finish());
+        assert!(packets.iter().all(|p| p.data().len() == 1));
+
+        let (output, silent_count) = decode(&ident_packet, &setup_packet, &packets);
+        assert_eq!(output[0], vec![0.0; 5000]);
+        assert_eq!(silent_count, packets.len() - 1);
+    }
+}
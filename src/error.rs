@@ -1,13 +1,150 @@
+#[cfg(feature = "std")]
 use std::io;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use bitstream::BitstreamError;
+use decoder::PacketKind;
 
 pub type Result<T> = ::std::result::Result<T, Error>;
 
+/// Decoding error. All variants except `WrongPacketKind`, `ExpectedEof` and `Io` are of
+/// `ErrorKind::Undecodable` kind, i.e. the stream violates the spec or uses an unsupported
+/// feature. The `Undecodable` errors of the header packets are wrapped in `Located`, use
+/// `inner()` to match the error itself. The errors of the audio packets aren't wrapped so that
+/// decoding doesn't allocate, the decoder reports their location instead (see
+/// `Decoder::error_location()`).
 #[derive(Debug)]
 pub enum Error {
-    Undecodable(&'static str),
+    // Headers.
+    UnsupportedVersion,
+    InvalidChannelCount,
+    InvalidSampleRate,
+    InvalidShortFrameLen,
+    InvalidLongFrameLen,
+    LongFrameShorterThanShort,
+    InvalidFramingBit,
+    InvalidPacketKind,
+    InvalidMagic,
+    NonZeroTimeDomainTransform,
+
+    // Codebooks.
+    InvalidCodebookSync,
+    CodewordLenCountMismatch,
+    /// The codeword lengths don't form a valid prefix code: there are more codewords of some
+    /// length than possible.
+    CodebookOverspecified,
+    InvalidLookupKind,
+    /// The codebook declares a codeword longer than `MAX_CODEWORD_LEN` bits.
+    CodewordTooLong {
+        codebook: usize,
+        len: u32,
+    },
+    /// The codebook's entry count and dimensions don't form a valid VQ lookup table.
+    InvalidLookupDimensions {
+        codebook: usize,
+        entry_count: usize,
+        dim_count: usize,
+    },
+    /// A VQ codebook is used for decoding but has no lookup table.
+    NoLookupTable,
+    /// Matched a null (unused) Huffman code entry.
+    NullHuffmanEntry,
+    /// Incomplete or unknown Huffman code.
+    InvalidHuffmanCode,
+
+    // Floors.
+    Floor0Unsupported,
+    InvalidFloorKind,
+    InvalidFloorPartitionCount,
+    InvalidFloorCodebook,
+    FloorXListTooLong,
+    /// The floor X list contains duplicates.
+    InvalidFloorXList,
+
+    // Residues.
+    Residue0Unsupported,
+    InvalidResidueKind,
+    InvalidResidueRange,
+    InvalidResidueClassbook,
+    /// The residue classbook has zero dimensions.
+    InvalidResidueClassbookDims,
+    InvalidResidueCodebook,
+    /// The residue partition size is not a multiple of the codebook dimensions.
+    ResiduePartitionLenMismatch,
+
+    // Mappings.
+    InvalidMappingKind,
+    /// Invalid (magnitude, angle) channel pair.
+    InvalidCouplingChannels,
+    /// Unexpected data in the reserved field.
+    NonZeroMappingReserved,
+    InvalidMappingMux,
+    InvalidMappingFloor,
+    InvalidMappingResidue,
+
+    // Modes.
+    InvalidModeWindowKind,
+    InvalidModeTransformKind,
+    InvalidModeMapping,
+    /// Invalid mode number in audio packet.
+    InvalidModeNumber,
+
+    /// The `DecoderState` is malformed or doesn't match the stream.
+    InvalidDecoderState,
+    /// The comment header exceeds one of the `CommentLimits`.
+    CommentLimitExceeded(&'static str),
+    /// The audio packet exceeds one of the `DecodeLimits`.
+    DecodeLimitExceeded(&'static str),
     WrongPacketKind(&'static str),
     ExpectedEof(&'static str),
+    /// The packet ended before all the data was read.
+    UnexpectedEof,
+    /// I/O error of the underlying reader (or writer).
+    #[cfg(feature = "std")]
     Io(io::Error),
+    /// An `ErrorKind::Undecodable` error of a header packet with the location in the packet
+    /// where it occurred.
+    Located {
+        error: Box<Error>,
+        /// Offset in bits from the beginning of the packet if the reader reports the position
+        /// (see `BitRead::bit_pos()`).
+        bit_pos: Option<u64>,
+        stage: Option<DecodeStage>,
+    },
+}
+
+/// Recoverable spec violation. The violations are reported as warnings instead of errors only
+/// in lenient mode, see `DecoderBuilder::lenient()`, except `TruncatedFloor` which is always
+/// recoverable.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Warning {
+    /// The framing bit of the header packet is unset or missing.
+    InvalidFramingBit(PacketKind),
+    /// The comment header is truncated, the specified number of comments was dropped.
+    DroppedComments(usize),
+    /// The end of packet was reached while decoding floor of the channel, the channel is
+    /// treated as unused.
+    TruncatedFloor {
+        channel: usize,
+    },
+    /// The residue has more passes than `DecodeLimits::max_residue_passes()` allows, the
+    /// specified number of last passes was skipped.
+    ResiduePassesSkipped(usize),
+}
+
+/// Component of the setup header or audio packet that was being decoded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DecodeStage {
+    Codebook(usize),
+    Floor(usize),
+    Residue {
+        residue: usize,
+        /// Residue decode pass, only known for audio packets.
+        pass: Option<usize>,
+    },
+    Mapping(usize),
+    Mode(usize),
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -15,26 +152,80 @@ pub enum ErrorKind {
     Undecodable,
     WrongPacketKind,
     ExpectedEof,
+    UnexpectedEof,
+    #[cfg(feature = "std")]
     Io,
 }
 
 impl Error {
     pub fn kind(&self) -> ErrorKind {
         match self {
-            &Error::Undecodable(_)      => ErrorKind::Undecodable,
-            &Error::ExpectedEof(_)      => ErrorKind::ExpectedEof,
-            &Error::WrongPacketKind(_)  => ErrorKind::WrongPacketKind,
-            &Error::Io(_)               => ErrorKind::Io,
+            &Error::ExpectedEof(_)                  => ErrorKind::ExpectedEof,
+            &Error::WrongPacketKind(_)              => ErrorKind::WrongPacketKind,
+            &Error::UnexpectedEof                   => ErrorKind::UnexpectedEof,
+            #[cfg(feature = "std")]
+            &Error::Io(_)                           => ErrorKind::Io,
+            &Error::Located { ref error, .. }       => error.kind(),
+            _                                       => ErrorKind::Undecodable,
+        }
+    }
+
+    /// Returns the error without the location.
+    pub fn inner(&self) -> &Error {
+        match self {
+            &Error::Located { ref error, .. } => error,
+            e => e,
+        }
+    }
+
+    pub fn bit_pos(&self) -> Option<u64> {
+        match self {
+            &Error::Located { bit_pos, .. } => bit_pos,
+            _ => None,
+        }
+    }
+
+    pub fn stage(&self) -> Option<DecodeStage> {
+        match self {
+            &Error::Located { stage, .. } => stage,
+            _ => None,
+        }
+    }
+
+    // Attaches the location to `ErrorKind::Undecodable` errors. The errors which already have
+    // location are returned as is since the innermost location is the most precise one.
+    pub fn located(self, bit_pos: Option<u64>, stage: Option<DecodeStage>) -> Self {
+        if self.kind() != ErrorKind::Undecodable {
+            return self;
+        }
+        match self {
+            e @ Error::Located { .. } => e,
+            e => Error::Located {
+                error: Box::new(e),
+                bit_pos: bit_pos,
+                stage: stage,
+            },
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Error {
         Error::Io(e)
     }
 }
 
+impl From<BitstreamError> for Error {
+    fn from(e: BitstreamError) -> Error {
+        match e {
+            BitstreamError::UnexpectedEof => Error::UnexpectedEof,
+            #[cfg(feature = "std")]
+            BitstreamError::Io(e) => Error::Io(e),
+        }
+    }
+}
+
 pub trait ExpectEof<T> {
     fn expect_eof(self) -> Result<T>;
 }
@@ -42,12 +233,15 @@ pub trait ExpectEof<T> {
 impl<T> ExpectEof<T> for Result<T> {
     fn expect_eof(self) -> Result<T> {
         match self {
+            Err(Error::UnexpectedEof) => Err(Error::ExpectedEof("Expected EOF")),
+            #[cfg(feature = "std")]
             Err(Error::Io(e)) => Err(expect_eof(e)),
             v => v,
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl<T> ExpectEof<T> for io::Result<T> {
     fn expect_eof(self) -> Result<T> {
         match self {
@@ -57,6 +251,7 @@ impl<T> ExpectEof<T> for io::Result<T> {
     }
 }
 
+#[cfg(feature = "std")]
 fn expect_eof(e: io::Error) -> Error {
     if e.kind() == io::ErrorKind::UnexpectedEof {
         Error::ExpectedEof("Expected EOF")
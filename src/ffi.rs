@@ -0,0 +1,560 @@
+//! C API mirroring the decoding part of libvorbis (`codec.h`).
+//!
+//! Enabled by the `ffi` feature. The structs have the same layout as the libvorbis ones so C and
+//! C++ code written against libvorbis can link this crate instead, built as a shared library with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`. The decoder state lives
+//! behind the `codec_setup`, `backend_state` and `internal` pointers which libvorbis also treats
+//! as private. Only the synthesis (decoding) functions are provided.
+//!
+//! Unlike libvorbis the audio packet is decoded in `vorbis_synthesis_blockin()` rather than in
+//! `vorbis_synthesis()`, the latter only checks the packet kind.
+
+#![allow(non_camel_case_types, non_snake_case)]
+
+use std::io::Cursor;
+use std::os::raw::{c_char, c_int, c_long, c_uchar, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::{ptr, slice};
+
+use bitstream::BitReader;
+use decoder::{Decoder, DecoderBuilder};
+
+pub const OV_EFAULT: c_int = -129;
+pub const OV_EINVAL: c_int = -131;
+pub const OV_ENOTVORBIS: c_int = -132;
+pub const OV_EBADHEADER: c_int = -133;
+pub const OV_ENOTAUDIO: c_int = -135;
+pub const OV_EBADPACKET: c_int = -136;
+
+pub type ogg_int64_t = i64;
+
+#[repr(C)]
+pub struct ogg_packet {
+    pub packet: *mut c_uchar,
+    pub bytes: c_long,
+    pub b_o_s: c_long,
+    pub e_o_s: c_long,
+    pub granulepos: ogg_int64_t,
+    pub packetno: ogg_int64_t,
+}
+
+#[repr(C)]
+pub struct oggpack_buffer {
+    pub endbyte: c_long,
+    pub endbit: c_int,
+    pub buffer: *mut c_uchar,
+    pub ptr: *mut c_uchar,
+    pub storage: c_long,
+}
+
+#[repr(C)]
+pub struct vorbis_info {
+    pub version: c_int,
+    pub channels: c_int,
+    pub rate: c_long,
+    pub bitrate_upper: c_long,
+    pub bitrate_nominal: c_long,
+    pub bitrate_lower: c_long,
+    pub bitrate_window: c_long,
+    pub codec_setup: *mut c_void,
+}
+
+#[repr(C)]
+pub struct vorbis_comment {
+    pub user_comments: *mut *mut c_char,
+    pub comment_lengths: *mut c_int,
+    pub comments: c_int,
+    pub vendor: *mut c_char,
+}
+
+#[repr(C)]
+pub struct vorbis_dsp_state {
+    pub analysisp: c_int,
+    pub vi: *mut vorbis_info,
+    pub pcm: *mut *mut f32,
+    pub pcmret: *mut *mut f32,
+    pub pcm_storage: c_int,
+    pub pcm_current: c_int,
+    pub pcm_returned: c_int,
+    pub preextrapolate: c_int,
+    pub eofflag: c_int,
+    pub lW: c_long,
+    pub W: c_long,
+    pub nW: c_long,
+    pub centerW: c_long,
+    pub granulepos: ogg_int64_t,
+    pub sequence: ogg_int64_t,
+    pub glue_bits: ogg_int64_t,
+    pub time_bits: ogg_int64_t,
+    pub floor_bits: ogg_int64_t,
+    pub res_bits: ogg_int64_t,
+    pub backend_state: *mut c_void,
+}
+
+#[repr(C)]
+pub struct vorbis_block {
+    pub pcm: *mut *mut f32,
+    pub opb: oggpack_buffer,
+    pub lW: c_long,
+    pub W: c_long,
+    pub nW: c_long,
+    pub pcmend: c_int,
+    pub mode: c_int,
+    pub eofflag: c_int,
+    pub granulepos: ogg_int64_t,
+    pub sequence: ogg_int64_t,
+    pub vd: *mut vorbis_dsp_state,
+    pub localstore: *mut c_void,
+    pub localtop: c_long,
+    pub localalloc: c_long,
+    pub totaluse: c_long,
+    pub reap: *mut c_void,
+    pub glue_bits: c_long,
+    pub time_bits: c_long,
+    pub floor_bits: c_long,
+    pub res_bits: c_long,
+    pub internal: *mut c_void,
+}
+
+// Behind `vorbis_info::codec_setup`.
+struct InfoState {
+    builder: DecoderBuilder,
+    ident: Vec<u8>,
+    setup: Option<Vec<u8>>,
+}
+
+// Behind `vorbis_dsp_state::backend_state`.
+struct DspState {
+    decoder: Decoder,
+    // Decoded samples of each channel, the first `pcm_returned` are already consumed.
+    pcm: Vec<Vec<f32>>,
+    pcm_ptrs: Vec<*mut f32>,
+}
+
+// Behind `vorbis_block::internal`.
+struct BlockState {
+    packet: Vec<u8>,
+}
+
+// Runs the body of an exported function returning `err` if it panics, unwinding into C is
+// undefined behavior.
+fn guard<R, F: FnOnce() -> R>(err: R, f: F) -> R {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(err)
+}
+
+unsafe fn packet_data<'a>(op: *const ogg_packet) -> Option<&'a [u8]> {
+    if op.is_null() || (*op).bytes < 0 || ((*op).packet.is_null() && (*op).bytes > 0) {
+        None
+    } else if (*op).bytes == 0 {
+        Some(&[])
+    } else {
+        Some(slice::from_raw_parts((*op).packet, (*op).bytes as usize))
+    }
+}
+
+// Allocates a NUL-terminated copy of the bytes. Must be freed with `free_c_string()`.
+fn to_c_string(bytes: &[u8]) -> *mut c_char {
+    let mut v = Vec::with_capacity(bytes.len() + 1);
+    v.extend_from_slice(bytes);
+    v.push(0);
+    Box::into_raw(v.into_boxed_slice()) as *mut c_char
+}
+
+unsafe fn free_c_string(s: *mut c_char, len: usize) {
+    if !s.is_null() {
+        drop(Box::from_raw(slice::from_raw_parts_mut(s as *mut u8, len + 1)));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vorbis_info_init(vi: *mut vorbis_info) {
+    guard((), || {
+        ptr::write_bytes(vi, 0, 1);
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vorbis_info_clear(vi: *mut vorbis_info) {
+    guard((), || {
+        if !(*vi).codec_setup.is_null() {
+            drop(Box::from_raw((*vi).codec_setup as *mut InfoState));
+        }
+        ptr::write_bytes(vi, 0, 1);
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vorbis_comment_init(vc: *mut vorbis_comment) {
+    guard((), || {
+        ptr::write_bytes(vc, 0, 1);
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vorbis_comment_clear(vc: *mut vorbis_comment) {
+    guard((), || {
+        let count = (*vc).comments as usize;
+        if !(*vc).user_comments.is_null() {
+            let comments = Box::from_raw(slice::from_raw_parts_mut((*vc).user_comments, count + 1));
+            let lens = Box::from_raw(slice::from_raw_parts_mut((*vc).comment_lengths, count + 1));
+            for (&s, &len) in comments.iter().zip(lens.iter()).take(count) {
+                free_c_string(s, len as usize);
+            }
+        }
+        if !(*vc).vendor.is_null() {
+            let len = ::std::ffi::CStr::from_ptr((*vc).vendor).to_bytes().len();
+            free_c_string((*vc).vendor, len);
+        }
+        ptr::write_bytes(vc, 0, 1);
+    })
+}
+
+/// Returns 1 if the packet is the identification header, otherwise 0.
+#[no_mangle]
+pub unsafe extern "C" fn vorbis_synthesis_idheader(op: *mut ogg_packet) -> c_int {
+    guard(0, || {
+        match packet_data(op) {
+            Some(data) => (data.len() >= 7 && data[0] == 1 && &data[1..7] == b"vorbis") as c_int,
+            None => 0,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vorbis_synthesis_headerin(vi: *mut vorbis_info, vc: *mut vorbis_comment,
+        op: *mut ogg_packet) -> c_int {
+    guard(OV_EFAULT, || {
+        let data = match packet_data(op) {
+            Some(data) => data,
+            None => return OV_EFAULT,
+        };
+        if data.len() < 7 || &data[1..7] != b"vorbis" {
+            return OV_ENOTVORBIS;
+        }
+        let reader = &mut BitReader::new(Cursor::new(data));
+        match data[0] {
+            1 => {
+                if !(*vi).codec_setup.is_null() {
+                    return OV_EINVAL;
+                }
+                let mut builder = Decoder::builder();
+                if builder.read_ident_packet(reader).is_err() {
+                    return OV_EBADHEADER;
+                }
+                {
+                    let header = builder.header().unwrap();
+                    let bitrates = header.bitrates();
+                    (*vi).version = header.version() as c_int;
+                    (*vi).channels = header.channel_count() as c_int;
+                    (*vi).rate = header.sample_rate() as c_long;
+                    (*vi).bitrate_upper = bitrates.max() as c_long;
+                    (*vi).bitrate_nominal = bitrates.nom() as c_long;
+                    (*vi).bitrate_lower = bitrates.min() as c_long;
+                    (*vi).bitrate_window = 0;
+                }
+                (*vi).codec_setup = Box::into_raw(Box::new(InfoState {
+                    builder: builder,
+                    ident: data.to_vec(),
+                    setup: None,
+                })) as *mut c_void;
+                0
+            }
+            3 => {
+                if (*vi).codec_setup.is_null() || !(*vc).vendor.is_null() {
+                    return OV_EBADHEADER;
+                }
+                let state = &mut *((*vi).codec_setup as *mut InfoState);
+                if state.builder.read_comment_packet(reader).is_err() {
+                    return OV_EBADHEADER;
+                }
+                let comments = state.builder.comments().unwrap();
+                let count = comments.len();
+                let mut strings = Vec::with_capacity(count + 1);
+                let mut lens = Vec::with_capacity(count + 1);
+                for c in comments.raw() {
+                    strings.push(to_c_string(c));
+                    lens.push(c.len() as c_int);
+                }
+                strings.push(ptr::null_mut());
+                lens.push(0);
+                (*vc).user_comments = Box::into_raw(strings.into_boxed_slice()) as *mut *mut c_char;
+                (*vc).comment_lengths = Box::into_raw(lens.into_boxed_slice()) as *mut c_int;
+                (*vc).comments = count as c_int;
+                // Truncated at NUL so the length can be restored when freeing.
+                let vendor = comments.vendor_raw();
+                let vendor_len = vendor.iter().position(|&b| b == 0).unwrap_or(vendor.len());
+                (*vc).vendor = to_c_string(&vendor[..vendor_len]);
+                0
+            }
+            5 => {
+                if (*vi).codec_setup.is_null() || (*vc).vendor.is_null() {
+                    return OV_EBADHEADER;
+                }
+                let state = &mut *((*vi).codec_setup as *mut InfoState);
+                if state.setup.is_some() || state.builder.read_setup_packet(reader).is_err() {
+                    return OV_EBADHEADER;
+                }
+                state.setup = Some(data.to_vec());
+                0
+            }
+            _ => OV_EBADHEADER,
+        }
+    })
+}
+
+/// Builds the decoder from the headers read into the `vi`. Returns 1 if the headers weren't
+/// read.
+#[no_mangle]
+pub unsafe extern "C" fn vorbis_synthesis_init(v: *mut vorbis_dsp_state, vi: *mut vorbis_info) -> c_int {
+    guard(1, || {
+        ptr::write_bytes(v, 0, 1);
+        if (*vi).codec_setup.is_null() {
+            return 1;
+        }
+        let state = &*((*vi).codec_setup as *const InfoState);
+        let setup = match state.setup {
+            Some(ref setup) => setup,
+            None => return 1,
+        };
+        // The builder in `vi` is kept so `vi` can be used for another decoder.
+        let mut builder = Decoder::builder();
+        if builder.read_ident_packet(&mut BitReader::new(Cursor::new(&state.ident[..]))).is_err() ||
+                builder.read_setup_packet(&mut BitReader::new(Cursor::new(&setup[..]))).is_err() {
+            return 1;
+        }
+        let decoder = builder.build();
+        // Not `(*vi).channels` which the caller may have changed.
+        let channel_count = decoder.header().channel_count();
+        let backend = Box::new(DspState {
+            decoder: decoder,
+            pcm: vec![Vec::new(); channel_count],
+            pcm_ptrs: vec![ptr::null_mut(); channel_count],
+        });
+        (*v).vi = vi;
+        (*v).backend_state = Box::into_raw(backend) as *mut c_void;
+        0
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vorbis_dsp_clear(v: *mut vorbis_dsp_state) {
+    guard((), || {
+        if !(*v).backend_state.is_null() {
+            drop(Box::from_raw((*v).backend_state as *mut DspState));
+        }
+        ptr::write_bytes(v, 0, 1);
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vorbis_synthesis_restart(v: *mut vorbis_dsp_state) -> c_int {
+    guard(-1, || {
+        if (*v).backend_state.is_null() {
+            return -1;
+        }
+        let state = &mut *((*v).backend_state as *mut DspState);
+        state.decoder.reset();
+        for c in &mut state.pcm {
+            c.clear();
+        }
+        (*v).pcm_current = 0;
+        (*v).pcm_returned = 0;
+        (*v).granulepos = -1;
+        (*v).sequence = -1;
+        0
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vorbis_block_init(v: *mut vorbis_dsp_state, vb: *mut vorbis_block) -> c_int {
+    guard(OV_EFAULT, || {
+        ptr::write_bytes(vb, 0, 1);
+        (*vb).vd = v;
+        (*vb).internal = Box::into_raw(Box::new(BlockState { packet: Vec::new() })) as *mut c_void;
+        0
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vorbis_block_clear(vb: *mut vorbis_block) -> c_int {
+    guard(OV_EFAULT, || {
+        if !(*vb).internal.is_null() {
+            drop(Box::from_raw((*vb).internal as *mut BlockState));
+        }
+        ptr::write_bytes(vb, 0, 1);
+        0
+    })
+}
+
+/// Takes the audio packet for decoding in `vorbis_synthesis_blockin()`.
+#[no_mangle]
+pub unsafe extern "C" fn vorbis_synthesis(vb: *mut vorbis_block, op: *mut ogg_packet) -> c_int {
+    guard(OV_EFAULT, || {
+        if (*vb).internal.is_null() {
+            return OV_EINVAL;
+        }
+        let data = match packet_data(op) {
+            Some(data) => data,
+            None => return OV_EFAULT,
+        };
+        if data.is_empty() || data[0] & 1 != 0 {
+            return OV_ENOTAUDIO;
+        }
+        let state = &mut *((*vb).internal as *mut BlockState);
+        state.packet.clear();
+        state.packet.extend_from_slice(data);
+        (*vb).granulepos = (*op).granulepos;
+        (*vb).sequence = (*op).packetno;
+        (*vb).eofflag = (*op).e_o_s as c_int;
+        0
+    })
+}
+
+/// Decodes the packet of the block and appends the samples to the ones available with
+/// `vorbis_synthesis_pcmout()`.
+#[no_mangle]
+pub unsafe extern "C" fn vorbis_synthesis_blockin(v: *mut vorbis_dsp_state, vb: *mut vorbis_block) -> c_int {
+    guard(OV_EFAULT, || {
+        if (*v).backend_state.is_null() || (*vb).internal.is_null() {
+            return OV_EINVAL;
+        }
+        let state = &mut *((*v).backend_state as *mut DspState);
+        let block = &*((*vb).internal as *const BlockState);
+
+        // Drop the consumed samples.
+        let returned = (*v).pcm_returned as usize;
+        for c in &mut state.pcm {
+            c.drain(..returned);
+        }
+        // Keep the counters in sync with the samples even if the decoding fails.
+        (*v).pcm_current = state.pcm[0].len() as c_int;
+        (*v).pcm_returned = 0;
+
+        let samples = match state.decoder.decode(&mut BitReader::new(Cursor::new(&block.packet[..]))) {
+            Ok(samples) => samples,
+            Err(_) => return OV_EBADPACKET,
+        };
+        for (pcm, channel) in state.pcm.iter_mut().zip(samples.channels()) {
+            pcm.extend(channel.iter().map(|&s| s as f32));
+        }
+
+        (*v).pcm_current = state.pcm[0].len() as c_int;
+        (*v).pcm_returned = 0;
+        (*v).granulepos = (*vb).granulepos;
+        (*v).sequence = (*vb).sequence;
+        (*v).eofflag = (*vb).eofflag;
+        0
+    })
+}
+
+/// Returns the number of available samples per channel and optionally the pointers to
+/// the channels' samples in `pcm`.
+#[no_mangle]
+pub unsafe extern "C" fn vorbis_synthesis_pcmout(v: *mut vorbis_dsp_state, pcm: *mut *mut *mut f32) -> c_int {
+    guard(0, || {
+        if (*v).backend_state.is_null() {
+            return 0;
+        }
+        let state = &mut *((*v).backend_state as *mut DspState);
+        let returned = (*v).pcm_returned as usize;
+        let available = (*v).pcm_current - (*v).pcm_returned;
+        if !pcm.is_null() && available > 0 {
+            for (p, c) in state.pcm_ptrs.iter_mut().zip(state.pcm.iter_mut()) {
+                *p = c[returned..].as_mut_ptr();
+            }
+            (*v).pcmret = state.pcm_ptrs.as_mut_ptr();
+            *pcm = (*v).pcmret;
+        }
+        available
+    })
+}
+
+/// Marks the `samples` returned by `vorbis_synthesis_pcmout()` as consumed.
+#[no_mangle]
+pub unsafe extern "C" fn vorbis_synthesis_read(v: *mut vorbis_dsp_state, samples: c_int) -> c_int {
+    guard(OV_EFAULT, || {
+        match (*v).pcm_returned.checked_add(samples) {
+            Some(end) if samples >= 0 && (*v).pcm_returned >= 0 && end <= (*v).pcm_current => {
+                (*v).pcm_returned = end;
+                0
+            }
+            _ => OV_EINVAL,
+        }
+    })
+}
+
+/// Returns the number of samples the audio packet decodes to, or a negative error code.
+#[no_mangle]
+pub unsafe extern "C" fn vorbis_packet_blocksize(vi: *mut vorbis_info, op: *mut ogg_packet) -> c_long {
+    guard(OV_EFAULT as c_long, || {
+        if (*vi).codec_setup.is_null() {
+            return OV_EFAULT as c_long;
+        }
+        let state = &*((*vi).codec_setup as *const InfoState);
+        let data = match packet_data(op) {
+            Some(data) if !data.is_empty() && data[0] & 1 == 0 => data,
+            _ => return OV_ENOTAUDIO as c_long,
+        };
+        let setup = match state.builder.setup() {
+            Some(setup) => setup,
+            None => return OV_EFAULT as c_long,
+        };
+        let header = state.builder.header().unwrap();
+        match setup.packet_frame_kind(&mut BitReader::new(Cursor::new(data))) {
+            Ok(kind) => header.frame_lens().get(kind) as c_long,
+            Err(_) => OV_EBADPACKET as c_long,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use super::*;
+
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    #[test]
+    fn layout() {
+        // The sizes of the libvorbis structs on LP64 targets.
+        assert_eq!(mem::size_of::<ogg_packet>(), 48);
+        assert_eq!(mem::size_of::<vorbis_info>(), 56);
+        assert_eq!(mem::size_of::<vorbis_comment>(), 32);
+        assert_eq!(mem::size_of::<vorbis_dsp_state>(), 144);
+        assert_eq!(mem::size_of::<vorbis_block>(), 192);
+    }
+
+    #[test]
+    fn headerin_rejects_garbage() {
+        unsafe {
+            let mut vi = mem::zeroed();
+            let mut vc = mem::zeroed();
+            vorbis_info_init(&mut vi);
+            vorbis_comment_init(&mut vc);
+
+            let mut data = *b"\x01vorbiz";
+            let mut op = ogg_packet {
+                packet: data.as_mut_ptr(),
+                bytes: data.len() as c_long,
+                b_o_s: 1,
+                e_o_s: 0,
+                granulepos: 0,
+                packetno: 0,
+            };
+            assert_eq!(vorbis_synthesis_idheader(&mut op), 0);
+            assert_eq!(vorbis_synthesis_headerin(&mut vi, &mut vc, &mut op), OV_ENOTVORBIS);
+
+            data[6] = b's';
+            assert_eq!(vorbis_synthesis_idheader(&mut op), 1);
+            assert_eq!(vorbis_synthesis_headerin(&mut vi, &mut vc, &mut op), OV_EBADHEADER);
+            assert!(vi.codec_setup.is_null());
+
+            // The comment header before the ident one.
+            data[0] = 3;
+            assert_eq!(vorbis_synthesis_headerin(&mut vi, &mut vc, &mut op), OV_EBADHEADER);
+
+            vorbis_comment_clear(&mut vc);
+            vorbis_info_clear(&mut vi);
+        }
+    }
+}
@@ -1,9 +1,12 @@
-use num::FromPrimitive;
+use std::cmp;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
 
 use bitstream::BitRead;
 use codebook::Codebook;
 use error::{Error, ErrorKind, ExpectEof, Result};
-use util::Bits;
+use sample::Sample;
+use util::{Bits, FromPrimitive};
 
 enum_from_primitive! {
 #[derive(Clone, Copy, Debug)]
@@ -12,41 +15,69 @@ pub enum FloorKind {
     Floor1 = 1,
 }}
 
+/// Floor 1 configuration as read from the setup header.
 #[derive(Debug)]
 pub struct Floor {
     mult: u8,
     range: u16,
     // [0..15]{1..31}.
     part_classes: Box<[usize]>,
-    classes: Box<[Class]>,
-    pub x_list: Box<[u16]>,
+    classes: Box<[FloorClass]>,
+    x_list: Box<[u16]>,
     sorted_x_list: Box<[(usize, u16)]>,
     neighbors: Box<[(usize, usize)]>,
 }
 
+/// Floor 1 partition class.
 #[derive(Debug)]
-struct Class {
+pub struct FloorClass {
     dim_count: usize,
     subclass_bit_count: usize,
     master_book: Option<usize>,
     subclass_books: Box<[Option<usize>]>,
 }
 
+impl FloorClass {
+    /// Returns the number of X list elements each partition of this class contributes.
+    pub fn dim_count(&self) -> usize {
+        self.dim_count
+    }
+
+    /// Returns the number of subclass bits, the subclass count is `1 << subclass_bit_count()`.
+    pub fn subclass_bit_count(&self) -> usize {
+        self.subclass_bit_count
+    }
+
+    /// Returns the codebook index used to decode subclass selectors, if there are any subclasses.
+    pub fn master_book(&self) -> Option<usize> {
+        self.master_book
+    }
+
+    /// Returns the codebook index for each subclass or `None` for subclasses with Y values
+    /// implicitly equal to zero.
+    pub fn subclass_books(&self) -> &[Option<usize>] {
+        &self.subclass_books
+    }
+}
+
 impl Floor {
     pub fn read<R: BitRead>(reader: &mut R, codebooks_len: usize) -> Result<Self> {
+        trace_field!(reader, "floor.kind");
         match FloorKind::from_u16(try!(reader.read_u16())) {
-            Some(FloorKind::Floor0) => return Err(Error::Undecodable("Floor 0 is not supported")),
+            Some(FloorKind::Floor0) => return Err(Error::Floor0Unsupported),
             Some(FloorKind::Floor1) => {},
-            None => return Err(Error::Undecodable("Unsupported floor type")),
+            None => return Err(Error::InvalidFloorKind),
         }
 
+        trace_field!(reader, "floor.partition_count");
         let part_count = try!(reader.read_u32_bits(5)) as usize;
         if part_count == 0 {
-            return Err(Error::Undecodable("Invalid floor partition count"));
+            return Err(Error::InvalidFloorPartitionCount);
         }
         let mut part_classes = Vec::with_capacity(part_count);
         let mut max_class = -1;
         for _ in 0..part_count {
+            trace_field!(reader, "floor.partition_class");
             let part_class = try!(reader.read_u8_bits(4));
             if part_class as i8 > max_class {
                 max_class = part_class as i8;
@@ -57,13 +88,16 @@ impl Floor {
         let class_count = max_class as usize + 1;
         let mut classes = Vec::with_capacity(max_class as usize + 1);
         for _ in 0..class_count {
+            trace_field!(reader, "floor.class_dim_count");
             let dim_count = try!(reader.read_u8_bits(3)) as usize + 1;
 
+            trace_field!(reader, "floor.subclass_bits");
             let subclass_bit_count = try!(reader.read_u8_bits(2)) as usize;
             let master_book = if subclass_bit_count != 0 {
+                trace_field!(reader, "floor.master_book");
                 let master_book = try!(reader.read_u8()) as usize;
                 if master_book >= codebooks_len {
-                    return Err(Error::Undecodable("Invalid codebook index in floor class master book"));
+                    return Err(Error::InvalidFloorCodebook);
                 }
                 Some(master_book)
             } else {
@@ -73,13 +107,13 @@ impl Floor {
             let subclass_books_count = 1 << subclass_bit_count;
             let mut subclass_books = Vec::with_capacity(subclass_books_count);
             for _ in 0..subclass_books_count {
+                trace_field!(reader, "floor.subclass_book");
                 let subclass_book = match try!(reader.read_u8()) as usize {
                     0 => None,
                     classbook_idx => {
                         let classbook_idx = classbook_idx - 1;
                         if classbook_idx >= codebooks_len {
-                            return Err(Error::Undecodable(
-                                "Invalid codebook index in floor subclass books"));
+                            return Err(Error::InvalidFloorCodebook);
                         }
                         Some(classbook_idx)
                     },
@@ -87,7 +121,7 @@ impl Floor {
                 subclass_books.push(subclass_book);
             }
 
-            classes.push(Class {
+            classes.push(FloorClass {
                 dim_count: dim_count,
                 subclass_bit_count: subclass_bit_count,
                 master_book: master_book,
@@ -95,17 +129,20 @@ impl Floor {
             })
         }
 
+        trace_field!(reader, "floor.multiplier");
         let mult = try!(reader.read_u8_bits(2)) + 1;
         let range = [256, 128, 86, 64][mult as usize - 1];
+        trace_field!(reader, "floor.range_bits");
         let rangebits = try!(reader.read_u8_bits(4)) as usize;
         let mut x_list = Vec::with_capacity(65);
         x_list.push(0);
         x_list.push(1 << rangebits);
         for &part_class in &part_classes {
             for _ in 0..classes[part_class].dim_count {
+                trace_field!(reader, "floor.x");
                 let x = try!(reader.read_u16_bits(rangebits));
                 if x_list.len() >= 65 {
-                    return Err(Error::Undecodable("Too many elements in floor X list"));
+                    return Err(Error::FloorXListTooLong);
                 }
                 x_list.push(x);
             }
@@ -119,7 +156,7 @@ impl Floor {
             let mut last = sorted_x_list[0].1;
             for &x in sorted_x_list.iter().skip(1) {
                 if x.1 == last {
-                    return Err(Error::Undecodable("Floor X list contains duplicates"));
+                    return Err(Error::InvalidFloorXList);
                 }
                 last = x.1;
             }
@@ -142,21 +179,47 @@ impl Floor {
         })
     }
 
+    /// Returns the Y value multiplier (`1..4`).
+    pub fn mult(&self) -> u8 {
+        self.mult
+    }
+
+    /// Returns the range of Y values as implied by the multiplier.
+    pub fn range(&self) -> u16 {
+        self.range
+    }
+
+    /// Returns the class index for each partition.
+    pub fn part_classes(&self) -> &[usize] {
+        &self.part_classes
+    }
+
+    pub fn classes(&self) -> &[FloorClass] {
+        &self.classes
+    }
+
+    /// Returns the X list in the order it's stored in the setup header (not sorted).
+    pub fn x_list(&self) -> &[u16] {
+        &self.x_list
+    }
+
+    /// Decodes the floor Y list. Returns `false` if the end of packet was reached, in this case
+    /// the Y list is empty (the channel is unused) as required by the spec.
     pub fn begin_decode<R: BitRead>(
                 &self,
                 result_y_list: &mut Vec<(u16, bool)>,
                 reader: &mut R,
-                codebooks: &[Codebook]) -> Result<()> {
+                codebooks: &[Codebook]) -> Result<bool> {
         match self.do_begin_decode(result_y_list, reader, codebooks).expect_eof() {
             Err(ref e) if e.kind() == ErrorKind::ExpectedEof => {
                 result_y_list.truncate(0);
-                Ok(())
+                Ok(false)
             },
-            r @ _ => r,
+            r @ _ => r.map(|_| true),
         }
     }
 
-    pub fn finish_decode(&self, result: &mut [f32], y_list: &[(u16, bool)]) {
+    pub fn finish_decode(&self, result: &mut [Sample], y_list: &[(u16, bool)]) {
         let mut hx = 0_i32;
         let mut hy = 0_i32;
         let mut lx = 0_i32;
@@ -178,6 +241,66 @@ impl Floor {
         }
     }
 
+    /// Computes the values coded in the packet for the Y values of the `y_list` (in the X list
+    /// order), the inverse of the amplitude decoding. The `y_list` flags are set the same way the
+    /// decoder sets them so the list can be passed to `finish_decode()` to get the decoded floor
+    /// curve. The Y values must be less than `range()`.
+    #[cfg(feature = "encoder")]
+    pub fn encode_amplitude(&self, y_list: &mut [(u16, bool)], coded: &mut Vec<u16>) {
+        assert_eq!(y_list.len(), self.x_list.len());
+        coded.truncate(0);
+        for y in y_list.iter_mut() {
+            debug_assert!(y.0 < self.range);
+            y.1 = true;
+        }
+        coded.push(y_list[0].0);
+        coded.push(y_list[1].0);
+        for i in 2..y_list.len() {
+            let (low_neighbor, high_neighbor) = self.neighbors[i - 2];
+            let predicted = Self::render_point(
+                    self.x_list[low_neighbor] as i32,
+                    y_list[low_neighbor].0 as i32,
+                    self.x_list[high_neighbor] as i32,
+                    y_list[high_neighbor].0 as i32,
+                    self.x_list[i] as i32);
+            let high_room = self.range as i32 - predicted;
+            let low_room = predicted;
+            let min_room = cmp::min(high_room, low_room);
+            let d = y_list[i].0 as i32 - predicted;
+            let y = if d >= 0 && d < min_room {
+                d * 2
+            } else if d < 0 && -d <= min_room {
+                -d * 2 - 1
+            } else if high_room > low_room {
+                d + low_room
+            } else {
+                high_room - 1 - d
+            };
+            if y != 0 {
+                y_list[low_neighbor].1 = true;
+                y_list[high_neighbor].1 = true;
+            } else {
+                y_list[i].1 = false;
+            }
+            coded.push(y as u16);
+        }
+    }
+
+    /// Returns the Y value (less than `range()`) of the floor curve point closest to the
+    /// `amplitude` on the dB scale.
+    #[cfg(feature = "encoder")]
+    pub fn amplitude_to_y(&self, amplitude: Sample) -> u16 {
+        let mult = self.mult as i32;
+        let y = (0..self.range as i32)
+            .position(|y| Self::inverse_db(y * mult) >= amplitude)
+            .unwrap_or(self.range as usize - 1) as i32;
+        if y > 0 && Self::inverse_db(y * mult) / amplitude > amplitude / Self::inverse_db((y - 1) * mult) {
+            y as u16 - 1
+        } else {
+            y as u16
+        }
+    }
+
     fn do_begin_decode<R: BitRead>(
                 &self,
                 result_y_list: &mut Vec<(u16, bool)>,
@@ -185,12 +308,14 @@ impl Floor {
                 codebooks: &[Codebook]) -> Result<()> {
         result_y_list.truncate(0);
 
+        trace_field!(reader, "floor.nonzero");
         let non_zero = try!(reader.read_bool());
         if !non_zero {
             return Ok(());
         }
 
         let len_bits = (self.range - 1).ilog() as usize;
+        trace_field!(reader, "floor.y");
         result_y_list.push((try!(reader.read_u16_bits(len_bits)), true));
         result_y_list.push((try!(reader.read_u16_bits(len_bits)), true));
         for &part_class in self.part_classes.iter() {
@@ -201,6 +326,7 @@ impl Floor {
             let mut cval = if cbits > 0 {
                 let codebook_idx = class.master_book.unwrap();
                 let codebook = &codebooks[codebook_idx];
+                trace_field!(reader, "floor.class_codeword");
                 try!(codebook.decode_scalar(reader)) as usize
             } else {
                 0
@@ -208,6 +334,7 @@ impl Floor {
             for _ in 0..class.dim_count {
                 let codebook = class.subclass_books[cval & csub].map(|i| &codebooks[i]);
                 cval >>= cbits;
+                trace_field!(reader, "floor.y");
                 let y = try!(codebook.map(|c| c.decode_scalar(reader)).unwrap_or(Ok(0)));
                 result_y_list.push((y as u16, true));
             }
@@ -257,7 +384,8 @@ impl Floor {
                 result_y_list[i].1 = false;
                 predicted
             };
-            result_y_list[i].0 = final_y as u16;
+            // Valid streams never produce values outside the range but malformed ones can.
+            result_y_list[i].0 = cmp::max(0, cmp::min(final_y, self.range as i32 - 1)) as u16;
         }
     }
 
@@ -301,7 +429,14 @@ impl Floor {
         }
     }
 
-    fn render_line(result: &mut [f32], x0: i32, y0: i32, x1: i32, y1: i32) {
+    /// Renders the line multiplying the `result` values. The part of the line beyond the
+    /// `result` bounds is ignored.
+    fn render_line(result: &mut [Sample], x0: i32, y0: i32, x1: i32, y1: i32) {
+        let len = result.len() as i32;
+        if x0 >= len {
+            return;
+        }
+
         let dy = y1 - y0;
         let adx = x1 - x0;
         let base = dy / adx;
@@ -312,11 +447,11 @@ impl Floor {
             base + 1
         };
 
-        result[x0 as usize] *= INVERSE_DB_TABLE[y0 as usize];
+        result[x0 as usize] *= Self::inverse_db(y0);
 
         let mut y = y0;
         let mut err = 0;
-        for x in x0 + 1..x1 {
+        for x in x0 + 1..cmp::min(x1, len) {
             err += ady;
             if err >= adx {
                 err -= adx;
@@ -324,12 +459,18 @@ impl Floor {
             } else {
                 y += base;
             }
-            result[x as usize] *= INVERSE_DB_TABLE[y as usize];
+            result[x as usize] *= Self::inverse_db(y);
         }
     }
+
+    #[inline]
+    fn inverse_db(y: i32) -> Sample {
+        INVERSE_DB_TABLE[cmp::max(0, cmp::min(y, INVERSE_DB_TABLE.len() as i32 - 1)) as usize]
+    }
 }
 
-const INVERSE_DB_TABLE: [f32; 256] = [
+// The values of the spec, parsed with the full precision of the `Sample` type.
+const INVERSE_DB_TABLE: [Sample; 256] = [
     1.0649863E-07, 1.1341951e-07, 1.2079015e-07, 1.2863978e-07,
     1.3699951e-07, 1.4590251e-07, 1.5538408e-07, 1.6548181e-07,
     1.7623575e-07, 1.8768855e-07, 1.9988561e-07, 2.1287530e-07,
@@ -394,4 +535,60 @@ const INVERSE_DB_TABLE: [f32; 256] = [
     0.50028648,    0.53279791,    0.56742212,    0.60429640,
     0.64356699,    0.68538959,    0.72993007,    0.77736504,
     0.82788260,    0.88168307,    0.9389798,     1.0
-];
\ No newline at end of file
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_line() {
+        let mut r = [1.0; 4];
+        Floor::render_line(&mut r, 0, 255, 4, 255);
+        assert_eq!(r, [1.0; 4]);
+
+        let mut r = [1.0; 4];
+        Floor::render_line(&mut r, 2, 0, 3, 0);
+        assert_eq!(r, [1.0, 1.0, Floor::inverse_db(0), 1.0]);
+    }
+
+    #[cfg(feature = "encoder")]
+    #[test]
+    fn encode_amplitude() {
+        let x_list = [0, 128, 64, 32, 96, 16, 112];
+        let floor = Floor {
+            mult: 2,
+            range: 128,
+            part_classes: Vec::new().into_boxed_slice(),
+            classes: Vec::new().into_boxed_slice(),
+            x_list: x_list.to_vec().into_boxed_slice(),
+            sorted_x_list: Vec::new().into_boxed_slice(),
+            neighbors: (2..x_list.len()).map(|i| Floor::find_neighbors(&x_list, i))
+                .collect::<Vec<_>>().into_boxed_slice(),
+        };
+        for ys in &[[10, 20, 15, 0, 127, 14, 90], [127, 0, 63, 127, 0, 127, 0], [5, 5, 5, 5, 5, 5, 5]] {
+            let mut y_list: Vec<_> = ys.iter().map(|&y| (y, false)).collect();
+            let mut coded = Vec::new();
+            floor.encode_amplitude(&mut y_list, &mut coded);
+
+            let mut decoded: Vec<_> = coded.iter().map(|&y| (y, true)).collect();
+            floor.decode_amplitude(&mut decoded);
+            assert_eq!(decoded, y_list);
+        }
+    }
+
+    #[test]
+    fn render_line_out_of_bounds() {
+        let mut r = [1.0; 4];
+        Floor::render_line(&mut r, 0, 255, 100, 1000);
+        assert_eq!(r, [1.0; 4]);
+
+        let mut r = [1.0; 4];
+        Floor::render_line(&mut r, 1, -10, 2, -10);
+        assert_eq!(r, [1.0, Floor::inverse_db(0), 1.0, 1.0]);
+
+        let mut r = [1.0; 4];
+        Floor::render_line(&mut r, 4, 0, 10, 0);
+        assert_eq!(r, [1.0; 4]);
+    }
+}
@@ -0,0 +1,105 @@
+use std::ops::{Index, IndexMut};
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+use std::slice;
+
+use sample::Sample;
+
+/// Sample buffers of all channels of a frame stored contiguously: channel after channel,
+/// `stride` samples each.
+#[derive(Debug)]
+pub struct Frame {
+    buf: Box<[Sample]>,
+    stride: usize,
+    channel_count: usize,
+}
+
+impl Frame {
+    pub fn new(channel_count: usize, stride: usize) -> Self {
+        assert!(stride > 0);
+        Frame {
+            buf: vec![0.0; channel_count * stride].into_boxed_slice(),
+            stride: stride,
+            channel_count: channel_count,
+        }
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channel_count
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Returns samples of all channels.
+    pub fn as_slice(&self) -> &[Sample] {
+        &self.buf
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [Sample] {
+        &mut self.buf
+    }
+
+    pub fn channels(&self) -> slice::Chunks<Sample> {
+        self.buf.chunks(self.stride)
+    }
+
+    pub fn channels_mut(&mut self) -> slice::ChunksMut<Sample> {
+        self.buf.chunks_mut(self.stride)
+    }
+
+    /// Returns two distinct channels for simultaneous modification.
+    pub fn channel_pair_mut(&mut self, first: usize, second: usize) -> (&mut [Sample], &mut [Sample]) {
+        assert!(first != second && first < self.channel_count && second < self.channel_count);
+        let stride = self.stride;
+        if first < second {
+            let (l, r) = self.buf.split_at_mut(second * stride);
+            (&mut l[first * stride..(first + 1) * stride], &mut r[..stride])
+        } else {
+            let (l, r) = self.buf.split_at_mut(first * stride);
+            (&mut r[..stride], &mut l[second * stride..(second + 1) * stride])
+        }
+    }
+}
+
+impl Index<usize> for Frame {
+    type Output = [Sample];
+
+    fn index(&self, channel: usize) -> &[Sample] {
+        assert!(channel < self.channel_count);
+        &self.buf[channel * self.stride..(channel + 1) * self.stride]
+    }
+}
+
+impl IndexMut<usize> for Frame {
+    fn index_mut(&mut self, channel: usize) -> &mut [Sample] {
+        assert!(channel < self.channel_count);
+        &mut self.buf[channel * self.stride..(channel + 1) * self.stride]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels() {
+        let mut frame = Frame::new(3, 2);
+        for (i, c) in frame.channels_mut().enumerate() {
+            c[0] = i as Sample;
+            c[1] = i as Sample + 0.5;
+        }
+        assert_eq!(frame.channel_count(), 3);
+        assert_eq!(frame.as_slice(), &[0.0, 0.5, 1.0, 1.5, 2.0, 2.5]);
+        assert_eq!(&frame[1], &[1.0, 1.5]);
+
+        {
+            let (a, b) = frame.channel_pair_mut(2, 0);
+            a[0] = 20.0;
+            b[1] = 0.25;
+        }
+        assert_eq!(&frame[0], &[0.0, 0.25]);
+        assert_eq!(&frame[2], &[20.0, 2.5]);
+    }
+}
@@ -0,0 +1,137 @@
+use std::cmp;
+use std::ops::Range;
+
+/// Trimming of the decoder output for gapless playback: the priming samples at the beginning
+/// and the padding samples at the end of the stream as signaled by the granule positions of the
+/// container (Ogg) pages.
+///
+/// The encoder pads the source audio to whole frames and the first decoded samples don't
+/// correspond to the source. The first audio page has the granule position lower than the
+/// number of samples decoded from the packets ending on the page, the difference is the
+/// number of priming samples to discard. The last page has the granule position lower than the
+/// number of samples decoded up to its end, the difference is the padding. Playing the trimmed
+/// outputs of consecutive tracks encoded from a single source reproduces the source without
+/// gaps.
+///
+/// The positions here are the sample positions of the decoder output (`Decoder::pos()`) counting
+/// from the first audio packet.
+///
+/// # Example
+///
+/// ```rust
+/// use vorbis::GaplessTrim;
+///
+/// // 1024 samples were decoded from the packets of the first audio page with granule
+/// // position 400, and the last page has granule position 44100.
+/// let mut trim = GaplessTrim::new(1024, 400);
+/// trim.set_last_granule_pos(44100);
+/// assert_eq!(trim.priming_samples(), 624);
+/// assert_eq!(trim.len(), Some(44100));
+/// assert_eq!(trim.padding_samples(45000), 276);
+///
+/// // The decoder returned 1024 samples at position 0: only the last 400 are played.
+/// assert_eq!(trim.trim(0, 1024), 624..1024);
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GaplessTrim {
+    priming: u64,
+    // Decoder position of the granule position zero: equals `priming` unless the stream starts
+    // after zero, negative then.
+    granule_offset: i64,
+    end: Option<u64>,
+}
+
+impl GaplessTrim {
+    /// Creates the trim from the number of samples decoded from the audio packets ending on the
+    /// first audio page and the granule position of the page. If the granule position is
+    /// greater than the sample count, the stream starts at a non-zero position (e.g. it was cut
+    /// from a live stream) and there are no priming samples.
+    pub fn new(first_page_sample_count: u64, first_granule_pos: u64) -> Self {
+        GaplessTrim {
+            priming: first_page_sample_count.saturating_sub(first_granule_pos),
+            granule_offset: first_page_sample_count as i64 - first_granule_pos as i64,
+            end: None,
+        }
+    }
+
+    /// Sets the granule position of the last page of the stream which determines the end of
+    /// the track. Since the last page is only known after reading the whole stream, the end is
+    /// unknown (nothing is trimmed at the end) until this is set.
+    pub fn set_last_granule_pos(&mut self, last_granule_pos: u64) -> &mut Self {
+        let end = cmp::max(last_granule_pos as i64 + self.granule_offset, 0) as u64;
+        self.end = Some(cmp::max(end, self.priming));
+        self
+    }
+
+    /// Returns the number of samples to discard at the beginning of the decoder output.
+    pub fn priming_samples(&self) -> u64 {
+        self.priming
+    }
+
+    /// Returns the number of samples to discard at the end of the `sample_count` samples
+    /// decoded from the whole stream. Returns zero if the end is unknown.
+    pub fn padding_samples(&self, sample_count: u64) -> u64 {
+        self.end.map(|end| sample_count.saturating_sub(end)).unwrap_or(0)
+    }
+
+    /// Returns the decoder position where the track ends or `None` if the end is unknown.
+    pub fn end(&self) -> Option<u64> {
+        self.end
+    }
+
+    /// Returns the number of samples of the track after trimming or `None` if the end is
+    /// unknown.
+    pub fn len(&self) -> Option<u64> {
+        self.end.map(|end| end - self.priming)
+    }
+
+    /// Returns the range of `len` samples decoded at the position `pos` (i.e. `Decoder::pos()`
+    /// before decoding them) to play. The range is empty if all the samples are trimmed.
+    pub fn trim(&self, pos: u64, len: usize) -> Range<usize> {
+        let start = cmp::min(self.priming.saturating_sub(pos), len as u64) as usize;
+        let end = match self.end {
+            Some(end) => cmp::min(end.saturating_sub(pos), len as u64) as usize,
+            None => len,
+        };
+        start..cmp::max(start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gapless_trim() {
+        let mut t = GaplessTrim::new(1600, 1000);
+        assert_eq!(t.priming_samples(), 600);
+        assert_eq!(t.end(), None);
+        assert_eq!(t.padding_samples(5000), 0);
+        assert_eq!(t.trim(0, 512), 512..512);
+        assert_eq!(t.trim(512, 512), 88..512);
+        assert_eq!(t.trim(100000, 512), 0..512);
+
+        t.set_last_granule_pos(4000);
+        assert_eq!(t.end(), Some(4600));
+        assert_eq!(t.len(), Some(4000));
+        assert_eq!(t.padding_samples(5000), 400);
+        assert_eq!(t.trim(4096, 1024), 0..504);
+        assert_eq!(t.trim(5000, 100), 0..0);
+    }
+
+    #[test]
+    fn gapless_trim_late_start() {
+        // The stream is cut from the middle: the first page has greater granule position than
+        // samples.
+        let mut t = GaplessTrim::new(1024, 50000);
+        assert_eq!(t.priming_samples(), 0);
+        t.set_last_granule_pos(60000);
+        assert_eq!(t.end(), Some(11024));
+        assert_eq!(t.padding_samples(12000), 976);
+
+        // Invalid end before the start.
+        t.set_last_granule_pos(10000);
+        assert_eq!(t.len(), Some(0));
+        assert_eq!(t.trim(0, 1024), 0..0);
+    }
+}
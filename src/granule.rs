@@ -0,0 +1,168 @@
+use std::ops::Add;
+use std::time::Duration;
+
+use header::Header;
+
+const NONE: u64 = !0;
+
+/// Granule position of a container page: the number of samples (per channel) decoded up to the
+/// end of the last packet finishing on the page.
+///
+/// The all ones value (-1 when read as signed like libogg does) is the sentinel of the pages
+/// where no packet ends. The conversions and the arithmetic keep it, so the positions read from
+/// the pages can be passed around without checking for it first.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use vorbis::{Bitrates, FrameLens, GranulePos, Header};
+///
+/// let header = Header::new(2, 44100, Bitrates::new(0, 128000, 0), FrameLens::new(256, 2048));
+/// let pos = GranulePos::from_samples(88200);
+/// assert_eq!(pos.to_duration(&header), Some(Duration::from_secs(2)));
+/// assert_eq!(GranulePos::from_duration(Duration::from_millis(500), &header).samples(), Some(22050));
+/// assert_eq!((pos + 100).samples_since(pos), Some(100));
+/// assert!((GranulePos::none() + 100).is_none());
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct GranulePos(u64);
+
+impl GranulePos {
+    /// Returns the position of the pages where no packet ends.
+    pub fn none() -> Self {
+        GranulePos(NONE)
+    }
+
+    /// Creates the position from the 64 bits of the page header.
+    pub fn from_raw(raw: u64) -> Self {
+        GranulePos(raw)
+    }
+
+    /// Creates the position after `sample_count` samples (per channel).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sample count is the sentinel value.
+    pub fn from_samples(sample_count: u64) -> Self {
+        assert!(sample_count != NONE, "Sample count is too large");
+        GranulePos(sample_count)
+    }
+
+    /// Returns the position of the first sample at or after the time `pos`, or `none()` if the
+    /// position overflows.
+    pub fn from_duration(pos: Duration, header: &Header) -> Self {
+        let rate = header.sample_rate() as u64;
+        // Rounded up so the positions returned by `to_duration()` convert back exactly.
+        let sub_sec = (pos.subsec_nanos() as u64 * rate + 999_999_999) / 1_000_000_000;
+        pos.as_secs().checked_mul(rate)
+            .and_then(|s| s.checked_add(sub_sec))
+            .map(GranulePos)
+            .unwrap_or(Self::none())
+    }
+
+    /// Returns the 64 bits for the page header.
+    pub fn raw(&self) -> u64 {
+        self.0
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.0 == NONE
+    }
+
+    /// Returns the sample count (per channel) or `None` for `none()`.
+    pub fn samples(&self) -> Option<u64> {
+        if self.is_none() {
+            None
+        } else {
+            Some(self.0)
+        }
+    }
+
+    /// Returns the time of the position, see `Header::duration()`.
+    pub fn to_duration(&self, header: &Header) -> Option<Duration> {
+        self.samples().map(|s| header.duration(s))
+    }
+
+    /// Returns the position `sample_count` samples later, or `None` if it's `none()` or the sum
+    /// overflows.
+    pub fn checked_add(&self, sample_count: u64) -> Option<Self> {
+        self.samples()
+            .and_then(|s| s.checked_add(sample_count))
+            .and_then(|s| if s == NONE { None } else { Some(GranulePos(s)) })
+    }
+
+    /// Returns the number of samples (per channel) from the `earlier` position to this one, or
+    /// `None` if either of them is `none()` or the `earlier` is actually later.
+    pub fn samples_since(&self, earlier: GranulePos) -> Option<u64> {
+        match (self.samples(), earlier.samples()) {
+            (Some(s), Some(e)) => s.checked_sub(e),
+            _ => None,
+        }
+    }
+}
+
+/// Advances the position by the sample count. `none()` stays `none()`.
+///
+/// # Panics
+///
+/// Panics if the sum overflows.
+impl Add<u64> for GranulePos {
+    type Output = GranulePos;
+
+    fn add(self, sample_count: u64) -> GranulePos {
+        if self.is_none() {
+            self
+        } else {
+            self.checked_add(sample_count).expect("Granule position overflow")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use header::{Bitrates, FrameLens};
+    use super::*;
+
+    fn header(sample_rate: u32) -> Header {
+        Header::new(1, sample_rate, Bitrates::new(0, 0, 0), FrameLens::new(256, 2048))
+    }
+
+    #[test]
+    fn none() {
+        let none = GranulePos::from_raw(!0);
+        assert_eq!(none, GranulePos::none());
+        assert!(none.is_none());
+        assert_eq!(none.samples(), None);
+        assert_eq!(none.to_duration(&header(44100)), None);
+        assert_eq!(none + 10, none);
+        assert_eq!(none.checked_add(10), None);
+        assert_eq!(GranulePos::from_samples(10).samples_since(none), None);
+        assert_eq!(none.samples_since(GranulePos::from_samples(0)), None);
+        assert_eq!(GranulePos::from_samples(!0 - 1).checked_add(1), None);
+        assert_eq!(GranulePos::from_duration(Duration::from_secs(!0), &header(44100)), none);
+    }
+
+    #[test]
+    fn duration_round_trip() {
+        for &rate in &[8000, 44100, 48000, 96000] {
+            let header = header(rate);
+            for &s in &[0, 1, 2, 441, rate as u64 - 1, rate as u64, 1 << 40, 123456789] {
+                let pos = GranulePos::from_samples(s);
+                let d = pos.to_duration(&header).unwrap();
+                assert_eq!(GranulePos::from_duration(d, &header), pos);
+            }
+        }
+        // Between the samples.
+        assert_eq!(GranulePos::from_duration(Duration::new(1, 1), &header(100)).samples(), Some(101));
+    }
+
+    #[test]
+    fn arithmetic() {
+        let pos = GranulePos::from_samples(1000);
+        assert_eq!((pos + 24).raw(), 1024);
+        assert_eq!(pos.checked_add(24), Some(GranulePos::from_samples(1024)));
+        assert_eq!((pos + 24).samples_since(pos), Some(24));
+        assert_eq!(pos.samples_since(pos + 24), None);
+    }
+}
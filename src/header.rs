@@ -1,13 +1,16 @@
-use std::ascii::AsciiExt;
-use std::cmp::PartialEq;
-use std::convert::From;
-use std::fmt;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+use std::time::Duration;
 
 use bitstream::BitRead;
-use error::{Error, Result};
+#[cfg(feature = "std")]
+use bitstream::{BitWrite, BitWriter};
+use decoder::PacketKind;
+use error::{Error, Result, Warning};
 
 #[derive(Clone, Debug)]
 pub struct Header {
+    version: u32,
     channel_count: usize,
     sample_rate: u32,
     bitrates: Bitrates,
@@ -15,42 +18,80 @@ pub struct Header {
 }
 
 impl Header {
+    /// Creates version 0 header, for example to write it with `to_packet()`.
+    /// # Panics
+    /// Panics if the channel count isn't in `1..256`, the sample rate is zero or the frame
+    /// lengths aren't powers of two in `64..8193`.
+    pub fn new(channel_count: usize, sample_rate: u32, bitrates: Bitrates, frame_lens: FrameLens)
+            -> Self {
+        assert!(channel_count > 0 && channel_count <= 255, "Invalid channel count");
+        assert!(sample_rate > 0, "Invalid sample rate");
+        for &len in &[frame_lens.short, frame_lens.long] {
+            assert!(len.is_power_of_two() && len >= 64 && len <= 8192, "Invalid frame length");
+        }
+        Header {
+            version: 0,
+            channel_count: channel_count,
+            sample_rate: sample_rate,
+            bitrates: bitrates,
+            frame_lens: frame_lens,
+        }
+    }
+
     pub fn read<R: BitRead>(reader: &mut R) -> Result<Header> {
-        if try!(reader.read_u32()) != 0 {
-            return Err(Error::Undecodable("Unsupported Vorbis version"));
+        Self::do_read(reader, None)
+    }
+
+    /// Same as `read()` but reports the recoverable spec violations to `warnings` instead of
+    /// failing.
+    pub fn read_lenient<R: BitRead>(reader: &mut R, warnings: &mut Vec<Warning>) -> Result<Header> {
+        Self::do_read(reader, Some(warnings))
+    }
+
+    fn do_read<R: BitRead>(reader: &mut R, warnings: Option<&mut Vec<Warning>>) -> Result<Header> {
+        trace_field!(reader, "ident.version");
+        let version = try!(reader.read_u32());
+        if version != 0 {
+            return Err(Error::UnsupportedVersion);
         }
 
+        trace_field!(reader, "ident.channel_count");
         let channel_count = try!(reader.read_u8()) as usize;
         if channel_count == 0 {
-            return Err(Error::Undecodable("Invalid channel count"));
+            return Err(Error::InvalidChannelCount);
         }
 
+        trace_field!(reader, "ident.sample_rate");
         let sample_rate = try!(reader.read_u32());
         if sample_rate == 0 {
-            return Err(Error::Undecodable("Invalid sample rate"));
+            return Err(Error::InvalidSampleRate);
         }
 
+        trace_field!(reader, "ident.bitrate_max");
         let bitrate_max = try!(reader.read_i32());
+        trace_field!(reader, "ident.bitrate_nom");
         let bitrate_nom = try!(reader.read_i32());
+        trace_field!(reader, "ident.bitrate_min");
         let bitrate_min = try!(reader.read_i32());
 
+        trace_field!(reader, "ident.blocksize_0");
         let frame_len_short = 1 << try!(reader.read_u8_bits(4)) as usize;
         if frame_len_short < 64 || frame_len_short > 8192 {
-            return Err(Error::Undecodable("Invalid short frame length"));
+            return Err(Error::InvalidShortFrameLen);
         }
+        trace_field!(reader, "ident.blocksize_1");
         let frame_len_long = 1 << try!(reader.read_u8_bits(4)) as usize;
         if frame_len_long < 64 || frame_len_long > 8192 {
-            return Err(Error::Undecodable("Invalid long frame length"));
+            return Err(Error::InvalidLongFrameLen);
         }
         if frame_len_long < frame_len_short {
-            return Err(Error::Undecodable("Long frame is shorter than short frame"));
+            return Err(Error::LongFrameShorterThanShort);
         }
 
-        if !try!(reader.read_bool()) {
-            return Err(Error::Undecodable("Invalid framing bit"));
-        }
+        try!(PacketKind::Ident.read_framing_bit(reader, warnings));
 
         Ok(Header {
+            version: version,
             channel_count: channel_count,
             sample_rate: sample_rate,
             bitrates: Bitrates {
@@ -65,6 +106,43 @@ impl Header {
         })
     }
 
+    /// Writes the identification header body, the counterpart of `read()`. The packet kind and
+    /// magic value aren't written, see `to_packet()`.
+    #[cfg(feature = "std")]
+    pub fn write<W: BitWrite>(&self, writer: &mut W) -> Result<()> {
+        try!(writer.write_u32(self.version));
+        try!(writer.write_u8(self.channel_count as u8));
+        try!(writer.write_u32(self.sample_rate));
+
+        try!(writer.write_i32(self.bitrates.max));
+        try!(writer.write_i32(self.bitrates.nom));
+        try!(writer.write_i32(self.bitrates.min));
+
+        let (short_exp, long_exp) = self.blocksize_exponents();
+        try!(writer.write_u8_bits(short_exp, 4));
+        try!(writer.write_u8_bits(long_exp, 4));
+
+        try!(writer.write_bool(true));
+        try!(writer.flush_bits());
+        Ok(())
+    }
+
+    /// Serializes into a complete Vorbis identification header packet.
+    #[cfg(feature = "std")]
+    pub fn to_packet(&self) -> Vec<u8> {
+        let mut writer = BitWriter::new(Vec::new());
+        PacketKind::Ident.write(&mut writer)
+            .and_then(|_| self.write(&mut writer))
+            .expect("Writing to Vec failed");
+        writer.into_inner().unwrap()
+    }
+
+    /// Returns the raw `vorbis_version` field. Currently it's always zero since other versions
+    /// are rejected by `read()`.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
     pub fn channel_count(&self) -> usize {
         self.channel_count
     }
@@ -80,6 +158,137 @@ impl Header {
     pub fn frame_lens(&self) -> FrameLens {
         self.frame_lens
     }
+
+    /// Returns the raw `blocksize_0` and `blocksize_1` fields: base 2 logarithms of the short
+    /// and long frame lengths.
+    pub fn blocksize_exponents(&self) -> (u8, u8) {
+        (self.frame_lens.short.trailing_zeros() as u8, self.frame_lens.long.trailing_zeros() as u8)
+    }
+
+    pub fn channel_layout(&self) -> ChannelLayout {
+        ChannelLayout::from_channel_count(self.channel_count)
+    }
+
+    /// Estimates the stream duration from the length of the stream in bytes and the average
+    /// bitrate (see `Bitrates::average()`). Returns `None` if the header doesn't specify enough
+    /// bitrate information. The estimate is rough since the actual bitrate of VBR streams can
+    /// vary a lot and the length usually includes the headers and container overhead.
+    pub fn estimate_duration(&self, stream_len: u64) -> Option<Duration> {
+        self.bitrates.average().map(|bitrate| {
+            let bits = stream_len.saturating_mul(8);
+            let bitrate = bitrate as u64;
+            Duration::new((bits / bitrate) as u64,
+                          ((bits % bitrate) * 1_000_000_000 / bitrate) as u32)
+        })
+    }
+
+    /// Returns the exact duration of `sample_count` samples (per channel). The sample count is
+    /// usually the granule position of the last page as reported by the container.
+    pub fn duration(&self, sample_count: u64) -> Duration {
+        let rate = self.sample_rate as u64;
+        Duration::new(sample_count / rate, ((sample_count % rate) * 1_000_000_000 / rate) as u32)
+    }
+}
+
+/// Speaker position of a channel.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SpeakerPosition {
+    Mono,
+    FrontLeft,
+    FrontCenter,
+    FrontRight,
+    SideLeft,
+    SideRight,
+    RearLeft,
+    RearCenter,
+    RearRight,
+    Lfe,
+}
+
+/// Channel layout as defined by the [channel order] of the Vorbis I specification. Streams with more
+/// than 8 channels have application defined channel order and use `Custom` layout.
+/// [channel order]: https://www.xiph.org/vorbis/doc/Vorbis_I_spec.html#x1-810004.3.9
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChannelLayout {
+    Mono,
+    Stereo,
+    /// Left, center, right.
+    Linear,
+    Quadraphonic,
+    Surround5_0,
+    Surround5_1,
+    Surround6_1,
+    Surround7_1,
+    /// Application defined layout with the specified number of channels.
+    Custom(usize),
+}
+
+impl ChannelLayout {
+    pub fn from_channel_count(channel_count: usize) -> Self {
+        match channel_count {
+            1 => ChannelLayout::Mono,
+            2 => ChannelLayout::Stereo,
+            3 => ChannelLayout::Linear,
+            4 => ChannelLayout::Quadraphonic,
+            5 => ChannelLayout::Surround5_0,
+            6 => ChannelLayout::Surround5_1,
+            7 => ChannelLayout::Surround6_1,
+            8 => ChannelLayout::Surround7_1,
+            n => ChannelLayout::Custom(n),
+        }
+    }
+
+    pub fn channel_count(&self) -> usize {
+        match self {
+            &ChannelLayout::Custom(n) => n,
+            _ => self.positions().len(),
+        }
+    }
+
+    /// Returns speaker positions of the channels in stream order. For `Custom` layout the returned
+    /// slice is empty.
+    pub fn positions(&self) -> &'static [SpeakerPosition] {
+        use self::SpeakerPosition::*;
+        match self {
+            &ChannelLayout::Mono         => &[Mono],
+            &ChannelLayout::Stereo       => &[FrontLeft, FrontRight],
+            &ChannelLayout::Linear       => &[FrontLeft, FrontCenter, FrontRight],
+            &ChannelLayout::Quadraphonic => &[FrontLeft, FrontRight, RearLeft, RearRight],
+            &ChannelLayout::Surround5_0  => &[FrontLeft, FrontCenter, FrontRight, RearLeft, RearRight],
+            &ChannelLayout::Surround5_1  => &[FrontLeft, FrontCenter, FrontRight, RearLeft, RearRight,
+                                              Lfe],
+            &ChannelLayout::Surround6_1  => &[FrontLeft, FrontCenter, FrontRight, SideLeft, SideRight,
+                                              RearCenter, Lfe],
+            &ChannelLayout::Surround7_1  => &[FrontLeft, FrontCenter, FrontRight, SideLeft, SideRight,
+                                              RearLeft, RearRight, Lfe],
+            &ChannelLayout::Custom(_)    => &[],
+        }
+    }
+
+    /// Returns speaker position of the specified channel or `None` if the channel index is out of
+    /// range or the layout is `Custom`.
+    pub fn position(&self, channel: usize) -> Option<SpeakerPosition> {
+        self.positions().get(channel).cloned()
+    }
+
+    /// Returns index of the channel with the specified speaker position.
+    pub fn channel(&self, position: SpeakerPosition) -> Option<usize> {
+        self.positions().iter().position(|&p| p == position)
+    }
+}
+
+/// Kind of the bitrate management as hinted by the bitrate fields. Note the fields are only
+/// hints and don't have to match the actual stream bitrate.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BitrateKind {
+    /// All three fields are set to the same value.
+    Fixed,
+    /// Only the nominal bitrate is set.
+    Average,
+    /// The minimum and/or maximum bitrate is set, the nominal may be set too.
+    Bounded,
+    /// None of the fields is set.
+    Unset,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -90,6 +299,14 @@ pub struct Bitrates {
 }
 
 impl Bitrates {
+    pub fn new(min: i32, nom: i32, max: i32) -> Self {
+        Bitrates {
+            min: min,
+            nom: nom,
+            max: max,
+        }
+    }
+
     pub fn min(&self) -> i32 {
         self.min
     }
@@ -101,6 +318,28 @@ impl Bitrates {
     pub fn max(&self) -> i32 {
         self.max
     }
+
+    /// Returns the kind of the bitrate management. Zero and negative values mean unset.
+    pub fn kind(&self) -> BitrateKind {
+        match (self.min > 0, self.nom > 0, self.max > 0) {
+            (true, true, true) if self.min == self.nom && self.nom == self.max => BitrateKind::Fixed,
+            (false, true, false) => BitrateKind::Average,
+            (false, false, false) => BitrateKind::Unset,
+            _ => BitrateKind::Bounded,
+        }
+    }
+
+    /// Returns the average bitrate: the nominal bitrate if it's set or the mean of the minimum
+    /// and maximum bitrates if both are set. Zero and negative values mean unset.
+    pub fn average(&self) -> Option<u32> {
+        if self.nom > 0 {
+            Some(self.nom as u32)
+        } else if self.min > 0 && self.max > 0 {
+            Some(((self.min as u64 + self.max as u64) / 2) as u32)
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -140,192 +379,76 @@ impl FrameLens {
     }
 }
 
-#[derive(Debug)]
-pub enum CommentTag<'a> {
-    Title,
-    Version,
-    Album,
-    TrackNumber,
-    Artist,
-    Performer,
-    Copyright,
-    License,
-    Organization,
-    Description,
-    Genre,
-    Date,
-    Location,
-    Contact,
-    Isrc,
-    Custom(&'a str),
-}
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
 
-impl<'a> CommentTag<'a> {
-    pub fn normalize(self) -> Self {
-        if let CommentTag::Custom(s) = self {
-            CommentTag::from(s)
-        } else {
-            self
-        }
-    }
-}
-
-impl<'a> AsRef<str> for CommentTag<'a> {
-    fn as_ref(&self) -> &str {
-        match self {
-            &CommentTag::Title        => "TITLE",
-            &CommentTag::Version      => "VERSION",
-            &CommentTag::Album        => "ALBUM",
-            &CommentTag::TrackNumber  => "TRACKNUMBER",
-            &CommentTag::Artist       => "ARTIST",
-            &CommentTag::Performer    => "PERFORMER",
-            &CommentTag::Copyright    => "COPYRIGHT",
-            &CommentTag::License      => "LICENSE",
-            &CommentTag::Organization => "ORGANIZATION",
-            &CommentTag::Description  => "DESCRIPTION",
-            &CommentTag::Genre        => "GENRE",
-            &CommentTag::Date         => "DATE",
-            &CommentTag::Location     => "LOCATION",
-            &CommentTag::Contact      => "CONTACT",
-            &CommentTag::Isrc         => "ISRC",
-            &CommentTag::Custom(s)    => s,
-        }
-    }
-}
+    use bitstream::BitReader;
+    use decoder::Decoder;
 
-impl<'a> From<&'a str> for CommentTag<'a> {
-    fn from(s: &'a str) -> Self {
-        match s {
-            s if "TITLE".eq_ignore_ascii_case(s)        => CommentTag::Title,
-            s if "VERSION".eq_ignore_ascii_case(s)      => CommentTag::Version,
-            s if "ALBUM".eq_ignore_ascii_case(s)        => CommentTag::Album,
-            s if "TRACKNUMBER".eq_ignore_ascii_case(s)  => CommentTag::TrackNumber,
-            s if "ARTIST".eq_ignore_ascii_case(s)       => CommentTag::Artist,
-            s if "PERFORMER".eq_ignore_ascii_case(s)    => CommentTag::Performer,
-            s if "COPYRIGHT".eq_ignore_ascii_case(s)    => CommentTag::Copyright,
-            s if "LICENSE".eq_ignore_ascii_case(s)      => CommentTag::License,
-            s if "ORGANIZATION".eq_ignore_ascii_case(s) => CommentTag::Organization,
-            s if "DESCRIPTION".eq_ignore_ascii_case(s)  => CommentTag::Description,
-            s if "GENRE".eq_ignore_ascii_case(s)        => CommentTag::Genre,
-            s if "DATE".eq_ignore_ascii_case(s)         => CommentTag::Date,
-            s if "LOCATION".eq_ignore_ascii_case(s)     => CommentTag::Location,
-            s if "CONTACT".eq_ignore_ascii_case(s)      => CommentTag::Contact,
-            s if "ISRC".eq_ignore_ascii_case(s)         => CommentTag::Isrc,
-            _ => CommentTag::Custom(s),
-        }
-    }
-}
+    use super::*;
 
-impl<'a> fmt::Display for CommentTag<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let s = match self {
-            &CommentTag::Title        => "Title",
-            &CommentTag::Version      => "Version",
-            &CommentTag::Album        => "Album",
-            &CommentTag::TrackNumber  => "Track number",
-            &CommentTag::Artist       => "Artist",
-            &CommentTag::Performer    => "Performer",
-            &CommentTag::Copyright    => "Copyright",
-            &CommentTag::License      => "License",
-            &CommentTag::Organization => "Organization",
-            &CommentTag::Description  => "Description",
-            &CommentTag::Genre        => "Genre",
-            &CommentTag::Date         => "Date",
-            &CommentTag::Location     => "Location",
-            &CommentTag::Contact      => "Contact",
-            &CommentTag::Isrc         => "ISRC",
-            &CommentTag::Custom(s)    => s,
+    #[test]
+    fn duration() {
+        let header = |min, nom, max| Header {
+            version: 0,
+            channel_count: 2,
+            sample_rate: 44100,
+            bitrates: Bitrates {
+                min: min,
+                nom: nom,
+                max: max,
+            },
+            frame_lens: FrameLens::new(256, 2048),
         };
-        write!(f, "{}", s)
-    }
-}
-
-impl<'a> PartialEq for CommentTag<'a> {
-    fn eq(&self, other: &CommentTag) -> bool {
-        self.as_ref().eq_ignore_ascii_case(other.as_ref())
-    }
-}
-
-#[derive(Clone, Debug)]
-pub struct Comments {
-    vendor: Option<String>,
-    comments: Box<[String]>,
-}
-
-impl Comments {
-    pub fn read<R: BitRead>(reader: &mut R) -> Result<Self> {
-        let vendor = try!(Self::read_string(reader));
-
-        let comment_count = try!(reader.read_u32()) as usize;
-        let mut comments = Vec::with_capacity(comment_count);
-        for _ in 0..comment_count {
-            let s = try!(Self::read_string(reader));
-            if let Some(s) = s {
-                comments.push(s);
-            }
-        }
-
-        let framing_bit = try!(reader.read_bool());
-        if !framing_bit {
-            return Err(Error::Undecodable("Invalid framing bit"));
-        }
 
-        Ok(Comments {
-            vendor: vendor,
-            comments: comments.into_boxed_slice(),
-        })
-    }
-
-    pub fn vendor(&self) -> Option<&str> {
-        self.vendor.as_ref().map(|s| s.as_str())
-    }
-
-    pub fn len(&self) -> usize {
-        self.comments.len()
-    }
+        assert_eq!(header(0, 128000, 0).estimate_duration(1_600_000), Some(Duration::new(100, 0)));
+        assert_eq!(header(96000, -1, 160000).estimate_duration(1_000_000),
+            Some(Duration::new(62, 500_000_000)));
+        assert_eq!(header(0, 0, 160000).estimate_duration(1_000_000), None);
+        assert_eq!(header(-1, -1, -1).estimate_duration(1_000_000), None);
 
-    pub fn raw(&self) -> &[String] {
-        &self.comments
+        assert_eq!(header(0, 0, 0).duration(44100 * 3 + 441), Duration::new(3, 10_000_000));
+        assert_eq!(header(0, 0, 0).duration(0), Duration::new(0, 0));
     }
 
-    pub fn iter<'a>(&'a self) -> Box<Iterator<Item=(CommentTag<'a>, &'a str)> + 'a> {
-        let iter = self.comments.iter()
-            .filter_map(move |ref s| {
-                let mut split_iter = s.splitn(2, '=');
-                let tag = split_iter.next();
-                let val = split_iter.next();
-                if let (Some(tag), Some(val)) = (tag, val) {
-                    Some((CommentTag::from(tag), val))
-                } else {
-                    None
-                }
-            });
-        Box::new(iter)
+    #[test]
+    fn bitrate_kind() {
+        let kind = |min, nom, max| Bitrates { min: min, nom: nom, max: max }.kind();
+        assert_eq!(kind(128000, 128000, 128000), BitrateKind::Fixed);
+        assert_eq!(kind(0, 128000, 0), BitrateKind::Average);
+        assert_eq!(kind(-1, 128000, -1), BitrateKind::Average);
+        assert_eq!(kind(96000, 128000, 160000), BitrateKind::Bounded);
+        assert_eq!(kind(0, 0, 160000), BitrateKind::Bounded);
+        assert_eq!(kind(0, 0, 0), BitrateKind::Unset);
     }
 
-    pub fn by_tag<'a>(&'a self, tag: CommentTag<'a>) -> Box<Iterator<Item=&'a str> + 'a> {
-        let iter = self.iter()
-            .filter_map(move |(t, v)| if t == tag {
-                Some(v)
-            } else {
-                None
-            });
-        Box::new(iter)
-    }
-
-    fn read_string<R: BitRead>(reader: &mut R) -> Result<Option<String>> {
-        let len = try!(reader.read_u32()) as usize;
-        let mut bytes = vec![0; len];
-        try!(reader.read_exact(&mut bytes));
-        Ok(String::from_utf8(bytes).ok())
-    }
-}
-
-impl<'a> IntoIterator for &'a Comments {
-    type Item = (CommentTag<'a>, &'a str);
-    type IntoIter = Box<Iterator<Item=Self::Item> + 'a>;
-
-    fn into_iter(self) -> Self::IntoIter {
-        self.iter()
+    #[test]
+    fn header_to_packet() {
+        let packet = [
+            1, b'v', b'o', b'r', b'b', b'i', b's',
+            0, 0, 0, 0,             // version
+            2,                      // channel count
+            0x44, 0xAC, 0, 0,       // sample rate
+            0xFF, 0xFF, 0xFF, 0xFF, // max bitrate
+            0x00, 0xF4, 0x01, 0,    // nominal bitrate
+            0xFF, 0xFF, 0xFF, 0xFF, // min bitrate
+            0xB8,                   // frame lengths
+            1,                      // framing bit
+        ];
+
+        let mut builder = Decoder::builder();
+        builder.read_ident_packet(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+        let header = builder.header().unwrap();
+        assert_eq!(header.channel_count(), 2);
+        assert_eq!(header.sample_rate(), 44100);
+        assert_eq!(header.bitrates().nom(), 128000);
+        assert_eq!(header.frame_lens().short(), 256);
+        assert_eq!(header.frame_lens().long(), 2048);
+        assert_eq!(header.version(), 0);
+        assert_eq!(header.blocksize_exponents(), (8, 11));
+        assert_eq!(header.bitrates().kind(), BitrateKind::Average);
+
+        assert_eq!(header.to_packet(), &packet[..]);
     }
 }
@@ -1,9 +1,42 @@
-use std::{cmp, io, usize};
+//! Huffman code builder and decoder of the Vorbis codebooks, public for the other Xiph codecs.
+//!
+//! The codewords are assigned from the codeword lengths the way Vorbis does it: each entry gets
+//! the lowest codeword of its length that isn't a prefix of the codewords assigned before and
+//! doesn't have one of them as a prefix. When the lengths come from walking a Huffman tree depth
+//! first (like the Theora DCT token trees) this reproduces the tree, so the codes of the other
+//! codecs can be decoded here too. The codewords are read from the bitstream with `BitRead`
+//! bit by bit, least significant bit of each byte first.
+//!
+//! # Example
+//!
+//! ```rust
+//! use vorbis::BitReader;
+//! use vorbis::huffman::HuffmanDecoder;
+//!
+//! // Entries 0 to 3 with codewords `00`, `1`, `010` and `011`.
+//! let mut builder = HuffmanDecoder::builder(8);
+//! for (entry, &len) in [2, 1, 3, 3].iter().enumerate() {
+//!     builder.create_code(entry as u32, len).unwrap();
+//! }
+//! let decoder = builder.build();
+//!
+//! // Codewords `1`, `00` and `011` followed by the zero padding.
+//! let mut reader = BitReader::new(&[0b00_110_00_1][..]);
+//! assert_eq!(decoder.decode(&mut reader).unwrap(), 1);
+//! assert_eq!(decoder.decode(&mut reader).unwrap(), 0);
+//! assert_eq!(decoder.decode(&mut reader).unwrap(), 3);
+//! ```
+
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+use std::{cmp, u32, usize};
 
 use bitstream::BitRead;
 use error::{Error, Result};
 use util::{self, Bits};
 
+/// Decoder of a Huffman code built with `HuffmanDecoderBuilder`. The codewords up to the lookup
+/// table length are decoded with a single table lookup, the longer ones are searched.
 #[derive(Debug)]
 pub struct HuffmanDecoder {
     lookup_table: LookupTable,
@@ -12,6 +45,13 @@ pub struct HuffmanDecoder {
 }
 
 impl HuffmanDecoder {
+    /// Returns the builder of the decoder with the lookup table indexed by `lookup_table_bits`
+    /// bits. The table has `2^lookup_table_bits` entries of 4 bytes, it's truncated to the
+    /// longest codeword length if shorter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `lookup_table_bits` isn't in `1..32`.
     pub fn builder(lookup_table_bits: usize) -> HuffmanDecoderBuilder {
         assert!(lookup_table_bits > 0 && lookup_table_bits < 32);
         let lookup_table_len = if lookup_table_bits == 0 {
@@ -19,7 +59,7 @@ impl HuffmanDecoder {
         } else {
             1 << lookup_table_bits
         };
-        let lookup_entries = vec![LookupEntry::Null; lookup_table_len];
+        let lookup_entries = vec![PackedLookupEntry::NULL; lookup_table_len];
 
         let long_codes = Vec::new();
 
@@ -31,39 +71,49 @@ impl HuffmanDecoder {
             long_codes: long_codes,
             cur_codes: [None; 31],
             max_code_len: 0,
+            short_code_count: 0,
         }
     }
 
+    /// Returns the length of the longest codeword.
+    pub fn max_code_len(&self) -> usize {
+        self.max_code_len
+    }
+
+    /// Reads a codeword from the `reader` and returns its entry value. The bits read past the
+    /// codeword are unread. Fails with `Error::UnexpectedEof` if the stream ends within the
+    /// codeword, with `Error::NullHuffmanEntry` or `Error::InvalidHuffmanCode` if the bits don't
+    /// start any codeword (the code is underspecified).
     pub fn decode<R: BitRead>(&self, reader: &mut R) -> Result<u32> {
         let lookup_len_bits = cmp::min(self.max_code_len, self.lookup_table.len_bits);
-        let (mut code_bits, mut read) = try!(reader.try_read_u32_bits(lookup_len_bits));
+        let (mut code_bits, mut read) = try!(reader.try_read_u32_bits(
+                if lookup_len_bits != 0 { lookup_len_bits } else { self.max_code_len }));
         if read == 0 {
-            return Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof,
-                    "Unexpected EOF while reading Huffman code")));
+            return Err(Error::UnexpectedEof);
         }
-        let entry = &self.lookup_table.entries[code_bits as usize];
-        let code = match entry {
-            &LookupEntry::Code(code) => code,
-            &LookupEntry::LongCode => {
-                let r = try!(reader.try_read_u32_bits(self.max_code_len - lookup_len_bits));
-                read += r.1;
-                if read == 0 {
-                    return Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof,
-                            "Incomplete Huffman code")));
-                }
-                code_bits |= r.0 << lookup_len_bits;
-
-                try!(self.find_long_code(code_bits, read))
-            },
-            &LookupEntry::Null => return Err(Error::Undecodable("Matched a null Huffman code entry")),
+        let code = if lookup_len_bits == 0 {
+            // No lookup table, all codes are long codes.
+            try!(self.find_long_code(code_bits, read))
+        } else {
+            match self.lookup_table.get(code_bits as usize) {
+                LookupEntry::Code(code) => code,
+                LookupEntry::LongCode => {
+                    let r = try!(reader.try_read_u32_bits(self.max_code_len - lookup_len_bits));
+                    read += r.1;
+                    code_bits |= r.0 << lookup_len_bits;
+
+                    try!(self.find_long_code(code_bits, read))
+                },
+                LookupEntry::Null => return Err(Error::NullHuffmanEntry),
+            }
         };
         if code.len < read {
             let unread_len = read - code.len;
             let unread_bits = code_bits >> code.len;
             reader.unread_u32_bits(unread_bits, unread_len);
         } else if code.len > read {
-            return Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof,
-                    "Incomplete Huffman code")));
+            // Incomplete Huffman code.
+            return Err(Error::UnexpectedEof);
         }
         Ok(code.value)
     }
@@ -78,19 +128,29 @@ impl HuffmanDecoder {
                 value: lc.value,
                 len: lc.len,
             })
-            .ok_or_else(|| Error::Undecodable("Incomplete or unknown Huffman code"))
+            .ok_or_else(|| Error::InvalidHuffmanCode)
     }
 }
 
+/// Builder of `HuffmanDecoder` returned by `HuffmanDecoder::builder()`.
 pub struct HuffmanDecoderBuilder {
     lookup_table: LookupTable,
     long_codes: Vec<LongCode>,
     /// Current lowest codes for each code length (length 1 is at index 0).
     cur_codes: [Option<u32>; 31],
     max_code_len: usize,
+    /// Number of codes that fit entirely into the lookup table.
+    short_code_count: usize,
 }
 
 impl HuffmanDecoderBuilder {
+    /// Assigns the next codeword of the length `len` to the entry `value` (see the module docs).
+    /// The unused entries must be skipped. Fails with `Error::CodebookOverspecified` if all
+    /// codewords of the length are taken.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `len` isn't in `1..32`.
     pub fn create_code(&mut self, value: u32, len: usize) -> Result<()> {
         let code_straight = try!(self.next_code(len));
         let code = code_straight.reverse_bits() >> (32 - len);
@@ -108,6 +168,9 @@ impl HuffmanDecoderBuilder {
                 (LookupEntry::LongCode, true)
             };
             self.lookup_table.set(code.truncate(lookup_table_len), entry);
+            if !is_long_code {
+                self.short_code_count += 1;
+            }
             is_long_code
         } else {
             true
@@ -126,12 +189,24 @@ impl HuffmanDecoderBuilder {
         Ok(())
     }
 
+    /// Builds the decoder of the codewords created so far.
     pub fn build(mut self) -> HuffmanDecoder {
         for lc in self.long_codes.iter_mut() {
             lc.pad_sort_key(self.max_code_len);
         }
         self.long_codes.sort_by_key(|lc| lc.sort_key);
 
+        // Sparse codebooks often end up with lookup tables full of LongCode entries alone,
+        // in this case the table is useless and all codes are resolved via long_codes. Otherwise
+        // keep only as much of the table as the longest code actually needs. Note the lookup
+        // entries are replicated with step 1 << code.len so the head of the table holds all the
+        // information.
+        if self.short_code_count == 0 {
+            self.lookup_table = LookupTable::empty();
+        } else if self.max_code_len < self.lookup_table.len_bits {
+            self.lookup_table.truncate(self.max_code_len);
+        }
+
         HuffmanDecoder {
             lookup_table: self.lookup_table,
             long_codes: self.long_codes.into_boxed_slice(),
@@ -170,7 +245,7 @@ impl HuffmanDecoderBuilder {
         }
 
         if len == 1 {
-            return Err(Error::Undecodable("Overspecified Huffman tree"));
+            return Err(Error::CodebookOverspecified);
         }
         let cur_code_bits = try!(self.do_next_code(idx)) << 1;
         self.cur_codes[idx] = Some(cur_code_bits);
@@ -178,6 +253,21 @@ impl HuffmanDecoderBuilder {
     }
 }
 
+/// Assigns the codewords to the entries with the codeword `lens` (zero for unused entries) the
+/// same way the decoder does. The codewords are returned in the stream bit order so they can be
+/// written with `BitWrite::write_u32_bits()`.
+#[cfg(feature = "std")]
+pub fn make_codewords(lens: &[u8]) -> Result<Vec<u32>> {
+    let mut builder = HuffmanDecoder::builder(1);
+    lens.iter()
+        .map(|&len| if len == 0 {
+            Ok(0)
+        } else {
+            builder.next_code(len as usize).map(|c| c.reverse_bits() >> (32 - len))
+        })
+        .collect()
+}
+
 #[derive(Clone, Copy, Debug)]
 struct Code {
     code: u32,
@@ -220,22 +310,35 @@ impl LongCode {
 
 #[derive(Debug)]
 struct LookupTable {
-    entries: Box<[LookupEntry]>,
+    entries: Box<[PackedLookupEntry]>,
     len_bits: usize,
 }
 
 impl LookupTable {
+    pub fn empty() -> Self {
+        LookupTable {
+            entries: Vec::new().into_boxed_slice(),
+            len_bits: 0,
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         self.len_bits == 0
     }
 
+    #[inline]
+    pub fn get(&self, index: usize) -> LookupEntry {
+        self.entries[index].unpack()
+    }
+
     pub fn set(&mut self, code: Code, entry: LookupEntry) {
         assert!(code.len <= self.len_bits);
+        let entry = PackedLookupEntry::pack(entry);
         let mut index = code.code as usize;
         let last_index = ((self.entries.len() - 1) & !util::lsb_mask(code.len) as usize) | index;
         let step = 1 << code.len;
         loop {
-            assert!(match self.entries[index] {
+            assert!(match self.entries[index].unpack() {
                 LookupEntry::Null | LookupEntry::LongCode => true,
                 _ => false,
             });
@@ -246,6 +349,12 @@ impl LookupTable {
             index += step;
         }
     }
+
+    pub fn truncate(&mut self, len_bits: usize) {
+        assert!(len_bits > 0 && len_bits <= self.len_bits);
+        self.entries = self.entries[..1 << len_bits].to_vec().into_boxed_slice();
+        self.len_bits = len_bits;
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -255,6 +364,40 @@ enum LookupEntry {
     LongCode,
 }
 
+/// `LookupEntry` packed into 32 bits to keep the lookup tables small:
+/// `0` is `Null`, `u32::MAX` is `LongCode`, anything else is `Code` with the code value (at most
+/// 24 bits) above the code length in the lower 5 bits (the length is never zero).
+#[derive(Clone, Copy, Debug)]
+struct PackedLookupEntry(u32);
+
+impl PackedLookupEntry {
+    const NULL: PackedLookupEntry = PackedLookupEntry(0);
+    const LONG_CODE: PackedLookupEntry = PackedLookupEntry(u32::MAX);
+
+    fn pack(entry: LookupEntry) -> Self {
+        match entry {
+            LookupEntry::Null => Self::NULL,
+            LookupEntry::Code(cv) => {
+                assert!(cv.len > 0 && cv.len < 32 && cv.value < 1 << 24);
+                PackedLookupEntry(cv.value << 5 | cv.len as u32)
+            },
+            LookupEntry::LongCode => Self::LONG_CODE,
+        }
+    }
+
+    #[inline]
+    fn unpack(self) -> LookupEntry {
+        match self.0 {
+            0 => LookupEntry::Null,
+            u32::MAX => LookupEntry::LongCode,
+            v => LookupEntry::Code(CodeValue {
+                value: v >> 5,
+                len: (v & 0x1F) as usize,
+            }),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::cmp;
@@ -262,6 +405,8 @@ mod tests {
 
     use super::*;
     use bitstream::BitReader;
+    #[cfg(feature = "std")]
+    use bitstream::{BitWrite, BitWriter};
     use error::ErrorKind;
 
     fn new_bit_reader(bits: &str) -> BitReader<Cursor<Vec<u8>>> {
@@ -340,6 +485,28 @@ mod tests {
                        &[0b0000000000, 0b0000001, 0b00000001, 0b0000000001000, 0b000000001, 0b000001, 0b0000100, 0b00000000011, 0b0000101000, 0b00001011, 0b00001100, 0b000000000101, 0b00000000010010000, 0b00000000010010001, 0b00000000010010010, 0b00000000010010011, 0b0000111, 0b00010, 0b00011, 0b000010101, 0b001000, 0b0011, 0b0100, 0b00001101, 0b00100100, 0b00101, 0b01010, 0b00100101, 0b0000000001001010, 0b00000000010011, 0b0000101001000, 0b0000000001001011, 0b0010011, 0b01011, 0b01100, 0b0110100, 0b011011, 0b100, 0b101, 0b01110, 0b01101010, 0b01111]);
     }
 
+    #[cfg(feature = "std")]
+    #[test]
+    fn make_codewords_() {
+        let lens = [2, 4, 4, 0, 4, 4, 2, 3, 3];
+        let codewords = make_codewords(&lens).unwrap();
+        assert_eq!(codewords, [0b00, 0b0010, 0b1010, 0, 0b0110, 0b1110, 0b01, 0b011, 0b111]);
+
+        let mut b = HuffmanDecoder::builder(4);
+        for (i, &len) in lens.iter().enumerate().filter(|&(_, &len)| len > 0) {
+            b.create_code(i as u32, len as usize).unwrap();
+        }
+        let d = b.build();
+        let mut writer = BitWriter::new(Vec::new());
+        for &i in &[7, 0, 5, 2, 8] {
+            writer.write_u32_bits(codewords[i], lens[i] as usize).unwrap();
+        }
+        let mut reader = BitReader::new(Cursor::new(writer.into_inner().unwrap()));
+        for &i in &[7, 0, 5, 2, 8] {
+            assert_eq!(d.decode(&mut reader).unwrap(), i);
+        }
+    }
+
     #[test]
     fn overspecified() {
         let mut b = HuffmanDecoder::builder(1);
@@ -389,6 +556,37 @@ mod tests {
                     &[0, 7,  4,   3,   6,  6,  7]);
     }
 
+    #[test]
+    fn decode_all_long_codes() {
+        let mut b = HuffmanDecoder::builder(1);
+        for (i, &code_len) in [2, 2, 3, 3, 3, 3].iter().enumerate() {
+            b.create_code(i as u32, code_len).unwrap();
+        }
+        let d = b.build();
+        assert!(d.lookup_table.is_empty());
+
+        let mut reader = new_bit_reader("110 01 111 00 100");
+        for &exp in &[4, 1, 5, 0, 2] {
+            assert_eq!(d.decode(&mut reader).unwrap(), exp);
+        }
+    }
+
+    #[test]
+    fn lookup_table_truncated() {
+        let mut b = HuffmanDecoder::builder(9);
+        for (i, &code_len) in [1, 2, 3, 3].iter().enumerate() {
+            b.create_code(i as u32, code_len).unwrap();
+        }
+        let d = b.build();
+        assert_eq!(d.lookup_table.len_bits, 3);
+        assert_eq!(d.lookup_table.entries.len(), 8);
+
+        let mut reader = new_bit_reader("111 0 10 110 0");
+        for &exp in &[3, 0, 1, 2, 0] {
+            assert_eq!(d.decode(&mut reader).unwrap(), exp);
+        }
+    }
+
     #[test]
     fn decode_2() {
         test_decode(&[10, 7, 8, 13, 9, 6, 7, 11, 10, 8, 8, 12, 17, 17, 17, 17, 7, 5, 5, 9, 6, 4, 4, 8, 8, 5, 5, 8, 16, 14, 13, 16, 7, 5, 5, 7, 6, 3, 3, 5, 8, 5],
@@ -38,25 +38,158 @@
 //!     // Do something with the sample_buf.
 //! }
 //! ```
+//!
+//! # `no_std`
+//!
+//! With the default `std` feature disabled the crate only depends on `core` and `alloc`.
+//! `BitReader` then reads from byte slices (or any `ByteRead` implementation) and the `libm`
+//! dependency must be enabled to provide the float functions.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(feature = "std"))]
+#[macro_use] extern crate alloc;
+#[cfg(not(feature = "std"))]
+extern crate libm;
+#[cfg(feature = "memmap")]
+extern crate memmap2;
+#[cfg(feature = "reftest")]
+extern crate ogg_vorbis_ref;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "rustfft")]
+extern crate rustfft;
+
+// Lets the modules import from `std` regardless of the `std` feature. Not all of the items are
+// used with every feature combination.
+#[cfg(not(feature = "std"))]
+#[allow(unused_imports)]
+mod std {
+    pub use core::*;
+    pub use alloc::{borrow, collections, fmt, rc, slice, str, sync};
+
+    // Items of the `std` prelude missing from the `core` one.
+    pub mod prelude {
+        pub use alloc::borrow::ToOwned;
+        pub use alloc::boxed::Box;
+        pub use alloc::string::{String, ToString};
+        pub use alloc::vec::Vec;
+    }
+}
 
-#[macro_use] extern crate enum_primitive;
-extern crate num;
+// Goes first so the macros are visible in the other modules.
+#[macro_use] mod util;
 
+mod bands;
 mod bitstream;
 mod codebook;
+#[cfg(feature = "comments")]
+mod comment;
+mod crossfade;
+#[cfg(feature = "std")]
+mod cut;
 mod decoder;
+mod dump;
+#[cfg(feature = "encoder")]
+mod encoder;
 mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 mod floor;
+mod gapless;
+mod frame;
+mod granule;
 mod header;
-mod huffman;
+pub mod huffman;
+mod looping;
 mod mapping;
 mod mdct;
+#[cfg(feature = "rustfft")]
+mod mdct_fft;
+#[cfg(feature = "tags")]
+mod metadata;
+mod mix;
+#[cfg(feature = "memmap")]
+mod mmap;
 mod mode;
+mod multi;
+#[cfg(feature = "ogg")]
+mod ogg;
+mod peaks;
+pub mod prelude;
+#[cfg(feature = "reftest")]
+mod reftest;
+#[cfg(feature = "resample")]
+mod resample;
 mod residue;
-mod util;
+mod sample;
+#[cfg(feature = "std")]
+mod setup_builder;
+mod sink;
+#[cfg(feature = "tags")]
+mod tag;
+mod tables;
+#[cfg(feature = "trace")]
+mod trace;
+mod validate;
+mod vendor;
+#[cfg(feature = "std")]
+mod wav;
 mod window;
 
-pub use bitstream::{BitRead, BitReader};
-pub use decoder::{Decoder, DecoderBuilder, ChannelIter, InterleavedSamplesIter, Samples};
-pub use error::{Error, ErrorKind, Result};
-pub use header::*;
\ No newline at end of file
+pub use bands::BandScale;
+pub use bitstream::{BitRead, BitReader, BitstreamError, ByteRead};
+#[cfg(feature = "std")]
+pub use bitstream::{BitWrite, BitWriter, BufByteReader};
+pub use codebook::{Codebook, LookupKind};
+#[cfg(feature = "comments")]
+pub use comment::{CommentEntry, CommentLimits, Comments};
+#[cfg(feature = "tags")]
+pub use comment::{CommentMap, CommentsBuilder};
+pub use crossfade::Crossfade;
+#[cfg(feature = "std")]
+pub use cut::PacketCut;
+pub use decoder::{DecodeLimits, DecodeOutcome, Decoder, DecoderBuilder, DecoderPool, DecoderState,
+    ChannelIter, ErrorLocation, FrameSpectrum, InterleavedSamplesIter, PacketInfo, PacketKind, PacketStats, Samples, Setup,
+    SetupParser};
+#[cfg(feature = "std")]
+pub use decoder::PipelinedDecoder;
+pub use dump::DebugDump;
+#[cfg(feature = "encoder")]
+pub use encoder::{EncodedPacket, Encoder, EncoderBuilder};
+pub use error::{DecodeStage, Error, ErrorKind, Result, Warning};
+pub use floor::{Floor, FloorClass};
+pub use gapless::GaplessTrim;
+pub use granule::GranulePos;
+pub use header::*;
+pub use looping::LoopingDecoder;
+pub use mapping::{ChannelCoupling, Mapping, Submap};
+pub use mdct::Mdct;
+pub use mix::{DownmixMatrix, Mixer};
+#[cfg(feature = "memmap")]
+pub use mmap::MmapDecoder;
+pub use mode::Mode;
+pub use multi::MultiDecoder;
+pub use peaks::{Peak, WaveformPeaks};
+#[cfg(feature = "reftest")]
+pub use reftest::{Mismatch, RefReport, RefTest, RefTestError, StbReport};
+#[cfg(feature = "resample")]
+pub use resample::Resampler;
+pub use residue::{Residue, ResidueKind};
+#[cfg(feature = "tags")]
+pub use metadata::{Chapter, Date, LoopPoints, ReplayGain};
+#[cfg(feature = "ogg")]
+pub use ogg::{decode_all, retag};
+pub use sample::Sample;
+#[cfg(feature = "std")]
+pub use setup_builder::{CodebookConfig, FloorConfig, MappingConfig, ResidueConfig, SetupBuilder};
+pub use sink::SampleSink;
+#[cfg(feature = "tags")]
+pub use tag::CommentTag;
+pub use tables::TableCache;
+#[cfg(feature = "trace")]
+pub use trace::{BitTracer, TraceEntry};
+pub use validate::{validate, Issue, IssueKind, Recovery, Severity, ValidationReport};
+pub use vendor::{EncoderInfo, EncoderKind};
+#[cfg(feature = "std")]
+pub use wav::{WavFormat, WavWriter};
\ No newline at end of file
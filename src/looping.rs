@@ -0,0 +1,156 @@
+use std::cmp;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use bitstream::BitReader;
+use decoder::{Decoder, Samples};
+use error::Result;
+
+/// Decoder of an in-memory stream that loops the `loop_start..loop_end` sample range
+/// indefinitely, e.g. game background music with the loop points from
+/// `Comments::loop_points()`.
+///
+/// The output is the stream up to the loop end followed by the loop repeated: it's
+/// sample-exact, without the gap or click of restarting the playback. To jump back the decoder
+/// is primed with the packet preceding the loop start (see `Decoder::prime()`), so the looped
+/// samples are bit-identical to the ones of the first pass. The sample positions are counted in
+/// the decoder output of the audio packets like `Decoder::pos()`.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use vorbis::{Decoder, LoopingDecoder};
+///
+/// # fn f(decoder: Decoder, audio_packets: Vec<Vec<u8>>) {
+/// let loop_points = decoder.comments().and_then(|c| c.loop_points());
+/// let (start, end) = loop_points.map(|l| (l.start(), l.end())).unwrap_or((0, None));
+/// let mut music = LoopingDecoder::new(decoder, audio_packets, start, end)
+///         .expect("Couldn't read packet");
+/// while let Some(samples) = music.decode().expect("Couldn't decode audio packet") {
+///     // Play the samples.
+/// #   let _ = samples;
+/// }
+/// # }
+/// ```
+pub struct LoopingDecoder {
+    decoder: Decoder,
+    packets: Box<[Vec<u8>]>,
+    // End sample position of each packet.
+    positions: Box<[u64]>,
+    loop_start: u64,
+    loop_end: u64,
+    looping: bool,
+    // Index of the next packet to decode.
+    next: usize,
+    // Position of the next output sample.
+    pos: u64,
+    loop_count: u64,
+}
+
+impl LoopingDecoder {
+    /// Creates the looping decoder of the audio `packets` (the header packets excluded) of the
+    /// `decoder`'s stream. The loop lasts until the end of the stream if `loop_end` is `None`,
+    /// it's clamped to the stream length otherwise. Only the packet kind and mode bits are read
+    /// from each packet here. If the loop is empty (e.g. the loop start is beyond the end of the
+    /// stream), the stream is played once.
+    pub fn new(mut decoder: Decoder, packets: Vec<Vec<u8>>, loop_start: u64, loop_end: Option<u64>)
+            -> Result<Self> {
+        let mut positions = Vec::with_capacity(packets.len());
+        let mut pos = 0;
+        let mut prev_frame_len = None;
+        for packet in &packets {
+            let kind = try!(decoder.setup().packet_frame_kind(&mut BitReader::new(&packet[..])));
+            let frame_len = decoder.header().frame_lens().get(kind);
+            if let Some(prev_frame_len) = prev_frame_len {
+                pos += (prev_frame_len / 4 + frame_len / 4) as u64;
+            }
+            positions.push(pos);
+            prev_frame_len = Some(frame_len);
+        }
+        let loop_end = cmp::min(loop_end.unwrap_or(pos), pos);
+        decoder.reset();
+        Ok(LoopingDecoder {
+            decoder: decoder,
+            packets: packets.into_boxed_slice(),
+            positions: positions.into_boxed_slice(),
+            loop_start: loop_start,
+            loop_end: loop_end,
+            looping: true,
+            next: 0,
+            pos: 0,
+            loop_count: 0,
+        })
+    }
+
+    /// Returns the next samples, which are empty for the first packet and after jumping back
+    /// to the loop start. Returns `None` at the end of the stream, which is only reached if the
+    /// loop is empty or the looping was stopped with `set_looping()`.
+    pub fn decode(&mut self) -> Result<Option<Samples>> {
+        if self.looping && self.loop_start < self.loop_end && self.pos >= self.loop_end {
+            let first = self.positions.iter().position(|&p| p > self.loop_start).unwrap() - 1;
+            try!(self.decoder.prime(&mut BitReader::new(&self.packets[first][..])));
+            self.next = first + 1;
+            self.pos = self.loop_start;
+            self.loop_count += 1;
+            return Ok(Some(self.decoder.samples()));
+        }
+
+        // The rest of the last packet is left after the loop end if the looping was stopped
+        // there.
+        let r = if self.next > 0 && self.pos < self.positions[self.next - 1] {
+            self.decoder.samples()
+        } else if self.next < self.packets.len() {
+            self.next += 1;
+            try!(self.decoder.decode(&mut BitReader::new(&self.packets[self.next - 1][..])))
+        } else {
+            return Ok(None);
+        };
+        let end = if self.looping && self.loop_start < self.loop_end {
+            self.loop_end
+        } else {
+            self.positions[self.positions.len() - 1]
+        };
+        let packet_end = self.positions[self.next - 1];
+        let packet_start = packet_end - r.len() as u64;
+        let start = cmp::max(self.pos, packet_start);
+        let end = cmp::max(cmp::min(packet_end, end), start);
+        self.pos = end;
+        Ok(Some(r.slice((start - packet_start) as usize..(end - packet_start) as usize)))
+    }
+
+    /// Sets whether the loop is repeated (enabled by default). After disabling, the playback
+    /// continues past the loop end to the end of the stream, e.g. to play the outro of the
+    /// music. Enabling it again after the loop end was passed jumps back to the loop start
+    /// immediately.
+    pub fn set_looping(&mut self, value: bool) -> &mut Self {
+        self.looping = value;
+        self
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Returns the position in the stream of the next output sample.
+    pub fn pos(&self) -> u64 {
+        self.pos
+    }
+
+    /// Returns the number of the jumps back to the loop start so far.
+    pub fn loop_count(&self) -> u64 {
+        self.loop_count
+    }
+
+    pub fn loop_start(&self) -> u64 {
+        self.loop_start
+    }
+
+    /// Returns the loop end clamped to the stream length.
+    pub fn loop_end(&self) -> u64 {
+        self.loop_end
+    }
+
+    pub fn decoder(&self) -> &Decoder {
+        &self.decoder
+    }
+}
@@ -1,53 +1,94 @@
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
 use bitstream::BitRead;
 use error::{Error, Result};
+use frame::Frame;
+use sample::Sample;
 use util::Bits;
 
+/// Channel mapping as read from the setup header.
 #[derive(Debug)]
 pub struct Mapping {
     channel_couplings: Box<[ChannelCoupling]>,
     /// Channel index -> submap index in self.submaps.
-    pub channel_to_submap: Box<[usize]>,
-    pub submaps: Box<[Submap]>,
+    channel_to_submap: Box<[usize]>,
+    submaps: Box<[Submap]>,
 }
 
-#[derive(Debug)]
-struct ChannelCoupling {
+/// Square polar coupled pair of channels.
+#[derive(Clone, Copy, Debug)]
+pub struct ChannelCoupling {
     mag_channel: usize,
     ang_channel: usize,
 }
 
+impl ChannelCoupling {
+    pub fn mag_channel(&self) -> usize {
+        self.mag_channel
+    }
+
+    pub fn ang_channel(&self) -> usize {
+        self.ang_channel
+    }
+}
+
 #[derive(Debug)]
 pub struct Submap {
-    pub channels: Box<[usize]>,
-    pub floor: usize,
-    pub residue: usize,
+    channels: Box<[usize]>,
+    floor: usize,
+    residue: usize,
+}
+
+impl Submap {
+    /// Returns indexes of the channels assigned to this submap in ascending order.
+    pub fn channels(&self) -> &[usize] {
+        &self.channels
+    }
+
+    /// Returns the floor index in the setup header.
+    pub fn floor(&self) -> usize {
+        self.floor
+    }
+
+    /// Returns the residue index in the setup header.
+    pub fn residue(&self) -> usize {
+        self.residue
+    }
 }
 
 impl Mapping {
     pub fn read<R: BitRead>(reader: &mut R, channel_count: usize, floor_count: usize, residue_count: usize) -> Result<Self> {
         assert!(channel_count > 0 && channel_count <= 255);
 
+        trace_field!(reader, "mapping.kind");
         if try!(reader.read_u16()) != 0 {
-            return Err(Error::Undecodable("Unsupported mapping type"));
+            return Err(Error::InvalidMappingKind);
         }
 
+        trace_field!(reader, "mapping.submap_flag");
         let submap_count = if try!(reader.read_bool()) {
+            trace_field!(reader, "mapping.submap_count");
             try!(reader.read_u8_bits(4)) as usize
         } else {
             1
         };
+        trace_field!(reader, "mapping.coupling_flag");
         let has_channel_couplings = try!(reader.read_bool());
         let channel_couplings = if has_channel_couplings {
+            trace_field!(reader, "mapping.coupling_steps");
             let len = try!(reader.read_u8()) as usize + 1;
             let mut channel_couplings = Vec::with_capacity(len);
             let channel_index_bits = (channel_count as u32 - 1).ilog() as usize;
             for _ in 0..channel_couplings.capacity() {
+                trace_field!(reader, "mapping.magnitude");
                 let mag_channel = try!(reader.read_u8_bits(channel_index_bits)) as usize;
+                trace_field!(reader, "mapping.angle");
                 let ang_channel = try!(reader.read_u8_bits(channel_index_bits)) as usize;
                 if mag_channel == ang_channel ||
                         mag_channel >= channel_count ||
                         ang_channel >= channel_count {
-                    return Err(Error::Undecodable("Invalid values of (magnitude, angle) channel pair"));
+                    return Err(Error::InvalidCouplingChannels);
                 }
                 channel_couplings.push(ChannelCoupling {
                     mag_channel: mag_channel,
@@ -60,16 +101,18 @@ impl Mapping {
         };
 
         // Reserved.
+        trace_field!(reader, "mapping.reserved");
         if try!(reader.read_u8_bits(2)) != 0 {
-            return Err(Error::Undecodable("Unexpected data in reserved field"));
+            return Err(Error::NonZeroMappingReserved);
         }
 
         let channel_to_submap = if submap_count > 1 {
             let mut channel_to_submap = Vec::with_capacity(channel_count);
             for _ in 0..channel_count {
+                trace_field!(reader, "mapping.mux");
                 let submap_idx = try!(reader.read_u8_bits(4)) as usize;
                 if submap_idx >= submap_count {
-                    return Err(Error::Undecodable("Invalid mapping mux value"));
+                    return Err(Error::InvalidMappingMux);
                 }
                 channel_to_submap.push(submap_idx)
             }
@@ -82,16 +125,19 @@ impl Mapping {
         let mut submaps = Vec::with_capacity(submap_count);
         for submap_idx in 0..submap_count {
             // Unused.
+            trace_field!(reader, "mapping.time_config");
             try!(reader.read_u8());
 
+            trace_field!(reader, "mapping.floor");
             let floor = try!(reader.read_u8()) as usize;
             if floor >= floor_count {
-                return Err(Error::Undecodable("Invalid mapping floor value"));
+                return Err(Error::InvalidMappingFloor);
             }
 
+            trace_field!(reader, "mapping.residue");
             let residue = try!(reader.read_u8()) as usize;
             if residue >= residue_count {
-                return Err(Error::Undecodable("Invalid mapping residue value"));
+                return Err(Error::InvalidMappingResidue);
             }
 
             let channels: Vec<_> = channel_to_submap.iter().enumerate()
@@ -116,6 +162,24 @@ impl Mapping {
         })
     }
 
+    pub fn channel_couplings(&self) -> &[ChannelCoupling] {
+        &self.channel_couplings
+    }
+
+    /// Returns the submap index for each channel.
+    pub fn channel_to_submap(&self) -> &[usize] {
+        &self.channel_to_submap
+    }
+
+    pub fn submaps(&self) -> &[Submap] {
+        &self.submaps
+    }
+
+    /// Returns the submap the specified channel is assigned to.
+    pub fn channel_submap(&self, channel: usize) -> &Submap {
+        &self.submaps[self.channel_to_submap[channel]]
+    }
+
     pub fn unzero_coupled_channels(&self, zero_channels: &mut [bool]) {
         for c in self.channel_couplings.iter() {
             let m = c.mag_channel;
@@ -127,25 +191,43 @@ impl Mapping {
         }
     }
 
-    pub fn decouple_channels(&self, channels: &mut [Box<[f32]>], channel_len: usize) {
+    pub fn decouple_channels(&self, channels: &mut Frame, channel_len: usize) {
         for c in self.channel_couplings.iter() {
-            for i in 0..channel_len {
-                let m = channels[c.mag_channel][i];
-                let a = channels[c.ang_channel][i];
-                let (new_m, new_a) = if m > 0.0 {
-                    if a > 0.0 {
-                        (m, m - a)
-                    } else {
-                        (m + a, m)
-                    }
-                } else if a > 0.0 {
-                    (m, m + a)
-                } else {
-                    (m - a, m)
-                };
-                channels[c.mag_channel][i] = new_m;
-                channels[c.ang_channel][i] = new_a;
+            let (mag, ang) = channels.channel_pair_mut(c.mag_channel, c.ang_channel);
+            for (mag, ang) in mag[..channel_len].iter_mut().zip(ang[..channel_len].iter_mut()) {
+                let (new_m, new_a) = Self::decouple(*mag, *ang);
+                *mag = new_m;
+                *ang = new_a;
+            }
+        }
+    }
+
+    #[cfg(not(feature = "fast-math"))]
+    #[inline(always)]
+    fn decouple(m: Sample, a: Sample) -> (Sample, Sample) {
+        if m > 0.0 {
+            if a > 0.0 {
+                (m, m - a)
+            } else {
+                (m + a, m)
             }
+        } else if a > 0.0 {
+            (m, m + a)
+        } else {
+            (m - a, m)
+        }
+    }
+
+    // Branchless form the compiler can vectorize. Negating the angle is exact so the result is
+    // the same.
+    #[cfg(feature = "fast-math")]
+    #[inline(always)]
+    fn decouple(m: Sample, a: Sample) -> (Sample, Sample) {
+        let d = if m > 0.0 { a } else { -a };
+        if a > 0.0 {
+            (m, m - d)
+        } else {
+            (m + d, m)
         }
     }
 }
\ No newline at end of file
@@ -1,38 +1,73 @@
-//! This is a direct port of the reference inverse MDCT implementation in [libvorbis].
+//! Forward and inverse MDCT of power-of-two lengths.
+//!
+//! This is a direct port of the reference MDCT implementation in [libvorbis].
 //! [libvorbis]: https://www.xiph.org/vorbis/doc/libvorbis/
-use std::f32::consts::PI;
-
+//!
+//! With the `rustfft` feature enabled the inverse MDCT is delegated to the FFT-based
+//! implementation in `mdct_fft` instead. Lengths below `MIN_FAST_LEN` are computed directly
+//! from the definition.
+
+use std::f64::consts::PI;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+#[cfg(feature = "rustfft")]
+use mdct_fft::FftMdct;
+use sample::{mul_add, Sample};
 use util::Bits;
-
-const PI3_8: f32 = 0.38268343236508977175;
-const PI2_8: f32 = 0.70710678118654752441;
-const PI1_8: f32 = 0.92387953251128675613;
-
+#[cfg(not(feature = "std"))]
+use util::Float;
+
+const PI3_8: Sample = 0.38268343236508977175;
+const PI2_8: Sample = 0.70710678118654752441;
+const PI1_8: Sample = 0.92387953251128675613;
+
+// The butterflies need at least one 32 point butterfly for each half of the buffer.
+const MIN_FAST_LEN: usize = 64;
+
+/// Modified discrete cosine transform of a fixed length `N` (`len()`) which transforms `N`
+/// samples into `N / 2` coefficients and back.
+///
+/// The inverse transform isn't scaled while the forward transform is scaled by `4 / N`, the same
+/// way as in libvorbis. With this scaling the input is reconstructed exactly when the forward
+/// transform input and the inverse transform output are multiplied by a window satisfying the
+/// Princen-Bradley condition (like the Vorbis window) and the consecutive frames are overlapped
+/// by `N / 2` samples and added. Neither transform applies a window by itself.
 pub struct Mdct {
     len: usize,
     log2len: usize,
-    trig: Box<[f32]>,
+    trig: Box<[Sample]>,
     bitrev: Box<[usize]>,
+    // Cosine table for the direct computation of the lengths below MIN_FAST_LEN.
+    cos: Box<[Sample]>,
+    #[cfg(feature = "rustfft")]
+    fft: Option<FftMdct>,
 }
 
 impl Mdct {
+    /// Creates MDCT of the specified `len` which must be a power of two and at least 2.
+    /// Vorbis uses lengths from 64 to 8192.
     pub fn new(len: usize) -> Self {
-        assert!(len >= 32 && len % 2 == 0);
+        assert!(len >= 2 && len.is_power_of_two(), "MDCT length must be a power of two >= 2");
+        if len < MIN_FAST_LEN {
+            return Self::new_direct(len);
+        }
 
-        let mut trig = vec![0_f32; len + len / 4];
+        let mut trig = vec![0.0; len + len / 4];
         let half_len = len / 2;
+        // The tables are computed in f64 regardless of the Sample type for better precision.
         for i in 0..len / 4 {
-            let len = len as f32;
-            let i2 = i as f32 * 2.0;
-            trig[i * 2] = ((PI / len) * (2.0 * i2)).cos();
-            trig[i * 2 + 1] = -((PI / len) * (2.0 * i2)).sin();
-            trig[half_len + i * 2] = ((PI / (2.0 * len)) * (i2 + 1.0)).cos();
-            trig[half_len + i * 2 + 1] = ((PI / (2.0 * len)) * (i2 + 1.0)).sin();
+            let len = len as f64;
+            let i2 = i as f64 * 2.0;
+            trig[i * 2] = ((PI / len) * (2.0 * i2)).cos() as Sample;
+            trig[i * 2 + 1] = -((PI / len) * (2.0 * i2)).sin() as Sample;
+            trig[half_len + i * 2] = ((PI / (2.0 * len)) * (i2 + 1.0)).cos() as Sample;
+            trig[half_len + i * 2 + 1] = ((PI / (2.0 * len)) * (i2 + 1.0)).sin() as Sample;
         }
         for i in 0..len / 8 {
-            let i2 = i as f32 * 2.0;
-            trig[len + i * 2] = ((PI / len as f32) * (2.0 * i2 + 2.0)).cos() * 0.5;
-            trig[len + i * 2 + 1] = -((PI / len as f32) * (2.0 * i2 + 2.0)).sin() * 0.5;
+            let i2 = i as f64 * 2.0;
+            trig[len + i * 2] = (((PI / len as f64) * (2.0 * i2 + 2.0)).cos() * 0.5) as Sample;
+            trig[len + i * 2 + 1] = (-((PI / len as f64) * (2.0 * i2 + 2.0)).sin() * 0.5) as Sample;
         }
 
         let log2len = ((len as u32).ilog() - 1) as usize;
@@ -59,11 +94,94 @@ impl Mdct {
             log2len: log2len,
             trig: trig.into_boxed_slice(),
             bitrev: bitrev.into_boxed_slice(),
+            cos: Vec::new().into_boxed_slice(),
+            #[cfg(feature = "rustfft")]
+            fft: Some(FftMdct::new(len)),
         }
     }
 
-    pub fn inverse(&self, buf: &mut [f32]) {
+    fn new_direct(len: usize) -> Self {
+        let half_len = len / 2;
+        let mut cos = Vec::with_capacity(len * half_len);
+        for i in 0..len {
+            for k in 0..half_len {
+                let n = len as f64;
+                cos.push((PI / 2.0 / n * (2.0 * i as f64 + 1.0 + n / 2.0) * (2.0 * k as f64 + 1.0)).cos() as Sample);
+            }
+        }
+
+        Mdct {
+            len: len,
+            log2len: 0,
+            trig: Vec::new().into_boxed_slice(),
+            bitrev: Vec::new().into_boxed_slice(),
+            cos: cos.into_boxed_slice(),
+            #[cfg(feature = "rustfft")]
+            fft: None,
+        }
+    }
+
+    /// Returns the transform length `N`: the number of samples (the number of coefficients
+    /// is `N / 2`).
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Computes inverse MDCT in place. The first `len() / 2` values of the `buf` are the input
+    /// coefficients `X[k]` which are replaced with `len()` output samples:
+    ///
+    /// `y[n] = sum(X[k] * cos(PI / (2 * N) * (2 * n + 1 + N / 2) * (2 * k + 1)))`
+    ///
+    /// The output is neither scaled nor windowed.
+    pub fn inverse(&self, buf: &mut [Sample]) {
         assert!(buf.len() == self.len);
+        if self.len < MIN_FAST_LEN {
+            self.inverse_direct(buf);
+        } else {
+            self.inverse_fast(buf);
+        }
+    }
+
+    /// Computes forward MDCT of the `input` of `len()` samples into the `output` of `len() / 2`
+    /// coefficients:
+    ///
+    /// `X[k] = 4 / N * sum(x[n] * cos(PI / (2 * N) * (2 * n + 1 + N / 2) * (2 * k + 1)))`
+    ///
+    /// Note the `input` isn't windowed.
+    pub fn forward(&self, input: &[Sample], output: &mut [Sample]) {
+        assert!(input.len() == self.len && output.len() == self.len / 2);
+        if self.len < MIN_FAST_LEN {
+            self.forward_direct(input, output);
+        } else {
+            self.forward_fast(input, output);
+        }
+    }
+
+    fn inverse_direct(&self, buf: &mut [Sample]) {
+        let half_len = self.len / 2;
+        let input = buf[..half_len].to_vec();
+        for (i, out) in buf.iter_mut().enumerate() {
+            let cos = &self.cos[i * half_len..(i + 1) * half_len];
+            *out = input.iter().zip(cos.iter()).fold(0.0, |acc, (&x, &c)| acc + x * c);
+        }
+    }
+
+    fn forward_direct(&self, input: &[Sample], output: &mut [Sample]) {
+        let half_len = self.len / 2;
+        let scale = 4.0 / self.len as Sample;
+        for (k, out) in output.iter_mut().enumerate() {
+            *out = input.iter().enumerate()
+                    .fold(0.0, |acc, (i, &x)| acc + x * self.cos[i * half_len + k]) * scale;
+        }
+    }
+
+    #[cfg(feature = "rustfft")]
+    fn inverse_fast(&self, buf: &mut [Sample]) {
+        self.fft.as_ref().unwrap().inverse(buf);
+    }
+
+    #[cfg(not(feature = "rustfft"))]
+    fn inverse_fast(&self, buf: &mut [Sample]) {
         let n = self.len;
         let n2 = n >> 1;
         let n4 = n >> 2;
@@ -77,10 +195,10 @@ impl Mdct {
 
         loop {
             o_x          -= 4;
-            buf[o_x + 0]  = -buf[i_x + 2] * tri[t + 3] - buf[i_x + 0]  * tri[t + 2];
-            buf[o_x + 1]  =  buf[i_x + 0] * tri[t + 3] - buf[i_x + 2]  * tri[t + 2];
-            buf[o_x + 2]  = -buf[i_x + 6] * tri[t + 1] - buf[i_x + 4]  * tri[t + 0];
-            buf[o_x + 3]  =  buf[i_x + 4] * tri[t + 1] - buf[i_x + 6]  * tri[t + 0];
+            buf[o_x + 0]  = mul_add(-buf[i_x + 2], tri[t + 3], -(buf[i_x + 0] * tri[t + 2]));
+            buf[o_x + 1]  = mul_add(buf[i_x + 0], tri[t + 3], -(buf[i_x + 2] * tri[t + 2]));
+            buf[o_x + 2]  = mul_add(-buf[i_x + 6], tri[t + 1], -(buf[i_x + 4] * tri[t + 0]));
+            buf[o_x + 3]  = mul_add(buf[i_x + 4], tri[t + 1], -(buf[i_x + 6] * tri[t + 0]));
 
             if i_x < 8 {
                 break;
@@ -96,10 +214,10 @@ impl Mdct {
 
         loop {
             t           -= 4;
-            buf[o_x + 0]  = buf[i_x + 4] * tri[t + 3] + buf[i_x + 6] * tri[t + 2];
-            buf[o_x + 1]  = buf[i_x + 4] * tri[t + 2] - buf[i_x + 6] * tri[t + 3];
-            buf[o_x + 2]  = buf[i_x + 0] * tri[t + 1] + buf[i_x + 2] * tri[t + 0];
-            buf[o_x + 3]  = buf[i_x + 0] * tri[t + 0] - buf[i_x + 2] * tri[t + 1];
+            buf[o_x + 0]  = mul_add(buf[i_x + 4], tri[t + 3], buf[i_x + 6] * tri[t + 2]);
+            buf[o_x + 1]  = mul_add(buf[i_x + 4], tri[t + 2], -(buf[i_x + 6] * tri[t + 3]));
+            buf[o_x + 2]  = mul_add(buf[i_x + 0], tri[t + 1], buf[i_x + 2] * tri[t + 0]);
+            buf[o_x + 3]  = mul_add(buf[i_x + 0], tri[t + 0], -(buf[i_x + 2] * tri[t + 1]));
 
             if i_x < 8 {
                 break;
@@ -122,17 +240,17 @@ impl Mdct {
         loop {
             o_x1    -= 4;
 
-            buf[o_x1 + 3]  =   buf[i_x + 0] * tri[t + 1] - buf[i_x + 1] * tri[t + 0];
-            buf[o_x2 + 0]  = -(buf[i_x + 0] * tri[t + 0] + buf[i_x + 1] * tri[t + 1]);
+            buf[o_x1 + 3]  = mul_add(buf[i_x + 0], tri[t + 1], -(buf[i_x + 1] * tri[t + 0]));
+            buf[o_x2 + 0]  = -mul_add(buf[i_x + 0], tri[t + 0], buf[i_x + 1] * tri[t + 1]);
 
-            buf[o_x1 + 2]  =   buf[i_x + 2] * tri[t + 3] - buf[i_x + 3] * tri[t + 2];
-            buf[o_x2 + 1]  = -(buf[i_x + 2] * tri[t + 2] + buf[i_x + 3] * tri[t + 3]);
+            buf[o_x1 + 2]  = mul_add(buf[i_x + 2], tri[t + 3], -(buf[i_x + 3] * tri[t + 2]));
+            buf[o_x2 + 1]  = -mul_add(buf[i_x + 2], tri[t + 2], buf[i_x + 3] * tri[t + 3]);
 
-            buf[o_x1 + 1]  =   buf[i_x + 4] * tri[t + 5] - buf[i_x + 5] * tri[t + 4];
-            buf[o_x2 + 2]  = -(buf[i_x + 4] * tri[t + 4] + buf[i_x + 5] * tri[t + 5]);
+            buf[o_x1 + 1]  = mul_add(buf[i_x + 4], tri[t + 5], -(buf[i_x + 5] * tri[t + 4]));
+            buf[o_x2 + 2]  = -mul_add(buf[i_x + 4], tri[t + 4], buf[i_x + 5] * tri[t + 5]);
 
-            buf[o_x1 + 0]  =   buf[i_x + 6] * tri[t + 7] - buf[i_x + 7] * tri[t + 6];
-            buf[o_x2 + 3]  = -(buf[i_x + 6] * tri[t + 6] + buf[i_x + 7] * tri[t + 7]);
+            buf[o_x1 + 0]  = mul_add(buf[i_x + 6], tri[t + 7], -(buf[i_x + 7] * tri[t + 6]));
+            buf[o_x2 + 3]  = -mul_add(buf[i_x + 6], tri[t + 6], buf[i_x + 7] * tri[t + 7]);
 
             o_x2    += 4;
             i_x     += 8;
@@ -191,7 +309,79 @@ impl Mdct {
         }
     }
 
-    fn butterflies(&self, x: &mut [f32]) {
+    fn forward_fast(&self, input: &[Sample], output: &mut [Sample]) {
+        let n = self.len;
+        let n2 = n >> 1;
+        let n4 = n >> 2;
+        let n8 = n >> 3;
+
+        let tri = &self.trig;
+
+        let mut w = vec![0.0; n];
+
+        /* rotate + step 1 */
+        let mut x0 = n2 + n4;
+        let mut x1 = x0 + 1;
+        let mut t = n2;
+        let mut i = 0;
+
+        while i < n8 {
+            x0      -= 4;
+            t      -= 2;
+            let r0   = input[x0 + 2] + input[x1 + 0];
+            let r1   = input[x0 + 0] + input[x1 + 2];
+            w[n2 + i]     = r1 * tri[t + 1] + r0 * tri[t + 0];
+            w[n2 + i + 1] = r1 * tri[t + 0] - r0 * tri[t + 1];
+            x1      += 4;
+            i       += 2;
+        }
+
+        let mut x1 = 1;
+
+        while i < n2 - n8 {
+            t      -= 2;
+            x0      -= 4;
+            let r0   = input[x0 + 2] - input[x1 + 0];
+            let r1   = input[x0 + 0] - input[x1 + 2];
+            w[n2 + i]     = r1 * tri[t + 1] + r0 * tri[t + 0];
+            w[n2 + i + 1] = r1 * tri[t + 0] - r0 * tri[t + 1];
+            x1      += 4;
+            i       += 2;
+        }
+
+        let mut x0 = n;
+
+        while i < n2 {
+            t      -= 2;
+            x0      -= 4;
+            let r0   = -input[x0 + 2] - input[x1 + 0];
+            let r1   = -input[x0 + 0] - input[x1 + 2];
+            w[n2 + i]     = r1 * tri[t + 1] + r0 * tri[t + 0];
+            w[n2 + i + 1] = r1 * tri[t + 0] - r0 * tri[t + 1];
+            x1      += 4;
+            i       += 2;
+        }
+
+        self.butterflies(&mut w[n2..]);
+        self.bitreverse(&mut w);
+
+        /* rotate */
+
+        let scale = 4.0 / n as Sample;
+        let mut t = n2;
+        let mut w_x = 0;
+        let mut o_x = n2;
+
+        for i in 0..n4 {
+            o_x        -= 1;
+            output[i]   = (w[w_x + 0] * tri[t + 0] + w[w_x + 1] * tri[t + 1]) * scale;
+            output[o_x] = (w[w_x + 0] * tri[t + 1] - w[w_x + 1] * tri[t + 0]) * scale;
+            w_x        += 2;
+            t          += 2;
+        }
+    }
+
+    fn butterflies(&self, x: &mut [Sample]) {
         let stages = self.log2len - 5;
 
         if stages > 1 {
@@ -216,7 +406,7 @@ impl Mdct {
 
     /* N point first stage butterfly */
     #[inline]
-    fn butterfly_first(&self, x: &mut [f32]) {
+    fn butterfly_first(&self, x: &mut [Sample]) {
         let tri = &self.trig;
         let mut t = 0;
         let mut x1 = x.len() - 8;
@@ -227,29 +417,29 @@ impl Mdct {
             let r1      = x[x1 + 7]      -  x[x2 + 7];
             x[x1 + 6]  += x[x2 + 6];
             x[x1 + 7]  += x[x2 + 7];
-            x[x2 + 6]   = r1 * tri[t + 1]  +  r0 * tri[t + 0];
-            x[x2 + 7]   = r1 * tri[t + 0]  -  r0 * tri[t + 1];
+            x[x2 + 6]   = mul_add(r1, tri[t + 1], r0 * tri[t + 0]);
+            x[x2 + 7]   = mul_add(r1, tri[t + 0], -(r0 * tri[t + 1]));
 
             let r0      = x[x1 + 4]      -  x[x2 + 4];
             let r1      = x[x1 + 5]      -  x[x2 + 5];
             x[x1 + 4]  += x[x2 + 4];
             x[x1 + 5]  += x[x2 + 5];
-            x[x2 + 4]   = r1 * tri[t + 5]  +  r0 * tri[t + 4];
-            x[x2 + 5]   = r1 * tri[t + 4]  -  r0 * tri[t + 5];
+            x[x2 + 4]   = mul_add(r1, tri[t + 5], r0 * tri[t + 4]);
+            x[x2 + 5]   = mul_add(r1, tri[t + 4], -(r0 * tri[t + 5]));
 
             let r0      = x[x1 + 2]      -  x[x2 + 2];
             let r1      = x[x1 + 3]      -  x[x2 + 3];
             x[x1 + 2]  += x[x2 + 2];
             x[x1 + 3]  += x[x2 + 3];
-            x[x2 + 2]   = r1 * tri[t + 9]  +  r0 * tri[t + 8];
-            x[x2 + 3]   = r1 * tri[t + 8]  -  r0 * tri[t + 9];
+            x[x2 + 2]   = mul_add(r1, tri[t + 9], r0 * tri[t + 8]);
+            x[x2 + 3]   = mul_add(r1, tri[t + 8], -(r0 * tri[t + 9]));
 
             let r0      = x[x1 + 0]      -  x[x2 + 0];
             let r1      = x[x1 + 1]      -  x[x2 + 1];
             x[x1 + 0]  += x[x2 + 0];
             x[x1 + 1]  += x[x2 + 1];
-            x[x2 + 0]   = r1 * tri[t + 13] +  r0 * tri[t + 12];
-            x[x2 + 1]   = r1 * tri[t + 12] -  r0 * tri[t + 13];
+            x[x2 + 0]   = mul_add(r1, tri[t + 13], r0 * tri[t + 12]);
+            x[x2 + 1]   = mul_add(r1, tri[t + 12], -(r0 * tri[t + 13]));
 
             if x2 < 8 {
                 break;
@@ -263,7 +453,7 @@ impl Mdct {
 
     /* N/stage point generic N stage butterfly */
     #[inline]
-    fn butterfly_generic(&self, x: &mut [f32], trigint: usize) {
+    fn butterfly_generic(&self, x: &mut [Sample], trigint: usize) {
         let tri = &self.trig;
 
         let mut x1 = x.len() - 8;
@@ -275,8 +465,8 @@ impl Mdct {
             let r1      = x[x1 + 7]      -  x[x2 + 7];
             x[x1 + 6]  += x[x2 + 6];
             x[x1 + 7]  += x[x2 + 7];
-            x[x2 + 6]   = r1 * tri[t + 1]  +  r0 * tri[t + 0];
-            x[x2 + 7]   = r1 * tri[t + 0]  -  r0 * tri[t + 1];
+            x[x2 + 6]   = mul_add(r1, tri[t + 1], r0 * tri[t + 0]);
+            x[x2 + 7]   = mul_add(r1, tri[t + 0], -(r0 * tri[t + 1]));
 
             t += trigint;
 
@@ -284,8 +474,8 @@ impl Mdct {
             let r1      = x[x1 + 5]      -  x[x2 + 5];
             x[x1 + 4]  += x[x2 + 4];
             x[x1 + 5]  += x[x2 + 5];
-            x[x2 + 4]   = r1 * tri[t + 1]  +  r0 * tri[t + 0];
-            x[x2 + 5]   = r1 * tri[t + 0]  -  r0 * tri[t + 1];
+            x[x2 + 4]   = mul_add(r1, tri[t + 1], r0 * tri[t + 0]);
+            x[x2 + 5]   = mul_add(r1, tri[t + 0], -(r0 * tri[t + 1]));
 
             t += trigint;
 
@@ -293,8 +483,8 @@ impl Mdct {
             let r1      = x[x1 + 3]      -  x[x2 + 3];
             x[x1 + 2]  += x[x2 + 2];
             x[x1 + 3]  += x[x2 + 3];
-            x[x2 + 2]   = r1 * tri[t + 1]  +  r0 * tri[t + 0];
-            x[x2 + 3]   = r1 * tri[t + 0]  -  r0 * tri[t + 1];
+            x[x2 + 2]   = mul_add(r1, tri[t + 1], r0 * tri[t + 0]);
+            x[x2 + 3]   = mul_add(r1, tri[t + 0], -(r0 * tri[t + 1]));
 
             t += trigint;
 
@@ -302,8 +492,8 @@ impl Mdct {
             let r1      = x[x1 + 1]      -  x[x2 + 1];
             x[x1 + 0]  += x[x2 + 0];
             x[x1 + 1]  += x[x2 + 1];
-            x[x2 + 0]   = r1 * tri[t + 1]  +  r0 * tri[t + 0];
-            x[x2 + 1]   = r1 * tri[t + 0]  -  r0 * tri[t + 1];
+            x[x2 + 0]   = mul_add(r1, tri[t + 1], r0 * tri[t + 0]);
+            x[x2 + 1]   = mul_add(r1, tri[t + 0], -(r0 * tri[t + 1]));
 
             t+=trigint;
             if x2 < 8 {
@@ -316,7 +506,7 @@ impl Mdct {
 
     /* 8 point butterfly */
     #[inline]
-    fn butterfly_8(x: &mut [f32]) {
+    fn butterfly_8(x: &mut [Sample]) {
         let r0   = x[6] + x[2];
         let r1   = x[6] - x[2];
         let r2   = x[4] + x[0];
@@ -340,7 +530,7 @@ impl Mdct {
 
     /* 16 point butterfly */
     #[inline]
-    fn butterfly_16(x: &mut [f32]){
+    fn butterfly_16(x: &mut [Sample]){
         let r0     = x[1]  - x[9];
         let r1     = x[0]  - x[8];
 
@@ -377,7 +567,7 @@ impl Mdct {
 
     /* 32 point butterfly */
     #[inline]
-    fn butterfly_32(x: &mut [f32]) {
+    fn butterfly_32(x: &mut [Sample]) {
         let r0 = x[30] - x[14];
         let r1 = x[31] - x[15];
 
@@ -439,7 +629,7 @@ impl Mdct {
         Self::butterfly_16(&mut x[16..]);
     }
 
-    fn bitreverse(&self, x: &mut [f32]){
+    fn bitreverse(&self, x: &mut [Sample]){
         let n       = self.len;
         let n2 = n >> 1;
         let brv = &self.bitrev;
@@ -497,52 +687,148 @@ impl Mdct {
 
 #[cfg(test)]
 mod tests {
-    use std::f32;
-    use std::f32::consts::PI;
+    use std::f64::consts::PI;
 
     use super::*;
 
-    pub fn inverse_mdct_slow(buf: &mut [f32]) {
-        assert!(buf.len() % 2 == 0);
-        let n = buf.len();
-        let n2 = n / 2;
-        let inp = buf[..n2].as_ref().to_vec();
-        for i in 0..n {
-            let mut acc = 0_f32;
-            for (j, x) in inp.iter().enumerate() {
-                let n = n as f32;
-                acc += x * (PI / 2.0 / n * (2.0 * i as f32 + 1.0 + n / 2.0) * (2.0 * j as f32 + 1.0)).cos();
+    // The slow references are computed in f64 for precision independent of Sample.
+    pub fn inverse_mdct_slow(input: &[Sample]) -> Vec<f64> {
+        let n = input.len() * 2;
+        (0..n).map(|i| {
+            let mut acc = 0.0;
+            for (j, &x) in input.iter().enumerate() {
+                let n = n as f64;
+                acc += x as f64 * (PI / 2.0 / n * (2.0 * i as f64 + 1.0 + n / 2.0) * (2.0 * j as f64 + 1.0)).cos();
             }
-            buf[i] = acc;
-        }
+            acc
+        }).collect()
     }
 
-    static INPUT: [f32; 64] = [-0.69401383, 0.03862691, -0.55153704, -0.78269863, -0.09741044, -0.49561787, 0.42875743, -0.19526768, -0.06347418, -0.00010037422, 0.6325817, -0.48571062, -0.8504288, -0.28039575, -0.6088922, 0.95481896, -0.1591835, 0.9108696, -0.54748464, -0.11515808, -0.985873, -0.1792016, 0.10024643, -0.65555835, 0.4586711, -0.28872848, 0.09826708, -0.19525862, 0.833838, -0.36552095, 0.037439585, 0.40315723, -0.96927285, 0.41392016, 0.408257, 0.15481758, 0.9985726, -0.98773885, 0.82968235, 0.46624875, 0.49264956, 0.11497569, -0.006861925, -0.9980333, -0.22240639, -0.6312058, 0.4906652, -0.010108948, -0.8477638, -0.056087017, -0.7326493, -0.73279214, -0.68954086, -0.4644475, 0.6687648, 0.62569046, -0.5956092, 0.9961209, -0.29823017, -0.03980136, -0.12348294, 0.83054876, 0.32812834, 0.3774073];
-    static EXPECTED: [f32; 128] = [-0.04398486, -0.104446724, -3.534832, 3.8501837, -0.14957228, 0.7534752, -2.6459243, 0.3395752, -0.40157068, 1.3667705, -1.5802002, -5.155503, -1.9898258, -0.3746807, 2.723372, -7.4657774, 1.1178919, 4.2596145, -4.2643995, 0.32841936, 0.72192276, 1.5253807, -5.8298798, -4.7367554, 2.3636713, 6.5154843, 3.032085, 2.8470132, 2.1626804, -6.993517, 2.662696, -0.41398838, 0.41398835, -2.6627026, 6.9935184, -2.1627154, -2.8469687, -3.032117, -6.51548, -2.363654, 4.7367563, 5.8298445, -1.5253813, -0.7219145, -0.32840136, 4.264414, -4.2596507, -1.1178186, 7.4657693, -2.7233686, 0.3747228, 1.9898224, 5.1555324, 1.5802336, -1.3667517, 0.40155572, -0.33958945, 2.6459208, -0.75346154, 0.149549, -3.8501651, 3.5348828, 0.104403034, 0.0439485, 4.050725, 0.5420946, 2.4831505, -0.5343465, 1.7392917, 0.9157535, -2.3912883, -1.3115467, 0.78983486, -4.5483594, -1.4655226, 3.1918535, 4.476434, -2.6109004, 4.347729, -5.4297366, -2.3821006, -2.3284597, -3.6841853, 3.1392276, 3.3745584, 0.91208255, -0.056582414, 0.049863316, 3.0820458, -3.0675306, 6.783364, -0.14948165, -2.019868, 4.173112, 1.8012438, 4.0068555, 4.0068464, 1.8012108, 4.173142, -2.019923, -0.14935923, 6.783282, -3.0675812, 3.0820832, 0.04983966, -0.056600958, 0.9120948, 3.3745806, 3.1391861, -3.684268, -2.3284128, -2.3821485, -5.4296513, 4.3477545, -2.6109486, 4.476468, 3.1918228, -1.4655291, -4.5483932, 0.7899155, -1.3116122, -2.3912494, 0.9158027, 1.7392603, -0.5343737, 2.483176, 0.542063, 4.0507092];
+    pub fn forward_mdct_slow(input: &[Sample]) -> Vec<f64> {
+        let n = input.len();
+        assert!(n % 2 == 0);
+        (0..n / 2).map(|k| {
+            let mut acc = 0.0;
+            for (i, &x) in input.iter().enumerate() {
+                let n = n as f64;
+                acc += x as f64 * (PI / 2.0 / n * (2.0 * i as f64 + 1.0 + n / 2.0) * (2.0 * k as f64 + 1.0)).cos();
+            }
+            acc
+        }).collect()
+    }
 
-    #[test] #[ignore]
-    fn test_inverse_mdct_slow() {
-        let mut actual = vec![0_f32; INPUT.len() * 2];
-        actual[..INPUT.len()].as_mut().clone_from_slice(&INPUT);
+    static INPUT: [Sample; 64] = [-0.69401383, 0.03862691, -0.55153704, -0.78269863, -0.09741044, -0.49561787, 0.42875743, -0.19526768, -0.06347418, -0.00010037422, 0.6325817, -0.48571062, -0.8504288, -0.28039575, -0.6088922, 0.95481896, -0.1591835, 0.9108696, -0.54748464, -0.11515808, -0.985873, -0.1792016, 0.10024643, -0.65555835, 0.4586711, -0.28872848, 0.09826708, -0.19525862, 0.833838, -0.36552095, 0.037439585, 0.40315723, -0.96927285, 0.41392016, 0.408257, 0.15481758, 0.9985726, -0.98773885, 0.82968235, 0.46624875, 0.49264956, 0.11497569, -0.006861925, -0.9980333, -0.22240639, -0.6312058, 0.4906652, -0.010108948, -0.8477638, -0.056087017, -0.7326493, -0.73279214, -0.68954086, -0.4644475, 0.6687648, 0.62569046, -0.5956092, 0.9961209, -0.29823017, -0.03980136, -0.12348294, 0.83054876, 0.32812834, 0.3774073];
+    static EXPECTED: [Sample; 128] = [-0.0439774468, -0.104448203, -3.53483825, 3.85017903, -0.14955571, 0.75347988, -2.64592206, 0.33957189, -0.401578258, 1.36677156, -1.58020448, -5.1555076, -1.98982115, -0.374696988, 2.72338202, -7.46575736, 1.11785506, 4.25961106, -4.2644085, 0.328419715, 0.721923563, 1.52538046, -5.82988722, -4.73675507, 2.36367091, 6.51546689, 3.03209177, 2.84697203, 2.16269811, -6.99350155, 2.66269335, -0.413992981, 0.413992981, -2.66269335, 6.99350155, -2.16269811, -2.84697203, -3.03209177, -6.51546689, -2.36367091, 4.73675507, 5.82988722, -1.52538046, -0.721923563, -0.328419715, 4.2644085, -4.25961106, -1.11785506, 7.46575736, -2.72338202, 0.374696988, 1.98982115, 5.1555076, 1.58020448, -1.36677156, 0.401578258, -0.33957189, 2.64592206, -0.75347988, 0.14955571, -3.85017903, 3.53483825, 0.104448203, 0.0439774468, 4.05071676, 0.542079658, 2.48318162, -0.534343583, 1.73928113, 0.91575664, -2.39129763, -1.3115428, 0.789888395, -4.54836157, -1.46553398, 3.19185035, 4.47646825, -2.61093577, 4.34772196, -5.4296904, -2.38207653, -2.32844805, -3.68424348, 3.13921067, 3.37456827, 0.912088013, -0.0565918613, 0.0498606855, 3.08207019, -3.06756138, 6.78328795, -0.14946368, -2.01989971, 4.17313276, 1.8012343, 4.0068415, 4.0068415, 1.8012343, 4.17313276, -2.01989971, -0.14946368, 6.78328795, -3.06756138, 3.08207019, 0.0498606855, -0.0565918613, 0.912088013, 3.37456827, 3.13921067, -3.68424348, -2.32844805, -2.38207653, -5.4296904, 4.34772196, -2.61093577, 4.47646825, 3.19185035, -1.46553398, -4.54836157, 0.789888395, -1.3115428, -2.39129763, 0.91575664, 1.73928113, -0.534343583, 2.48318162, 0.542079658, 4.05071676];
 
-        inverse_mdct_slow(&mut actual);
+    // Max absolute error relative to the max absolute value of the reference.
+    fn max_rel_error(actual: &[Sample], expected: &[f64], scale: f64) -> f64 {
+        let max = expected.iter().fold(0.0, |m: f64, &e| m.max((e * scale).abs()));
+        actual.iter().zip(expected.iter())
+                .fold(0.0, |m: f64, (&a, &e)| m.max((a as f64 - e * scale).abs())) / max
+    }
+
+    #[test]
+    fn test_inverse_mdct_slow() {
+        let actual = inverse_mdct_slow(&INPUT);
 
         assert_eq!(actual.len(), EXPECTED.len());
         for (&a, &e) in actual.iter().zip(EXPECTED.iter()) {
-            assert!((a - e).abs() < 1e-11);
+            assert!((a - e as f64).abs() < 1e-5);
         }
     }
 
     #[test]
     fn inverse() {
-        let mut actual = vec![0_f32; INPUT.len() * 2];
+        let mut actual = vec![0.0; INPUT.len() * 2];
         actual[..INPUT.len()].as_mut().clone_from_slice(&INPUT);
 
         Mdct::new(actual.len()).inverse(&mut actual);
 
         assert_eq!(actual.len(), EXPECTED.len());
         for (&a, &e) in actual.iter().zip(EXPECTED.iter()) {
-            assert!((a - e).abs() < 1e-3);
+            assert!((a - e).abs() < 1e-5, "{} != {}", a, e);
+        }
+
+        for &len in &[256, 2048] {
+            let input: Vec<Sample> = (0..len / 2).map(|i| ((i * 7919) % 201) as Sample / 100.0 - 1.0).collect();
+            let expected = inverse_mdct_slow(&input);
+
+            let mut actual = vec![0.0; len];
+            actual[..len / 2].clone_from_slice(&input);
+            Mdct::new(len).inverse(&mut actual);
+
+            let e = max_rel_error(&actual, &expected, 1.0);
+            assert!(e < 1e-6, "{}", e);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn small_lens() {
+        for &len in &[2, 4, 8, 16, 32] {
+            let input: Vec<Sample> = (0..len).map(|i| ((i * 7919) % 201) as Sample / 100.0 - 1.0).collect();
+            let mdct = Mdct::new(len);
+            assert_eq!(mdct.len(), len);
+
+            let expected = inverse_mdct_slow(&input[..len / 2]);
+            let mut actual = input.clone();
+            mdct.inverse(&mut actual);
+            let e = max_rel_error(&actual, &expected, 1.0);
+            assert!(e < 1e-6, "{}", e);
+
+            let expected = forward_mdct_slow(&input);
+            let mut actual = vec![0.0; len / 2];
+            mdct.forward(&input, &mut actual);
+            let e = max_rel_error(&actual, &expected, 4.0 / len as f64);
+            assert!(e < 1e-6, "{}", e);
+        }
+    }
+    #[test]
+    fn forward_inverse_reconstructs() {
+        use window::Windows;
+
+        for &len in &[16, 256] {
+            let mdct = Mdct::new(len);
+            let slope = Windows::make_slope(len / 2);
+            let window: Vec<Sample> = slope.iter().chain(slope.iter().rev()).cloned().collect();
+            let input: Vec<Sample> = (0..len * 2).map(|i| ((i * 7919) % 201) as Sample / 100.0 - 1.0).collect();
+
+            let mut output = vec![0.0; len * 2];
+            for start in (0..len + 1).filter(|s| s % (len / 2) == 0) {
+                let frame: Vec<Sample> = input[start..start + len].iter().zip(window.iter())
+                        .map(|(&x, &w)| x * w).collect();
+                let mut buf = vec![0.0; len];
+                mdct.forward(&frame, &mut buf[..len / 2]);
+                mdct.inverse(&mut buf);
+                for (o, (&y, &w)) in output[start..].iter_mut().zip(buf.iter().zip(window.iter())) {
+                    *o += y * w;
+                }
+            }
+
+            // Only the middle section has both overlapping halves.
+            for (&a, &e) in output[len / 2..len * 3 / 2].iter().zip(input[len / 2..].iter()) {
+                assert!((a - e).abs() < 1e-3, "{} != {}", a, e);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn non_power_of_two_len() {
+        Mdct::new(96);
+    }
+
+    #[test]
+    fn forward() {
+        for &len in &[64, 256, 2048] {
+            let input: Vec<Sample> = (0..len).map(|i| ((i * 7919) % 201) as Sample / 100.0 - 1.0).collect();
+
+            let expected = forward_mdct_slow(&input);
+
+            let mut actual = vec![0.0; len / 2];
+            Mdct::new(len).forward(&input, &mut actual);
+
+            let e = max_rel_error(&actual, &expected, 4.0 / len as f64);
+            assert!(e < 1e-6, "{}", e);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Inverse MDCT computed via an `len / 4` point complex FFT provided by [rustfft].
+//! [rustfft]: https://crates.io/crates/rustfft
+//!
+//! The `len / 2` input coefficients are first transformed with DCT-IV which is computed by
+//! folding the coefficients into `len / 4` complex values, pre-twiddling, FFT and post-twiddling.
+//! The IMDCT output is then unfolded from DCT-IV output using its symmetries.
+
+use rustfft::{Fft, FftPlanner};
+use rustfft::num_complex::Complex;
+use std::f64::consts::PI;
+use std::sync::{Arc, Mutex};
+
+use sample::Sample;
+
+// Computed in f64 regardless of the Sample type for better precision.
+fn twiddle(angle: f64) -> Complex<Sample> {
+    Complex::new(angle.cos() as Sample, angle.sin() as Sample)
+}
+
+// Buffers of a single `inverse()` call.
+struct Scratch {
+    z: Box<[Complex<Sample>]>,
+    fft: Box<[Complex<Sample>]>,
+}
+
+pub struct FftMdct {
+    len: usize,
+    fft: Arc<dyn Fft<Sample>>,
+    pre_twiddle: Box<[Complex<Sample>]>,
+    post_twiddle: Box<[Complex<Sample>]>,
+    // The MDCT is shared between the decoders and the channels decoded in parallel, so each
+    // call takes the buffers from the pool and puts them back. Only the concurrent calls
+    // allocate until the pool has enough buffers for them.
+    scratch: Mutex<Vec<Scratch>>,
+}
+
+impl FftMdct {
+    pub fn new(len: usize) -> Self {
+        assert!(len >= 32 && len % 4 == 0);
+        let n2 = len / 2;
+        let n4 = len / 4;
+
+        let fft = FftPlanner::new().plan_fft_forward(n4);
+        let pre_twiddle: Vec<_> = (0..n4)
+                .map(|k| twiddle(-PI * (4 * k + 1) as f64 / (4 * n2) as f64))
+                .collect();
+        let post_twiddle: Vec<_> = (0..n4)
+                .map(|k| twiddle(-PI * k as f64 / n2 as f64))
+                .collect();
+
+        let mut r = FftMdct {
+            len: len,
+            fft: fft,
+            pre_twiddle: pre_twiddle.into_boxed_slice(),
+            post_twiddle: post_twiddle.into_boxed_slice(),
+            scratch: Mutex::new(Vec::new()),
+        };
+        let scratch = r.new_scratch();
+        r.scratch.get_mut().unwrap().push(scratch);
+        r
+    }
+
+    fn new_scratch(&self) -> Scratch {
+        let zero = Complex::new(0.0, 0.0);
+        Scratch {
+            z: vec![zero; self.len / 4].into_boxed_slice(),
+            fft: vec![zero; self.fft.get_inplace_scratch_len()].into_boxed_slice(),
+        }
+    }
+
+    pub fn inverse(&self, buf: &mut [Sample]) {
+        assert!(buf.len() == self.len);
+        let scratch = self.scratch.lock().unwrap().pop();
+        let mut scratch = scratch.unwrap_or_else(|| self.new_scratch());
+        self.transform(buf, &mut scratch);
+        self.scratch.lock().unwrap().push(scratch);
+    }
+
+    fn transform(&self, buf: &mut [Sample], scratch: &mut Scratch) {
+        let n = self.len;
+        let n2 = n >> 1;
+        let n4 = n >> 2;
+
+        let z = &mut scratch.z;
+        for (k, (v, &w)) in z.iter_mut().zip(self.pre_twiddle.iter()).enumerate() {
+            *v = Complex::new(buf[k * 2], buf[n2 - 1 - k * 2]) * w;
+        }
+
+        self.fft.process_with_scratch(z, &mut scratch.fft);
+
+        for (v, &w) in z.iter_mut().zip(self.post_twiddle.iter()) {
+            *v = *v * w;
+        }
+
+        // DCT-IV output: u[2k] = re(z[k]), u[n2 - 1 - 2k] = -im(z[k]).
+        let dct4 = |i: usize| if i % 2 == 0 {
+            z[i / 2].re
+        } else {
+            -z[(n2 - 1 - i) / 2].im
+        };
+
+        for i in 0..n4 {
+            buf[i] = dct4(i + n4);
+        }
+        for i in n4..n2 + n4 {
+            buf[i] = -dct4(n2 + n4 - 1 - i);
+        }
+        for i in n2 + n4..n {
+            buf[i] = -dct4(i - n2 - n4);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use sample::Sample;
+
+    use super::*;
+
+    // Computed in f64 since f32 isn't accurate enough for the larger lengths.
+    fn inverse_mdct_slow(input: &[Sample]) -> Vec<f64> {
+        let n = input.len() * 2;
+        (0..n).map(|i| {
+            input.iter().enumerate().map(|(j, &x)| {
+                let n = n as f64;
+                x as f64 * (PI / 2.0 / n * (2.0 * i as f64 + 1.0 + n / 2.0) * (2.0 * j as f64 + 1.0)).cos()
+            }).fold(0.0, |acc, v| acc + v)
+        }).collect()
+    }
+
+    #[test]
+    fn inverse() {
+        for &len in &[64, 256, 2048] {
+            let input: Vec<Sample> = (0..len / 2).map(|i| ((i * 7919) % 201) as Sample / 100.0 - 1.0).collect();
+            let expected = inverse_mdct_slow(&input);
+
+            let mut actual = vec![0.0; len];
+            actual[..len / 2].clone_from_slice(&input);
+            let mdct = FftMdct::new(len);
+            mdct.inverse(&mut actual);
+
+            let max = expected.iter().fold(0.0, |m: f64, &e| m.max(e.abs()));
+            for (&a, &e) in actual.iter().zip(expected.iter()) {
+                assert!((a as f64 - e).abs() / max < 1e-6, "{} != {}", a, e);
+            }
+
+            // The buffers are reused by the next call.
+            let mut again = vec![0.0; len];
+            again[..len / 2].clone_from_slice(&input);
+            mdct.inverse(&mut again);
+            assert_eq!(again, actual);
+        }
+    }
+}
@@ -0,0 +1,448 @@
+//! Typed accessors for the common comment conventions.
+
+use std::collections::BTreeMap;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+use std::time::Duration;
+
+use comment::Comments;
+use tag::CommentTag;
+
+/// Chapter defined by the `CHAPTERxxx=HH:MM:SS.mmm` and `CHAPTERxxxNAME=name` comments.
+/// [Chapter extension]: https://wiki.xiph.org/Chapter_Extension
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Chapter {
+    number: u32,
+    start: Duration,
+    name: Option<String>,
+}
+
+impl Chapter {
+    /// Returns the number from the `CHAPTERxxx` tag.
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    pub fn start(&self) -> Duration {
+        self.start
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(|s| s.as_str())
+    }
+}
+
+/// ReplayGain values from the `REPLAYGAIN_TRACK_GAIN`, `REPLAYGAIN_TRACK_PEAK`,
+/// `REPLAYGAIN_ALBUM_GAIN` and `REPLAYGAIN_ALBUM_PEAK` comments.
+/// [ReplayGain]: https://wiki.xiph.org/VorbisComment#Replay_Gain
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReplayGain {
+    track_gain: Option<f32>,
+    track_peak: Option<f32>,
+    album_gain: Option<f32>,
+    album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    /// Returns the track gain in dB.
+    pub fn track_gain(&self) -> Option<f32> {
+        self.track_gain
+    }
+
+    /// Returns the track peak amplitude where 1.0 is the full scale.
+    pub fn track_peak(&self) -> Option<f32> {
+        self.track_peak
+    }
+
+    /// Returns the album gain in dB.
+    pub fn album_gain(&self) -> Option<f32> {
+        self.album_gain
+    }
+
+    /// Returns the album peak amplitude where 1.0 is the full scale.
+    pub fn album_peak(&self) -> Option<f32> {
+        self.album_peak
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Date from the `DATE` comment. Only the date part is parsed, the time part (if any) is
+/// ignored.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Date {
+    year: u32,
+    month: Option<u32>,
+    day: Option<u32>,
+}
+
+impl Date {
+    pub fn year(&self) -> u32 {
+        self.year
+    }
+
+    /// Returns the month in range `1..13`.
+    pub fn month(&self) -> Option<u32> {
+        self.month
+    }
+
+    /// Returns the day of month in range `1..32`. Can be `Some` only if the month is `Some`.
+    pub fn day(&self) -> Option<u32> {
+        self.day
+    }
+}
+
+/// Loop points from the `LOOPSTART` and `LOOPLENGTH` or `LOOPEND` comments, the convention of
+/// the game engines (e.g. RPG Maker) for music with an intro: after reaching the loop end the
+/// playback continues from the loop start. The positions are in samples (per channel) from the
+/// beginning of the stream.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LoopPoints {
+    start: u64,
+    end: Option<u64>,
+}
+
+impl LoopPoints {
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Returns the position right after the last sample of the loop or `None` if the loop lasts
+    /// until the end of the stream.
+    pub fn end(&self) -> Option<u64> {
+        self.end
+    }
+
+    /// Returns the number of samples (per channel) in the loop if the end is known.
+    pub fn len(&self) -> Option<u64> {
+        self.end.map(|end| end - self.start)
+    }
+}
+
+impl Comments {
+    /// Returns track number and optional total track count parsed from the `TRACKNUMBER`
+    /// comment. Both `3` and `3/12` formats are supported, if the total isn't specified in
+    /// `TRACKNUMBER` the `TRACKTOTAL` or `TOTALTRACKS` comment is used.
+    pub fn track_number(&self) -> Option<(u32, Option<u32>)> {
+        self.number_with_total(CommentTag::TrackNumber, &["TRACKTOTAL", "TOTALTRACKS"])
+    }
+
+    /// Same as `track_number()` but for the `DISCNUMBER` and `DISCTOTAL` or `TOTALDISCS`
+    /// comments.
+    pub fn disc_number(&self) -> Option<(u32, Option<u32>)> {
+        self.number_with_total(CommentTag::DiscNumber, &["DISCTOTAL", "TOTALDISCS"])
+    }
+
+    /// Returns date parsed from the `DATE` comment. The supported formats are `YYYY`,
+    /// `YYYY-MM` and `YYYY-MM-DD` optionally followed by time separated with `T` or space.
+    pub fn date(&self) -> Option<Date> {
+        self.by_tag(CommentTag::Date).filter_map(parse_date).next()
+    }
+
+    fn number_with_total(&self, tag: CommentTag, total_tags: &[&str]) -> Option<(u32, Option<u32>)> {
+        let (number, total) = match self.by_tag(tag).filter_map(parse_number_with_total).next() {
+            Some(v) => v,
+            None => return None,
+        };
+        let total = total.or_else(|| total_tags.iter()
+            .flat_map(|&t| self.by_tag(CommentTag::Custom(t)))
+            .filter_map(|v| v.trim().parse().ok())
+            .next());
+        Some((number, total))
+    }
+
+    /// Returns the ReplayGain values. Missing or unparsable values are `None`. The gain values
+    /// may have the `dB` suffix (as in `-6.5 dB`) which is ignored.
+    pub fn replay_gain(&self) -> ReplayGain {
+        let first = |tag| self.by_tag(CommentTag::Custom(tag)).filter_map(parse_gain).next();
+        ReplayGain {
+            track_gain: first("REPLAYGAIN_TRACK_GAIN"),
+            track_peak: first("REPLAYGAIN_TRACK_PEAK"),
+            album_gain: first("REPLAYGAIN_ALBUM_GAIN"),
+            album_peak: first("REPLAYGAIN_ALBUM_PEAK"),
+        }
+    }
+
+    /// Returns the loop points parsed from the `LOOPSTART` and `LOOPLENGTH` comments, or
+    /// `LOOPEND` (the end position, exclusive) if there's no valid `LOOPLENGTH`. Returns `None`
+    /// if there's no valid `LOOPSTART`. An empty loop or a loop ending before the start is
+    /// ignored, the loop lasts until the end of the stream then.
+    pub fn loop_points(&self) -> Option<LoopPoints> {
+        let first = |tag| self.by_tag(CommentTag::Custom(tag))
+            .filter_map(|v| v.trim().parse::<u64>().ok())
+            .next();
+        let start = match first("LOOPSTART") {
+            Some(v) => v,
+            None => return None,
+        };
+        let end = first("LOOPLENGTH").and_then(|len| start.checked_add(len))
+            .or_else(|| first("LOOPEND"))
+            .and_then(|end| if end > start { Some(end) } else { None });
+        Some(LoopPoints {
+            start: start,
+            end: end,
+        })
+    }
+
+    /// Returns chapters sorted by the start time. Chapters without valid start time are skipped.
+    pub fn chapters(&self) -> Vec<Chapter> {
+        let mut chapters = BTreeMap::new();
+        for (tag, value) in self.iter() {
+            let tag = tag.as_ref();
+            if tag.len() <= 7 || !tag.as_bytes()[..7].eq_ignore_ascii_case(b"CHAPTER") {
+                continue;
+            }
+            let tag = &tag[7..];
+            let digit_count = tag.bytes().take_while(|b| b.is_ascii_digit()).count();
+            let number = match tag[..digit_count].parse::<u32>() {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            let suffix = &tag[digit_count..];
+            let entry = chapters.entry(number).or_insert((None, None));
+            if suffix.is_empty() {
+                entry.0 = parse_timestamp(value);
+            } else if suffix.eq_ignore_ascii_case("NAME") {
+                entry.1 = Some(value.to_owned());
+            }
+        }
+
+        let mut r: Vec<_> = chapters.into_iter()
+            .filter_map(|(number, (start, name))| start.map(|start| Chapter {
+                number: number,
+                start: start,
+                name: name,
+            }))
+            .collect();
+        r.sort_by_key(|c| (c.start, c.number));
+        r
+    }
+}
+
+// Parses `N` or `N/TOTAL`.
+fn parse_number_with_total(s: &str) -> Option<(u32, Option<u32>)> {
+    let mut parts = s.splitn(2, '/');
+    let number = match parts.next().and_then(|s| s.trim().parse().ok()) {
+        Some(v) => v,
+        None => return None,
+    };
+    let total = match parts.next() {
+        Some(s) => match s.trim().parse() {
+            Ok(v) => Some(v),
+            Err(_) => return None,
+        },
+        None => None,
+    };
+    Some((number, total))
+}
+
+// Parses `YYYY[-MM[-DD]]` optionally followed by time.
+fn parse_date(s: &str) -> Option<Date> {
+    let s = s.trim();
+    let s = match s.find(|c| c == 'T' || c == ' ') {
+        Some(i) => &s[..i],
+        None => s,
+    };
+    let mut parts = s.split('-').map(|p| if !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()) {
+        p.parse::<u32>().ok()
+    } else {
+        None
+    });
+    let year = match parts.next() {
+        Some(Some(v)) => v,
+        _ => return None,
+    };
+    let (month, day) = match (parts.next(), parts.next(), parts.next()) {
+        (None, None, None) => (None, None),
+        (Some(Some(m)), None, None) if m >= 1 && m <= 12 => (Some(m), None),
+        (Some(Some(m)), Some(Some(d)), None) if m >= 1 && m <= 12 && d >= 1 && d <= 31
+            => (Some(m), Some(d)),
+        _ => return None,
+    };
+    Some(Date {
+        year: year,
+        month: month,
+        day: day,
+    })
+}
+
+// Parses number with optional `dB` suffix.
+fn parse_gain(s: &str) -> Option<f32> {
+    let s = s.trim();
+    let s = if s.len() >= 2 && s[s.len() - 2..].eq_ignore_ascii_case("dB") {
+        s[..s.len() - 2].trim_right()
+    } else {
+        s
+    };
+    s.parse().ok()
+}
+
+// Parses HH:MM:SS.mmm timestamp. The fractional part may have any number of digits (up to
+// nanoseconds) or be omitted.
+fn parse_timestamp(s: &str) -> Option<Duration> {
+    let mut parts = s.trim().split(':');
+    let (h, m, s) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(h), Some(m), Some(s), None) => (h, m, s),
+        _ => return None,
+    };
+    let (s, frac) = match s.find('.') {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+    let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !all_digits(h) || !all_digits(m) || !all_digits(s) {
+        return None;
+    }
+    let frac = match frac {
+        Some(frac) if all_digits(frac) && frac.len() <= 9 => frac,
+        Some(_) => return None,
+        None => "",
+    };
+    let (h, m, s) = match (h.parse::<u64>(), m.parse::<u64>(), s.parse::<u64>()) {
+        (Ok(h), Ok(m), Ok(s)) if m < 60 && s < 60 => (h, m, s),
+        _ => return None,
+    };
+    let nanos = if frac.is_empty() {
+        0
+    } else {
+        // Can't overflow since there are at most 9 digits.
+        frac.parse::<u32>().unwrap() * 10_u32.pow(9 - frac.len() as u32)
+    };
+    Some(Duration::new(h * 3600 + m * 60 + s, nanos))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use comment::Comments;
+use tag::CommentTag;
+
+    use super::*;
+
+    #[test]
+    fn parse_timestamp_() {
+        assert_eq!(parse_timestamp("00:00:00.000"), Some(Duration::new(0, 0)));
+        assert_eq!(parse_timestamp("01:02:03.5"), Some(Duration::new(3723, 500_000_000)));
+        assert_eq!(parse_timestamp("10:00:00"), Some(Duration::new(36000, 0)));
+        assert_eq!(parse_timestamp("00:00:01.000000001"), Some(Duration::new(1, 1)));
+        assert_eq!(parse_timestamp("00:60:00.000"), None);
+        assert_eq!(parse_timestamp("00:00.000"), None);
+        assert_eq!(parse_timestamp("00:00:00."), None);
+        assert_eq!(parse_timestamp("aa:00:00.000"), None);
+    }
+
+    #[test]
+    fn parse_number_with_total_() {
+        assert_eq!(parse_number_with_total("3"), Some((3, None)));
+        assert_eq!(parse_number_with_total("03/12"), Some((3, Some(12))));
+        assert_eq!(parse_number_with_total(" 3 / 12 "), Some((3, Some(12))));
+        assert_eq!(parse_number_with_total("3/"), None);
+        assert_eq!(parse_number_with_total("A1"), None);
+    }
+
+    #[test]
+    fn parse_date_() {
+        let date = |y, m, d| Some(Date { year: y, month: m, day: d });
+        assert_eq!(parse_date("2004"), date(2004, None, None));
+        assert_eq!(parse_date("2004-07"), date(2004, Some(7), None));
+        assert_eq!(parse_date("2004-07-31"), date(2004, Some(7), Some(31)));
+        assert_eq!(parse_date("2004-07-31T12:00:00Z"), date(2004, Some(7), Some(31)));
+        assert_eq!(parse_date("2004-07-31 12:00"), date(2004, Some(7), Some(31)));
+        assert_eq!(parse_date("2004-13"), None);
+        assert_eq!(parse_date("2004-07-00"), None);
+        assert_eq!(parse_date("July 2004"), None);
+        assert_eq!(parse_date("2004--31"), None);
+    }
+
+    #[test]
+    fn track_and_disc_number() {
+        let comments = Comments::builder()
+            .add(CommentTag::TrackNumber, "5")
+            .add(CommentTag::Custom("TOTALTRACKS"), "11")
+            .add(CommentTag::DiscNumber, "1/2")
+            .add(CommentTag::Custom("DISCTOTAL"), "3")
+            .add(CommentTag::Date, "1999-12-31")
+            .build();
+        assert_eq!(comments.track_number(), Some((5, Some(11))));
+        assert_eq!(comments.disc_number(), Some((1, Some(2))));
+        assert_eq!(comments.date(), Some(Date { year: 1999, month: Some(12), day: Some(31) }));
+
+        let comments = Comments::builder().add(CommentTag::TrackNumber, "x").build();
+        assert_eq!(comments.track_number(), None);
+        assert_eq!(comments.disc_number(), None);
+        assert_eq!(comments.date(), None);
+    }
+
+    #[test]
+    fn parse_gain_() {
+        assert_eq!(parse_gain("-6.5 dB"), Some(-6.5));
+        assert_eq!(parse_gain("+1.25dB"), Some(1.25));
+        assert_eq!(parse_gain(" 0.988 "), Some(0.988));
+        assert_eq!(parse_gain("3 DB"), Some(3.0));
+        assert_eq!(parse_gain("dB"), None);
+        assert_eq!(parse_gain("loud"), None);
+    }
+
+    #[test]
+    fn replay_gain() {
+        let comments = Comments::builder()
+            .add(CommentTag::Custom("replaygain_track_gain"), "-7.03 dB")
+            .add(CommentTag::Custom("REPLAYGAIN_TRACK_PEAK"), "0.99996948")
+            .add(CommentTag::Custom("REPLAYGAIN_ALBUM_GAIN"), "invalid")
+            .add(CommentTag::Custom("REPLAYGAIN_ALBUM_GAIN"), "-6.50 dB")
+            .build();
+        let rg = comments.replay_gain();
+        assert_eq!(rg.track_gain(), Some(-7.03));
+        assert_eq!(rg.track_peak(), Some(0.99996948));
+        assert_eq!(rg.album_gain(), Some(-6.5));
+        assert_eq!(rg.album_peak(), None);
+        assert!(!rg.is_empty());
+
+        assert!(Comments::builder().build().replay_gain().is_empty());
+    }
+
+    #[test]
+    fn chapters() {
+        let comments = Comments::builder()
+            .add(CommentTag::Custom("CHAPTER002"), "00:10:00.000")
+            .add(CommentTag::Custom("CHAPTER002NAME"), "Second")
+            .add(CommentTag::Custom("chapter001"), "00:00:00.000")
+            .add(CommentTag::Custom("CHAPTER001name"), "First")
+            .add(CommentTag::Custom("CHAPTER003NAME"), "No start")
+            .add(CommentTag::Custom("CHAPTER004"), "00:05:00.250")
+            .add(CommentTag::Custom("CHAPTERS"), "garbage")
+            .build();
+        assert_eq!(comments.chapters(), vec![
+            Chapter { number: 1, start: Duration::new(0, 0), name: Some("First".into()) },
+            Chapter { number: 4, start: Duration::new(300, 250_000_000), name: None },
+            Chapter { number: 2, start: Duration::new(600, 0), name: Some("Second".into()) },
+        ]);
+    }
+
+    #[test]
+    fn loop_points() {
+        let loop_points = |tags: &[(&'static str, &'static str)]| {
+            let mut builder = Comments::builder();
+            for &(tag, value) in tags {
+                builder.add(CommentTag::Custom(tag), value);
+            }
+            builder.build().loop_points().map(|l| (l.start(), l.end(), l.len()))
+        };
+        assert_eq!(loop_points(&[("LOOPSTART", "44100"), ("LOOPLENGTH", "88200")]),
+            Some((44100, Some(132300), Some(88200))));
+        assert_eq!(loop_points(&[("loopstart", " 100 "), ("LOOPEND", "300")]),
+            Some((100, Some(300), Some(200))));
+        // LOOPLENGTH takes precedence.
+        assert_eq!(loop_points(&[("LOOPSTART", "100"), ("LOOPEND", "300"), ("LOOPLENGTH", "50")]),
+            Some((100, Some(150), Some(50))));
+        assert_eq!(loop_points(&[("LOOPSTART", "100"), ("LOOPLENGTH", "x"), ("LOOPEND", "300")]),
+            Some((100, Some(300), Some(200))));
+        assert_eq!(loop_points(&[("LOOPSTART", "0")]), Some((0, None, None)));
+        assert_eq!(loop_points(&[("LOOPSTART", "100"), ("LOOPEND", "100")]), Some((100, None, None)));
+        assert_eq!(loop_points(&[("LOOPSTART", "-1"), ("LOOPLENGTH", "100")]), None);
+        assert_eq!(loop_points(&[("LOOPEND", "100")]), None);
+    }
+}
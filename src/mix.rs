@@ -0,0 +1,418 @@
+use std::collections::VecDeque;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use decoder::Samples;
+use header::ChannelLayout;
+use sample::Sample;
+
+// Gain of the channels split to both outputs of the stereo downmix: -3 dB keeps the power.
+const SPLIT_GAIN: Sample = 0.70710677;
+
+/// Sums the output of several decoders with per-source gain into one interleaved buffer.
+///
+/// Each source has a queue of the decoded samples pushed with `push()`. `mix()` only produces
+/// the samples all the sources have queued so the sources stay in sync regardless of their
+/// packet sizes. A source that has no more input is marked with `finish()`: it's treated as
+/// silence once its queue is drained and then removed. The sources must have the mixer's
+/// channel count and sample rate. The sum isn't clipped, lower the gains to avoid clipping
+/// when converting to integer samples.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::io::Cursor;
+/// use vorbis::{BitReader, Decoder, Mixer};
+///
+/// # fn f(mut music: Decoder, mut effect: Decoder, music_packets: Vec<Vec<u8>>,
+/// #         effect_packets: Vec<Vec<u8>>) {
+/// let mut mixer = Mixer::new(2);
+/// let music_source = mixer.add_source(0.7);
+/// let effect_source = mixer.add_source(1.0);
+/// for packet in &music_packets {
+///     mixer.push(music_source, &music.decode(&mut BitReader::new(Cursor::new(&packet[..])))
+///         .expect("Couldn't decode audio packet"));
+/// }
+/// for packet in &effect_packets {
+///     mixer.push(effect_source, &effect.decode(&mut BitReader::new(Cursor::new(&packet[..])))
+///         .expect("Couldn't decode audio packet"));
+/// }
+/// mixer.finish(effect_source);
+/// let mut buf = vec![0.0; 4096];
+/// let len = mixer.mix(&mut buf);
+/// // Play the buf[..len * 2].
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Mixer {
+    channel_count: usize,
+    sources: Vec<Option<Source>>,
+}
+
+#[derive(Clone, Debug)]
+struct Source {
+    gain: Sample,
+    // Interleaved samples.
+    queue: VecDeque<Sample>,
+    finished: bool,
+}
+
+impl Mixer {
+    /// Creates a mixer of sources with `channel_count` channels.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel count is zero.
+    pub fn new(channel_count: usize) -> Self {
+        assert!(channel_count > 0, "Channel count must be positive");
+        Mixer {
+            channel_count: channel_count,
+            sources: Vec::new(),
+        }
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channel_count
+    }
+
+    /// Adds a source with the `gain` applied to its samples. Returns the source index used by
+    /// the other methods. The indexes of the removed sources are reused.
+    pub fn add_source(&mut self, gain: Sample) -> usize {
+        let source = Source {
+            gain: gain,
+            queue: VecDeque::new(),
+            finished: false,
+        };
+        match self.sources.iter().position(|s| s.is_none()) {
+            Some(i) => {
+                self.sources[i] = Some(source);
+                i
+            }
+            None => {
+                self.sources.push(Some(source));
+                self.sources.len() - 1
+            }
+        }
+    }
+
+    /// Removes the source discarding its queued samples.
+    pub fn remove_source(&mut self, source: usize) {
+        self.sources[source] = None;
+    }
+
+    /// Returns whether the source exists, i.e. it was added and not removed explicitly or after
+    /// finishing.
+    pub fn has_source(&self, source: usize) -> bool {
+        self.sources.get(source).map(|s| s.is_some()).unwrap_or(false)
+    }
+
+    /// Returns the number of sources.
+    pub fn source_count(&self) -> usize {
+        self.sources.iter().filter(|s| s.is_some()).count()
+    }
+
+    pub fn set_gain(&mut self, source: usize, gain: Sample) {
+        self.source_mut(source).gain = gain;
+    }
+
+    /// Queues the decoded `samples` of the source.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the source doesn't exist or the channel count of the samples doesn't match.
+    pub fn push(&mut self, source: usize, samples: &Samples) {
+        assert_eq!(samples.channel_count(), self.channel_count, "Channel count mismatch");
+        self.source_mut(source).queue.extend(samples.interleave());
+    }
+
+    /// Same as `push()` but for interleaved samples.
+    pub fn push_interleaved(&mut self, source: usize, samples: &[Sample]) {
+        assert!(samples.len() % self.channel_count == 0,
+            "Sample count isn't a multiple of the channel count");
+        self.source_mut(source).queue.extend(samples.iter().cloned());
+    }
+
+    /// Marks the source as having no more input. It's removed when its queue is drained.
+    pub fn finish(&mut self, source: usize) {
+        self.source_mut(source).finished = true;
+    }
+
+    /// Returns the number of queued samples (per channel) of the source.
+    pub fn queued(&self, source: usize) -> usize {
+        self.sources[source].as_ref().expect("No such source").queue.len() / self.channel_count
+    }
+
+    /// Returns the number of samples (per channel) `mix()` can produce: the lowest queued count
+    /// of the unfinished sources, or the highest one if all the sources are finished.
+    pub fn available(&self) -> usize {
+        let sources = || self.sources.iter().filter_map(|s| s.as_ref());
+        let len = match sources().filter(|s| !s.finished).map(|s| s.queue.len()).min() {
+            Some(len) => len,
+            None => sources().map(|s| s.queue.len()).max().unwrap_or(0),
+        };
+        len / self.channel_count
+    }
+
+    /// Mixes the queued samples into the interleaved `buf` and returns the number of samples
+    /// (per channel) written, which is at most `available()`. The finished sources with drained
+    /// queues are removed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer length isn't a multiple of the channel count.
+    pub fn mix(&mut self, buf: &mut [Sample]) -> usize {
+        assert!(buf.len() % self.channel_count == 0,
+            "Buffer length isn't a multiple of the channel count");
+        let len = self.available().min(buf.len() / self.channel_count);
+        let buf = &mut buf[..len * self.channel_count];
+        for s in buf.iter_mut() {
+            *s = 0.0;
+        }
+        for slot in &mut self.sources {
+            let drained = if let Some(ref mut source) = *slot {
+                let n = source.queue.len().min(buf.len());
+                for (s, v) in buf.iter_mut().zip(source.queue.drain(..n)) {
+                    *s += v * source.gain;
+                }
+                source.finished && source.queue.is_empty()
+            } else {
+                false
+            };
+            if drained {
+                *slot = None;
+            }
+        }
+        len
+    }
+
+    fn source_mut(&mut self, source: usize) -> &mut Source {
+        self.sources[source].as_mut().expect("No such source")
+    }
+}
+
+/// Matrix of gains mixing the decoded channels (the inputs) into the output channels, e.g. to fold
+/// down a surround stream to stereo.
+///
+/// Each output sample is the sum of the input samples of the same instant multiplied by their
+/// gains for the output. Arbitrary matrices allow the bespoke fold-downs: the policy of the LFE
+/// channel handling, the dialog level of the center channel, or routing the channels to
+/// the outputs of a bigger layout. The sum isn't clipped, choose the gains so that the sum of
+/// the absolute gains of each output is at most 1 to avoid clipping.
+///
+/// # Example
+///
+/// ```rust
+/// use vorbis::{ChannelLayout, DownmixMatrix};
+///
+/// // 5.1 to stereo with the LFE channel at -6 dB.
+/// let mut matrix = DownmixMatrix::stereo(ChannelLayout::Surround5_1, 0.5);
+/// // Louder dialog.
+/// matrix.set_gain(1, 0, 1.0).set_gain(1, 1, 1.0);
+///
+/// let input = [0.1, 0.2, 0.3, 0.0, 0.0, 0.0];
+/// let mut output = [0.0; 2];
+/// assert_eq!(matrix.apply_interleaved(&input, &mut output), 1);
+/// assert!((output[0] - 0.3).abs() < 1e-6);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct DownmixMatrix {
+    input_count: usize,
+    output_count: usize,
+    // Gains of the inputs for each output in order.
+    gains: Box<[Sample]>,
+}
+
+impl DownmixMatrix {
+    /// Creates a matrix with all the gains zero.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either count is zero.
+    pub fn new(input_count: usize, output_count: usize) -> Self {
+        assert!(input_count > 0 && output_count > 0, "Channel count must be positive");
+        DownmixMatrix {
+            input_count: input_count,
+            output_count: output_count,
+            gains: vec![0.0; input_count * output_count].into_boxed_slice(),
+        }
+    }
+
+    /// Creates a matrix passing the channels through unchanged.
+    pub fn identity(channel_count: usize) -> Self {
+        let mut r = Self::new(channel_count, channel_count);
+        for channel in 0..channel_count {
+            r.set_gain(channel, channel, 1.0);
+        }
+        r
+    }
+
+    /// Creates the stereo downmix of the `layout` as recommended by ITU-R BS.775: the left and
+    /// right channels are passed through, the center, side and rear channels are added to
+    /// the outputs of their side at -3 dB, and the channels without a side (mono, center and rear
+    /// center) are split to both outputs at -3 dB. The LFE channel is added to both outputs
+    /// with the `lfe_gain`, zero drops it as the recommendation does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the layout is `Custom`.
+    pub fn stereo(layout: ChannelLayout, lfe_gain: Sample) -> Self {
+        use header::SpeakerPosition::*;
+
+        let positions = layout.positions();
+        assert!(!positions.is_empty(), "Custom layout doesn't have speaker positions");
+        let mut r = Self::new(positions.len(), 2);
+        for (channel, &position) in positions.iter().enumerate() {
+            let (left, right) = match position {
+                FrontLeft => (1.0, 0.0),
+                FrontRight => (0.0, 1.0),
+                SideLeft | RearLeft => (SPLIT_GAIN, 0.0),
+                SideRight | RearRight => (0.0, SPLIT_GAIN),
+                Mono | FrontCenter | RearCenter => (SPLIT_GAIN, SPLIT_GAIN),
+                Lfe => (lfe_gain, lfe_gain),
+            };
+            r.set_gain(channel, 0, left).set_gain(channel, 1, right);
+        }
+        r
+    }
+
+    pub fn input_count(&self) -> usize {
+        self.input_count
+    }
+
+    pub fn output_count(&self) -> usize {
+        self.output_count
+    }
+
+    /// Returns the gain of the `input` channel in the `output` channel.
+    pub fn gain(&self, input: usize, output: usize) -> Sample {
+        self.gains[self.index(input, output)]
+    }
+
+    pub fn set_gain(&mut self, input: usize, output: usize, gain: Sample) -> &mut Self {
+        let i = self.index(input, output);
+        self.gains[i] = gain;
+        self
+    }
+
+    /// Mixes the decoded `samples` into the interleaved `buf` and returns the number of samples
+    /// (per channel) written.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel count of the samples isn't `input_count()` or the buffer is
+    /// shorter than `samples.len() * output_count()`.
+    pub fn apply(&self, samples: &Samples, buf: &mut [Sample]) -> usize {
+        assert_eq!(samples.channel_count(), self.input_count, "Channel count mismatch");
+        assert!(buf.len() >= samples.len() * self.output_count, "Buffer is too short");
+        let buf = &mut buf[..samples.len() * self.output_count];
+        for s in buf.iter_mut() {
+            *s = 0.0;
+        }
+        for (input, channel) in samples.channels().enumerate() {
+            for output in 0..self.output_count {
+                let gain = self.gains[output * self.input_count + input];
+                if gain == 0.0 {
+                    continue;
+                }
+                for (d, &s) in buf.chunks_mut(self.output_count).zip(channel) {
+                    d[output] += s * gain;
+                }
+            }
+        }
+        samples.len()
+    }
+
+    /// Same as `apply()` but for interleaved input samples. Writes as many samples as fit into
+    /// the `output`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the input length isn't a multiple of `input_count()`.
+    pub fn apply_interleaved(&self, input: &[Sample], output: &mut [Sample]) -> usize {
+        assert!(input.len() % self.input_count == 0,
+            "Sample count isn't a multiple of the channel count");
+        let mut len = 0;
+        for (i, o) in input.chunks(self.input_count).zip(output.chunks_mut(self.output_count)) {
+            if o.len() < self.output_count {
+                break;
+            }
+            let gains = self.gains.chunks(self.input_count);
+            for (o, gains) in o.iter_mut().zip(gains) {
+                *o = i.iter().zip(gains).fold(0.0, |a, (&s, &g)| a + s * g);
+            }
+            len += 1;
+        }
+        len
+    }
+
+    fn index(&self, input: usize, output: usize) -> usize {
+        assert!(input < self.input_count && output < self.output_count, "Channel is out of bounds");
+        output * self.input_count + input
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mix() {
+        let mut m = Mixer::new(2);
+        let a = m.add_source(1.0);
+        let b = m.add_source(0.5);
+        m.push_interleaved(a, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        m.push_interleaved(b, &[2.0, 2.0]);
+        assert_eq!(m.available(), 1);
+
+        let mut buf = [0.0; 8];
+        assert_eq!(m.mix(&mut buf), 1);
+        assert_eq!(&buf[..2], &[2.0, 3.0]);
+        assert_eq!(m.mix(&mut buf), 0);
+
+        // Finished source is silent after its queue is drained.
+        m.push_interleaved(b, &[4.0, 4.0]);
+        m.finish(b);
+        assert_eq!(m.available(), 2);
+        assert_eq!(m.mix(&mut buf), 2);
+        assert_eq!(&buf[..4], &[5.0, 6.0, 5.0, 6.0]);
+        assert!(!m.has_source(b));
+        assert_eq!(m.source_count(), 1);
+
+        // The index of the removed source is reused.
+        assert_eq!(m.add_source(2.0), b);
+        m.set_gain(a, 0.0);
+        m.push_interleaved(a, &[7.0, 7.0]);
+        m.push_interleaved(b, &[1.0, -1.0, 1.0, 1.0]);
+        assert_eq!(m.queued(b), 2);
+        assert_eq!(m.mix(&mut buf[..2]), 1);
+        assert_eq!(&buf[..2], &[2.0, -2.0]);
+    }
+
+    #[test]
+    fn downmix() {
+        let mut m = DownmixMatrix::new(3, 2);
+        m.set_gain(0, 0, 1.0).set_gain(1, 0, 0.5).set_gain(1, 1, 0.5).set_gain(2, 1, -1.0);
+        assert_eq!(m.gain(1, 1), 0.5);
+        let mut buf = [9.0; 5];
+        assert_eq!(m.apply_interleaved(&[1.0, 2.0, 3.0, 4.0, 6.0, 8.0], &mut buf), 2);
+        assert_eq!(buf, [2.0, -2.0, 7.0, -5.0, 9.0]);
+        assert_eq!(m.apply_interleaved(&[1.0, 2.0, 3.0, 4.0, 6.0, 8.0], &mut buf[..3]), 1);
+
+        let m = DownmixMatrix::identity(2);
+        assert_eq!(m.apply_interleaved(&[1.0, 2.0], &mut buf), 1);
+        assert_eq!(&buf[..2], &[1.0, 2.0]);
+
+        let m = DownmixMatrix::stereo(ChannelLayout::Surround5_1, 0.0);
+        assert_eq!(m.input_count(), 6);
+        let c = SPLIT_GAIN;
+        let rows = [[1.0, c, 0.0, c, 0.0, 0.0], [0.0, c, 1.0, 0.0, c, 0.0]];
+        for (output, row) in rows.iter().enumerate() {
+            for (input, &gain) in row.iter().enumerate() {
+                assert_eq!(m.gain(input, output), gain);
+            }
+        }
+        assert_eq!(DownmixMatrix::stereo(ChannelLayout::Stereo, 0.0), DownmixMatrix::identity(2));
+        let m = DownmixMatrix::stereo(ChannelLayout::Mono, 0.0);
+        assert_eq!((m.gain(0, 0), m.gain(0, 1)), (c, c));
+    }
+}
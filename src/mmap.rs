@@ -0,0 +1,108 @@
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use bitstream::BitReader;
+use decoder::{Decoder, DecoderBuilder, Samples};
+use error::{Error, Result};
+use granule::GranulePos;
+use ogg::{PacketReader, SliceDemuxer};
+
+/// Decoder of an Ogg Vorbis file mapped into memory.
+///
+/// The pages are demuxed in place and the packets are read by `BitReader` straight from the
+/// mapped bytes, even the ones split between pages, so no packet is copied and the file isn't
+/// read into buffers. This is the cheapest way to decode whole files, e.g. when transcoding
+/// large libraries. The first Vorbis stream of the file is decoded, the pages of other logical
+/// streams are skipped and the chained streams following it are ignored.
+///
+/// The file must not be modified while it's mapped, the decoder may read the changed bytes or
+/// crash otherwise.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use vorbis::MmapDecoder;
+///
+/// let mut decoder = MmapDecoder::open("in.ogg").expect("Couldn't open file");
+/// while let Some(samples) = decoder.decode().expect("Couldn't decode audio packet") {
+///     // Encode the samples.
+/// #   let _ = samples;
+/// }
+/// ```
+pub struct MmapDecoder {
+    mmap: Mmap,
+    demuxer: SliceDemuxer,
+    // Ranges of the parts of the current packet in the `mmap`.
+    parts: Vec<Range<usize>>,
+    decoder: Decoder,
+}
+
+impl MmapDecoder {
+    /// Maps the file at the `path` and reads the header packets building the decoder with
+    /// `Decoder::builder()`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_with(path, Decoder::builder())
+    }
+
+    /// Same as `open()` but builds the decoder with the `builder`, e.g. to set the
+    /// `DecodeLimits` or share a `TableCache`.
+    pub fn open_with<P: AsRef<Path>>(path: P, mut builder: DecoderBuilder) -> Result<Self> {
+        let file = try!(File::open(path));
+        // The mapping is only read, it's up to the caller to not modify the file meanwhile.
+        let mmap = try!(unsafe { Mmap::map(&file) });
+        let mut demuxer = SliceDemuxer::new();
+        let mut parts = Vec::new();
+        for i in 0..3 {
+            if !try!(demuxer.next_packet(&mmap, &mut parts)) {
+                return Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof,
+                    "Missing Vorbis header packets")));
+            }
+            let mut reader = BitReader::new(PacketReader::new(&mmap, &parts));
+            try!(match i {
+                0 => builder.read_ident_packet(&mut reader),
+                1 => builder.read_comment_packet(&mut reader),
+                _ => builder.read_setup_packet(&mut reader),
+            });
+        }
+        Ok(MmapDecoder {
+            mmap: mmap,
+            demuxer: demuxer,
+            parts: parts,
+            decoder: builder.build(),
+        })
+    }
+
+    /// Decodes the next audio packet. Returns `None` at the end of the stream. The samples are
+    /// empty for the first packet (see `Decoder::decode()`). After an error decoding continues
+    /// with the next packet.
+    pub fn decode(&mut self) -> Result<Option<Samples>> {
+        if !try!(self.demuxer.next_packet(&self.mmap, &mut self.parts)) {
+            return Ok(None);
+        }
+        let mut reader = BitReader::new(PacketReader::new(&self.mmap, &self.parts));
+        self.decoder.decode(&mut reader).map(Some)
+    }
+
+    /// Returns the granule position of the page the last packet read ended on. For the last
+    /// packet ending on the page it's the stream position of the packet end (see `GaplessTrim`).
+    pub fn granule_pos(&self) -> GranulePos {
+        self.demuxer.granule_pos()
+    }
+
+    /// Returns the mapped bytes of the file.
+    pub fn bytes(&self) -> &[u8] {
+        &self.mmap
+    }
+
+    pub fn decoder(&self) -> &Decoder {
+        &self.decoder
+    }
+
+    pub fn decoder_mut(&mut self) -> &mut Decoder {
+        &mut self.decoder
+    }
+}
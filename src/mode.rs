@@ -2,6 +2,8 @@ use bitstream::BitRead;
 use error::{Error, Result};
 use header::FrameKind;
 
+/// Mode as read from the setup header: the frame kind and the mapping index of the audio
+/// packets using the mode.
 #[derive(Debug)]
 pub struct Mode {
     pub frame_kind: FrameKind,
@@ -10,20 +12,24 @@ pub struct Mode {
 
 impl Mode {
     pub fn read<R: BitRead>(reader: &mut R, mapping_count: usize) -> Result<Self> {
+        trace_field!(reader, "mode.block_flag");
         let frame_kind = if try!(reader.read_bool()) {
             FrameKind::Long
         } else {
             FrameKind::Short
         };
+        trace_field!(reader, "mode.window_kind");
         if try!(reader.read_u16()) != 0 {
-            return Err(Error::Undecodable("Invalid mode window type"));
+            return Err(Error::InvalidModeWindowKind);
         }
+        trace_field!(reader, "mode.transform_kind");
         if try!(reader.read_u16()) != 0 {
-            return Err(Error::Undecodable("Invalid mode transform type"));
+            return Err(Error::InvalidModeTransformKind);
         }
+        trace_field!(reader, "mode.mapping");
         let mapping = try!(reader.read_u8()) as usize;
         if mapping >= mapping_count {
-            return Err(Error::Undecodable("Invalid mode mapping"));
+            return Err(Error::InvalidModeMapping);
         }
 
         Ok(Mode {
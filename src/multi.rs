@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::mem;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use bitstream::BitReader;
+use decoder::{Decoder, DecoderBuilder, Samples};
+use error::Result;
+
+// Beginning of the identification header packet.
+const IDENT_PREFIX: &'static [u8] = b"\x01vorbis";
+
+enum Stream {
+    // Reading the header packets, the number of the packets read so far.
+    Headers(DecoderBuilder, usize),
+    Audio(Decoder),
+    // Not a Vorbis stream or its headers couldn't be decoded: the packets are dropped.
+    Ignored,
+}
+
+/// Decoders of the Vorbis streams multiplexed into one physical stream keyed by the serial
+/// number of the logical stream, e.g. the audio tracks in several languages of an Ogg file.
+///
+/// The container demuxer passes the packets of all logical streams with their serial numbers to
+/// `push()`. The first packet of an unknown serial number starts a new stream: if it's a Vorbis
+/// identification header the stream's decoder is built once all three header packets arrive and
+/// the following packets are decoded, otherwise (e.g. a video or a skeleton stream) all its
+/// packets are ignored. The decoders can be accessed with `decoder()` to select the track by its
+/// comments (like the `LANGUAGE` tag).
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use vorbis::MultiDecoder;
+///
+/// # fn f(packets: Vec<(u32, Vec<u8>)>) {
+/// let mut decoders = MultiDecoder::new();
+/// for &(serial, ref packet) in &packets {
+///     if let Some(samples) = decoders.push(serial, packet).expect("Couldn't decode packet") {
+///         // Play the samples of the track `serial`.
+/// #       let _ = samples;
+///     }
+/// }
+/// # }
+/// ```
+pub struct MultiDecoder {
+    streams: BTreeMap<u32, Stream>,
+    new_builder: Box<FnMut() -> DecoderBuilder>,
+}
+
+impl MultiDecoder {
+    /// Creates the manager building the decoders with the default `Decoder::builder()`.
+    pub fn new() -> Self {
+        Self::with_builder(Decoder::builder)
+    }
+
+    /// Creates the manager building the decoders with the builders returned by the
+    /// `new_builder`, e.g. to share a `TableCache` or set the `DecodeLimits`.
+    pub fn with_builder<F>(new_builder: F) -> Self
+            where F: FnMut() -> DecoderBuilder + 'static {
+        MultiDecoder {
+            streams: BTreeMap::new(),
+            new_builder: Box::new(new_builder),
+        }
+    }
+
+    /// Routes the `packet` to the decoder of the stream with the `serial` number. Returns the
+    /// decoded samples of the audio packets and `None` for the header packets and the packets
+    /// of the ignored streams. For the first audio packet the samples are empty (see
+    /// `Decoder::decode()`).
+    ///
+    /// An error in a header packet makes the stream ignored from then on. After an error in an
+    /// audio packet the stream's decoder continues with the next packet as after
+    /// `Decoder::decode()` failure.
+    pub fn push(&mut self, serial: u32, packet: &[u8]) -> Result<Option<Samples>> {
+        let new_builder = &mut self.new_builder;
+        let stream = self.streams.entry(serial).or_insert_with(|| {
+            if packet.starts_with(IDENT_PREFIX) {
+                Stream::Headers(new_builder(), 0)
+            } else {
+                Stream::Ignored
+            }
+        });
+        let complete = match *stream {
+            Stream::Audio(ref mut decoder) =>
+                return decoder.decode(&mut BitReader::new(packet)).map(Some),
+            Stream::Ignored => return Ok(None),
+            Stream::Headers(ref mut builder, ref mut read) => {
+                let mut reader = BitReader::new(packet);
+                let r = match *read {
+                    0 => builder.read_ident_packet(&mut reader),
+                    1 => builder.read_comment_packet(&mut reader),
+                    _ => builder.read_setup_packet(&mut reader),
+                };
+                *read += 1;
+                r.map(|()| *read == 3)
+            }
+        };
+        match complete {
+            Ok(true) => {
+                if let Stream::Headers(builder, _) = mem::replace(stream, Stream::Ignored) {
+                    *stream = Stream::Audio(builder.build());
+                }
+                Ok(None)
+            }
+            Ok(false) => Ok(None),
+            Err(e) => {
+                *stream = Stream::Ignored;
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns the decoder of the stream with the `serial` number. Returns `None` if the stream
+    /// is unknown, ignored or its header packets haven't been read yet.
+    pub fn decoder(&self, serial: u32) -> Option<&Decoder> {
+        match self.streams.get(&serial) {
+            Some(&Stream::Audio(ref decoder)) => Some(decoder),
+            _ => None,
+        }
+    }
+
+    pub fn decoder_mut(&mut self, serial: u32) -> Option<&mut Decoder> {
+        match self.streams.get_mut(&serial) {
+            Some(&mut Stream::Audio(ref mut decoder)) => Some(decoder),
+            _ => None,
+        }
+    }
+
+    /// Returns the serial numbers of the streams with the decoders built, in ascending order.
+    pub fn serials(&self) -> Vec<u32> {
+        self.streams.iter()
+            .filter_map(|(&serial, s)| if let Stream::Audio(_) = *s { Some(serial) } else { None })
+            .collect()
+    }
+
+    /// Returns whether the stream with the `serial` number was seen and isn't a Vorbis stream
+    /// or failed to decode its headers.
+    pub fn is_ignored(&self, serial: u32) -> bool {
+        match self.streams.get(&serial) {
+            Some(&Stream::Ignored) => true,
+            _ => false,
+        }
+    }
+
+    /// Forgets the stream with the `serial` number, e.g. at its end so the serial number can be
+    /// reused by a chained stream. Returns the stream's decoder if it was built, it can be
+    /// recycled with `DecoderPool::recycle()`.
+    pub fn remove(&mut self, serial: u32) -> Option<Decoder> {
+        match self.streams.remove(&serial) {
+            Some(Stream::Audio(decoder)) => Some(decoder),
+            _ => None,
+        }
+    }
+}
+
+impl Default for MultiDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,642 @@
+//! Minimal Ogg page reading and writing used to rewrite the header packets of Ogg Vorbis
+//! files without touching the audio, decode whole files and demux the Ogg Vorbis streams in
+//! memory.
+
+use std::cmp;
+use std::io::{self, Read, Write};
+#[cfg(feature = "memmap")]
+use std::ops::Range;
+
+use comment::Comments;
+use error::{self, Error};
+use gapless::GaplessTrim;
+use granule::GranulePos;
+use header::Header;
+use multi::MultiDecoder;
+use sample::Sample;
+
+const CAPTURE_PATTERN: &'static [u8] = b"OggS";
+const HEADER_LEN: usize = 27;
+const MAX_SEGMENT_COUNT: usize = 255;
+
+const FLAG_CONTINUED: u8 = 1;
+const FLAG_BOS: u8 = 2;
+const FLAG_EOS: u8 = 4;
+
+// Beginning of the Vorbis identification header packet.
+const IDENT_PREFIX: &'static [u8] = b"\x01vorbis";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Page {
+    flags: u8,
+    granule_pos: GranulePos,
+    serial: u32,
+    seq: u32,
+    lacing: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl Page {
+    // Reads the next page. Returns None at the end of the input.
+    fn read<R: Read>(reader: &mut R) -> io::Result<Option<Self>> {
+        let mut header = [0; HEADER_LEN];
+        let len = try!(read_full(reader, &mut header));
+        if len == 0 {
+            return Ok(None);
+        }
+        if len < HEADER_LEN {
+            return Err(invalid_data("Truncated Ogg page"));
+        }
+        if &header[..4] != CAPTURE_PATTERN || header[4] != 0 {
+            return Err(invalid_data("Invalid Ogg page header"));
+        }
+        let mut lacing = vec![0; header[26] as usize];
+        try!(reader.read_exact(&mut lacing));
+        let mut data = vec![0; lacing.iter().fold(0, |a, &l| a + l as usize)];
+        try!(reader.read_exact(&mut data));
+
+        let granule_pos = get_u32(&header[6..]) as u64 | (get_u32(&header[10..]) as u64) << 32;
+        let page = Page {
+            flags: header[5],
+            granule_pos: GranulePos::from_raw(granule_pos),
+            serial: get_u32(&header[14..]),
+            seq: get_u32(&header[18..]),
+            lacing: lacing,
+            data: data,
+        };
+        if page.to_bytes()[22..26] != header[22..26] {
+            return Err(invalid_data("Ogg page CRC mismatch"));
+        }
+        Ok(Some(page))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut r = Vec::with_capacity(HEADER_LEN + self.lacing.len() + self.data.len());
+        r.extend_from_slice(CAPTURE_PATTERN);
+        r.push(0);
+        r.push(self.flags);
+        put_u32(&mut r, self.granule_pos.raw() as u32);
+        put_u32(&mut r, (self.granule_pos.raw() >> 32) as u32);
+        put_u32(&mut r, self.serial);
+        put_u32(&mut r, self.seq);
+        put_u32(&mut r, 0);
+        r.push(self.lacing.len() as u8);
+        r.extend_from_slice(&self.lacing);
+        r.extend_from_slice(&self.data);
+        let crc = crc32(&r);
+        r[22..26].copy_from_slice(&[crc as u8, (crc >> 8) as u8, (crc >> 16) as u8, (crc >> 24) as u8]);
+        r
+    }
+
+    // Splits the page data into the packet parts. The last part is incomplete (continued on the
+    // next page) if the flag is set.
+    fn packets(&self) -> Vec<(&[u8], bool)> {
+        let mut r = Vec::new();
+        let mut start = 0;
+        let mut end = 0;
+        for &l in &self.lacing {
+            end += l as usize;
+            if l < 255 {
+                r.push((&self.data[start..end], true));
+                start = end;
+            }
+        }
+        if start < end {
+            r.push((&self.data[start..end], false));
+        }
+        r
+    }
+}
+
+// Page borrowed from the Ogg stream in memory.
+#[cfg(feature = "memmap")]
+struct PageRef<'a> {
+    flags: u8,
+    granule_pos: GranulePos,
+    serial: u32,
+    seq: u32,
+    lacing: &'a [u8],
+    // Length of the page including the header.
+    len: usize,
+}
+
+#[cfg(feature = "memmap")]
+impl<'a> PageRef<'a> {
+    // Parses the page at the beginning of the `bytes`.
+    fn parse(bytes: &'a [u8]) -> io::Result<Self> {
+        if bytes.len() < HEADER_LEN {
+            return Err(invalid_data("Truncated Ogg page"));
+        }
+        if &bytes[..4] != CAPTURE_PATTERN || bytes[4] != 0 {
+            return Err(invalid_data("Invalid Ogg page header"));
+        }
+        let lacing_end = HEADER_LEN + bytes[26] as usize;
+        if bytes.len() < lacing_end {
+            return Err(invalid_data("Truncated Ogg page"));
+        }
+        let lacing = &bytes[HEADER_LEN..lacing_end];
+        let len = lacing.iter().fold(lacing_end, |a, &l| a + l as usize);
+        if bytes.len() < len {
+            return Err(invalid_data("Truncated Ogg page"));
+        }
+        let crc = crc32_update(crc32_update(crc32_update(0, &bytes[..22]), &[0; 4]), &bytes[26..len]);
+        if crc != get_u32(&bytes[22..]) {
+            return Err(invalid_data("Ogg page CRC mismatch"));
+        }
+        let granule_pos = get_u32(&bytes[6..]) as u64 | (get_u32(&bytes[10..]) as u64) << 32;
+        Ok(PageRef {
+            flags: bytes[5],
+            granule_pos: GranulePos::from_raw(granule_pos),
+            serial: get_u32(&bytes[14..]),
+            seq: get_u32(&bytes[18..]),
+            lacing: lacing,
+            len: len,
+        })
+    }
+
+    fn data(&self, bytes: &'a [u8]) -> &'a [u8] {
+        &bytes[HEADER_LEN + self.lacing.len()..self.len]
+    }
+}
+
+// Demuxer of the packets of the first Vorbis stream of the Ogg stream in memory. The packets
+// are returned as the byte ranges of their parts in the stream, so they're read in place
+// (see `PacketReader`) without copying. The pages of other logical streams are skipped and the
+// demuxing ends at the last page of the Vorbis stream. The packets with a part on a missing
+// page are dropped.
+#[cfg(feature = "memmap")]
+pub struct SliceDemuxer {
+    serial: Option<u32>,
+    // Sequence number of the last page read.
+    seq: Option<u32>,
+    // The page preceding the current one is missing.
+    lost: bool,
+    // Offset of the next page.
+    pos: usize,
+    // Range of the unread lacing values of the current page.
+    lacing: Range<usize>,
+    // Offset of the unread data of the current page.
+    data_pos: usize,
+    continued: bool,
+    eos: bool,
+    granule_pos: GranulePos,
+}
+
+#[cfg(feature = "memmap")]
+impl SliceDemuxer {
+    pub fn new() -> Self {
+        SliceDemuxer {
+            serial: None,
+            seq: None,
+            lost: false,
+            pos: 0,
+            lacing: 0..0,
+            data_pos: 0,
+            continued: false,
+            eos: false,
+            granule_pos: GranulePos::none(),
+        }
+    }
+
+    // Returns the granule position of the last page read.
+    pub fn granule_pos(&self) -> GranulePos {
+        self.granule_pos
+    }
+
+    // Sets the `parts` to the ranges of the next packet in the `bytes`. Returns `false` at the
+    // end of the stream.
+    pub fn next_packet(&mut self, bytes: &[u8], parts: &mut Vec<Range<usize>>) -> io::Result<bool> {
+        parts.clear();
+        // The packet continued from a page before the first one read or a missing page.
+        let mut skip = false;
+        loop {
+            if self.lacing.start == self.lacing.end {
+                if !try!(self.next_page(bytes)) {
+                    return Ok(false);
+                }
+                // Either the page continues a packet that didn't start on the pages read or
+                // the continuation of the started packet is missing.
+                if self.lost || self.continued != !parts.is_empty() {
+                    parts.clear();
+                    skip = self.continued;
+                }
+                continue;
+            }
+            let mut len = 0;
+            let mut complete = false;
+            while self.lacing.start < self.lacing.end {
+                let l = bytes[self.lacing.start];
+                self.lacing.start += 1;
+                len += l as usize;
+                if l < 255 {
+                    complete = true;
+                    break;
+                }
+            }
+            if !skip {
+                parts.push(self.data_pos..self.data_pos + len);
+            }
+            self.data_pos += len;
+            if complete {
+                if !skip {
+                    return Ok(true);
+                }
+                skip = false;
+            }
+        }
+    }
+
+    // Advances to the next page of the Vorbis stream. Returns `false` at the end of it.
+    fn next_page(&mut self, bytes: &[u8]) -> io::Result<bool> {
+        while !self.eos && self.pos < bytes.len() {
+            let start = self.pos;
+            let page = try!(PageRef::parse(&bytes[start..]));
+            self.pos += page.len;
+            if self.serial.is_none() && page.flags & FLAG_BOS != 0 &&
+                    page.data(&bytes[start..]).starts_with(IDENT_PREFIX) {
+                self.serial = Some(page.serial);
+            }
+            if self.serial != Some(page.serial) {
+                continue;
+            }
+            self.lacing = start + HEADER_LEN..start + HEADER_LEN + page.lacing.len();
+            self.data_pos = self.lacing.end;
+            self.lost = self.seq.map_or(false, |seq| page.seq != seq.wrapping_add(1));
+            self.seq = Some(page.seq);
+            self.continued = page.flags & FLAG_CONTINUED != 0;
+            self.eos = page.flags & FLAG_EOS != 0;
+            self.granule_pos = page.granule_pos;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+// Reads the packet from its parts returned by `SliceDemuxer::next_packet()`.
+#[cfg(feature = "memmap")]
+pub struct PacketReader<'a> {
+    bytes: &'a [u8],
+    parts: &'a [Range<usize>],
+    // The unread bytes of the current part.
+    part: &'a [u8],
+}
+
+#[cfg(feature = "memmap")]
+impl<'a> PacketReader<'a> {
+    pub fn new(bytes: &'a [u8], parts: &'a [Range<usize>]) -> Self {
+        PacketReader {
+            bytes: bytes,
+            parts: parts,
+            part: &[],
+        }
+    }
+}
+
+#[cfg(feature = "memmap")]
+impl<'a> Read for PacketReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.part.is_empty() {
+            match self.parts.split_first() {
+                Some((first, rest)) => {
+                    self.part = &self.bytes[first.clone()];
+                    self.parts = rest;
+                }
+                None => return Ok(0),
+            }
+        }
+        let len = cmp::min(buf.len(), self.part.len());
+        buf[..len].copy_from_slice(&self.part[..len]);
+        self.part = &self.part[len..];
+        Ok(len)
+    }
+}
+
+// Splits the packets into pages ending the last page with the last packet.
+fn paginate(packets: &[&[u8]], serial: u32, first_seq: u32, granule_pos: GranulePos) -> Vec<Page> {
+    let mut pages = Vec::new();
+    let mut page = Page {
+        flags: 0,
+        granule_pos: GranulePos::none(),
+        serial: serial,
+        seq: first_seq,
+        lacing: Vec::new(),
+        data: Vec::new(),
+    };
+    for packet in packets {
+        // Every packet ends with a segment shorter than 255 bytes, possibly empty.
+        let mut rest = *packet;
+        loop {
+            if page.lacing.len() == MAX_SEGMENT_COUNT {
+                let seq = page.seq + 1;
+                pages.push(page);
+                page = Page {
+                    flags: FLAG_CONTINUED,
+                    granule_pos: GranulePos::none(),
+                    serial: serial,
+                    seq: seq,
+                    lacing: Vec::new(),
+                    data: Vec::new(),
+                };
+            }
+            let len = cmp::min(rest.len(), 255);
+            page.lacing.push(len as u8);
+            page.data.extend_from_slice(&rest[..len]);
+            rest = &rest[len..];
+            if len < 255 {
+                page.granule_pos = granule_pos;
+                break;
+            }
+        }
+    }
+    if !page.lacing.is_empty() {
+        pages.push(page);
+    }
+    pages
+}
+
+/// Replaces the comment header of the Ogg Vorbis stream with the `comments` without decoding
+/// the audio.
+///
+/// The comment and setup header packets are written on the new pages, the rest of the pages
+/// of the Vorbis stream are copied as is except the page sequence numbers which are
+/// adjusted if the number of the header pages has changed. The pages of other logical streams
+/// multiplexed with the Vorbis one and the chained streams following it are copied unchanged.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::fs::File;
+/// use std::io::{BufReader, BufWriter};
+/// use vorbis::{retag, CommentTag, Comments};
+///
+/// let input = BufReader::new(File::open("in.ogg").expect("Couldn't open input file"));
+/// let output = BufWriter::new(File::create("out.ogg").expect("Couldn't create output file"));
+/// let comments = Comments::builder()
+///     .vendor("retag")
+///     .add(CommentTag::Title, "New title")
+///     .build();
+/// retag(input, output, &comments).expect("Couldn't retag");
+/// ```
+pub fn retag<R: Read, W: Write>(mut input: R, mut output: W, comments: &Comments) -> io::Result<()> {
+    let first_page = match try!(Page::read(&mut input)) {
+        Some(page) => page,
+        None => return Err(invalid_data("Empty Ogg stream")),
+    };
+    let serial = first_page.serial;
+    if first_page.flags & FLAG_BOS == 0 || first_page.packets().len() != 1 ||
+            !first_page.data.starts_with(IDENT_PREFIX) {
+        return Err(invalid_data("Ogg stream doesn't start with Vorbis identification header"));
+    }
+    try!(output.write_all(&first_page.to_bytes()));
+
+    // Collect the comment and setup packets skipping the pages of other streams.
+    let mut headers = Vec::new();
+    let mut packet = Vec::new();
+    let mut header_page_count = 1;
+    while headers.len() < 2 {
+        let page = match try!(Page::read(&mut input)) {
+            Some(page) => page,
+            None => return Err(invalid_data("Missing Vorbis header packets")),
+        };
+        if page.serial != serial {
+            try!(output.write_all(&page.to_bytes()));
+            continue;
+        }
+        header_page_count += 1;
+        for (part, complete) in page.packets() {
+            if headers.len() == 2 {
+                return Err(invalid_data("Vorbis setup header doesn't end the page"));
+            }
+            packet.extend_from_slice(part);
+            if complete {
+                headers.push(packet);
+                packet = Vec::new();
+            }
+        }
+    }
+    if !headers[0].starts_with(b"\x03vorbis") || !headers[1].starts_with(b"\x05vorbis") {
+        return Err(invalid_data("Invalid Vorbis header packets"));
+    }
+
+    let comment_packet = comments.to_packet();
+    let pages = paginate(&[&comment_packet, &headers[1]], serial, 1, GranulePos::from_samples(0));
+    for page in &pages {
+        try!(output.write_all(&page.to_bytes()));
+    }
+
+    let new_header_page_count = pages.len() as u32 + 1;
+    let mut done = false;
+    while let Some(mut page) = try!(Page::read(&mut input)) {
+        if !done && page.serial == serial {
+            page.seq = page.seq.wrapping_sub(header_page_count).wrapping_add(new_header_page_count);
+            done = page.flags & FLAG_EOS != 0;
+        }
+        try!(output.write_all(&page.to_bytes()));
+    }
+    output.flush()
+}
+
+/// Decodes the first Vorbis stream of the Ogg file read from the `input` at once. Returns the
+/// stream header, the comments and the interleaved samples of the whole stream trimmed to
+/// the granule positions of the first and last audio pages (see `GaplessTrim`) like libvorbis
+/// does. The pages of other logical streams are skipped and the chained streams are ignored.
+///
+/// This is for the scripts and tests that don't need streaming, use `Decoder` otherwise.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// use vorbis::decode_all;
+///
+/// let input = BufReader::new(File::open("in.ogg").expect("Couldn't open file"));
+/// let (header, comments, samples) = decode_all(input).expect("Couldn't decode");
+/// println!("Encoded by {}, {} channels, {} samples per channel", comments.vendor_lossy(),
+///     header.channel_count(), samples.len() / header.channel_count());
+/// ```
+pub fn decode_all<R: Read>(mut input: R) -> error::Result<(Header, Comments, Vec<Sample>)> {
+    let mut decoders = MultiDecoder::new();
+    let mut serial = None;
+    let mut packet = Vec::new();
+    let mut samples = Vec::new();
+    let mut trim = None;
+    let mut last_granule_pos = GranulePos::none();
+    while let Some(page) = try!(Page::read(&mut input)) {
+        if serial.is_none() && page.flags & FLAG_BOS != 0 && page.data.starts_with(IDENT_PREFIX) {
+            serial = Some(page.serial);
+        }
+        if serial != Some(page.serial) {
+            continue;
+        }
+        let mut audio = false;
+        for (part, complete) in page.packets() {
+            packet.extend_from_slice(part);
+            if complete {
+                if let Some(s) = try!(decoders.push(page.serial, &packet)) {
+                    samples.extend(s.interleave());
+                    audio = true;
+                }
+                packet.clear();
+            }
+        }
+        if audio {
+            if let (None, Some(granule_pos)) = (trim, page.granule_pos.samples()) {
+                let pos = decoders.decoder(page.serial).unwrap().pos();
+                trim = Some(GaplessTrim::new(pos, granule_pos));
+            }
+            last_granule_pos = page.granule_pos;
+        }
+        if page.flags & FLAG_EOS != 0 {
+            break;
+        }
+    }
+    let decoder = match serial.and_then(|serial| decoders.remove(serial)) {
+        Some(decoder) => decoder,
+        None => return Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof,
+            "Missing Vorbis header packets"))),
+    };
+
+    let channel_count = decoder.header().channel_count();
+    if let Some(mut trim) = trim {
+        if let Some(last_granule_pos) = last_granule_pos.samples() {
+            trim.set_last_granule_pos(last_granule_pos);
+        }
+        let range = trim.trim(0, samples.len() / channel_count);
+        samples.truncate(range.end * channel_count);
+        samples.drain(..range.start * channel_count);
+    }
+    // The comment header is always parsed with the `ogg` feature.
+    let comments = decoder.comments().unwrap().clone();
+    Ok((decoder.header().clone(), comments, samples))
+}
+
+// CRC-32 of the Ogg pages: 0x04C11DB7 polynomial, zero initial value and no final XOR.
+fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+// Continues the CRC-32 `crc` of the preceding bytes with the `data`.
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &b in data {
+        crc ^= (b as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 { crc << 1 ^ 0x04C1_1DB7 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+// Reads until the buffer is full or the end of input is reached. Returns the number of bytes
+// read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn get_u32(b: &[u8]) -> u32 {
+    b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24
+}
+
+fn put_u32(buf: &mut Vec<u8>, v: u32) {
+    buf.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn paginate_read() {
+        let big = vec![7; 255 * 600];
+        let packets: [&[u8]; 3] = [b"first", &[], &big];
+        let pages = paginate(&packets, 42, 1, GranulePos::from_samples(0));
+        assert_eq!(pages.iter().map(|p| (p.seq, p.flags, p.granule_pos.raw(), p.lacing.len()))
+                .collect::<Vec<_>>(),
+            &[(1, 0, 0, 255), (2, FLAG_CONTINUED, !0, 255), (3, FLAG_CONTINUED, 0, 93)]);
+
+        let mut bytes = Vec::new();
+        for page in &pages {
+            bytes.extend(page.to_bytes());
+        }
+        let mut reader = Cursor::new(bytes);
+        let read_pages: Vec<_> = (0..3).map(|_| Page::read(&mut reader).unwrap().unwrap()).collect();
+        assert_eq!(read_pages, pages);
+        assert!(Page::read(&mut reader).unwrap().is_none());
+
+        assert_eq!(pages[0].packets(), vec![(&b"first"[..], true), (&[][..], true), (&big[..253 * 255], false)]);
+        assert_eq!(pages[1].packets(), vec![(&big[253 * 255..508 * 255], false)]);
+        assert_eq!(pages[2].packets(), vec![(&big[508 * 255..], true)]);
+    }
+
+    #[cfg(feature = "memmap")]
+    #[test]
+    fn slice_demuxer() {
+        let big = vec![7; 255 * 600];
+        let ident = b"\x01vorbis ident";
+        let mut bytes = Vec::new();
+        // A page of another stream before the Vorbis one and in the middle of it.
+        let other = paginate(&[b"\x80theora"], 7, 0, GranulePos::from_samples(0));
+        let mut vorbis = paginate(&[ident], 42, 0, GranulePos::from_samples(0));
+        vorbis[0].flags |= FLAG_BOS;
+        vorbis.extend(paginate(&[b"first", &[], &big, b"last"], 42, 1, GranulePos::from_samples(9)));
+        vorbis.last_mut().unwrap().flags |= FLAG_EOS;
+        for (i, page) in other.iter().chain(&vorbis[..2]).chain(&other).chain(&vorbis[2..]).enumerate() {
+            let mut page = page.clone();
+            if i == 0 {
+                page.flags |= FLAG_BOS;
+            }
+            bytes.extend(page.to_bytes());
+        }
+        // The chained stream isn't demuxed.
+        bytes.extend(vorbis[0].to_bytes());
+
+        let mut demuxer = SliceDemuxer::new();
+        let mut parts = Vec::new();
+        let mut packets = Vec::new();
+        while demuxer.next_packet(&bytes, &mut parts).unwrap() {
+            let mut packet = Vec::new();
+            PacketReader::new(&bytes, &parts).read_to_end(&mut packet).unwrap();
+            packets.push((packet, parts.len()));
+        }
+        assert_eq!(packets, vec![(ident.to_vec(), 1), (b"first".to_vec(), 1), (vec![], 1),
+            (big.clone(), 3), (b"last".to_vec(), 1)]);
+        assert_eq!(demuxer.granule_pos(), GranulePos::from_samples(9));
+
+        // The packet continued from the missing page is dropped.
+        let mut bytes = Vec::new();
+        for page in &vorbis[..2] {
+            bytes.extend(page.to_bytes());
+        }
+        bytes.extend(vorbis[3].to_bytes());
+        let mut demuxer = SliceDemuxer::new();
+        assert!(demuxer.next_packet(&bytes, &mut parts).unwrap());
+        assert!(demuxer.next_packet(&bytes, &mut parts).unwrap());
+        assert!(demuxer.next_packet(&bytes, &mut parts).unwrap());
+        assert!(demuxer.next_packet(&bytes, &mut parts).unwrap());
+        assert_eq!(&bytes[parts[0].clone()], b"last");
+        assert!(!demuxer.next_packet(&bytes, &mut parts).unwrap());
+    }
+
+    #[test]
+    fn read_crc_mismatch() {
+        let mut bytes = paginate(&[b"data"], 1, 0, GranulePos::from_samples(0))[0].to_bytes();
+        *bytes.last_mut().unwrap() ^= 1;
+        let e = Page::read(&mut Cursor::new(bytes)).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
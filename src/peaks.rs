@@ -0,0 +1,195 @@
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use decoder::Samples;
+use sample::Sample;
+
+/// Minimum and maximum sample values of a block of samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Peak {
+    min: Sample,
+    max: Sample,
+}
+
+impl Peak {
+    pub fn min(&self) -> Sample {
+        self.min
+    }
+
+    pub fn max(&self) -> Sample {
+        self.max
+    }
+}
+
+/// Accumulates the minimum and maximum values of each channel over blocks of
+/// `samples_per_peak()` samples for waveform overviews.
+///
+/// The decoded samples are pushed as they are produced so no second pass over the output is
+/// needed. The peaks of the complete blocks are available with `channel()` right away, the last
+/// incomplete block is added by `finish()`.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::io::Cursor;
+/// use vorbis::{BitReader, Decoder, WaveformPeaks};
+///
+/// # fn f(mut decoder: Decoder, audio_packets: Vec<Vec<u8>>) {
+/// let sample_rate = decoder.header().sample_rate() as usize;
+/// // 100 peaks per second.
+/// let mut peaks = WaveformPeaks::new(decoder.header().channel_count(), sample_rate / 100);
+/// for packet in &audio_packets {
+///     let samples = decoder.decode(&mut BitReader::new(Cursor::new(&packet[..])))
+///             .expect("Couldn't decode audio packet");
+///     peaks.push(&samples);
+/// }
+/// peaks.finish();
+/// for peak in peaks.channel(0) {
+///     // Draw a line from peak.min() to peak.max().
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct WaveformPeaks {
+    samples_per_peak: usize,
+    // Number of samples in the current incomplete block.
+    block_len: usize,
+    // Peak of the current block of each channel, valid if the block isn't empty.
+    block_peaks: Box<[Peak]>,
+    peaks: Box<[Vec<Peak>]>,
+}
+
+impl WaveformPeaks {
+    /// Creates the accumulator for `channel_count` channels with a peak for each
+    /// `samples_per_peak` samples.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `samples_per_peak` is zero.
+    pub fn new(channel_count: usize, samples_per_peak: usize) -> Self {
+        assert!(samples_per_peak > 0);
+        WaveformPeaks {
+            samples_per_peak: samples_per_peak,
+            block_len: 0,
+            block_peaks: vec![Peak { min: 0.0, max: 0.0 }; channel_count].into_boxed_slice(),
+            peaks: vec![Vec::new(); channel_count].into_boxed_slice(),
+        }
+    }
+
+    pub fn samples_per_peak(&self) -> usize {
+        self.samples_per_peak
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.peaks.len()
+    }
+
+    /// Adds the decoded samples.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel count of the `samples` doesn't match.
+    pub fn push(&mut self, samples: &Samples) {
+        assert_eq!(samples.channel_count(), self.channel_count());
+        self.push_channels(samples.channels());
+    }
+
+    /// Adds the samples of each channel in order. All channels must have the same number of
+    /// samples.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of channels doesn't match or the channels differ in length.
+    pub fn push_channels<'a, I>(&mut self, channels: I)
+            where I: IntoIterator<Item=&'a [Sample]> {
+        let mut count = 0;
+        let mut len = None;
+        for (channel, samples) in channels.into_iter().enumerate() {
+            assert!(channel < self.channel_count(), "Too many channels");
+            assert!(len.map(|l| l == samples.len()).unwrap_or(true), "Channels differ in length");
+            len = Some(samples.len());
+            count += 1;
+
+            let peaks = &mut self.peaks[channel];
+            let block_peak = &mut self.block_peaks[channel];
+            let mut block_len = self.block_len;
+            for &s in samples {
+                if block_len == 0 {
+                    *block_peak = Peak { min: s, max: s };
+                } else if s < block_peak.min {
+                    block_peak.min = s;
+                } else if s > block_peak.max {
+                    block_peak.max = s;
+                }
+                block_len += 1;
+                if block_len == self.samples_per_peak {
+                    peaks.push(*block_peak);
+                    block_len = 0;
+                }
+            }
+        }
+        assert_eq!(count, self.channel_count(), "Too few channels");
+        if let Some(len) = len {
+            self.block_len = (self.block_len + len) % self.samples_per_peak;
+        }
+    }
+
+    /// Adds the peak of the incomplete last block if there are samples in it.
+    pub fn finish(&mut self) {
+        if self.block_len > 0 {
+            for (peaks, &block_peak) in self.peaks.iter_mut().zip(self.block_peaks.iter()) {
+                peaks.push(block_peak);
+            }
+            self.block_len = 0;
+        }
+    }
+
+    /// Returns the number of peaks in each channel.
+    pub fn len(&self) -> usize {
+        self.peaks.first().map(|p| p.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the peaks of the channel.
+    pub fn channel(&self, index: usize) -> &[Peak] {
+        &self.peaks[index]
+    }
+
+    /// Removes the peaks accumulated so far keeping the incomplete block, e.g. after they were
+    /// drawn.
+    pub fn clear(&mut self) {
+        for peaks in self.peaks.iter_mut() {
+            peaks.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waveform_peaks() {
+        let mut peaks = WaveformPeaks::new(2, 3);
+        peaks.push_channels(vec![&[0.5, -0.25][..], &[0.0, 0.125][..]]);
+        assert!(peaks.is_empty());
+        peaks.push_channels(vec![&[0.75, 0.25, 0.5, -1.0, 0.0][..], &[-0.5, 0.25, 0.25, 0.25, 0.5][..]]);
+        assert_eq!(peaks.len(), 2);
+        peaks.push_channels(vec![&[][..], &[][..]]);
+        peaks.finish();
+        peaks.finish();
+
+        let p = |min, max| Peak { min: min, max: max };
+        assert_eq!(peaks.channel(0), &[p(-0.25, 0.75), p(-1.0, 0.5), p(0.0, 0.0)]);
+        assert_eq!(peaks.channel(1), &[p(-0.5, 0.125), p(0.25, 0.25), p(0.5, 0.5)]);
+
+        peaks.clear();
+        assert!(peaks.is_empty());
+        peaks.push_channels(vec![&[0.25][..], &[0.25][..]]);
+        peaks.finish();
+        assert_eq!(peaks.channel(0), &[p(0.25, 0.25)]);
+    }
+}
@@ -0,0 +1,18 @@
+//! The traits and types most decoding code needs, for glob import:
+//!
+//! ```rust
+//! use vorbis::prelude::*;
+//! ```
+//!
+//! `Result` and `Error` aren't included since the names are too common (`Result` would shadow
+//! the `std` one), refer to them as `vorbis::Result` and `vorbis::Error`.
+
+pub use bitstream::{BitRead, BitReader, ByteRead};
+#[cfg(feature = "comments")]
+pub use comment::Comments;
+pub use decoder::{Decoder, DecoderBuilder, Samples};
+pub use header::{ChannelLayout, Header};
+#[cfg(feature = "ogg")]
+pub use ogg::decode_all;
+pub use sample::Sample;
+pub use sink::SampleSink;
@@ -0,0 +1,486 @@
+//! Comparison of the decoder output against the reference libvorbis decoder and optionally
+//! stb_vorbis.
+
+use std::cmp;
+use std::fmt::Debug;
+use std::io::{Cursor, Read};
+
+use ogg_vorbis_ref::{OggRefDecoder, VorbisRefDecoder};
+
+use bitstream::BitReader;
+use decoder::Decoder;
+use error::Error;
+
+/// Error of `RefTest::run()`.
+#[derive(Debug)]
+pub enum RefTestError {
+    /// This decoder failed to decode the stream.
+    Decoder(Error),
+    /// The Ogg demuxer or the reference decoder failed.
+    Reference(String),
+    /// The header or comment values differ from the reference ones.
+    HeaderMismatch(&'static str),
+}
+
+/// First sample that differs from the reference one by more than `RefTest::max_diff()`.
+#[derive(Clone, Copy, Debug)]
+pub struct Mismatch {
+    packet: usize,
+    channel: usize,
+    pos: u64,
+    expected: f64,
+    actual: f64,
+}
+
+impl Mismatch {
+    /// Returns the index of the audio packet (the header packets excluded).
+    pub fn packet(&self) -> usize {
+        self.packet
+    }
+
+    pub fn channel(&self) -> usize {
+        self.channel
+    }
+
+    /// Returns the sample position in the decoded stream.
+    pub fn pos(&self) -> u64 {
+        self.pos
+    }
+
+    pub fn expected(&self) -> f64 {
+        self.expected
+    }
+
+    pub fn actual(&self) -> f64 {
+        self.actual
+    }
+}
+
+// Differences of the compared samples from the expected ones.
+#[derive(Clone, Copy, Debug, Default)]
+struct Diff {
+    max_diff: f64,
+    mismatch_count: u64,
+    first_mismatch: Option<Mismatch>,
+}
+
+/// Result of `RefTest::run()`.
+#[derive(Clone, Debug)]
+pub struct RefReport {
+    packet_count: usize,
+    sample_count: u64,
+    diff: Diff,
+    len_mismatch: Option<usize>,
+    max_mismatch_count: u64,
+    stb: Option<StbReport>,
+}
+
+impl RefReport {
+    /// Returns `true` if the output matches the reference within the tolerances.
+    pub fn is_ok(&self) -> bool {
+        self.len_mismatch.is_none() && self.diff.mismatch_count <= self.max_mismatch_count
+    }
+
+    /// Returns the number of the audio packets decoded.
+    pub fn packet_count(&self) -> usize {
+        self.packet_count
+    }
+
+    /// Returns the number of samples per channel compared.
+    pub fn sample_count(&self) -> u64 {
+        self.sample_count
+    }
+
+    /// Returns the largest absolute difference from the reference samples.
+    pub fn max_diff(&self) -> f64 {
+        self.diff.max_diff
+    }
+
+    /// Returns the number of the samples (of all channels) that differ from the reference ones
+    /// by more than `RefTest::max_diff()`.
+    pub fn mismatch_count(&self) -> u64 {
+        self.diff.mismatch_count
+    }
+
+    pub fn first_mismatch(&self) -> Option<Mismatch> {
+        self.diff.first_mismatch
+    }
+
+    /// Returns the index of the first audio packet that decoded to a different number of
+    /// samples than the reference. The comparison stops at such packet.
+    pub fn len_mismatch(&self) -> Option<usize> {
+        self.len_mismatch
+    }
+
+    /// Returns the comparison with stb_vorbis if enabled with `RefTest::stb_vorbis()`.
+    pub fn stb(&self) -> Option<&StbReport> {
+        self.stb.as_ref()
+    }
+}
+
+/// Comparison of this decoder and libvorbis with stb_vorbis returned by `RefReport::stb()`.
+///
+/// The samples are compared over the packets compared with libvorbis, so the mismatches have
+/// the same packet indices and positions. The stb_vorbis samples are the `actual()` ones of
+/// the reference mismatches. Where the two references disagree (e.g. on the damaged or unusual
+/// streams) this decoder matching either of them is a deliberate choice rather than a bug.
+#[derive(Clone, Debug)]
+pub struct StbReport {
+    sample_count: u64,
+    len_mismatch: bool,
+    diff: Diff,
+    ref_diff: Diff,
+    max_mismatch_count: u64,
+}
+
+impl StbReport {
+    /// Returns `true` if this decoder's output matches stb_vorbis within the tolerances of
+    /// the `RefTest`.
+    pub fn is_ok(&self) -> bool {
+        self.diff.mismatch_count <= self.max_mismatch_count
+    }
+
+    /// Returns `true` if libvorbis and stb_vorbis output the same number of samples that don't
+    /// differ by more than `RefTest::max_diff()`.
+    pub fn references_agree(&self) -> bool {
+        self.ref_diff.mismatch_count == 0 && !self.len_mismatch()
+    }
+
+    /// Returns the number of samples per channel stb_vorbis decoded.
+    pub fn sample_count(&self) -> u64 {
+        self.sample_count
+    }
+
+    /// Returns `true` if stb_vorbis decoded a different number of samples than libvorbis. Only
+    /// the samples both decoded are compared.
+    pub fn len_mismatch(&self) -> bool {
+        self.len_mismatch
+    }
+
+    /// Returns the largest absolute difference of this decoder's samples from the stb_vorbis
+    /// ones.
+    pub fn max_diff(&self) -> f64 {
+        self.diff.max_diff
+    }
+
+    pub fn mismatch_count(&self) -> u64 {
+        self.diff.mismatch_count
+    }
+
+    pub fn first_mismatch(&self) -> Option<Mismatch> {
+        self.diff.first_mismatch
+    }
+
+    /// Returns the largest absolute difference of the stb_vorbis samples from the libvorbis
+    /// ones.
+    pub fn ref_max_diff(&self) -> f64 {
+        self.ref_diff.max_diff
+    }
+
+    pub fn ref_mismatch_count(&self) -> u64 {
+        self.ref_diff.mismatch_count
+    }
+
+    pub fn ref_first_mismatch(&self) -> Option<Mismatch> {
+        self.ref_diff.first_mismatch
+    }
+}
+
+/// Decodes an Ogg Vorbis stream with this decoder and the reference libvorbis decoder (via
+/// the `ogg_vorbis_ref` crate) and compares the output with configurable tolerances.
+///
+/// This lets the integrators validate the decoder on their own corpora.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::fs::File;
+/// use vorbis::RefTest;
+///
+/// let report = RefTest::new()
+///     .max_diff(1e-5)
+///     .run(File::open("test.ogg").expect("Couldn't open file"))
+///     .expect("Couldn't decode");
+/// assert!(report.is_ok(), "{:?}", report);
+/// ```
+#[derive(Clone, Debug)]
+pub struct RefTest {
+    max_diff: f64,
+    max_mismatch_count: u64,
+    check_comments: bool,
+    #[cfg(feature = "reftest-stb")]
+    stb_vorbis: bool,
+}
+
+impl RefTest {
+    pub fn new() -> Self {
+        RefTest {
+            max_diff: 1e-6,
+            max_mismatch_count: 0,
+            check_comments: true,
+            #[cfg(feature = "reftest-stb")]
+            stb_vorbis: false,
+        }
+    }
+
+    /// Sets the largest allowed absolute difference of a sample from the reference one
+    /// (`1e-6` by default).
+    pub fn max_diff(&mut self, value: f64) -> &mut Self {
+        self.max_diff = value;
+        self
+    }
+
+    /// Sets the number of the samples allowed to exceed the `max_diff()` (zero by default).
+    pub fn max_mismatch_count(&mut self, value: u64) -> &mut Self {
+        self.max_mismatch_count = value;
+        self
+    }
+
+    /// Sets whether the vendor string and the comments should be compared with the reference
+    /// ones (enabled by default).
+    pub fn check_comments(&mut self, value: bool) -> &mut Self {
+        self.check_comments = value;
+        self
+    }
+
+    /// Sets whether the output should also be compared with stb_vorbis (disabled by default),
+    /// see `RefReport::stb()`. The whole input is read into memory then.
+    #[cfg(feature = "reftest-stb")]
+    pub fn stb_vorbis(&mut self, value: bool) -> &mut Self {
+        self.stb_vorbis = value;
+        self
+    }
+
+    /// Decodes the Ogg Vorbis stream read from the `input` and compares the output. Only
+    /// the first logical stream is compared.
+    pub fn run<R: Read>(&self, input: R) -> Result<RefReport, RefTestError> {
+        #[cfg(feature = "reftest-stb")]
+        {
+            if self.stb_vorbis {
+                let mut input = input;
+                let mut data = Vec::new();
+                try!(input.read_to_end(&mut data).map_err(reference_error));
+                let stb_samples = try!(stb::decode(&data));
+                return self.compare(Cursor::new(&data[..]), Some(stb_samples));
+            }
+        }
+        self.compare(input, None)
+    }
+
+    // Compares the output with libvorbis and the `stb_samples` of each channel if given.
+    fn compare<R: Read>(&self, input: R, stb_samples: Option<Box<[Vec<f32>]>>)
+            -> Result<RefReport, RefTestError> {
+        let mut ogg = OggRefDecoder::new(input, 4096);
+        let mut ref_decoder = VorbisRefDecoder::new();
+        let mut decoder_builder = Decoder::builder();
+
+        if !try!(ogg.next_packet().map_err(reference_error)) {
+            return Err(unexpected_end());
+        }
+        try!(ref_decoder.decode_header(ogg.raw_packet_mut()).map_err(reference_error));
+        try!(decoder_builder.read_ident_packet(&mut BitReader::new(Cursor::new(ogg.packet_data())))
+            .map_err(RefTestError::Decoder));
+
+        if !try!(ogg.next_packet().map_err(reference_error)) {
+            return Err(unexpected_end());
+        }
+        try!(ref_decoder.decode_header(ogg.raw_packet_mut()).map_err(reference_error));
+        try!(decoder_builder.read_comment_packet(&mut BitReader::new(Cursor::new(ogg.packet_data())))
+            .map_err(RefTestError::Decoder));
+
+        if self.check_comments {
+            let actual = decoder_builder.comments().unwrap();
+            if actual.vendor() != ref_decoder.comment_vendor() {
+                return Err(RefTestError::HeaderMismatch("Vendor string differs"));
+            }
+            if actual.len() != ref_decoder.comment_count() {
+                return Err(RefTestError::HeaderMismatch("Comment count differs"));
+            }
+            for i in 0..ref_decoder.comment_count() {
+                if &actual.raw()[i][..] != ref_decoder.comment(i).unwrap().as_bytes() {
+                    return Err(RefTestError::HeaderMismatch("Comment differs"));
+                }
+            }
+        }
+
+        if !try!(ogg.next_packet().map_err(reference_error)) {
+            return Err(unexpected_end());
+        }
+        try!(ref_decoder.decode_header(ogg.raw_packet_mut()).map_err(reference_error));
+        try!(decoder_builder.read_setup_packet(&mut BitReader::new(Cursor::new(ogg.packet_data())))
+            .map_err(RefTestError::Decoder));
+
+        let mut decoder = decoder_builder.build();
+        let channel_count = decoder.header().channel_count();
+        if channel_count != ref_decoder.channel_count() {
+            return Err(RefTestError::HeaderMismatch("Channel count differs"));
+        }
+        if stb_samples.as_ref().map_or(false, |s| s.len() != channel_count) {
+            return Err(RefTestError::HeaderMismatch("stb_vorbis channel count differs"));
+        }
+
+        let mut report = RefReport {
+            packet_count: 0,
+            sample_count: 0,
+            diff: Diff::default(),
+            len_mismatch: None,
+            max_mismatch_count: self.max_mismatch_count,
+            stb: stb_samples.as_ref().map(|s| StbReport {
+                sample_count: s[0].len() as u64,
+                len_mismatch: false,
+                diff: Diff::default(),
+                ref_diff: Diff::default(),
+                max_mismatch_count: self.max_mismatch_count,
+            }),
+        };
+        while try!(ogg.next_packet().map_err(reference_error)) {
+            try!(ref_decoder.decode(ogg.raw_packet_mut()).map_err(reference_error));
+            let actual = try!(decoder.decode(&mut BitReader::new(Cursor::new(ogg.packet_data())))
+                .map_err(RefTestError::Decoder));
+
+            let mut len = 0;
+            for channel in 0..channel_count {
+                let expected = ref_decoder.pcm(channel);
+                let actual = actual.channel(channel);
+                // The last packet is trimmed to the end granule position by the reference.
+                let actual = if ogg.is_eos() && expected.len() < actual.len() {
+                    &actual[..expected.len()]
+                } else {
+                    actual
+                };
+                if expected.len() != actual.len() {
+                    report.len_mismatch = Some(report.packet_count);
+                    return Ok(report.finish());
+                }
+                len = actual.len();
+                let (packet, pos) = (report.packet_count, report.sample_count);
+                report.diff.compare(self.max_diff, packet, channel, pos, expected, actual);
+                if let (Some(stb_samples), Some(stb)) = (stb_samples.as_ref(), report.stb.as_mut()) {
+                    let stb_samples = &stb_samples[channel];
+                    let start = cmp::min(pos as usize, stb_samples.len());
+                    let stb_samples = &stb_samples[start..cmp::min(start + len, stb_samples.len())];
+                    stb.diff.compare(self.max_diff, packet, channel, pos, stb_samples, actual);
+                    stb.ref_diff.compare(self.max_diff, packet, channel, pos, expected, stb_samples);
+                }
+            }
+            report.packet_count += 1;
+            report.sample_count += len as u64;
+            if ogg.is_eos() {
+                break;
+            }
+        }
+        Ok(report.finish())
+    }
+}
+
+impl Default for RefTest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RefReport {
+    // Sets the stb_vorbis length mismatch once the comparison is over.
+    fn finish(mut self) -> Self {
+        let sample_count = self.sample_count;
+        if let Some(ref mut stb) = self.stb {
+            stb.len_mismatch = stb.sample_count != sample_count;
+        }
+        self
+    }
+}
+
+impl Diff {
+    // Compares the samples of the `channel` of the `packet` starting at the sample position
+    // `pos`. Only the samples present in both `expected` and `actual` are compared.
+    fn compare<T: Copy + Into<f64>>(&mut self, max_diff: f64, packet: usize, channel: usize, pos: u64,
+            expected: &[f32], actual: &[T]) {
+        for (i, (&e, &a)) in expected.iter().zip(actual.iter()).enumerate() {
+            let diff = (e as f64 - a.into()).abs();
+            if diff > self.max_diff {
+                self.max_diff = diff;
+            }
+            if diff > max_diff {
+                self.mismatch_count += 1;
+                if self.first_mismatch.is_none() {
+                    self.first_mismatch = Some(Mismatch {
+                        packet: packet,
+                        channel: channel,
+                        pos: pos + i as u64,
+                        expected: e as f64,
+                        actual: a.into(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn unexpected_end() -> RefTestError {
+    RefTestError::Reference("Unexpected end of Ogg stream".into())
+}
+
+fn reference_error<E: Debug>(e: E) -> RefTestError {
+    RefTestError::Reference(format!("{:?}", e))
+}
+
+// Bindings of the stb_vorbis functions used, the library is linked as `stb_vorbis`.
+#[cfg(feature = "reftest-stb")]
+#[allow(non_camel_case_types)]
+mod stb {
+    use std::os::raw::{c_float, c_int, c_uchar, c_uint, c_void};
+    use std::{ptr, slice};
+
+    use super::RefTestError;
+
+    enum stb_vorbis {}
+
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct stb_vorbis_info {
+        sample_rate: c_uint,
+        channels: c_int,
+        setup_memory_required: c_uint,
+        setup_temp_memory_required: c_uint,
+        temp_memory_required: c_uint,
+        max_frame_size: c_int,
+    }
+
+    #[link(name = "stb_vorbis")]
+    extern "C" {
+        fn stb_vorbis_open_memory(data: *const c_uchar, len: c_int, error: *mut c_int,
+            alloc_buffer: *const c_void) -> *mut stb_vorbis;
+        fn stb_vorbis_get_info(f: *mut stb_vorbis) -> stb_vorbis_info;
+        fn stb_vorbis_get_frame_float(f: *mut stb_vorbis, channels: *mut c_int,
+            output: *mut *mut *mut c_float) -> c_int;
+        fn stb_vorbis_close(f: *mut stb_vorbis);
+    }
+
+    // Decodes the first logical stream of the Ogg Vorbis `data` and returns the samples of each
+    // channel.
+    pub fn decode(data: &[u8]) -> Result<Box<[Vec<f32>]>, RefTestError> {
+        let mut error = 0;
+        let f = unsafe {
+            stb_vorbis_open_memory(data.as_ptr(), data.len() as c_int, &mut error, ptr::null())
+        };
+        if f.is_null() {
+            return Err(RefTestError::Reference(format!("stb_vorbis error {}", error)));
+        }
+        let channel_count = unsafe { stb_vorbis_get_info(f) }.channels as usize;
+        let mut channels = vec![Vec::new(); channel_count];
+        loop {
+            let mut output = ptr::null_mut();
+            let len = unsafe { stb_vorbis_get_frame_float(f, ptr::null_mut(), &mut output) };
+            if len <= 0 {
+                break;
+            }
+            for (i, channel) in channels.iter_mut().enumerate() {
+                channel.extend_from_slice(unsafe {
+                    slice::from_raw_parts(*output.offset(i as isize), len as usize)
+                });
+            }
+        }
+        unsafe { stb_vorbis_close(f) };
+        Ok(channels.into_boxed_slice())
+    }
+}
@@ -0,0 +1,300 @@
+use std::f64::consts::PI;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use decoder::Samples;
+use sample::Sample;
+#[cfg(not(feature = "std"))]
+use util::Float;
+
+// Number of zero crossings of the sinc on each side of the filter at the cutoff frequency.
+const ZERO_CROSSINGS: usize = 16;
+
+// Cutoff frequency relative to the lower of the two Nyquist frequencies, leaves room for the
+// transition band of the filter.
+const ROLLOFF: f64 = 0.95;
+
+/// Polyphase windowed sinc resampler converting the decoder output to another sample rate.
+///
+/// The rates ratio is reduced to `up / down` and the filter is precomputed for each of the `up`
+/// phases, so the resampling itself only does one dot product per output sample and channel
+/// and doesn't allocate once the output buffer has grown enough. The common rates like 44.1 kHz
+/// and 48 kHz have small ratios (160 / 147), unusual rates may need proportionally larger filter
+/// tables.
+///
+/// The output is aligned with the input: the first output sample is at the time of the first
+/// input sample, and after `flush()` the output has `ceil(input_len * to_rate / from_rate)`
+/// samples.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::io::Cursor;
+/// use vorbis::{BitReader, Decoder, Resampler};
+///
+/// # fn f(mut decoder: Decoder, audio_packets: Vec<Vec<u8>>) {
+/// let mut resampler = Resampler::new(decoder.header().channel_count(),
+///     decoder.header().sample_rate(), 48000);
+/// let mut output = Vec::new();
+/// for packet in &audio_packets {
+///     let samples = decoder.decode(&mut BitReader::new(Cursor::new(&packet[..])))
+///             .expect("Couldn't decode audio packet");
+///     output.clear();
+///     resampler.process(&samples, &mut output);
+///     // Play the interleaved 48 kHz samples in output.
+/// }
+/// output.clear();
+/// resampler.flush(&mut output);
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Resampler {
+    channel_count: usize,
+    from_rate: u32,
+    to_rate: u32,
+    up: usize,
+    down: usize,
+    taps: usize,
+    // Filter coefficients of each phase, `taps` per phase.
+    filter: Box<[Sample]>,
+    // Interleaved input samples starting with the first tap of the next output sample.
+    buf: Vec<Sample>,
+    // Number of the input frames to drop before appending to `buf`, when the next output sample
+    // needs no samples that are already there.
+    skip: usize,
+    phase: usize,
+    input_len: u64,
+    output_len: u64,
+}
+
+impl Resampler {
+    /// Creates a resampler of audio with `channel_count` channels from the `from_rate` to the
+    /// `to_rate` sample rate.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel count or any of the rates is zero.
+    pub fn new(channel_count: usize, from_rate: u32, to_rate: u32) -> Self {
+        assert!(channel_count > 0, "Channel count must be positive");
+        assert!(from_rate > 0 && to_rate > 0, "Sample rates must be positive");
+        let gcd = gcd(from_rate, to_rate);
+        let up = (to_rate / gcd) as usize;
+        let down = (from_rate / gcd) as usize;
+
+        // The cutoff relative to the input Nyquist frequency.
+        let cutoff = if to_rate < from_rate {
+            to_rate as f64 / from_rate as f64
+        } else {
+            1.0
+        } * ROLLOFF;
+        let half_len = (ZERO_CROSSINGS as f64 / cutoff) as usize + 1;
+        let taps = half_len * 2;
+
+        // The output sample of phase `p` is at `p / up` after the input sample of tap
+        // `half_len - 1`.
+        let mut filter = Vec::with_capacity(up * taps);
+        for p in 0..up {
+            let frac = p as f64 / up as f64;
+            for k in 0..taps {
+                let t = frac + half_len as f64 - 1.0 - k as f64;
+                filter.push((cutoff * sinc(cutoff * t) * blackman(t / half_len as f64)) as Sample);
+            }
+        }
+
+        let mut r = Resampler {
+            channel_count: channel_count,
+            from_rate: from_rate,
+            to_rate: to_rate,
+            up: up,
+            down: down,
+            taps: taps,
+            filter: filter.into_boxed_slice(),
+            buf: Vec::new(),
+            skip: 0,
+            phase: 0,
+            input_len: 0,
+            output_len: 0,
+        };
+        r.reset();
+        r
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channel_count
+    }
+
+    pub fn from_rate(&self) -> u32 {
+        self.from_rate
+    }
+
+    pub fn to_rate(&self) -> u32 {
+        self.to_rate
+    }
+
+    /// Resamples the decoded `samples` appending the interleaved output samples to the `output`.
+    /// The output lags the input by the filter half length (at least 17 samples): the last input
+    /// samples are kept until more input follows or `flush()` is called.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel count of the samples doesn't match.
+    pub fn process(&mut self, samples: &Samples, output: &mut Vec<Sample>) {
+        assert_eq!(samples.channel_count(), self.channel_count, "Channel count mismatch");
+        let skip = self.skip.min(samples.len());
+        self.skip -= skip;
+        self.buf.extend(samples.interleave().skip(skip * self.channel_count));
+        self.input_len += samples.len() as u64;
+        self.run(output, None);
+    }
+
+    /// Same as `process()` but for interleaved input samples.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the input length isn't a multiple of the channel count.
+    pub fn process_interleaved(&mut self, input: &[Sample], output: &mut Vec<Sample>) {
+        assert!(input.len() % self.channel_count == 0,
+            "Input length isn't a multiple of the channel count");
+        let len = input.len() / self.channel_count;
+        let skip = self.skip.min(len);
+        self.skip -= skip;
+        self.buf.extend_from_slice(&input[skip * self.channel_count..]);
+        self.input_len += len as u64;
+        self.run(output, None);
+    }
+
+    /// Appends the rest of the output to the `output` as if the input was followed by silence.
+    /// The resampler is reset afterwards and can be used for another stream.
+    pub fn flush(&mut self, output: &mut Vec<Sample>) {
+        let total = (self.input_len * self.up as u64 + self.down as u64 - 1) / self.down as u64;
+        let zeros = (self.taps / 2 + self.skip) * self.channel_count;
+        self.buf.extend((0..zeros).map(|_| 0.0));
+        self.skip = 0;
+        self.run(output, Some(total));
+        self.reset();
+    }
+
+    /// Discards the buffered input as if the resampler was newly created.
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        // Aligns the first input sample with the first output sample.
+        let len = (self.taps / 2 - 1) * self.channel_count;
+        self.buf.extend((0..len).map(|_| 0.0));
+        self.skip = 0;
+        self.phase = 0;
+        self.input_len = 0;
+        self.output_len = 0;
+    }
+
+    // Produces the output samples while there's enough input for them (and until the output
+    // length reaches the `limit` if set).
+    fn run(&mut self, output: &mut Vec<Sample>, limit: Option<u64>) {
+        let channel_count = self.channel_count;
+        let frame_count = self.buf.len() / channel_count;
+        let mut pos = 0;
+        while pos + self.taps <= frame_count && limit.map(|l| self.output_len < l).unwrap_or(true) {
+            let filter = &self.filter[self.phase * self.taps..(self.phase + 1) * self.taps];
+            let input = &self.buf[pos * channel_count..(pos + self.taps) * channel_count];
+            for channel in 0..channel_count {
+                let mut v = 0.0;
+                for (&h, &s) in filter.iter().zip(input[channel..].iter().step_by(channel_count)) {
+                    v += h * s;
+                }
+                output.push(v);
+            }
+            self.output_len += 1;
+            self.phase += self.down;
+            pos += self.phase / self.up;
+            self.phase %= self.up;
+        }
+        let consumed = pos.min(frame_count);
+        self.skip += pos - consumed;
+        self.buf.drain(..consumed * channel_count);
+    }
+}
+
+fn gcd(mut a: u32, mut b: u32) -> u32 {
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// Blackman window over -1..1.
+fn blackman(x: f64) -> f64 {
+    if x <= -1.0 || x >= 1.0 {
+        0.0
+    } else {
+        0.42 + 0.5 * (PI * x).cos() + 0.08 * (2.0 * PI * x).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+
+    fn sine(rate: u32, freq: f64, len: usize) -> Vec<Sample> {
+        (0..len).map(|i| (2.0 * PI * freq * i as f64 / rate as f64).sin() as Sample * 0.5).collect()
+    }
+
+    fn resample(from_rate: u32, to_rate: u32, input: &[Sample], chunk_len: usize) -> Vec<Sample> {
+        let mut r = Resampler::new(1, from_rate, to_rate);
+        let mut output = Vec::new();
+        for chunk in input.chunks(chunk_len) {
+            r.process_interleaved(chunk, &mut output);
+        }
+        r.flush(&mut output);
+        output
+    }
+
+    #[test]
+    fn resample_sine() {
+        for &(from_rate, to_rate) in &[(44100, 48000), (48000, 44100), (22050, 44100), (48000, 8000)] {
+            let input = sine(from_rate, 440.0, from_rate as usize / 2);
+            let output = resample(from_rate, to_rate, &input, 1000);
+            let len = (input.len() as u64 * to_rate as u64 + from_rate as u64 - 1) / from_rate as u64;
+            assert_eq!(output.len() as u64, len);
+
+            let expected = sine(to_rate, 440.0, output.len());
+            // The start and end are affected by the silence outside the input.
+            let margin = to_rate as usize / 100;
+            let max_diff = output[margin..output.len() - margin].iter()
+                .zip(&expected[margin..])
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, |a: Sample, b| a.max(b));
+            assert!(max_diff < 1e-3, "{} -> {}: {}", from_rate, to_rate, max_diff);
+        }
+    }
+
+    #[test]
+    fn resample_chunked() {
+        let input = sine(48000, 1000.0, 10000);
+        let expected = resample(48000, 8000, &input, input.len());
+        for &chunk_len in &[1, 5, 7, 1000] {
+            assert!(resample(48000, 8000, &input, chunk_len) == expected);
+        }
+    }
+
+    #[test]
+    fn resample_stereo() {
+        let mut r = Resampler::new(2, 44100, 48000);
+        let input: Vec<_> = sine(44100, 440.0, 4410).iter().flat_map(|&s| vec![s, -s]).collect();
+        let mut output = Vec::new();
+        r.process_interleaved(&input, &mut output);
+        r.flush(&mut output);
+        assert_eq!(output.len(), 4800 * 2);
+        assert!(output.chunks(2).all(|c| c[0] == -c[1]));
+    }
+}
@@ -1,9 +1,13 @@
-use num::FromPrimitive;
+use std::cmp;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
 
 use bitstream::BitRead;
 use codebook::Codebook;
 use error::{Error, ErrorKind, ExpectEof, Result};
-use util::{Bits, Push, Pusher2d, Pusher2dStep};
+use frame::Frame;
+use sample::Sample;
+use util::{Bits, FromPrimitive};
 
 enum_from_primitive! {
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
@@ -13,6 +17,7 @@ pub enum ResidueKind {
     Residue2 = 2,
 }}
 
+/// Residue configuration as read from the setup header.
 #[derive(Debug)]
 pub struct Residue {
     kind: ResidueKind,
@@ -23,28 +28,72 @@ pub struct Residue {
     class_codebooks: Box<[[Option<usize>; 8]]>,
 }
 
+/// Scratch buffers of `Residue::decode()` preallocated when the decoder is built so decoding
+/// doesn't allocate.
+pub struct ResidueBuf {
+    classes: Box<[usize]>,
+    vq: Vec<Sample>,
+}
+
+impl ResidueBuf {
+    /// Creates buffers big enough for decoding any of the `residues` with up to `channel_count`
+    /// channels and vectors of up to `max_len` values per channel.
+    pub fn new(residues: &[Residue], codebooks: &[Codebook], channel_count: usize, max_len: usize)
+            -> Self {
+        let mut classes_len = 0;
+        let mut vq_len = 0;
+        for residue in residues {
+            let actual_size = if residue.kind == ResidueKind::Residue2 {
+                max_len * channel_count
+            } else {
+                max_len
+            };
+            let n_to_read = cmp::min(residue.end, actual_size) - cmp::min(residue.start, actual_size);
+            let classwords_per_codeword = codebooks[residue.classbook].dim_count();
+            classes_len = cmp::max(classes_len, channel_count *
+                (classwords_per_codeword + n_to_read / residue.part_len));
+            for books in residue.class_codebooks.iter() {
+                for &book in books.iter().filter_map(|b| b.as_ref()) {
+                    vq_len = cmp::max(vq_len, codebooks[book].dim_count());
+                }
+            }
+        }
+        ResidueBuf {
+            classes: vec![0; classes_len].into_boxed_slice(),
+            vq: Vec::with_capacity(vq_len),
+        }
+    }
+}
+
 impl Residue {
     pub fn read<R: BitRead>(reader: &mut R, codebook_count: usize) -> Result<Self> {
+        trace_field!(reader, "residue.kind");
         let kind = if let Some(kind) = ResidueKind::from_u16(try!(reader.read_u16())) {
             kind
         } else {
-            return Err(Error::Undecodable("Unsupported residue type"));
+            return Err(Error::InvalidResidueKind);
         };
+        trace_field!(reader, "residue.begin");
         let start = try!(reader.read_u32_bits(24)) as usize;
+        trace_field!(reader, "residue.end");
         let end = try!(reader.read_u32_bits(24)) as usize;
         if end < start {
-            return Err(Error::Undecodable("Invalid residue range"));
+            return Err(Error::InvalidResidueRange);
         }
 
+        trace_field!(reader, "residue.partition_size");
         let part_len = try!(reader.read_u32_bits(24)) as usize + 1;
+        trace_field!(reader, "residue.classification_count");
         let class_count = try!(reader.read_u8_bits(6)) as usize + 1;
+        trace_field!(reader, "residue.classbook");
         let classbook = try!(reader.read_u8_bits(8)) as usize;
         if classbook >= codebook_count {
-            return Err(Error::Undecodable("Invalid codebook index in residue classbook"));
+            return Err(Error::InvalidResidueClassbook);
         }
 
         let mut cascade = Vec::with_capacity(class_count);
         for _ in 0..class_count {
+            trace_field!(reader, "residue.cascade");
             let low_bits = try!(reader.read_u8_bits(3));
             let has_high_bits = try!(reader.read_bool());
             let high_bits = if has_high_bits {
@@ -60,9 +109,10 @@ impl Residue {
             let mut book_set = [None; 8];
             for bit in 0..8 {
                 if c.is_bit_set(bit) {
+                    trace_field!(reader, "residue.book");
                     let codebook_idx = try!(reader.read_u8()) as usize;
                     if codebook_idx >= codebook_count {
-                        return Err(Error::Undecodable("Invalid codebook index in residue"));
+                        return Err(Error::InvalidResidueCodebook);
                     }
                     book_set[bit] = Some(codebook_idx);
                 }
@@ -82,14 +132,72 @@ impl Residue {
         })
     }
 
+    pub fn kind(&self) -> ResidueKind {
+        self.kind
+    }
+
+    /// Returns the start of the coded range of the residue vectors.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Returns the end (exclusive) of the coded range of the residue vectors.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Returns the length of each partition of the coded range.
+    pub fn part_len(&self) -> usize {
+        self.part_len
+    }
+
+    /// Returns the index of the codebook used to decode the partition classes.
+    pub fn classbook(&self) -> usize {
+        self.classbook
+    }
+
+    /// Returns the codebook index for each class and pass or `None` if the pass is skipped.
+    pub fn class_books(&self) -> &[[Option<usize>; 8]] {
+        &self.class_codebooks
+    }
+
+    /// Returns the number of decode passes: the last pass any class has a codebook for plus one.
+    pub fn pass_count(&self) -> usize {
+        self.class_codebooks.iter()
+            .filter_map(|books| books.iter().rposition(|b| b.is_some()))
+            .max()
+            .map(|p| p + 1)
+            .unwrap_or(0)
+    }
+
+    /// Decodes the residue vectors. Doesn't allocate if the `buf` was created for this residue.
+    /// The number of bits read in each pass is added to the `pass_bits` if the reader tracks the
+    /// bit position. Only the first `pass_count` passes are decoded. Fails with
+    /// `Error::DecodeLimitExceeded` if more than `vq_reads_left` VQ vectors would be read, the
+    /// number of the vectors read is subtracted from it otherwise. The `pass` is set to the
+    /// pass being decoded, i.e. where decoding stopped if it failed.
     pub fn decode<R: BitRead>(&self,
             reader: &mut R,
-            result: &mut [Box<[f32]>],
+            result: &mut Frame,
             len: usize,
             channels: &[usize],
             zero_channels: &[bool],
-            codebooks: &[Codebook]) -> Result<()> {
-        match self.do_decode(reader, result, len, channels, zero_channels, codebooks).expect_eof() {
+            codebooks: &[Codebook],
+            buf: &mut ResidueBuf,
+            pass_bits: &mut [u64; 8],
+            pass_count: usize,
+            vq_reads_left: &mut usize,
+            pass: &mut Option<usize>) -> Result<()> {
+        let start_pos = reader.bit_pos();
+        let counted_bits: u64 = pass_bits.iter().sum();
+        let r = self.do_decode(reader, result, len, channels, zero_channels, codebooks, buf, pass_bits,
+            pass_count, vq_reads_left, pass);
+        // Add the bits of the pass interrupted by the end of packet.
+        if let (Some(pass), Some(start_pos), Some(end_pos)) = (*pass, start_pos, reader.bit_pos()) {
+            let added_bits = pass_bits.iter().sum::<u64>() - counted_bits;
+            pass_bits[pass] += end_pos - start_pos - added_bits;
+        }
+        match r.expect_eof() {
             Err(ref e) if e.kind() == ErrorKind::ExpectedEof => Ok(()),
             r @ _ => r,
         }
@@ -97,12 +205,28 @@ impl Residue {
 
     fn do_decode<R: BitRead>(&self,
             reader: &mut R,
-            result: &mut [Box<[f32]>],
+            result: &mut Frame,
             len: usize,
             channels: &[usize],
             zero_channels: &[bool],
-            codebooks: &[Codebook]) -> Result<()> {
-        let n_to_read = self.end - self.start;
+            codebooks: &[Codebook],
+            buf: &mut ResidueBuf,
+            pass_bits: &mut [u64; 8],
+            pass_count: usize,
+            vq_reads_left: &mut usize,
+            cur_pass: &mut Option<usize>) -> Result<()> {
+        let is_residue2 = self.kind == ResidueKind::Residue2;
+
+        // As per spec the residue range is clamped to the actual vector size which is
+        // smaller than the range for short frames in some streams.
+        let actual_size = if is_residue2 {
+            len * channels.len()
+        } else {
+            len
+        };
+        let start = cmp::min(self.start, actual_size);
+        let end = cmp::min(self.end, actual_size);
+        let n_to_read = end - start;
 
         for &c in channels {
             for r in &mut result[c][..len] {
@@ -110,7 +234,7 @@ impl Residue {
             }
         }
 
-        if n_to_read == 0 {
+        if n_to_read == 0 || channels.is_empty() {
             return Ok(());
         }
 
@@ -119,37 +243,39 @@ impl Residue {
             return Ok(());
         }
 
+        if self.kind == ResidueKind::Residue0 {
+            return Err(Error::Residue0Unsupported);
+        }
+
         let codebook = &codebooks[self.classbook];
-        let classwords_per_codeword = codebook.dim_count as usize;
+        let classwords_per_codeword = codebook.dim_count();
+        if classwords_per_codeword == 0 {
+            return Err(Error::InvalidResidueClassbookDims);
+        }
         let parts_to_read = n_to_read / self.part_len;
 
-        let is_residue2 = self.kind == ResidueKind::Residue2;
+        // Classifications of the partitions, `classes_stride` values per channel. Every value is
+        // written in the first pass before it's read so the buffer doesn't need to be cleared.
+        let classes_stride = classwords_per_codeword + parts_to_read - 1;
+        let classes = &mut buf.classes[..classes_stride * channels.len()];
 
-        let mut classes = Vec::with_capacity(channels.len());
-        for _ in 0..channels.len() {
-            classes.push(vec![0; classwords_per_codeword + parts_to_read - 1]);
-        }
+        // Only needed to scatter VQ vectors over interleaved channels in Residue2.
+        let vq_buf = &mut buf.vq;
 
-        for pass in 0..8 {
+        for pass in 0..cmp::min(pass_count, 8) {
+            *cur_pass = Some(pass);
+            let pass_start_pos = reader.bit_pos();
             let mut part_count = 0;
-            let (pusher_pos, pusher_step) = match self.kind {
-                ResidueKind::Residue0 => unimplemented!(),
-                ResidueKind::Residue1 => ((0, 0),
-                                          Pusher2dStep::RightDown(0, 1)),
-                ResidueKind::Residue2 => ((self.start % channels.len(), self.start / channels.len()),
-                                          Pusher2dStep::DownRight(1, 1)),
-            };
-            let mut pusher = Pusher2d::new(&mut result[..], channels, pusher_pos, pusher_step,
-                    |r, v| *r += v);
             'outer: while part_count < parts_to_read {
                 if pass == 0 {
                     for (i, &c) in channels.iter().enumerate() {
                         if !is_residue2 && zero_channels[c] {
                             continue;
                         }
+                        trace_field!(reader, "residue.classification");
                         let mut temp = try!(codebook.decode_scalar(reader)) as usize;
                         for cw in (0..classwords_per_codeword).rev() {
-                            classes[i][cw + part_count] =
+                            classes[i * classes_stride + cw + part_count] =
                                 temp % self.class_codebooks.len();
                             temp /= self.class_codebooks.len();
                         }
@@ -165,19 +291,19 @@ impl Residue {
                         if !is_residue2 && zero_channels[c] {
                             continue;
                         }
-                        let vq_class = classes[i][part_count];
+                        let vq_class = classes[i * classes_stride + part_count];
                         let vq_book = self.class_codebooks[vq_class][pass];
                         if let Some(vq_book) = vq_book {
+                            trace_field!(reader, "residue.vq");
                             let codebook = &codebooks[vq_book];
-                            match self.kind {
-                                ResidueKind::Residue0 => unimplemented!(),
-                                ResidueKind::Residue1 =>
-                                    pusher.set_pos((c, self.start + part_count * self.part_len)),
-                                ResidueKind::Residue2 => {},
+                            let offset = start + part_count * self.part_len;
+                            if !is_residue2 || channels.len() == 1 {
+                                let part = &mut result[c][offset..offset + self.part_len];
+                                try!(self.codebook_decode(part, reader, codebook, vq_reads_left));
+                            } else {
+                                try!(self.codebook_decode_interleaved(result, channels, offset,
+                                        vq_buf, reader, codebook, vq_reads_left));
                             }
-                            try!(self.codebook_decode(&mut pusher, reader, codebook));
-                        } else {
-                            pusher.advance_flat_pos(self.part_len);
                         }
                         if is_residue2 {
                             // In Residue2 all channels are in a single partition.
@@ -190,16 +316,67 @@ impl Residue {
                     }
                 }
             }
+            if let (Some(start_pos), Some(end_pos)) = (pass_start_pos, reader.bit_pos()) {
+                pass_bits[pass] += end_pos - start_pos;
+            }
         }
 
         Ok(())
     }
 
-    fn codebook_decode<P: Push<f32>, R: BitRead>(&self, result: &mut P, reader: &mut R, codebook: &Codebook) -> Result<()> {
-        assert!(self.part_len % codebook.dim_count == 0);
-        for _ in 0..self.part_len / codebook.dim_count {
-            try!(codebook.decode_vq(reader, result));
+    // Checks the partition can be decoded with the codebook and takes its VQ reads from the
+    // budget. Returns the codebook dimensions.
+    fn check_codebook_dims(&self, codebook: &Codebook, vq_reads_left: &mut usize) -> Result<usize> {
+        let dim_count = codebook.dim_count();
+        if dim_count == 0 || self.part_len % dim_count != 0 {
+            return Err(Error::ResiduePartitionLenMismatch);
+        }
+        let vq_reads = self.part_len / dim_count;
+        if vq_reads > *vq_reads_left {
+            return Err(Error::DecodeLimitExceeded("Too many VQ reads"));
+        }
+        *vq_reads_left -= vq_reads;
+        Ok(dim_count)
+    }
+
+    fn codebook_decode<R: BitRead>(&self, result: &mut [Sample], reader: &mut R, codebook: &Codebook,
+            vq_reads_left: &mut usize) -> Result<()> {
+        let dim_count = try!(self.check_codebook_dims(codebook, vq_reads_left));
+        for vq in result.chunks_mut(dim_count) {
+            try!(codebook.decode_vq_add(reader, vq));
+        }
+        Ok(())
+    }
+
+    /// Decodes a partition of the Residue2 vector interleaved across `channels` starting at the
+    /// flat `offset`.
+    fn codebook_decode_interleaved<R: BitRead>(&self,
+            result: &mut Frame,
+            channels: &[usize],
+            offset: usize,
+            vq_buf: &mut Vec<Sample>,
+            reader: &mut R,
+            codebook: &Codebook,
+            vq_reads_left: &mut usize) -> Result<()> {
+        let dim_count = try!(self.check_codebook_dims(codebook, vq_reads_left));
+        vq_buf.resize(dim_count, 0.0);
+        let channel_count = channels.len();
+        let mut channel = offset % channel_count;
+        let mut pos = offset / channel_count;
+        for _ in 0..self.part_len / dim_count {
+            for v in vq_buf.iter_mut() {
+                *v = 0.0;
+            }
+            try!(codebook.decode_vq_add(reader, vq_buf));
+            for &v in vq_buf.iter() {
+                result[channels[channel]][pos] += v;
+                channel += 1;
+                if channel == channel_count {
+                    channel = 0;
+                    pos += 1;
+                }
+            }
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
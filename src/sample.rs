@@ -0,0 +1,38 @@
+//! Floating point type used for decoded samples and throughout the decoding pipeline.
+//! This is `f32` unless the `f64` feature is enabled.
+
+#[cfg(not(feature = "std"))]
+use util::Float;
+
+#[cfg(not(feature = "f64"))]
+pub type Sample = f32;
+
+#[cfg(feature = "f64")]
+pub type Sample = f64;
+
+// Returns `a * b + c`. With the `fast-math` feature it's computed with a single rounding which
+// compiles to the fused multiply-add instruction if the target has one, the result differs in
+// the last bits then. Without FMA support it's a slow library call.
+#[cfg(not(feature = "fast-math"))]
+#[inline(always)]
+pub fn mul_add(a: Sample, b: Sample, c: Sample) -> Sample {
+    a * b + c
+}
+
+#[cfg(feature = "fast-math")]
+#[inline(always)]
+pub fn mul_add(a: Sample, b: Sample, c: Sample) -> Sample {
+    a.mul_add(b, c)
+}
+
+// Converts the sample to 16-bit integer clipping the out of range values.
+pub fn to_i16(s: Sample) -> i16 {
+    let v = (s * 32767.0 + 0.5).floor();
+    if v > 32767.0 {
+        32767
+    } else if v < -32768.0 {
+        -32768
+    } else {
+        v as i16
+    }
+}
@@ -0,0 +1,622 @@
+//! Building the setup header packet from the codebook, floor, residue, mapping and mode configs.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::Cursor;
+
+use bitstream::{BitReader, BitWrite, BitWriter};
+use codebook::{Codebook, LookupKind, MAX_CODEWORD_LEN};
+#[cfg(feature = "comments")]
+use comment::Comments;
+use decoder::{PacketKind, Setup};
+use error::Result;
+use header::{FrameKind, Header};
+use huffman;
+use residue::ResidueKind;
+use util::Bits;
+
+/// Codebook config for `SetupBuilder`.
+#[derive(Clone, Debug)]
+pub struct CodebookConfig {
+    dim_count: usize,
+    lens: Vec<u8>,
+    lookup: Option<LookupConfig>,
+}
+
+#[derive(Clone, Debug)]
+struct LookupConfig {
+    kind: LookupKind,
+    min: f32,
+    delta: f32,
+    value_bits: usize,
+    sequence_p: bool,
+    mults: Vec<u32>,
+}
+
+impl CodebookConfig {
+    /// Creates codebook without lookup table with the codeword length of each entry, zero for
+    /// unused entries. The lengths must make a complete Huffman tree unless only one entry is
+    /// used.
+    pub fn new(dim_count: usize, codeword_lens: Vec<u8>) -> Self {
+        assert!(dim_count > 0 && dim_count <= 0xFFFF);
+        assert!(!codeword_lens.is_empty() && codeword_lens.len() < 1 << 24);
+        assert!(codeword_lens.iter().all(|&len| len as u32 <= MAX_CODEWORD_LEN));
+        CodebookConfig {
+            dim_count: dim_count,
+            lens: codeword_lens,
+            lookup: None,
+        }
+    }
+
+    /// Creates codebook without lookup table with the Huffman codeword lengths computed from
+    /// the relative frequency of each entry. The entries with zero weight are unused.
+    pub fn with_weights(dim_count: usize, weights: &[f64]) -> Self {
+        Self::new(dim_count, huffman_lens(weights))
+    }
+
+    /// Sets the VQ lookup table. The `mults` are the multiplicands of `value_bits` bits each
+    /// that map to `mult * delta + min` values. The lookup type 1 needs
+    /// `Codebook::lookup1_value_count()` multiplicands, the lookup type 2 needs
+    /// `entry_count() * dim_count()`.
+    pub fn lookup(&mut self, kind: LookupKind, min: f32, delta: f32, value_bits: usize,
+            mults: Vec<u32>) -> &mut Self {
+        assert!(value_bits > 0 && value_bits <= 16);
+        let mults_len = match kind {
+            LookupKind::Lookup1 => Codebook::lookup1_value_count(self.lens.len(), self.dim_count),
+            LookupKind::Lookup2 => self.lens.len() * self.dim_count,
+        };
+        assert_eq!(mults.len(), mults_len);
+        assert!(mults.iter().all(|&m| m < 1 << value_bits));
+        self.lookup = Some(LookupConfig {
+            kind: kind,
+            min: min,
+            delta: delta,
+            value_bits: value_bits,
+            sequence_p: false,
+            mults: mults,
+        });
+        self
+    }
+
+    /// Sets the lookup table `sequence_p` flag: each value of the vector is added to the
+    /// previous one. Must be called after `lookup()`.
+    pub fn sequence_p(&mut self, value: bool) -> &mut Self {
+        self.lookup.as_mut().expect("No lookup table").sequence_p = value;
+        self
+    }
+
+    pub fn dim_count(&self) -> usize {
+        self.dim_count
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.lens.len()
+    }
+
+    pub fn codeword_lens(&self) -> &[u8] {
+        &self.lens
+    }
+
+    /// Returns the codeword of each entry (zero for unused ones) in the stream bit order so
+    /// it can be written with `BitWrite::write_u32_bits()` with the codeword length.
+    pub fn codewords(&self) -> Result<Vec<u32>> {
+        huffman::make_codewords(&self.lens)
+    }
+
+    fn write<W: BitWrite>(&self, writer: &mut W) -> Result<()> {
+        try!(writer.write_all(b"BCV"));
+        try!(writer.write_u16(self.dim_count as u16));
+        try!(writer.write_u32_bits(self.lens.len() as u32, 24));
+        // Not ordered.
+        try!(writer.write_bool(false));
+        let sparse = self.lens.iter().any(|&len| len == 0);
+        try!(writer.write_bool(sparse));
+        for &len in &self.lens {
+            if sparse {
+                try!(writer.write_bool(len != 0));
+            }
+            if len != 0 {
+                try!(writer.write_u8_bits(len - 1, 5));
+            }
+        }
+        if let Some(ref lookup) = self.lookup {
+            try!(writer.write_u8_bits(lookup.kind as u8, 4));
+            try!(writer.write_f32(lookup.min));
+            try!(writer.write_f32(lookup.delta));
+            try!(writer.write_u8_bits(lookup.value_bits as u8 - 1, 4));
+            try!(writer.write_bool(lookup.sequence_p));
+            for &mult in &lookup.mults {
+                try!(writer.write_u32_bits(mult, lookup.value_bits));
+            }
+        } else {
+            try!(writer.write_u8_bits(0, 4));
+        }
+        Ok(())
+    }
+}
+
+/// Floor 1 config for `SetupBuilder`.
+#[derive(Clone, Debug)]
+pub struct FloorConfig {
+    mult: u8,
+    range_bits: usize,
+    part_classes: Vec<usize>,
+    classes: Vec<FloorClassConfig>,
+    x_list: Vec<u16>,
+}
+
+#[derive(Clone, Debug)]
+struct FloorClassConfig {
+    dim_count: usize,
+    master_book: Option<usize>,
+    subclass_books: Vec<Option<usize>>,
+}
+
+impl FloorConfig {
+    /// Creates floor with the Y value multiplier (`1..4`) and the number of bits of the X
+    /// values. The implicit last X value is `1 << range_bits`.
+    pub fn new(mult: u8, range_bits: usize) -> Self {
+        assert!(mult >= 1 && mult <= 4);
+        assert!(range_bits < 16);
+        FloorConfig {
+            mult: mult,
+            range_bits: range_bits,
+            part_classes: Vec::new(),
+            classes: Vec::new(),
+            x_list: Vec::new(),
+        }
+    }
+
+    /// Adds partition class with `dim_count` (`1..8`) X values in each partition. The
+    /// `subclass_books` are the codebooks of the subclasses, `None` for the subclasses with
+    /// the Y values implicitly equal to zero. There must be 1, 2, 4 or 8 subclasses, the
+    /// `master_book` selecting the subclass is required when there's more than one.
+    pub fn class(&mut self, dim_count: usize, master_book: Option<usize>,
+            subclass_books: Vec<Option<usize>>) -> &mut Self {
+        assert!(self.classes.len() < 16);
+        assert!(dim_count >= 1 && dim_count <= 8);
+        assert!([1, 2, 4, 8].contains(&subclass_books.len()));
+        assert_eq!(master_book.is_some(), subclass_books.len() > 1);
+        self.classes.push(FloorClassConfig {
+            dim_count: dim_count,
+            master_book: master_book,
+            subclass_books: subclass_books,
+        });
+        self
+    }
+
+    /// Adds partition of the `class` with its X values, as many as the class dimension.
+    pub fn partition(&mut self, class: usize, x_values: &[u16]) -> &mut Self {
+        assert!(self.part_classes.len() < 31);
+        assert_eq!(x_values.len(), self.classes[class].dim_count);
+        assert!(x_values.iter().all(|&x| x < 1 << self.range_bits));
+        self.part_classes.push(class);
+        self.x_list.extend_from_slice(x_values);
+        self
+    }
+
+    fn write<W: BitWrite>(&self, writer: &mut W) -> Result<()> {
+        // The decoder reads as many classes as referenced by the partitions.
+        assert_eq!(self.part_classes.iter().max().map(|&c| c + 1), Some(self.classes.len()),
+            "Floor has no partitions or unused classes");
+        try!(writer.write_u16(1));
+        try!(writer.write_u32_bits(self.part_classes.len() as u32, 5));
+        for &class in &self.part_classes {
+            try!(writer.write_u8_bits(class as u8, 4));
+        }
+        for class in &self.classes {
+            try!(writer.write_u8_bits(class.dim_count as u8 - 1, 3));
+            let subclass_bits = (class.subclass_books.len() as u32 - 1).ilog();
+            try!(writer.write_u8_bits(subclass_bits as u8, 2));
+            if let Some(book) = class.master_book {
+                try!(writer.write_u8(book as u8));
+            }
+            for book in &class.subclass_books {
+                try!(writer.write_u8(book.map(|b| b as u8 + 1).unwrap_or(0)));
+            }
+        }
+        try!(writer.write_u8_bits(self.mult - 1, 2));
+        try!(writer.write_u8_bits(self.range_bits as u8, 4));
+        for &x in &self.x_list {
+            try!(writer.write_u16_bits(x, self.range_bits));
+        }
+        Ok(())
+    }
+}
+
+/// Residue config for `SetupBuilder`.
+#[derive(Clone, Debug)]
+pub struct ResidueConfig {
+    kind: ResidueKind,
+    start: usize,
+    end: usize,
+    part_len: usize,
+    classbook: usize,
+    class_books: Vec<Vec<Option<usize>>>,
+}
+
+impl ResidueConfig {
+    /// Creates residue coding the spectrum values in `start..end` range in partitions of
+    /// `part_len` values. The partition classes are coded with the `classbook`.
+    pub fn new(kind: ResidueKind, start: usize, end: usize, part_len: usize, classbook: usize)
+            -> Self {
+        assert!(start <= end && end < 1 << 24);
+        assert!(part_len > 0 && part_len <= 1 << 24);
+        ResidueConfig {
+            kind: kind,
+            start: start,
+            end: end,
+            part_len: part_len,
+            classbook: classbook,
+            class_books: Vec::new(),
+        }
+    }
+
+    /// Adds partition class with the VQ codebook of each pass (up to 8), `None` for the passes
+    /// where the partitions of the class are skipped.
+    pub fn class(&mut self, books: &[Option<usize>]) -> &mut Self {
+        assert!(self.class_books.len() < 64);
+        assert!(books.len() <= 8);
+        self.class_books.push(books.to_vec());
+        self
+    }
+
+    fn write<W: BitWrite>(&self, writer: &mut W) -> Result<()> {
+        try!(writer.write_u16(self.kind as u16));
+        try!(writer.write_u32_bits(self.start as u32, 24));
+        try!(writer.write_u32_bits(self.end as u32, 24));
+        try!(writer.write_u32_bits(self.part_len as u32 - 1, 24));
+        try!(writer.write_u8_bits(self.class_books.len() as u8 - 1, 6));
+        try!(writer.write_u8(self.classbook as u8));
+        for books in &self.class_books {
+            let cascade = books.iter().enumerate()
+                .fold(0, |c, (pass, b)| if b.is_some() { c | 1 << pass } else { c });
+            try!(writer.write_u8_bits(cascade & 7, 3));
+            try!(writer.write_bool(cascade > 7));
+            if cascade > 7 {
+                try!(writer.write_u8_bits(cascade >> 3, 5));
+            }
+        }
+        for books in &self.class_books {
+            for &book in books.iter().filter_map(|b| b.as_ref()) {
+                try!(writer.write_u8(book as u8));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Mapping config for `SetupBuilder`.
+#[derive(Clone, Debug, Default)]
+pub struct MappingConfig {
+    couplings: Vec<(usize, usize)>,
+    channel_submaps: Vec<usize>,
+    submaps: Vec<(usize, usize)>,
+}
+
+impl MappingConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds square polar coupling of the magnitude and angle channels.
+    pub fn coupling(&mut self, mag_channel: usize, ang_channel: usize) -> &mut Self {
+        assert!(self.couplings.len() < 256);
+        self.couplings.push((mag_channel, ang_channel));
+        self
+    }
+
+    /// Adds submap with the floor and residue indexes.
+    pub fn submap(&mut self, floor: usize, residue: usize) -> &mut Self {
+        assert!(self.submaps.len() < 16);
+        self.submaps.push((floor, residue));
+        self
+    }
+
+    /// Sets the submap of each channel. Must be set when there's more than one submap, by
+    /// default all channels use the first one.
+    pub fn channel_submaps(&mut self, value: Vec<usize>) -> &mut Self {
+        self.channel_submaps = value;
+        self
+    }
+
+    fn write<W: BitWrite>(&self, writer: &mut W, channel_count: usize) -> Result<()> {
+        assert!(!self.submaps.is_empty(), "Mapping has no submaps");
+        try!(writer.write_u16(0));
+        let multiple_submaps = self.submaps.len() > 1;
+        try!(writer.write_bool(multiple_submaps));
+        if multiple_submaps {
+            try!(writer.write_u8_bits(self.submaps.len() as u8, 4));
+        }
+        try!(writer.write_bool(!self.couplings.is_empty()));
+        if !self.couplings.is_empty() {
+            try!(writer.write_u8(self.couplings.len() as u8 - 1));
+            let channel_bits = (channel_count as u32 - 1).ilog();
+            for &(mag, ang) in &self.couplings {
+                try!(writer.write_u32_bits(mag as u32, channel_bits));
+                try!(writer.write_u32_bits(ang as u32, channel_bits));
+            }
+        }
+        // Reserved.
+        try!(writer.write_u8_bits(0, 2));
+        if multiple_submaps {
+            assert_eq!(self.channel_submaps.len(), channel_count);
+            for &submap in &self.channel_submaps {
+                try!(writer.write_u8_bits(submap as u8, 4));
+            }
+        }
+        for &(floor, residue) in &self.submaps {
+            // Unused time config.
+            try!(writer.write_u8(0));
+            try!(writer.write_u8(floor as u8));
+            try!(writer.write_u8(residue as u8));
+        }
+        Ok(())
+    }
+}
+
+/// Builds a setup header packet, for example to generate test streams or replace the missing
+/// headers of a stream.
+///
+/// The codebooks, floors, residues and mappings are referenced by the index in the order they
+/// were added. Only floor 1 is supported.
+///
+/// # Example
+///
+/// ```rust
+/// use vorbis::{CodebookConfig, FloorConfig, FrameKind, FrameLens, Header, Bitrates,
+///     LookupKind, MappingConfig, ResidueConfig, ResidueKind, SetupBuilder};
+///
+/// let header = Header::new(1, 44100, Bitrates::new(0, 0, 0), FrameLens::new(256, 2048));
+/// let mut vq_book = CodebookConfig::with_weights(2, &[1.0; 9]);
+/// vq_book.lookup(LookupKind::Lookup1, -1.0, 1.0, 2, vec![0, 1, 2]);
+/// let mut floor = FloorConfig::new(2, 10);
+/// floor.class(1, None, vec![Some(0)]).partition(0, &[128]);
+/// let mut residue = ResidueConfig::new(ResidueKind::Residue1, 0, 1024, 32, 1);
+/// residue.class(&[]).class(&[Some(2)]);
+/// let mut mapping = MappingConfig::new();
+/// mapping.submap(0, 0);
+///
+/// let packet = SetupBuilder::new()
+///     .codebook(CodebookConfig::with_weights(1, &[1.0; 128]))
+///     .codebook(CodebookConfig::with_weights(1, &[1.0; 2]))
+///     .codebook(vq_book)
+///     .floor(floor)
+///     .residue(residue)
+///     .mapping(mapping)
+///     .mode(FrameKind::Long, 0)
+///     .to_packet(&header)
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SetupBuilder {
+    codebooks: Vec<CodebookConfig>,
+    floors: Vec<FloorConfig>,
+    residues: Vec<ResidueConfig>,
+    mappings: Vec<MappingConfig>,
+    modes: Vec<(FrameKind, usize)>,
+}
+
+impl SetupBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn codebook(&mut self, value: CodebookConfig) -> &mut Self {
+        assert!(self.codebooks.len() < 256);
+        self.codebooks.push(value);
+        self
+    }
+
+    pub fn floor(&mut self, value: FloorConfig) -> &mut Self {
+        assert!(self.floors.len() < 64);
+        self.floors.push(value);
+        self
+    }
+
+    pub fn residue(&mut self, value: ResidueConfig) -> &mut Self {
+        assert!(self.residues.len() < 64);
+        self.residues.push(value);
+        self
+    }
+
+    pub fn mapping(&mut self, value: MappingConfig) -> &mut Self {
+        assert!(self.mappings.len() < 64);
+        self.mappings.push(value);
+        self
+    }
+
+    /// Adds mode with the frame kind and the mapping index.
+    pub fn mode(&mut self, frame_kind: FrameKind, mapping: usize) -> &mut Self {
+        assert!(self.modes.len() < 64);
+        self.modes.push((frame_kind, mapping));
+        self
+    }
+
+    /// Writes the setup header packet of the stream with the `header`. The packet is read back
+    /// to check it's valid, the read error is returned if it isn't.
+    pub fn to_packet(&self, header: &Header) -> Result<Vec<u8>> {
+        assert!(!self.codebooks.is_empty() && !self.floors.is_empty() &&
+            !self.residues.is_empty() && !self.mappings.is_empty() && !self.modes.is_empty(),
+            "Setup must have at least one codebook, floor, residue, mapping and mode");
+
+        let mut writer = BitWriter::new(Vec::new());
+        self.write(&mut writer, header.channel_count()).expect("Writing to Vec failed");
+        let packet = writer.into_inner().unwrap();
+
+        try!(Setup::read_packet(&mut BitReader::new(Cursor::new(&packet[..])), header));
+        Ok(packet)
+    }
+
+    /// Returns the complete header packets (identification, comment and setup) of the stream.
+    #[cfg(feature = "comments")]
+    pub fn header_packets(&self, header: &Header, comments: &Comments) -> Result<[Vec<u8>; 3]> {
+        let setup_packet = try!(self.to_packet(header));
+        Ok([header.to_packet(), comments.to_packet(), setup_packet])
+    }
+
+    fn write<W: BitWrite>(&self, writer: &mut W, channel_count: usize) -> Result<()> {
+        try!(PacketKind::Setup.write(writer));
+
+        try!(writer.write_u8(self.codebooks.len() as u8 - 1));
+        for codebook in &self.codebooks {
+            try!(codebook.write(writer));
+        }
+
+        // Time domain transforms (placeholders).
+        try!(writer.write_u8_bits(0, 6));
+        try!(writer.write_u16(0));
+
+        try!(writer.write_u8_bits(self.floors.len() as u8 - 1, 6));
+        for floor in &self.floors {
+            try!(floor.write(writer));
+        }
+
+        try!(writer.write_u8_bits(self.residues.len() as u8 - 1, 6));
+        for residue in &self.residues {
+            try!(residue.write(writer));
+        }
+
+        try!(writer.write_u8_bits(self.mappings.len() as u8 - 1, 6));
+        for mapping in &self.mappings {
+            try!(mapping.write(writer, channel_count));
+        }
+
+        try!(writer.write_u8_bits(self.modes.len() as u8 - 1, 6));
+        for &(frame_kind, mapping) in &self.modes {
+            try!(writer.write_bool(frame_kind == FrameKind::Long));
+            // Window and transform types.
+            try!(writer.write_u16(0));
+            try!(writer.write_u16(0));
+            try!(writer.write_u8(mapping as u8));
+        }
+
+        try!(writer.write_bool(true));
+        try!(writer.flush_bits());
+        Ok(())
+    }
+}
+
+// Computes the Huffman codeword lengths for the entry weights, zero for the entries with zero
+// weight. The lengths are limited by flattening the weights distribution until the longest
+// codeword fits in MAX_CODEWORD_LEN bits.
+fn huffman_lens(weights: &[f64]) -> Vec<u8> {
+    assert!(weights.iter().all(|&w| w >= 0.0));
+    let used: Vec<_> = (0..weights.len()).filter(|&i| weights[i] > 0.0).collect();
+    assert!(!used.is_empty(), "All weights are zero");
+    let max = used.iter().fold(0.0, |m: f64, &i| m.max(weights[i]));
+
+    let mut min_ratio = 1e-9;
+    loop {
+        let mut heap: BinaryHeap<_> = used.iter().enumerate()
+            .map(|(node, &i)| Reverse((((weights[i] / max).max(min_ratio) * 1e9) as u64, node)))
+            .collect();
+        // The leaves go first, then the inner nodes.
+        let mut parents = vec![0; used.len() * 2 - 1];
+        let mut next = used.len();
+        while heap.len() > 1 {
+            let Reverse((w1, n1)) = heap.pop().unwrap();
+            let Reverse((w2, n2)) = heap.pop().unwrap();
+            parents[n1] = next;
+            parents[n2] = next;
+            heap.push(Reverse((w1 + w2, next)));
+            next += 1;
+        }
+        let root = next - 1;
+
+        let mut r = vec![0; weights.len()];
+        for (node, &i) in used.iter().enumerate() {
+            let mut n = node;
+            // A single used entry is still coded with one bit.
+            let mut len = if used.len() == 1 { 1 } else { 0 };
+            while n != root {
+                n = parents[n];
+                len += 1;
+            }
+            r[i] = len;
+        }
+        if r.iter().all(|&len| len as u32 <= MAX_CODEWORD_LEN) {
+            return r;
+        }
+        min_ratio *= 4.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use header::{Bitrates, FrameLens};
+    use super::*;
+
+    fn header() -> Header {
+        Header::new(2, 44100, Bitrates::new(0, 0, 0), FrameLens::new(256, 2048))
+    }
+
+    fn builder() -> SetupBuilder {
+        let mut vq_book = CodebookConfig::with_weights(2, &[1.0, 2.0, 1.0, 2.0, 4.0, 2.0, 1.0, 2.0, 1.0]);
+        vq_book.lookup(LookupKind::Lookup1, -1.0, 1.0, 2, vec![0, 1, 2]).sequence_p(true);
+
+        let mut floor = FloorConfig::new(1, 8);
+        floor.class(2, Some(1), vec![None, Some(0)])
+            .class(1, None, vec![Some(0)])
+            .partition(0, &[128, 64])
+            .partition(1, &[32]);
+
+        let mut residue = ResidueConfig::new(ResidueKind::Residue2, 0, 512, 16, 1);
+        residue.class(&[]).class(&[None, Some(2)]);
+
+        let mut mapping = MappingConfig::new();
+        mapping.coupling(0, 1).submap(0, 0);
+
+        let mut builder = SetupBuilder::new();
+        builder.codebook(CodebookConfig::with_weights(1, &[8.0, 4.0, 0.0, 2.0, 1.0, 1.0]))
+            .codebook(CodebookConfig::new(1, vec![1, 1]))
+            .codebook(vq_book)
+            .floor(floor)
+            .residue(residue)
+            .mapping(mapping)
+            .mode(FrameKind::Short, 0)
+            .mode(FrameKind::Long, 0);
+        builder
+    }
+
+    #[test]
+    fn to_packet() {
+        let header = header();
+        let packet = builder().to_packet(&header).unwrap();
+        let setup = Setup::read_packet(&mut BitReader::new(Cursor::new(&packet[..])), &header).unwrap();
+
+        assert_eq!(setup.codebooks().len(), 3);
+        assert_eq!(setup.codebooks()[0].entry_count(), 6);
+        assert_eq!(setup.codebooks()[2].dim_count(), 2);
+        assert_eq!(setup.codebooks()[2].lookup_kind(), Some(LookupKind::Lookup1));
+        let floor = &setup.floors()[0];
+        assert_eq!(floor.mult(), 1);
+        assert_eq!(floor.part_classes(), &[0, 1]);
+        assert_eq!(floor.x_list(), &[0, 256, 128, 64, 32]);
+        assert_eq!(floor.classes()[0].master_book(), Some(1));
+        assert_eq!(floor.classes()[0].subclass_books(), &[None, Some(0)]);
+        let mapping = &setup.mappings()[0];
+        assert_eq!(mapping.channel_couplings()[0].mag_channel(), 0);
+        assert_eq!(mapping.channel_couplings()[0].ang_channel(), 1);
+        assert_eq!(mapping.submaps()[0].channels(), &[0, 1]);
+    }
+
+    #[test]
+    fn to_packet_invalid() {
+        let mut builder = builder();
+        builder.mode(FrameKind::Long, 1);
+        assert!(builder.to_packet(&header()).is_err());
+    }
+
+    #[test]
+    fn huffman_lens_() {
+        assert_eq!(huffman_lens(&[1.0, 1.0]), vec![1, 1]);
+        assert_eq!(huffman_lens(&[4.0, 2.0, 1.0, 1.0]), vec![1, 2, 3, 3]);
+        assert_eq!(huffman_lens(&[0.0, 1.0, 0.0]), vec![0, 1, 0]);
+
+        let weights: Vec<_> = (0..40).map(|i| 0.5_f64.powi(i)).collect();
+        let lens = huffman_lens(&weights);
+        assert!(lens.iter().all(|&len| len as u32 <= MAX_CODEWORD_LEN));
+        assert!(huffman::make_codewords(&lens).is_ok());
+    }
+}
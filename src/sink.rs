@@ -0,0 +1,83 @@
+use std::cmp;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use decoder::Samples;
+use error::Result;
+use sample::Sample;
+
+// Length of the stack buffer the default `write_planar()` interleaves through.
+const INTERLEAVE_BUF_LEN: usize = 1024;
+
+/// Consumer of the decoded samples, see `Decoder::decode_to()`.
+///
+/// The decoder passes the samples of each packet to `write_planar()` straight from its frame
+/// buffers. The default implementation interleaves them through a small stack buffer and
+/// passes the chunks to `write_interleaved()`, so the sinks consuming interleaved samples (ring
+/// buffers of the audio devices, files) only implement the latter while the planar ones
+/// override the former.
+///
+/// # Example
+///
+/// ```rust
+/// use vorbis::{Result, Sample, SampleSink};
+///
+/// // Keeps the peak value.
+/// struct PeakMeter(Sample);
+///
+/// impl SampleSink for PeakMeter {
+///     fn write_interleaved(&mut self, samples: &[Sample]) -> Result<()> {
+///         for &s in samples {
+///             self.0 = self.0.max(s.abs());
+///         }
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait SampleSink {
+    /// Writes the samples decoded from a packet. Not called for the empty samples.
+    fn write_planar(&mut self, samples: &Samples) -> Result<()> {
+        let channel_count = samples.channel_count();
+        let chunk_len = INTERLEAVE_BUF_LEN / channel_count;
+        let mut buf = [0.0; INTERLEAVE_BUF_LEN];
+        let mut start = 0;
+        while start < samples.len() {
+            let len = cmp::min(chunk_len, samples.len() - start);
+            for (c, channel) in samples.channels().enumerate() {
+                for (i, &s) in channel[start..start + len].iter().enumerate() {
+                    buf[i * channel_count + c] = s;
+                }
+            }
+            try!(self.write_interleaved(&buf[..len * channel_count]));
+            start += len;
+        }
+        Ok(())
+    }
+
+    /// Writes the samples interleaved in the Vorbis channel order (like `Samples::interleave()`
+    /// returns).
+    fn write_interleaved(&mut self, samples: &[Sample]) -> Result<()>;
+}
+
+/// Appends the interleaved samples.
+impl SampleSink for Vec<Sample> {
+    fn write_planar(&mut self, samples: &Samples) -> Result<()> {
+        self.extend(samples.interleave());
+        Ok(())
+    }
+
+    fn write_interleaved(&mut self, samples: &[Sample]) -> Result<()> {
+        self.extend_from_slice(samples);
+        Ok(())
+    }
+}
+
+impl<'a, S: SampleSink + ?Sized> SampleSink for &'a mut S {
+    fn write_planar(&mut self, samples: &Samples) -> Result<()> {
+        (**self).write_planar(samples)
+    }
+
+    fn write_interleaved(&mut self, samples: &[Sample]) -> Result<()> {
+        (**self).write_interleaved(samples)
+    }
+}
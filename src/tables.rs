@@ -0,0 +1,93 @@
+#[cfg(not(feature = "std"))]
+use std::cell::{RefCell, RefMut};
+use std::collections::BTreeMap;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+#[cfg(not(feature = "std"))]
+use std::rc::Rc;
+use std::sync::Arc;
+#[cfg(feature = "std")]
+use std::sync::{Mutex, MutexGuard};
+
+use mdct::Mdct;
+use sample::Sample;
+use window::Windows;
+
+/// Cache of the MDCT trig tables and window slopes keyed by the block size.
+///
+/// By default each decoder computes its own tables. When many streams with identical block sizes
+/// are decoded (512/2048 is the most common case) the same cache instance can be passed to every
+/// `DecoderBuilder` to compute the tables only once and share them between the decoders.
+/// The cache is cheap to clone (all clones refer to the same tables) and can be shared
+/// between threads. Without the `std` feature there's no locking and the cache can only be
+/// used on one thread.
+#[derive(Clone, Default)]
+pub struct TableCache {
+    #[cfg(feature = "std")]
+    tables: Arc<Mutex<Tables>>,
+    #[cfg(not(feature = "std"))]
+    tables: Rc<RefCell<Tables>>,
+}
+
+#[derive(Default)]
+struct Tables {
+    mdct: BTreeMap<usize, Arc<Mdct>>,
+    slopes: BTreeMap<usize, Arc<Box<[Sample]>>>,
+}
+
+impl TableCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes all cached tables. The tables still used by the existing decoders are not freed
+    /// until the decoders are dropped.
+    pub fn clear(&self) {
+        let mut tables = self.tables();
+        tables.mdct.clear();
+        tables.slopes.clear();
+    }
+
+    #[cfg(feature = "std")]
+    fn tables(&self) -> MutexGuard<Tables> {
+        self.tables.lock().unwrap()
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn tables(&self) -> RefMut<Tables> {
+        self.tables.borrow_mut()
+    }
+
+    pub fn mdct(&self, len: usize) -> Arc<Mdct> {
+        self.tables().mdct.entry(len)
+                .or_insert_with(|| Arc::new(Mdct::new(len)))
+                .clone()
+    }
+
+    pub fn slope(&self, len: usize) -> Arc<Box<[Sample]>> {
+        self.tables().slopes.entry(len)
+                .or_insert_with(|| Arc::new(Windows::make_slope(len)))
+                .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn shared() {
+        let cache = TableCache::new();
+        let cache2 = cache.clone();
+
+        assert!(Arc::ptr_eq(&cache.mdct(256), &cache2.mdct(256)));
+        assert!(!Arc::ptr_eq(&cache.mdct(256), &cache.mdct(2048)));
+        assert!(Arc::ptr_eq(&cache.slope(128), &cache2.slope(128)));
+
+        let slope = cache.slope(128);
+        cache.clear();
+        assert!(!Arc::ptr_eq(&slope, &cache.slope(128)));
+    }
+}
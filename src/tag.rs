@@ -0,0 +1,187 @@
+//! Typed comment tags used to look up and edit `Comments`.
+
+use std::convert::From;
+use std::fmt;
+
+/// Comment tag (field name). The known tags are matched case-insensitively, use `normalize()`
+/// to turn `Custom` tag into the known one.
+#[derive(Debug)]
+pub enum CommentTag<'a> {
+    Title,
+    Version,
+    Album,
+    TrackNumber,
+    Artist,
+    Performer,
+    Copyright,
+    License,
+    Organization,
+    Description,
+    Genre,
+    Date,
+    Location,
+    Contact,
+    Isrc,
+    AlbumArtist,
+    Composer,
+    DiscNumber,
+    Comment,
+    Lyrics,
+    Encoder,
+    Bpm,
+    MusicBrainzTrackId,
+    MusicBrainzAlbumId,
+    MusicBrainzArtistId,
+    MusicBrainzAlbumArtistId,
+    MusicBrainzReleaseGroupId,
+    MusicBrainzWorkId,
+    Custom(&'a str),
+}
+
+impl<'a> CommentTag<'a> {
+    pub fn normalize(self) -> Self {
+        if let CommentTag::Custom(s) = self {
+            CommentTag::from(s)
+        } else {
+            self
+        }
+    }
+}
+
+impl<'a> AsRef<str> for CommentTag<'a> {
+    fn as_ref(&self) -> &str {
+        match self {
+            &CommentTag::Title                     => "TITLE",
+            &CommentTag::Version                   => "VERSION",
+            &CommentTag::Album                     => "ALBUM",
+            &CommentTag::TrackNumber               => "TRACKNUMBER",
+            &CommentTag::Artist                    => "ARTIST",
+            &CommentTag::Performer                 => "PERFORMER",
+            &CommentTag::Copyright                 => "COPYRIGHT",
+            &CommentTag::License                   => "LICENSE",
+            &CommentTag::Organization              => "ORGANIZATION",
+            &CommentTag::Description               => "DESCRIPTION",
+            &CommentTag::Genre                     => "GENRE",
+            &CommentTag::Date                      => "DATE",
+            &CommentTag::Location                  => "LOCATION",
+            &CommentTag::Contact                   => "CONTACT",
+            &CommentTag::Isrc                      => "ISRC",
+            &CommentTag::AlbumArtist               => "ALBUMARTIST",
+            &CommentTag::Composer                  => "COMPOSER",
+            &CommentTag::DiscNumber                => "DISCNUMBER",
+            &CommentTag::Comment                   => "COMMENT",
+            &CommentTag::Lyrics                    => "LYRICS",
+            &CommentTag::Encoder                   => "ENCODER",
+            &CommentTag::Bpm                       => "BPM",
+            &CommentTag::MusicBrainzTrackId        => "MUSICBRAINZ_TRACKID",
+            &CommentTag::MusicBrainzAlbumId        => "MUSICBRAINZ_ALBUMID",
+            &CommentTag::MusicBrainzArtistId       => "MUSICBRAINZ_ARTISTID",
+            &CommentTag::MusicBrainzAlbumArtistId  => "MUSICBRAINZ_ALBUMARTISTID",
+            &CommentTag::MusicBrainzReleaseGroupId => "MUSICBRAINZ_RELEASEGROUPID",
+            &CommentTag::MusicBrainzWorkId         => "MUSICBRAINZ_WORKID",
+            &CommentTag::Custom(s)                 => s,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for CommentTag<'a> {
+    fn from(s: &'a str) -> Self {
+        match s {
+            s if "TITLE".eq_ignore_ascii_case(s)                      => CommentTag::Title,
+            s if "VERSION".eq_ignore_ascii_case(s)                    => CommentTag::Version,
+            s if "ALBUM".eq_ignore_ascii_case(s)                      => CommentTag::Album,
+            s if "TRACKNUMBER".eq_ignore_ascii_case(s)                => CommentTag::TrackNumber,
+            s if "ARTIST".eq_ignore_ascii_case(s)                     => CommentTag::Artist,
+            s if "PERFORMER".eq_ignore_ascii_case(s)                  => CommentTag::Performer,
+            s if "COPYRIGHT".eq_ignore_ascii_case(s)                  => CommentTag::Copyright,
+            s if "LICENSE".eq_ignore_ascii_case(s)                    => CommentTag::License,
+            s if "ORGANIZATION".eq_ignore_ascii_case(s)               => CommentTag::Organization,
+            s if "DESCRIPTION".eq_ignore_ascii_case(s)                => CommentTag::Description,
+            s if "GENRE".eq_ignore_ascii_case(s)                      => CommentTag::Genre,
+            s if "DATE".eq_ignore_ascii_case(s)                       => CommentTag::Date,
+            s if "LOCATION".eq_ignore_ascii_case(s)                   => CommentTag::Location,
+            s if "CONTACT".eq_ignore_ascii_case(s)                    => CommentTag::Contact,
+            s if "ISRC".eq_ignore_ascii_case(s)                       => CommentTag::Isrc,
+            s if "ALBUMARTIST".eq_ignore_ascii_case(s)                => CommentTag::AlbumArtist,
+            s if "COMPOSER".eq_ignore_ascii_case(s)                   => CommentTag::Composer,
+            s if "DISCNUMBER".eq_ignore_ascii_case(s)                 => CommentTag::DiscNumber,
+            s if "COMMENT".eq_ignore_ascii_case(s)                    => CommentTag::Comment,
+            s if "LYRICS".eq_ignore_ascii_case(s)                     => CommentTag::Lyrics,
+            s if "ENCODER".eq_ignore_ascii_case(s)                    => CommentTag::Encoder,
+            s if "BPM".eq_ignore_ascii_case(s)                        => CommentTag::Bpm,
+            s if "MUSICBRAINZ_TRACKID".eq_ignore_ascii_case(s)        => CommentTag::MusicBrainzTrackId,
+            s if "MUSICBRAINZ_ALBUMID".eq_ignore_ascii_case(s)        => CommentTag::MusicBrainzAlbumId,
+            s if "MUSICBRAINZ_ARTISTID".eq_ignore_ascii_case(s)       => CommentTag::MusicBrainzArtistId,
+            s if "MUSICBRAINZ_ALBUMARTISTID".eq_ignore_ascii_case(s)  => CommentTag::MusicBrainzAlbumArtistId,
+            s if "MUSICBRAINZ_RELEASEGROUPID".eq_ignore_ascii_case(s) => CommentTag::MusicBrainzReleaseGroupId,
+            s if "MUSICBRAINZ_WORKID".eq_ignore_ascii_case(s)         => CommentTag::MusicBrainzWorkId,
+            _ => CommentTag::Custom(s),
+        }
+    }
+}
+
+impl<'a> fmt::Display for CommentTag<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            &CommentTag::Title                     => "Title",
+            &CommentTag::Version                   => "Version",
+            &CommentTag::Album                     => "Album",
+            &CommentTag::TrackNumber               => "Track number",
+            &CommentTag::Artist                    => "Artist",
+            &CommentTag::Performer                 => "Performer",
+            &CommentTag::Copyright                 => "Copyright",
+            &CommentTag::License                   => "License",
+            &CommentTag::Organization              => "Organization",
+            &CommentTag::Description               => "Description",
+            &CommentTag::Genre                     => "Genre",
+            &CommentTag::Date                      => "Date",
+            &CommentTag::Location                  => "Location",
+            &CommentTag::Contact                   => "Contact",
+            &CommentTag::Isrc                      => "ISRC",
+            &CommentTag::AlbumArtist               => "Album artist",
+            &CommentTag::Composer                  => "Composer",
+            &CommentTag::DiscNumber                => "Disc number",
+            &CommentTag::Comment                   => "Comment",
+            &CommentTag::Lyrics                    => "Lyrics",
+            &CommentTag::Encoder                   => "Encoder",
+            &CommentTag::Bpm                       => "BPM",
+            &CommentTag::MusicBrainzTrackId        => "MusicBrainz track ID",
+            &CommentTag::MusicBrainzAlbumId        => "MusicBrainz album ID",
+            &CommentTag::MusicBrainzArtistId       => "MusicBrainz artist ID",
+            &CommentTag::MusicBrainzAlbumArtistId  => "MusicBrainz album artist ID",
+            &CommentTag::MusicBrainzReleaseGroupId => "MusicBrainz release group ID",
+            &CommentTag::MusicBrainzWorkId         => "MusicBrainz work ID",
+            &CommentTag::Custom(s)                 => s,
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl<'a> PartialEq for CommentTag<'a> {
+    fn eq(&self, other: &CommentTag) -> bool {
+        self.as_ref().eq_ignore_ascii_case(other.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comment_tag_normalize() {
+        match CommentTag::Custom("AlbumArtist").normalize() {
+            CommentTag::AlbumArtist => {}
+            t => panic!("{:?}", t),
+        }
+        match CommentTag::Custom("musicbrainz_trackid").normalize() {
+            CommentTag::MusicBrainzTrackId => {}
+            t => panic!("{:?}", t),
+        }
+        match CommentTag::Custom("MUSICBRAINZ_DISCID").normalize() {
+            CommentTag::Custom("MUSICBRAINZ_DISCID") => {}
+            t => panic!("{:?}", t),
+        }
+        assert_eq!(CommentTag::Bpm, CommentTag::Custom("bpm"));
+        assert_eq!(CommentTag::Composer.to_string(), "Composer");
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use bitstream::{BitRead, Result};
+use util::Bits;
+
+/// A read of the traced `BitRead`, see `BitTracer`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TraceEntry {
+    field: Option<&'static str>,
+    bit_pos: Option<u64>,
+    len_bits: usize,
+    value: u32,
+}
+
+impl TraceEntry {
+    /// Returns the name of the field the read belongs to or `None` if the decoder didn't name
+    /// it (e.g. the reads before the first named field).
+    pub fn field(&self) -> Option<&'static str> {
+        self.field
+    }
+
+    /// Returns the offset of the read in bits if the inner reader tracks the position.
+    pub fn bit_pos(&self) -> Option<u64> {
+        self.bit_pos
+    }
+
+    pub fn len_bits(&self) -> usize {
+        self.len_bits
+    }
+
+    pub fn value(&self) -> u32 {
+        self.value
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.bit_pos {
+            Some(bit_pos) => try!(write!(f, "{:>10} ", bit_pos)),
+            None => try!(write!(f, "{:>10} ", "?")),
+        }
+        write!(f, "{}: {} ({} bits)", self.field.unwrap_or("?"), self.value, self.len_bits)
+    }
+}
+
+/// `BitRead` wrapper that records every read with the bit offset, value and the name of the
+/// field the decoder was reading. Requires the `trace` feature, without it the decoder doesn't
+/// name the fields and the tracing costs nothing.
+///
+/// Useful to find out where decoding of an unusual stream goes wrong: the trace of the
+/// packet that failed with `ErrorKind::Undecodable` shows the fields read up to the error.
+/// Each read of the inner reader is an entry, so a field can span several entries (e.g. the
+/// magnitude and the sign of a signed value). The bits read ahead and pushed back (e.g. by the
+/// Huffman decoder) are excluded.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::io::Cursor;
+/// use vorbis::{BitReader, BitTracer, Decoder};
+///
+/// # fn f(mut decoder: Decoder, packet: &[u8]) {
+/// let mut reader = BitTracer::new(BitReader::new(Cursor::new(packet)));
+/// if let Err(e) = decoder.decode(&mut reader) {
+///     println!("{:?}", e);
+///     for entry in reader.entries() {
+///         println!("{}", entry);
+///     }
+/// }
+/// # }
+/// ```
+pub struct BitTracer<R> {
+    inner: R,
+    field: Option<&'static str>,
+    entries: Vec<TraceEntry>,
+}
+
+impl<R: BitRead> BitTracer<R> {
+    pub fn new(reader: R) -> Self {
+        BitTracer {
+            inner: reader,
+            field: None,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Returns the reads recorded so far.
+    pub fn entries(&self) -> &[TraceEntry] {
+        &self.entries
+    }
+
+    /// Clears the recorded reads, e.g. before the next packet when reusing the tracer.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.field = None;
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: BitRead> BitRead for BitTracer<R> {
+    fn try_read_u32_bits(&mut self, len_bits: usize) -> Result<(u32, usize)> {
+        let bit_pos = self.inner.bit_pos();
+        let (value, read) = try!(self.inner.try_read_u32_bits(len_bits));
+        if read > 0 {
+            self.entries.push(TraceEntry {
+                field: self.field,
+                bit_pos: bit_pos,
+                len_bits: read,
+                value: value,
+            });
+        }
+        Ok((value, read))
+    }
+
+    // The unread bits are the last ones read, so they're removed from the end of the last
+    // entries.
+    fn unread_u32_bits(&mut self, bits: u32, len_bits: usize) {
+        self.inner.unread_u32_bits(bits, len_bits);
+        let mut left = len_bits;
+        while left > 0 {
+            let remove = match self.entries.last_mut() {
+                Some(entry) => if entry.len_bits > left {
+                    entry.len_bits -= left;
+                    entry.value = entry.value.ls_bits(entry.len_bits);
+                    left = 0;
+                    false
+                } else {
+                    left -= entry.len_bits;
+                    true
+                },
+                None => break,
+            };
+            if remove {
+                self.entries.pop();
+            }
+        }
+    }
+
+    fn bit_pos(&self) -> Option<u64> {
+        self.inner.bit_pos()
+    }
+
+    fn trace_field(&mut self, name: &'static str) {
+        self.field = Some(name);
+        self.inner.trace_field(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use bitstream::BitReader;
+    use header::Header;
+    use super::*;
+
+    #[test]
+    fn trace_header() {
+        let header = Header::new(2, 44100, ::header::Bitrates::new(0, 128000, 0),
+            ::header::FrameLens::new(256, 2048));
+        let packet = header.to_packet();
+        let mut reader = BitTracer::new(BitReader::new(Cursor::new(&packet[7..])));
+        Header::read(&mut reader).unwrap();
+        let fields: Vec<_> = reader.entries().iter()
+            .map(|e| (e.field().unwrap(), e.bit_pos().unwrap(), e.value()))
+            .collect();
+        assert_eq!(fields, &[
+            ("ident.version", 0, 0),
+            ("ident.channel_count", 32, 2),
+            ("ident.sample_rate", 40, 44100),
+            // Signed values are read as the magnitude and the sign bit.
+            ("ident.bitrate_max", 72, 0),
+            ("ident.bitrate_max", 103, 0),
+            ("ident.bitrate_nom", 104, 128000),
+            ("ident.bitrate_nom", 135, 0),
+            ("ident.bitrate_min", 136, 0),
+            ("ident.bitrate_min", 167, 0),
+            ("ident.blocksize_0", 168, 8),
+            ("ident.blocksize_1", 172, 11),
+            ("framing_bit", 176, 1),
+        ]);
+        assert_eq!(reader.entries()[2].to_string(), "        40 ident.sample_rate: 44100 (32 bits)");
+    }
+
+    #[test]
+    fn unread() {
+        let mut reader = BitTracer::new(BitReader::new(Cursor::new([0xff, 0x0f, 0x12])));
+        reader.trace_field("a");
+        reader.read_u32_bits(12).unwrap();
+        reader.trace_field("b");
+        reader.read_u32_bits(8).unwrap();
+        reader.unread_u32_bits(0x20f, 12);
+        assert_eq!(reader.entries(), &[TraceEntry {
+            field: Some("a"),
+            bit_pos: Some(0),
+            len_bits: 8,
+            value: 0xff,
+        }]);
+    }
+}
@@ -89,96 +89,90 @@ impl Bits for u8 {
     }
 }
 
-pub trait Push<T> {
-    fn push(&mut self, value: T);
+#[inline]
+pub fn lsb_mask(len: usize) -> u32 {
+    0xFFFF_FFFF >> (32 - len)
 }
 
-impl<'a, T: 'a, I> Push<T> for I where I: Iterator<Item=&'a mut T> {
-    fn push(&mut self, value: T) {
-        let r = self.next().unwrap();
-        *r = value;
+// Conversion of the integer fields read from the bitstream into enums. Implemented by
+// `enum_from_primitive!`.
+pub trait FromPrimitive: Sized {
+    fn from_u64(n: u64) -> Option<Self>;
+
+    fn from_u8(n: u8) -> Option<Self> {
+        Self::from_u64(n as u64)
     }
-}
 
-pub enum Pusher2dStep {
-    RightDown(usize, usize),
-    DownRight(usize, usize),
+    fn from_u16(n: u16) -> Option<Self> {
+        Self::from_u64(n as u64)
+    }
 }
 
-pub struct Pusher2d<'a, T: 'a, F> {
-    array2d: &'a mut [Box<[T]>],
-    index_map: &'a [usize],
-    len: (usize, usize),
-    step: Pusher2dStep,
-    pos: (usize, usize),
-    mutator: F,
+// Names the field read next from the `BitRead` for `BitTracer`. Compiled out without the
+// `trace` feature.
+macro_rules! trace_field {
+    ($reader:expr, $name:expr) => {
+        #[cfg(feature = "trace")]
+        ::bitstream::BitRead::trace_field($reader, $name);
+    }
 }
 
-impl<'a, T, F: FnMut(&mut T, T)> Pusher2d<'a, T, F> {
-    pub fn new(
-            array2d: &'a mut [Box<[T]>],
-            index_map: &'a [usize],
-            pos: (usize, usize),
-            step: Pusher2dStep,
-            mutator: F) -> Self {
-        let len = (index_map.len(), array2d[0].len());
-        Pusher2d {
-            array2d: array2d,
-            index_map: index_map,
-            len: len,
-            pos: pos,
-            step: step,
-            mutator: mutator,
+// Defines C-like enum implementing `FromPrimitive`. Replaces the `enum_primitive` crate which
+// doesn't work without `std`.
+macro_rules! enum_from_primitive {
+    ($(#[$attr:meta])* pub enum $name:ident { $($variant:ident = $value:expr,)* }) => {
+        $(#[$attr])*
+        pub enum $name {
+            $($variant = $value,)*
         }
-    }
-
-    pub fn set_pos(&mut self, pos: (usize, usize)) {
-        self.pos = pos;
-    }
 
-    pub fn advance_flat_pos(&mut self, flat_offset: usize) {
-        match self.step {
-            Pusher2dStep::RightDown(_, _) => {
-                let pos_1 = self.pos.1 + flat_offset;
-                self.pos.0 = pos_1 / self.len.1;
-                self.pos.1 += pos_1 % self.len.1;
-            },
-            Pusher2dStep::DownRight(_, _) => {
-                let pos_0 = self.pos.0 + flat_offset;
-                self.pos.0 = pos_0 % self.len.0;
-                self.pos.1 += pos_0 / self.len.0;
-            },
+        impl ::util::FromPrimitive for $name {
+            fn from_u64(n: u64) -> Option<Self> {
+                $(if n == $value {
+                    return Some($name::$variant);
+                })*
+                None
+            }
         }
     }
 }
 
-impl<'a, T, F: FnMut(&mut T, T)> Push<T> for Pusher2d<'a, T, F> {
-    fn push(&mut self, value: T) {
-        let index = self.index_map[self.pos.0];
-        let r = &mut self.array2d[index][self.pos.1];
-        (self.mutator)(r, value);
-        match self.step {
-            Pusher2dStep::RightDown(step_0, step_1) => {
-                self.pos.1 += step_1;
-                if self.pos.1 >= self.len.1 {
-                    self.pos.1 = 0;
-                    self.pos.0 += step_0;
-                }
-            },
-            Pusher2dStep::DownRight(step_0, step_1) => {
-                self.pos.0 += step_0;
-                if self.pos.0 >= self.len.0 {
-                    self.pos.0 = 0;
-                    self.pos.1 += step_1;
-                }
-            },
-        }
-    }
+// Float functions that `core` doesn't provide, implemented with `libm` when building without
+// `std`. With `std` the inherent methods are used.
+#[cfg(not(feature = "std"))]
+pub trait Float {
+    fn floor(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn powf(self, n: Self) -> Self;
+    fn powi(self, n: i32) -> Self;
+    fn log10(self) -> Self;
+    #[cfg(feature = "fast-math")]
+    fn mul_add(self, a: Self, b: Self) -> Self;
 }
 
-#[inline]
-pub fn lsb_mask(len: usize) -> u32 {
-    0xFFFF_FFFF >> (32 - len)
+#[cfg(not(feature = "std"))]
+impl Float for f32 {
+    fn floor(self) -> Self { ::libm::floorf(self) }
+    fn sin(self) -> Self { ::libm::sinf(self) }
+    fn cos(self) -> Self { ::libm::cosf(self) }
+    fn powf(self, n: Self) -> Self { ::libm::powf(self, n) }
+    fn powi(self, n: i32) -> Self { ::libm::powf(self, n as f32) }
+    fn log10(self) -> Self { ::libm::log10f(self) }
+    #[cfg(feature = "fast-math")]
+    fn mul_add(self, a: Self, b: Self) -> Self { ::libm::fmaf(self, a, b) }
+}
+
+#[cfg(not(feature = "std"))]
+impl Float for f64 {
+    fn floor(self) -> Self { ::libm::floor(self) }
+    fn sin(self) -> Self { ::libm::sin(self) }
+    fn cos(self) -> Self { ::libm::cos(self) }
+    fn powf(self, n: Self) -> Self { ::libm::pow(self, n) }
+    fn powi(self, n: i32) -> Self { ::libm::pow(self, n as f64) }
+    fn log10(self) -> Self { ::libm::log10(self) }
+    #[cfg(feature = "fast-math")]
+    fn mul_add(self, a: Self, b: Self) -> Self { ::libm::fma(self, a, b) }
 }
 
 #[cfg(test)]
@@ -187,6 +181,13 @@ mod tests {
 
     use std::mem;
 
+    enum_from_primitive! {
+    #[derive(Debug, PartialEq)]
+    pub enum Kind {
+        A = 0,
+        B = 3,
+    }}
+
     #[test]
     fn bits_ilog() {
         const TEST_SET_LEN: usize = 6;
@@ -214,4 +215,12 @@ mod tests {
         assert_eq!(0b00110111_11010110_10101100_00000001_u32.reverse_bits(),
                    0b10000000_00110101_01101011_11101100);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn from_primitive() {
+        assert_eq!(Kind::from_u8(0), Some(Kind::A));
+        assert_eq!(Kind::from_u16(3), Some(Kind::B));
+        assert_eq!(Kind::from_u8(1), None);
+        assert_eq!(Kind::from_u64(0x100), None);
+    }
+}
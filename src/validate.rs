@@ -0,0 +1,293 @@
+use std::fmt;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use bitstream::{BitRead, BitReader};
+use decoder::{Decoder, PacketKind};
+use error::{Error, ErrorKind, Warning};
+use header::FrameKind;
+
+/// How bad an `Issue` is.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Severity {
+    /// The packet couldn't be decoded.
+    Error,
+    /// The stream violates the spec but the packet was decoded.
+    Violation,
+    /// The stream is valid but unusual, e.g. it decodes differently in some decoders or hints at
+    /// a buggy encoder or muxer.
+    Suspicious,
+}
+
+/// What the decoder did about an `Issue`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Recovery {
+    /// Decoding continued as usual.
+    Continued,
+    /// The packet was skipped, the window flags of the next packet aren't checked against it.
+    PacketSkipped,
+    /// A header packet is invalid or missing, the rest of the stream wasn't validated.
+    Stopped,
+}
+
+#[derive(Debug)]
+pub enum IssueKind {
+    /// Reading of the packet failed.
+    Error(Error),
+    /// The decoder (in lenient mode) recovered from the problem, see `Warning`.
+    Warning(Warning),
+    /// The packets ended before the header packet.
+    MissingHeader(PacketKind),
+    /// The audio packet is empty.
+    EmptyPacket,
+    /// The previous window flag of the long frame doesn't match the previous frame. This decoder
+    /// and libvorbis overlap with the actual previous frame, but the decoders following the
+    /// flag don't.
+    PrevWindowFlagMismatch,
+    /// The next window flag of the long frame doesn't match the next frame.
+    NextWindowFlagMismatch,
+}
+
+/// Problem found by `validate()`.
+#[derive(Debug)]
+pub struct Issue {
+    packet: usize,
+    bit_pos: Option<u64>,
+    kind: IssueKind,
+    recovery: Recovery,
+}
+
+impl Issue {
+    /// Returns the index of the packet in the stream, the header packets included.
+    pub fn packet(&self) -> usize {
+        self.packet
+    }
+
+    /// Returns the offset in bits from the beginning of the packet if it's known.
+    pub fn bit_pos(&self) -> Option<u64> {
+        self.bit_pos
+    }
+
+    pub fn kind(&self) -> &IssueKind {
+        &self.kind
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self.kind {
+            IssueKind::Error(_) | IssueKind::MissingHeader(_) => Severity::Error,
+            IssueKind::Warning(Warning::InvalidFramingBit(_)) |
+                    IssueKind::Warning(Warning::DroppedComments(_)) => Severity::Violation,
+            // Ending the packet in the floor is legal, the pass limit is this decoder's own.
+            IssueKind::Warning(Warning::TruncatedFloor { .. }) |
+                    IssueKind::Warning(Warning::ResiduePassesSkipped(_)) => Severity::Suspicious,
+            IssueKind::EmptyPacket |
+                    IssueKind::PrevWindowFlagMismatch |
+                    IssueKind::NextWindowFlagMismatch => Severity::Suspicious,
+        }
+    }
+
+    pub fn recovery(&self) -> Recovery {
+        self.recovery
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "packet {}", self.packet));
+        if let Some(bit_pos) = self.bit_pos {
+            try!(write!(f, " bit {}", bit_pos));
+        }
+        let recovery = match self.recovery {
+            Recovery::Continued => "",
+            Recovery::PacketSkipped => " (packet skipped)",
+            Recovery::Stopped => " (validation stopped)",
+        };
+        write!(f, ": {:?}: {:?}{}", self.severity(), self.kind, recovery)
+    }
+}
+
+/// Result of `validate()`.
+#[derive(Debug)]
+pub struct ValidationReport {
+    issues: Vec<Issue>,
+    packet_count: usize,
+    sample_count: u64,
+}
+
+impl ValidationReport {
+    /// Returns the issues ordered by the packet.
+    pub fn issues(&self) -> &[Issue] {
+        &self.issues
+    }
+
+    /// Returns `true` if there are no issues of `Severity::Error` and `Severity::Violation`.
+    pub fn is_valid(&self) -> bool {
+        self.issues.iter().all(|i| i.severity() == Severity::Suspicious)
+    }
+
+    /// Returns the number of the packets read, the header packets included.
+    pub fn packet_count(&self) -> usize {
+        self.packet_count
+    }
+
+    /// Returns the number of samples (per channel) decoding the stream would produce, the
+    /// skipped packets excluded. Should match the granule position of the last page unless
+    /// the end is trimmed.
+    pub fn sample_count(&self) -> u64 {
+        self.sample_count
+    }
+
+    fn push(&mut self, packet: usize, bit_pos: Option<u64>, kind: IssueKind, recovery: Recovery) {
+        self.issues.push(Issue {
+            packet: packet,
+            bit_pos: bit_pos,
+            kind: kind,
+            recovery: recovery,
+        });
+    }
+
+    fn push_error(&mut self, packet: usize, error: Error, recovery: Recovery) {
+        let bit_pos = error.bit_pos();
+        self.push(packet, bit_pos, IssueKind::Error(error), recovery);
+    }
+}
+
+/// Checks the whole stream (the header packets followed by the audio packets) reporting every
+/// spec violation, suspicious construct and recovery of the decoder, like a lint.
+///
+/// The packets are decoded in lenient mode (see `DecoderBuilder::lenient()`) to report the
+/// recoverable violations as such, but without the synthesis (the inverse MDCT and overlap-add),
+/// so it's several times faster than decoding. The failed audio packets are skipped and
+/// validation continues, but an invalid header packet stops it.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use vorbis::validate;
+///
+/// # fn f(packets: Vec<Vec<u8>>) {
+/// let report = validate(&packets);
+/// for issue in report.issues() {
+///     println!("{}", issue);
+/// }
+/// if !report.is_valid() {
+///     println!("The stream is invalid");
+/// }
+/// # }
+/// ```
+pub fn validate<I>(packets: I) -> ValidationReport
+        where I: IntoIterator, I::Item: AsRef<[u8]> {
+    let mut report = ValidationReport {
+        issues: Vec::new(),
+        packet_count: 0,
+        sample_count: 0,
+    };
+    let mut packets = packets.into_iter();
+
+    let mut builder = Decoder::builder();
+    builder.lenient(true);
+    for &kind in &[PacketKind::Ident, PacketKind::Comment, PacketKind::Setup] {
+        let i = report.packet_count;
+        let packet = match packets.next() {
+            Some(packet) => packet,
+            None => {
+                report.push(i, None, IssueKind::MissingHeader(kind), Recovery::Stopped);
+                return report;
+            }
+        };
+        report.packet_count += 1;
+        let warning_count = builder.warnings().len();
+        let mut reader = BitReader::new(packet.as_ref());
+        let r = match kind {
+            PacketKind::Ident => builder.read_ident_packet(&mut reader),
+            PacketKind::Comment => builder.read_comment_packet(&mut reader),
+            _ => builder.read_setup_packet(&mut reader),
+        };
+        for &w in &builder.warnings()[warning_count..] {
+            report.push(i, None, IssueKind::Warning(w), Recovery::Continued);
+        }
+        if let Err(e) = r {
+            report.push_error(i, e, Recovery::Stopped);
+            return report;
+        }
+    }
+
+    let mut decoder = builder.build();
+    // The header warnings are reported already.
+    decoder.take_warnings();
+    let frame_lens = decoder.header().frame_lens();
+    // The packet index, frame kind and the next window flag with its position of the previous
+    // decoded packet.
+    let mut prev: Option<(usize, FrameKind, Option<(bool, Option<u64>)>)> = None;
+    for packet in packets {
+        let i = report.packet_count;
+        report.packet_count += 1;
+        let packet = packet.as_ref();
+        if packet.is_empty() {
+            report.push(i, None, IssueKind::EmptyPacket, Recovery::PacketSkipped);
+            prev = None;
+            continue;
+        }
+        let mut reader = BitReader::new(packet);
+        let r = decoder.decode_spectrum_only(&mut reader);
+        for w in decoder.take_warnings() {
+            report.push(i, None, IssueKind::Warning(w), Recovery::Continued);
+        }
+        let info = match r {
+            Ok(info) => info,
+            Err(e) => {
+                // The audio packet errors aren't located, the reader stopped where the
+                // undecodable data is.
+                let bit_pos = if e.kind() == ErrorKind::Undecodable { reader.bit_pos() } else { None };
+                report.push(i, bit_pos, IssueKind::Error(e), Recovery::PacketSkipped);
+                prev = None;
+                continue;
+            }
+        };
+        let frame_kind = info.frame_kind();
+        // The window flags are the last bits of the packet header.
+        let flags_pos = decoder.packet_stats().map(|s| s.header_bits() - 2);
+        if let Some((prev_packet, prev_kind, prev_next_flag)) = prev {
+            if let Some(prev_long) = info.prev_window_long() {
+                if prev_long != (prev_kind == FrameKind::Long) {
+                    report.push(i, flags_pos, IssueKind::PrevWindowFlagMismatch, Recovery::Continued);
+                }
+            }
+            if let Some((next_long, pos)) = prev_next_flag {
+                if next_long != (frame_kind == FrameKind::Long) {
+                    report.push(prev_packet, pos, IssueKind::NextWindowFlagMismatch,
+                        Recovery::Continued);
+                }
+            }
+            report.sample_count += (frame_lens.get(prev_kind) / 4 + frame_lens.get(frame_kind) / 4) as u64;
+        }
+        let next_flag = info.next_window_long().map(|l| (l, flags_pos.map(|p| p + 1)));
+        prev = Some((i, frame_kind, next_flag));
+    }
+    // The next window flag issues are found with the following packet.
+    report.issues.sort_by_key(|i| i.packet);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_headers() {
+        let report = validate(&[] as &[&[u8]]);
+        assert_eq!(report.packet_count(), 0);
+        assert!(!report.is_valid());
+        match report.issues()[0].kind() {
+            &IssueKind::MissingHeader(PacketKind::Ident) => {}
+            k => panic!("{:?}", k),
+        }
+        assert_eq!(report.issues()[0].recovery(), Recovery::Stopped);
+
+        let report = validate(&[&b"\x01vorbis"[..]]);
+        assert_eq!(report.packet_count(), 1);
+        assert_eq!(report.issues().len(), 1);
+        assert_eq!(report.issues()[0].severity(), Severity::Error);
+        assert_eq!(report.issues()[0].packet(), 0);
+    }
+}
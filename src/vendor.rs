@@ -0,0 +1,242 @@
+use std::cmp;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+use std::str;
+
+use decoder::Setup;
+use header::FrameKind;
+use residue::ResidueKind;
+
+// Build dates of the libvorbis vendor strings and the releases using them. 1.1.2 kept the
+// vendor string of 1.1.1.
+const LIBVORBIS_RELEASES: &'static [(u32, &'static str)] = &[
+    (20020717, "1.0"),
+    (20030909, "1.0.1"),
+    (20040629, "1.1.0"),
+    (20050304, "1.1.1"),
+    (20070622, "1.2.0"),
+    (20080501, "1.2.1"),
+    (20090624, "1.2.2"),
+    (20090709, "1.2.3"),
+    (20100325, "1.3.1"),
+    (20101101, "1.3.2"),
+    (20120203, "1.3.3"),
+    (20140122, "1.3.4"),
+    (20150105, "1.3.5"),
+    (20180316, "1.3.6"),
+    (20200704, "1.3.7"),
+];
+
+// Start of the reordered floor X list of the long frames used by the libvorbis encoding
+// templates for 44.1 and 48 kHz, after the implicit 0 and the range.
+const LIBVORBIS_LONG_FLOOR_X_LIST: [u16; 3] = [93, 23, 372];
+
+// Start of the floor X list of `Encoder`, after the implicit 0 and the range.
+const PURE_VORBIS_FLOOR_X_LIST: [u16; 4] = [48, 10, 238, 6];
+
+/// Family of the encoder that produced the stream, see `EncoderInfo`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EncoderKind {
+    /// The Xiph.Org reference encoder.
+    Libvorbis,
+    /// The aoTuV fork of libvorbis.
+    AoTuv,
+    /// Lancer, the optimized builds of aoTuV.
+    Lancer,
+    /// The native encoder of FFmpeg (not the libvorbis wrapper).
+    Ffmpeg,
+    /// `Encoder` of this crate.
+    PureVorbis,
+    Unknown,
+}
+
+/// Encoder identification from the vendor string of the comment header and the layout of the
+/// setup header.
+///
+/// The vendor string is checked first. When it's missing, stripped or replaced (e.g. by a muxer
+/// like FFmpeg that writes its own), the encoder is guessed from the setup: the libvorbis
+/// encoding templates and `Encoder` have recognizable floor layouts. aoTuV and Lancer share the
+/// libvorbis templates so they're reported as libvorbis then. The detection is a heuristic, a
+/// crafted stream can claim any encoder.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use vorbis::{Decoder, EncoderInfo, EncoderKind};
+///
+/// # fn f(decoder: Decoder) {
+/// let vendor = decoder.comments().map(|c| c.vendor_raw()).unwrap_or(b"");
+/// let info = EncoderInfo::detect(vendor, decoder.setup());
+/// if info.kind() == EncoderKind::Libvorbis && info.date().map(|d| d < 20020717).unwrap_or(false) {
+///     println!("Pre-1.0 libvorbis");
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EncoderInfo {
+    kind: EncoderKind,
+    version: Option<String>,
+    date: Option<u32>,
+    from_setup: bool,
+}
+
+impl EncoderInfo {
+    /// Identifies the encoder from the raw vendor string (`Comments::vendor_raw()`, can be empty
+    /// if there are no comments) and the setup header.
+    pub fn detect(vendor: &[u8], setup: &Setup) -> Self {
+        if let Some(r) = Self::from_vendor(vendor) {
+            return r;
+        }
+        let kind = Self::kind_from_setup(setup);
+        EncoderInfo {
+            kind: kind,
+            version: None,
+            date: None,
+            from_setup: kind != EncoderKind::Unknown,
+        }
+    }
+
+    pub fn kind(&self) -> EncoderKind {
+        self.kind
+    }
+
+    /// Returns the release of the encoder if it's known from the vendor string, e.g. `1.3.5` for
+    /// libvorbis or `b6.03` for aoTuV.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_ref().map(|s| &s[..])
+    }
+
+    /// Returns the build date from the vendor string as a `YYYYMMDD` number. libvorbis, aoTuV
+    /// and Lancer include it.
+    pub fn date(&self) -> Option<u32> {
+        self.date
+    }
+
+    /// Returns whether the encoder was guessed from the setup header because the vendor string
+    /// wasn't recognized.
+    pub fn is_from_setup(&self) -> bool {
+        self.from_setup
+    }
+
+    fn from_vendor(vendor: &[u8]) -> Option<Self> {
+        let vendor = match str::from_utf8(vendor) {
+            Ok(v) => v,
+            Err(_) => return None,
+        };
+        let date = find_date(vendor);
+        let (kind, version) = if vendor.contains("Lancer") {
+            (EncoderKind::Lancer, None)
+        } else if let Some(i) = vendor.find("aoTuV") {
+            // The version is missing in some releases, the date in brackets follows then.
+            let version = match vendor[i + "aoTuV".len()..].split_whitespace().next() {
+                Some(v) if !v.starts_with('[') => Some(v.to_string()),
+                _ => None,
+            };
+            (EncoderKind::AoTuv, version)
+        } else if vendor.contains("libVorbis") {
+            let version = date.and_then(|date| if date < LIBVORBIS_RELEASES[0].0 {
+                Some("pre-1.0")
+            } else {
+                LIBVORBIS_RELEASES.iter().find(|&&(d, _)| d == date).map(|&(_, v)| v)
+            });
+            (EncoderKind::Libvorbis, version.map(|v| v.to_string()))
+        } else if vendor.starts_with("Lavc") {
+            (EncoderKind::Ffmpeg, None)
+        } else {
+            return None;
+        };
+        Some(EncoderInfo {
+            kind: kind,
+            version: version,
+            date: date,
+            from_setup: false,
+        })
+    }
+
+    fn kind_from_setup(setup: &Setup) -> EncoderKind {
+        let floor_starts_with = |setup: &Setup, mapping: usize, x_list: &[u16]| {
+            setup.mappings()[mapping].submaps().iter()
+                .all(|s| setup.floors()[s.floor()].x_list()[2..].starts_with(x_list))
+        };
+        let modes = setup.modes();
+        if modes.len() == 1 && modes[0].frame_kind == FrameKind::Long &&
+                setup.residues().iter().all(|r| r.kind() == ResidueKind::Residue1) &&
+                floor_starts_with(setup, modes[0].mapping, &PURE_VORBIS_FLOOR_X_LIST) {
+            EncoderKind::PureVorbis
+        } else if modes.len() == 2 && modes[0].frame_kind == FrameKind::Short &&
+                modes[1].frame_kind == FrameKind::Long &&
+                floor_starts_with(setup, modes[1].mapping, &LIBVORBIS_LONG_FLOOR_X_LIST) {
+            EncoderKind::Libvorbis
+        } else {
+            EncoderKind::Unknown
+        }
+    }
+}
+
+// Returns the first `YYYYMMDD` number of the string.
+fn find_date(s: &str) -> Option<u32> {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let len = bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+        if len == 8 && (bytes[i..].starts_with(b"19") || bytes[i..].starts_with(b"20")) {
+            return s[i..i + 8].parse().ok();
+        }
+        i += cmp::max(len, 1);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "encoder")]
+    use std::io::Cursor;
+
+    #[cfg(feature = "encoder")]
+    use bitstream::BitReader;
+    #[cfg(feature = "encoder")]
+    use encoder::Encoder;
+    use super::*;
+
+    fn from_vendor(vendor: &str) -> Option<(EncoderKind, Option<String>, Option<u32>)> {
+        EncoderInfo::from_vendor(vendor.as_bytes()).map(|r| (r.kind, r.version, r.date))
+    }
+
+    #[test]
+    fn vendor() {
+        assert_eq!(from_vendor("Xiph.Org libVorbis I 20150105 (\u{26c4}\u{26c4}\u{26c4}\u{26c4})"),
+            Some((EncoderKind::Libvorbis, Some("1.3.5".into()), Some(20150105))));
+        assert_eq!(from_vendor("Xiph.Org libVorbis I 20050304"),
+            Some((EncoderKind::Libvorbis, Some("1.1.1".into()), Some(20050304))));
+        assert_eq!(from_vendor("Xiphophorus libVorbis I 20000508"),
+            Some((EncoderKind::Libvorbis, Some("pre-1.0".into()), Some(20000508))));
+        assert_eq!(from_vendor("Xiph.Org libVorbis I 20991231"),
+            Some((EncoderKind::Libvorbis, None, Some(20991231))));
+        assert_eq!(from_vendor("AO; aoTuV b5 [20061024] (based on Xiph.Org's libVorbis)"),
+            Some((EncoderKind::AoTuv, Some("b5".into()), Some(20061024))));
+        assert_eq!(from_vendor("AO; aoTuV [20110424] (based on Xiph.Org's libVorbis)"),
+            Some((EncoderKind::AoTuv, None, Some(20110424))));
+        assert_eq!(from_vendor("BS; Lancer(SSE) [20061110] (based on aoTuV b5)"),
+            Some((EncoderKind::Lancer, None, Some(20061110))));
+        assert_eq!(from_vendor("Lavc58.54.100"), Some((EncoderKind::Ffmpeg, None, None)));
+        assert_eq!(from_vendor("Lavf58.29.100"), None);
+        assert_eq!(from_vendor(""), None);
+    }
+
+    #[cfg(feature = "encoder")]
+    #[test]
+    fn pure_vorbis_setup() {
+        let encoder = Encoder::builder(2, 44100).build();
+        let setup = Setup::read_packet(&mut BitReader::new(Cursor::new(&encoder.setup_packet()[..])),
+            encoder.header()).unwrap();
+        let info = EncoderInfo::detect(b"my encoder", &setup);
+        assert_eq!(info.kind(), EncoderKind::PureVorbis);
+        assert!(info.is_from_setup());
+    }
+
+    #[test]
+    fn find_date_() {
+        assert_eq!(find_date("abc 123456789 20101101x"), Some(20101101));
+        assert_eq!(find_date("30101101 2010110"), None);
+    }
+}
@@ -0,0 +1,338 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use decoder::Samples;
+use error::Result;
+use header::{ChannelLayout, SpeakerPosition};
+use sample::{to_i16, Sample};
+use sink::SampleSink;
+
+// Offset of the RIFF chunk size patched when finishing.
+const RIFF_SIZE_OFFSET: u64 = 4;
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Sample format of the WAV file written by `WavWriter`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WavFormat {
+    /// 16-bit signed integer.
+    I16,
+    /// 24-bit signed integer.
+    I24,
+    /// 32-bit IEEE float.
+    F32,
+}
+
+impl WavFormat {
+    fn bits(&self) -> u16 {
+        match self {
+            &WavFormat::I16 => 16,
+            &WavFormat::I24 => 24,
+            &WavFormat::F32 => 32,
+        }
+    }
+}
+
+/// Writes decoded samples into a WAV file.
+///
+/// The channels are reordered from the Vorbis channel order to the WAV one and the speaker
+/// positions are stored in the channel mask for streams with more than two channels.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::fs::File;
+/// use std::io::{BufWriter, Cursor};
+/// use vorbis::{BitReader, Decoder, WavFormat, WavWriter};
+///
+/// # fn f(mut decoder: Decoder, packets: Vec<Vec<u8>>) {
+/// let file = BufWriter::new(File::create("out.wav").expect("Couldn't create output file"));
+/// let mut wav = WavWriter::new(file, decoder.header().channel_count(),
+///                              decoder.header().sample_rate(), WavFormat::I16)
+///         .expect("Couldn't write WAV header");
+/// for packet in &packets {
+///     let samples = decoder.decode(&mut BitReader::new(Cursor::new(&packet[..])))
+///             .expect("Couldn't decode audio packet");
+///     wav.write_samples(&samples).expect("Couldn't write samples");
+/// }
+/// wav.finish().expect("Couldn't finish WAV file");
+/// # }
+/// ```
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    format: WavFormat,
+    // WAV channel index -> Vorbis channel index.
+    channel_map: Box<[usize]>,
+    data_size_offset: u64,
+    data_len: u64,
+    buf: Vec<u8>,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Writes the WAV header. The chunk sizes are written by `finish()`.
+    pub fn new(mut writer: W, channel_count: usize, sample_rate: u32, format: WavFormat)
+            -> io::Result<Self> {
+        if channel_count == 0 || channel_count > 0xFFFF {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid channel count"));
+        }
+        let layout = ChannelLayout::from_channel_count(channel_count);
+        let positions = layout.positions();
+
+        let mut channel_map: Vec<_> = (0..channel_count).collect();
+        let mut channel_mask = 0;
+        if !positions.is_empty() {
+            channel_map.sort_by_key(|&c| speaker_mask(positions[c]));
+            channel_mask = positions.iter().fold(0, |m, &p| m | speaker_mask(p));
+        }
+
+        let bits = format.bits();
+        let block_align = channel_count as u64 * bits as u64 / 8;
+        let byte_rate = sample_rate as u64 * block_align;
+        if block_align > 0xFFFF || byte_rate > 0xFFFF_FFFF {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                "Channel count or sample rate is too big for WAV"));
+        }
+        let extensible = channel_count > 2 || format == WavFormat::I24;
+        let format_tag = match format {
+            WavFormat::F32 => WAVE_FORMAT_IEEE_FLOAT,
+            _ => WAVE_FORMAT_PCM,
+        };
+
+        let mut h = Vec::with_capacity(68);
+        h.extend_from_slice(b"RIFF");
+        put_u32(&mut h, 0);
+        h.extend_from_slice(b"WAVE");
+        h.extend_from_slice(b"fmt ");
+        put_u32(&mut h, if extensible { 40 } else { 16 });
+        put_u16(&mut h, if extensible { WAVE_FORMAT_EXTENSIBLE } else { format_tag });
+        put_u16(&mut h, channel_count as u16);
+        put_u32(&mut h, sample_rate);
+        put_u32(&mut h, byte_rate as u32);
+        put_u16(&mut h, block_align as u16);
+        put_u16(&mut h, bits);
+        if extensible {
+            put_u16(&mut h, 22);
+            put_u16(&mut h, bits);
+            put_u32(&mut h, channel_mask);
+            // KSDATAFORMAT_SUBTYPE_PCM or _IEEE_FLOAT GUID.
+            put_u16(&mut h, format_tag);
+            h.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00,
+                                  0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71]);
+        }
+        h.extend_from_slice(b"data");
+        let data_size_offset = h.len() as u64;
+        put_u32(&mut h, 0);
+
+        try!(writer.write_all(&h));
+
+        Ok(WavWriter {
+            writer: writer,
+            format: format,
+            channel_map: channel_map.into_boxed_slice(),
+            data_size_offset: data_size_offset,
+            data_len: 0,
+            buf: Vec::new(),
+        })
+    }
+
+    /// Writes the samples returned by `Decoder::decode()`.
+    pub fn write_samples(&mut self, samples: &Samples) -> io::Result<()> {
+        assert_eq!(samples.channel_count(), self.channel_map.len());
+        self.buf.clear();
+        for i in 0..samples.len() {
+            for &c in self.channel_map.iter() {
+                put_sample(&mut self.buf, self.format, samples.channel(c)[i]);
+            }
+        }
+        self.write_buf()
+    }
+
+    /// Writes the samples interleaved in the Vorbis channel order (like
+    /// `Samples::interleave()` returns).
+    pub fn write_interleaved(&mut self, samples: &[Sample]) -> io::Result<()> {
+        let channel_count = self.channel_map.len();
+        assert!(samples.len() % channel_count == 0);
+        self.buf.clear();
+        for frame in samples.chunks(channel_count) {
+            for &c in self.channel_map.iter() {
+                put_sample(&mut self.buf, self.format, frame[c]);
+            }
+        }
+        self.write_buf()
+    }
+
+    /// Writes the chunk sizes and returns the underlying writer. Fails if the data doesn't fit
+    /// in the 4 GiB limit of the WAV format.
+    pub fn finish(mut self) -> io::Result<W> {
+        // The RIFF chunks are word-aligned: the odd length data chunk (e.g. 24-bit mono) is
+        // followed by a pad byte which is counted in the RIFF size but not in the data size.
+        let pad = self.data_len % 2;
+        let riff_size = self.data_size_offset + 4 + self.data_len + pad - 8;
+        if riff_size > 0xFFFF_FFFF {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Data is too long for WAV"));
+        }
+        if pad != 0 {
+            try!(self.writer.write_all(&[0]));
+        }
+        let mut b = Vec::with_capacity(4);
+        let end = try!(self.writer.seek(SeekFrom::Current(0)));
+        put_u32(&mut b, riff_size as u32);
+        try!(self.writer.seek(SeekFrom::Start(RIFF_SIZE_OFFSET)));
+        try!(self.writer.write_all(&b));
+        b.clear();
+        put_u32(&mut b, self.data_len as u32);
+        try!(self.writer.seek(SeekFrom::Start(self.data_size_offset)));
+        try!(self.writer.write_all(&b));
+        try!(self.writer.seek(SeekFrom::Start(end)));
+        try!(self.writer.flush());
+        Ok(self.writer)
+    }
+
+    fn write_buf(&mut self) -> io::Result<()> {
+        try!(self.writer.write_all(&self.buf));
+        self.data_len += self.buf.len() as u64;
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> SampleSink for WavWriter<W> {
+    fn write_planar(&mut self, samples: &Samples) -> Result<()> {
+        Ok(try!(self.write_samples(samples)))
+    }
+
+    fn write_interleaved(&mut self, samples: &[Sample]) -> Result<()> {
+        Ok(try!(WavWriter::write_interleaved(self, samples)))
+    }
+}
+
+// Returns the bit of the speaker in the WAVE_FORMAT_EXTENSIBLE channel mask. The WAV channels
+// are ordered by the bit.
+fn speaker_mask(pos: SpeakerPosition) -> u32 {
+    match pos {
+        SpeakerPosition::FrontLeft   => 0x1,
+        SpeakerPosition::FrontRight  => 0x2,
+        SpeakerPosition::FrontCenter => 0x4,
+        SpeakerPosition::Mono        => 0x4,
+        SpeakerPosition::Lfe         => 0x8,
+        SpeakerPosition::RearLeft    => 0x10,
+        SpeakerPosition::RearRight   => 0x20,
+        SpeakerPosition::RearCenter  => 0x100,
+        SpeakerPosition::SideLeft    => 0x200,
+        SpeakerPosition::SideRight   => 0x400,
+    }
+}
+
+fn put_u16(buf: &mut Vec<u8>, v: u16) {
+    buf.push(v as u8);
+    buf.push((v >> 8) as u8);
+}
+
+fn put_u32(buf: &mut Vec<u8>, v: u32) {
+    put_u16(buf, v as u16);
+    put_u16(buf, (v >> 16) as u16);
+}
+
+fn put_sample(buf: &mut Vec<u8>, format: WavFormat, s: Sample) {
+    match format {
+        WavFormat::I16 => put_u16(buf, to_i16(s) as u16),
+        WavFormat::I24 => {
+            let v = (s * 8388607.0 + 0.5).floor();
+            let v = if v > 8388607.0 {
+                8388607
+            } else if v < -8388608.0 {
+                -8388608
+            } else {
+                v as i32
+            };
+            buf.push(v as u8);
+            buf.push((v >> 8) as u8);
+            buf.push((v >> 16) as u8);
+        }
+        WavFormat::F32 => {
+            let v = (s as f32).to_bits();
+            put_u32(buf, v);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn u16_at(b: &[u8], i: usize) -> u16 {
+        b[i] as u16 | (b[i + 1] as u16) << 8
+    }
+
+    fn u32_at(b: &[u8], i: usize) -> u32 {
+        u16_at(b, i) as u32 | (u16_at(b, i + 2) as u32) << 16
+    }
+
+    #[test]
+    fn stereo_i16() {
+        let mut w = WavWriter::new(Cursor::new(Vec::new()), 2, 44100, WavFormat::I16).unwrap();
+        w.write_interleaved(&[0.0, 1.0, -1.0, 0.5]).unwrap();
+        let b = w.finish().unwrap().into_inner();
+
+        assert_eq!(b.len(), 44 + 8);
+        assert_eq!(&b[..4], b"RIFF");
+        assert_eq!(u32_at(&b, 4), 44 + 8 - 8);
+        assert_eq!(u16_at(&b, 20), WAVE_FORMAT_PCM);
+        assert_eq!(u16_at(&b, 22), 2);
+        assert_eq!(u32_at(&b, 24), 44100);
+        assert_eq!(u32_at(&b, 28), 44100 * 4);
+        assert_eq!(u16_at(&b, 32), 4);
+        assert_eq!(u16_at(&b, 34), 16);
+        assert_eq!(&b[36..40], b"data");
+        assert_eq!(u32_at(&b, 40), 8);
+        assert_eq!(&b[44..], &[0, 0, 0xFF, 0x7F, 0x01, 0x80, 0x00, 0x40]);
+    }
+
+    #[test]
+    fn surround_5_1_order() {
+        let mut w = WavWriter::new(Cursor::new(Vec::new()), 6, 48000, WavFormat::I24).unwrap();
+        // Vorbis order: FL, FC, FR, RL, RR, LFE.
+        w.write_interleaved(&[0.0, 0.5, 0.25, -0.25, -0.5, 1.0]).unwrap();
+        let b = w.finish().unwrap().into_inner();
+
+        assert_eq!(u16_at(&b, 20), WAVE_FORMAT_EXTENSIBLE);
+        assert_eq!(u32_at(&b, 16), 40);
+        assert_eq!(u32_at(&b, 40), 0x3F);
+        assert_eq!(u16_at(&b, 44), WAVE_FORMAT_PCM);
+        assert_eq!(&b[60..64], b"data");
+        assert_eq!(u32_at(&b, 64), 18);
+        let s: Vec<i32> = b[68..].chunks(3)
+            .map(|c| (c[0] as i32 | (c[1] as i32) << 8 | (c[2] as i32) << 16) << 8 >> 8)
+            .collect();
+        // WAV order: FL, FR, FC, LFE, BL, BR.
+        assert_eq!(s, &[0, 2097152, 4194304, 8388607, -2097152, -4194303]);
+    }
+
+    #[test]
+    fn odd_data_len_padded() {
+        let mut w = WavWriter::new(Cursor::new(Vec::new()), 1, 8000, WavFormat::I24).unwrap();
+        w.write_interleaved(&[0.5]).unwrap();
+        let b = w.finish().unwrap().into_inner();
+
+        assert_eq!(b.len(), 68 + 4);
+        assert_eq!(u32_at(&b, 4), 68 + 4 - 8);
+        assert_eq!(&b[60..64], b"data");
+        assert_eq!(u32_at(&b, 64), 3);
+        assert_eq!(&b[68..], &[0x00, 0x00, 0x40, 0]);
+    }
+
+    #[test]
+    fn header_limits() {
+        let w = WavWriter::new(Cursor::new(Vec::new()), 255, 48000, WavFormat::F32).unwrap();
+        let b = w.finish().unwrap().into_inner();
+        assert_eq!(u32_at(&b, 28), 48000 * 255 * 4);
+        assert_eq!(u16_at(&b, 32), 255 * 4);
+
+        for &(channel_count, sample_rate) in &[(0xFFFF, 44100), (255, 0xFFFF_FFFF)] {
+            let e = WavWriter::new(Cursor::new(Vec::new()), channel_count, sample_rate, WavFormat::F32);
+            assert_eq!(e.err().unwrap().kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
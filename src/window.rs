@@ -1,7 +1,13 @@
-use std::f32::consts::PI;
-use std::rc::Rc;
+use std::f64::consts::PI;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+use std::sync::Arc;
 
 use header::{FrameKind, FrameLens};
+use sample::{mul_add, Sample};
+use tables::TableCache;
+#[cfg(not(feature = "std"))]
+use util::Float;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum OverlapTarget {
@@ -29,12 +35,12 @@ pub struct Window {
     pub right: WindowRange,
     // right_slope_start == right.start
     right_slope_end: usize,
-    slope: Rc<Box<[f32]>>,
+    slope: Arc<Box<[Sample]>>,
     pub overlap_target: OverlapTarget,
 }
 
 impl Window {
-    fn new(left_len: usize, right_len: usize, slope: Rc<Box<[f32]>>) -> Self {
+    fn new(left_len: usize, right_len: usize, slope: Arc<Box<[Sample]>>) -> Self {
         let left_start = left_len / 2;
         let right_end = right_len / 2;
         let (left,
@@ -102,36 +108,86 @@ impl Window {
         }
     }
 
-    pub fn overlap(&self, left: &mut [f32], right: &mut [f32]) {
+    pub fn overlap(&self, left: &mut [Sample], right: &mut [Sample]) {
         let mut l_it = left[self.left_slope_start..self.left.end].iter_mut();
         let mut r_it = right[self.right.start..self.right_slope_end].iter_mut();
         let mut l_slope_it = self.slope.iter().rev();
         let mut r_slope_it = self.slope.iter();
         while let (Some(l), Some(r), Some(&l_slope), Some(&r_slope)) =
                 (l_it.next(), r_it.next(), l_slope_it.next(), r_slope_it.next()) {
-            let v = *l * l_slope + *r * r_slope;
+            let v = mul_add(*l, l_slope, *r * r_slope);
             match self.overlap_target {
                 OverlapTarget::Left => *l = v,
                 OverlapTarget::Right => *r = v,
             }
         }
     }
+
+    /// Same as `overlap()` but also passes every sample of the output range (`left` or `right`
+    /// depending on the `overlap_target`) to `f` along with its index in the range.
+    pub fn overlap_output<F: FnMut(usize, Sample)>(&self, left: &mut [Sample], right: &mut [Sample],
+            mut f: F) {
+        let mut i = 0;
+        if self.overlap_target == OverlapTarget::Left {
+            for &v in &left[self.left.start..self.left_slope_start] {
+                f(i, v);
+                i += 1;
+            }
+        }
+        {
+            let l_it = left[self.left_slope_start..self.left.end].iter_mut();
+            let r_it = right[self.right.start..self.right_slope_end].iter_mut();
+            for (((l, r), &l_slope), &r_slope) in l_it.zip(r_it)
+                    .zip(self.slope.iter().rev()).zip(self.slope.iter()) {
+                let v = mul_add(*l, l_slope, *r * r_slope);
+                match self.overlap_target {
+                    OverlapTarget::Left => *l = v,
+                    OverlapTarget::Right => *r = v,
+                }
+                f(i, v);
+                i += 1;
+            }
+        }
+        if self.overlap_target == OverlapTarget::Right {
+            for &v in &right[self.right_slope_end..self.right.end] {
+                f(i, v);
+                i += 1;
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Windows {
-    windows: [Window; 4],
+    windows: [Option<Window>; 4],
 }
 
 impl Windows {
-    pub fn new(frame_lens: FrameLens) -> Self {
-        let short_slope = Rc::new(Self::make_slope(frame_lens.short() / 2));
-        let long_slope = Rc::new(Self::make_slope(frame_lens.long() / 2));
+    /// Creates windows only for the frame kinds used by the stream. The `used_kinds` is indexed
+    /// by `FrameKind`.
+    pub fn new(frame_lens: FrameLens, used_kinds: [bool; 2], cache: &TableCache) -> Self {
+        let short = used_kinds[FrameKind::Short as usize];
+        let long = used_kinds[FrameKind::Long as usize];
+        let short_slope = if short {
+            Some(cache.slope(frame_lens.short() / 2))
+        } else {
+            None
+        };
+        let long_slope = if long {
+            Some(cache.slope(frame_lens.long() / 2))
+        } else {
+            None
+        };
+        let mixed_slope = if short && long {
+            short_slope.clone()
+        } else {
+            None
+        };
         let windows = [
-            Window::new(frame_lens.short(), frame_lens.short(), short_slope.clone()),
-            Window::new(frame_lens.long(),  frame_lens.short(), short_slope.clone()),
-            Window::new(frame_lens.short(), frame_lens.long(),  short_slope.clone()),
-            Window::new(frame_lens.long(),  frame_lens.long(),  long_slope.clone()),
+            short_slope.map(|s| Window::new(frame_lens.short(), frame_lens.short(), s)),
+            mixed_slope.clone().map(|s| Window::new(frame_lens.long(),  frame_lens.short(), s)),
+            mixed_slope.map(|s| Window::new(frame_lens.short(), frame_lens.long(),  s)),
+            long_slope.map(|s| Window::new(frame_lens.long(),  frame_lens.long(),  s)),
         ];
         Windows {
             windows: windows,
@@ -139,7 +195,8 @@ impl Windows {
     }
 
     pub fn get(&self, left_kind: FrameKind, right_kind: FrameKind) -> &Window {
-        &self.windows[Self::window_idx(left_kind, right_kind)]
+        self.windows[Self::window_idx(left_kind, right_kind)].as_ref()
+                .expect("Window for unused frame kind")
     }
 
     fn window_idx(left_kind: FrameKind, right_kind: FrameKind) -> usize {
@@ -148,12 +205,15 @@ impl Windows {
         l | (r << 1)
     }
 
-    fn make_slope(len: usize) -> Box<[f32]> {
+    /// Returns the rising half of the Vorbis window of `len * 2` samples as per spec:
+    /// `sin(PI / 2 * sin((x + 0.5) / len * PI / 2) ^ 2)`. Computed in f64 regardless of the
+    /// Sample type for better precision.
+    pub fn make_slope(len: usize) -> Box<[Sample]> {
         let mut r = Vec::with_capacity(len);
-        let len = len as f32;
+        let len = len as f64;
         for x in 0..r.capacity() {
-            let y = (0.5_f32 * PI * ((x as f32 + 0.5_f32) / len * 0.5_f32 * PI).sin().powi(2)).sin();
-            r.push(y);
+            let y = (0.5 * PI * ((x as f64 + 0.5) / len * 0.5 * PI).sin().powi(2)).sin();
+            r.push(y as Sample);
         }
         r.into_boxed_slice()
     }
@@ -162,12 +222,13 @@ impl Windows {
 #[cfg(test)]
 mod tests {
     use header::{FrameKind, FrameLens};
+    use tables::TableCache;
 
     use super::*;
 
     #[test]
     fn windows() {
-        let wins = Windows::new(FrameLens::new(512, 2048));
+        let wins = Windows::new(FrameLens::new(512, 2048), [true, true], &TableCache::new());
 
         let w = wins.get(FrameKind::Short, FrameKind::Short);
         assert_eq!(w.left, WindowRange { start: 256, end: 512 });
@@ -201,4 +262,44 @@ mod tests {
         assert_eq!(w.slope.len(), 256);
         assert_eq!(w.overlap_target, OverlapTarget::Right);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn windows_single_kind() {
+        let wins = Windows::new(FrameLens::new(512, 2048), [false, true], &TableCache::new());
+        assert!(wins.windows[0].is_none());
+        assert!(wins.windows[1].is_none());
+        assert!(wins.windows[2].is_none());
+        assert_eq!(wins.get(FrameKind::Long, FrameKind::Long).slope.len(), 1024);
+
+        let wins = Windows::new(FrameLens::new(512, 2048), [true, false], &TableCache::new());
+        assert_eq!(wins.get(FrameKind::Short, FrameKind::Short).slope.len(), 256);
+        assert!(wins.windows[1].is_none());
+        assert!(wins.windows[2].is_none());
+        assert!(wins.windows[3].is_none());
+    }
+
+    #[test]
+    fn overlap_output() {
+        let wins = Windows::new(FrameLens::new(64, 256), [true, true], &TableCache::new());
+        for &(l, r) in &[(FrameKind::Short, FrameKind::Short), (FrameKind::Long, FrameKind::Short),
+                         (FrameKind::Short, FrameKind::Long), (FrameKind::Long, FrameKind::Long)] {
+            let w = wins.get(l, r);
+            let left: Vec<Sample> = (0..256).map(|i| i as Sample).collect();
+            let right: Vec<Sample> = (0..256).map(|i| -(i as Sample) / 2.0).collect();
+
+            let (mut exp_left, mut exp_right) = (left.clone(), right.clone());
+            w.overlap(&mut exp_left, &mut exp_right);
+            let expected = match w.overlap_target {
+                OverlapTarget::Left => &exp_left[w.left.start..w.left.end],
+                OverlapTarget::Right => &exp_right[w.right.start..w.right.end],
+            };
+
+            let (mut act_left, mut act_right) = (left.clone(), right.clone());
+            let mut actual = vec![0.0; w.len()];
+            w.overlap_output(&mut act_left, &mut act_right, |i, v| actual[i] = v);
+            assert_eq!(&actual[..], expected);
+            assert_eq!(act_left, exp_left);
+            assert_eq!(act_right, exp_right);
+        }
+    }
+}
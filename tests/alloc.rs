@@ -0,0 +1,90 @@
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use vorbis::BitReader;
+
+static ALLOC_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOC_COUNT.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOC_COUNT.fetch_add(1, Ordering::SeqCst);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+#[test]
+fn decode_does_not_allocate() {
+    for entry in fs::read_dir(common::ref_dir()).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_file() && path.to_string_lossy().ends_with(".ogg") {
+            println!("> Checking allocations: {}", path.file_name().unwrap().to_string_lossy());
+            check_decode_allocs(path);
+        }
+    }
+    // Checked in the same test since the allocations of all threads are counted.
+    check_decode_error_allocs();
+}
+
+fn check_decode_allocs<P: AsRef<Path>>(path: P) {
+    let packets = common::read_packets(path);
+    let mut decoder = common::build_decoder(&packets);
+
+    for (packet_idx, packet) in packets[3..].iter().enumerate() {
+        let mut reader = BitReader::new(Cursor::new(&packet[..]));
+        let before = ALLOC_COUNT.load(Ordering::SeqCst);
+        let len = decoder.decode(&mut reader).unwrap().len();
+        let allocs = ALLOC_COUNT.load(Ordering::SeqCst) - before;
+        assert!(allocs == 0, "packet {} ({} samples): {} allocations", packet_idx, len, allocs);
+    }
+}
+
+fn check_decode_error_allocs() {
+    let packets = common::read_packets(common::ref_file("01_30s.ogg"));
+    let mut decoder = common::build_decoder(&packets);
+
+    // Packets with invalid kind, cut off after the packet kind and with inverted data after the
+    // mode number, interleaved with valid packets.
+    let mut corrupt = vec![vec![1], vec![]];
+    for packet in &packets[3..23] {
+        let mut bad = packet.clone();
+        for b in &mut bad[1..] {
+            *b = !*b;
+        }
+        corrupt.push(bad);
+    }
+    let mut error_count = 0;
+    for (packet_idx, (bad, good)) in corrupt.iter().zip(packets[3..].iter()).enumerate() {
+        for packet in &[bad, good] {
+            let mut reader = BitReader::new(Cursor::new(&packet[..]));
+            let before = ALLOC_COUNT.load(Ordering::SeqCst);
+            if decoder.decode(&mut reader).is_err() {
+                error_count += 1;
+            }
+            let allocs = ALLOC_COUNT.load(Ordering::SeqCst) - before;
+            assert!(allocs == 0, "packet {}: {} allocations", packet_idx, allocs);
+        }
+    }
+    assert!(error_count > 2 && decoder.error_location().is_none());
+}
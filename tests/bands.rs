@@ -0,0 +1,51 @@
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use std::io::Cursor;
+
+use vorbis::{BandScale, BitReader, Decoder, FrameSpectrum, Sample};
+
+fn build_decoder(packets: &[Vec<u8>]) -> Decoder {
+    let mut decoder_builder = Decoder::builder();
+    decoder_builder.spectrum_analysis(true);
+    common::read_headers(&mut decoder_builder, packets);
+    decoder_builder.build()
+}
+
+fn power(db: Sample) -> f64 {
+    10.0f64.powf(db as f64 / 10.0)
+}
+
+// Checks the total energy of the bands equals the energy of the bins below `max_frequency`.
+fn check_total(spectrum: &FrameSpectrum, channel: usize, scale: BandScale, max_frequency: f32) {
+    let mut bands = vec![0.0; scale.band_count()];
+    spectrum.band_energies(channel, scale, &mut bands);
+    let expected = spectrum.channel(channel).iter().enumerate()
+        .filter(|&(bin, _)| spectrum.frequency(bin) < max_frequency)
+        .fold(0.0, |a, (_, &db)| a + power(db));
+    let actual = bands.iter().fold(0.0, |a, &db| a + power(db));
+    assert!((actual - expected).abs() <= expected * 1e-3 + 1e-10, "{:?}: {} != {}",
+        scale, actual, expected);
+}
+
+#[test]
+fn band_energies() {
+    let packets = common::read_packets(common::ref_file("01_30s.ogg"));
+    let mut decoder = build_decoder(&packets);
+    let nyquist = decoder.header().sample_rate() as f32 / 2.0;
+    let mut short_frames = 0;
+    for packet in &packets[3..] {
+        decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+        let spectrum = decoder.spectrum().unwrap();
+        if spectrum.len() < 1024 {
+            short_frames += 1;
+        }
+        for channel in 0..spectrum.channel_count() {
+            check_total(&spectrum, channel, BandScale::Bark, 15500.0);
+            check_total(&spectrum, channel, BandScale::Mel(40), nyquist);
+        }
+    }
+    assert!(short_frames > 0);
+}
@@ -0,0 +1,152 @@
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use std::io::Cursor;
+
+use vorbis::{BitReader, ChannelLayout, Decoder, DownmixMatrix, Result, Sample, SampleSink, SpeakerPosition};
+
+// Returns the decoder and the first audio packets of the file.
+fn build_decoder(file: &str) -> (Decoder, Vec<Vec<u8>>) {
+    let packets = common::read_first_packets(common::ref_file(file), 10);
+    (common::build_decoder(&packets), packets[3..].to_vec())
+}
+
+#[test]
+fn named_channels() {
+    let (mut decoder, packets) = build_decoder("02_1m_6ch.ogg");
+    for packet in &packets {
+        let samples = decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+        assert_eq!(samples.get_channel(5), Some(samples.channel(5)));
+        assert_eq!(samples.get_channel(6), None);
+        assert_eq!(samples.left(), Some(samples.channel(0)));
+        assert_eq!(samples.center(), Some(samples.channel(1)));
+        assert_eq!(samples.right(), Some(samples.channel(2)));
+        assert_eq!(samples.by_position(SpeakerPosition::Lfe), Some(samples.channel(5)));
+        assert_eq!(samples.by_position(SpeakerPosition::SideLeft), None);
+    }
+
+    let (mut decoder, packets) = build_decoder("01_30s_mono.ogg");
+    for packet in &packets {
+        let samples = decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+        assert_eq!(samples.left(), None);
+        assert_eq!(samples.right(), None);
+        assert_eq!(samples.center(), Some(samples.channel(0)));
+        assert_eq!(samples.get_channel(1), None);
+    }
+}
+
+#[test]
+fn decode_downmixed() {
+    let (mut decoder, packets) = build_decoder("02_1m_6ch.ogg");
+    let (mut expected_decoder, _) = build_decoder("02_1m_6ch.ogg");
+    let mut matrix = DownmixMatrix::stereo(ChannelLayout::Surround5_1, 0.5);
+    // Dialog to the left only.
+    matrix.set_gain(1, 0, 1.0).set_gain(1, 1, 0.0);
+    let mut buf = vec![0.0; decoder.header().frame_lens().long()];
+    for packet in &packets {
+        let written = decoder.decode_downmixed(&mut BitReader::new(Cursor::new(&packet[..])),
+            &matrix, &mut buf).unwrap();
+        let expected = expected_decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+        assert_eq!(written, expected.len());
+        let c = 0.70710677;
+        for i in 0..written {
+            let s = |channel: usize| expected.channel(channel)[i];
+            let left = s(0) + s(1) + s(3) * c + s(5) * 0.5;
+            let right = s(2) + s(4) * c + s(5) * 0.5;
+            assert!((buf[i * 2] - left).abs() < 1e-6 && (buf[i * 2 + 1] - right).abs() < 1e-6);
+        }
+    }
+}
+
+#[test]
+fn decode_planar_into() {
+    let (mut decoder, packets) = build_decoder("01_30s.ogg");
+    let (mut expected_decoder, _) = build_decoder("01_30s.ogg");
+    let len = decoder.header().frame_lens().long() / 2;
+    let mut left = vec![0.0; len];
+    let mut right = vec![0.0; len + 10];
+    for packet in &packets {
+        let written = decoder.decode_planar_into(&mut BitReader::new(Cursor::new(&packet[..])),
+            &mut [&mut left[..], &mut right[..]]).unwrap();
+        let expected = expected_decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+        assert_eq!(written, expected.len());
+        assert_eq!(&left[..written], expected.channel(0));
+        assert_eq!(&right[..written], expected.channel(1));
+        assert_eq!(decoder.samples().channel(1), expected.channel(1));
+    }
+}
+
+#[test]
+#[should_panic(expected = "Buffer is too short")]
+fn decode_planar_into_short_buffer() {
+    let (mut decoder, packets) = build_decoder("01_30s.ogg");
+    let mut left = vec![0.0; 100];
+    let mut right = vec![0.0; 100];
+    decoder.decode_planar_into(&mut BitReader::new(Cursor::new(&packets[0][..])),
+        &mut [&mut left[..], &mut right[..]]).unwrap();
+}
+
+#[test]
+fn decode_to() {
+    // Interleaves through the default `write_planar()`.
+    struct Interleaved(Vec<Sample>);
+
+    impl SampleSink for Interleaved {
+        fn write_interleaved(&mut self, samples: &[Sample]) -> Result<()> {
+            assert_eq!(samples.len() % 6, 0);
+            self.0.extend_from_slice(samples);
+            Ok(())
+        }
+    }
+
+    let (mut vec_decoder, packets) = build_decoder("02_1m_6ch.ogg");
+    let (mut interleaved_decoder, _) = build_decoder("02_1m_6ch.ogg");
+    let (mut expected_decoder, _) = build_decoder("02_1m_6ch.ogg");
+    let mut vec = Vec::new();
+    let mut interleaved = Interleaved(Vec::new());
+    let mut expected = Vec::new();
+    for packet in &packets {
+        let len = vec_decoder.decode_to(&mut BitReader::new(Cursor::new(&packet[..])), &mut vec)
+            .unwrap();
+        interleaved_decoder.decode_to(&mut BitReader::new(Cursor::new(&packet[..])), &mut interleaved)
+            .unwrap();
+        let samples = expected_decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+        assert_eq!(len, samples.len());
+        expected.extend(samples.interleave());
+    }
+    assert!(!expected.is_empty());
+    assert!(vec == expected);
+    assert!(interleaved.0 == expected);
+}
+
+#[test]
+fn samples_container() {
+    // Sums the channels in generic code taking any collection of channels.
+    fn sum<'a, C: IntoIterator<Item=&'a [Sample]>>(channels: C) -> Sample {
+        channels.into_iter().map(|c| c.iter().sum::<Sample>()).sum()
+    }
+
+    let (mut decoder, packets) = build_decoder("02_1m_6ch.ogg");
+    for packet in &packets {
+        let samples = decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+        for i in 0..6 {
+            assert_eq!(&samples[i], samples.channel(i));
+        }
+        assert_eq!(samples.channels().len(), 6);
+        assert_eq!(samples.channels().rev().next(), Some(samples.channel(5)));
+        assert_eq!(samples.interleave().len(), samples.len() * 6);
+        let mut interleaved = samples.interleave();
+        interleaved.next();
+        assert_eq!(interleaved.len(), samples.len() * 6 - samples.len().min(1));
+        let mut count = 0;
+        for (i, channel) in (&samples).into_iter().enumerate() {
+            assert_eq!(channel, &samples[i]);
+            count += 1;
+        }
+        assert_eq!(count, 6);
+        assert_eq!(sum(&samples), sum(samples.channels()));
+        assert_eq!(sum(samples), sum(decoder.samples().channels()));
+    }
+}
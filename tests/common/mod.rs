@@ -0,0 +1,69 @@
+// Helpers shared by the integration tests. Each test crate uses only some of them.
+#![allow(dead_code)]
+
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+
+use ogg_vorbis_ref::OggRefDecoder;
+use vorbis::{BitReader, Decoder, DecoderBuilder};
+
+// Returns the directory of the reference Ogg Vorbis files.
+pub fn ref_dir() -> PathBuf {
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("tests/data/ref");
+    dir
+}
+
+// Returns the path of the reference file.
+pub fn ref_file(name: &str) -> PathBuf {
+    ref_dir().join(name)
+}
+
+// Returns the packets of the Ogg stream.
+pub fn read_packets_from<R: Read>(input: R) -> Vec<Vec<u8>> {
+    read_first_packets_from(input, usize::max_value())
+}
+
+// Returns the packets of the Ogg file.
+pub fn read_packets<P: AsRef<Path>>(path: P) -> Vec<Vec<u8>> {
+    read_packets_from(File::open(path).unwrap())
+}
+
+// Returns up to `count` first packets of the Ogg file, including the header packets.
+pub fn read_first_packets<P: AsRef<Path>>(path: P, count: usize) -> Vec<Vec<u8>> {
+    read_first_packets_from(File::open(path).unwrap(), count)
+}
+
+fn read_first_packets_from<R: Read>(input: R, count: usize) -> Vec<Vec<u8>> {
+    let mut ogg = OggRefDecoder::new(input, 4096);
+    let mut packets = Vec::new();
+    while packets.len() < count && ogg.next_packet().unwrap() {
+        packets.push(ogg.packet_data().to_vec());
+    }
+    packets
+}
+
+// Reads the three header packets into the `builder`.
+pub fn read_headers(builder: &mut DecoderBuilder, packets: &[Vec<u8>]) {
+    builder.read_ident_packet(&mut BitReader::new(Cursor::new(&packets[0][..]))).unwrap();
+    builder.read_comment_packet(&mut BitReader::new(Cursor::new(&packets[1][..]))).unwrap();
+    builder.read_setup_packet(&mut BitReader::new(Cursor::new(&packets[2][..]))).unwrap();
+}
+
+// Builds the decoder with the default options from the header packets.
+pub fn build_decoder(packets: &[Vec<u8>]) -> Decoder {
+    let mut builder = Decoder::builder();
+    read_headers(&mut builder, packets);
+    builder.build()
+}
+
+// Decodes the audio packets returning the interleaved samples.
+pub fn decode(decoder: &mut Decoder, packets: &[Vec<u8>]) -> Vec<f32> {
+    let mut r = Vec::new();
+    for packet in packets {
+        let samples = decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+        r.extend(samples.interleave().map(|s| s as f32));
+    }
+    r
+}
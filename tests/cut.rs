@@ -0,0 +1,34 @@
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use vorbis::PacketCut;
+
+#[test]
+fn cut_matches_full_decode() {
+    for name in &["01_30s.ogg", "02_1m_6ch.ogg"] {
+        println!("> Checking cut: {}", name);
+        let packets = common::read_packets(common::ref_file(name));
+        let mut decoder = common::build_decoder(&packets);
+        let channel_count = decoder.header().channel_count();
+        let full = common::decode(&mut decoder, &packets[3..]);
+        let full_len = (full.len() / channel_count) as u64;
+
+        for &(start, end) in &[(0, 1000), (12345, 100000), (44100, full_len), (full_len - 1, full_len + 10)] {
+            let cut = PacketCut::new(decoder.header(), decoder.setup(), &packets[3..], start, end).unwrap();
+            assert_eq!(cut.len(), end.min(full_len) - start);
+
+            let mut cut_decoder = common::build_decoder(&packets);
+            let range = cut.packets();
+            let decoded = common::decode(&mut cut_decoder, &packets[3 + range.start..3 + range.end]);
+            let trim = cut.start_trim() as usize * channel_count;
+            let len = cut.len() as usize * channel_count;
+            assert_eq!(cut.granule_pos(range.end - 1), cut.len());
+            assert!(decoded.len() >= trim + len);
+
+            let expected = &full[start as usize * channel_count..][..len];
+            assert!(&decoded[trim..trim + len] == expected, "{}: {}..{}", name, start, end);
+        }
+    }
+}
@@ -0,0 +1,25 @@
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use vorbis::{EncoderInfo, EncoderKind};
+
+#[test]
+fn detect() {
+    for &(file, version) in &[("01_30s.ogg", "1.3.5"), ("02_1m_6ch.ogg", "1.1.1")] {
+        let packets = common::read_first_packets(common::ref_file(file), 3);
+        let decoder = common::build_decoder(&packets);
+
+        let info = EncoderInfo::detect(decoder.comments().unwrap().vendor_raw(), decoder.setup());
+        assert_eq!(info.kind(), EncoderKind::Libvorbis);
+        assert_eq!(info.version(), Some(version));
+        assert!(!info.is_from_setup());
+
+        // Stripped vendor string.
+        let info = EncoderInfo::detect(b"", decoder.setup());
+        assert_eq!(info.kind(), EncoderKind::Libvorbis);
+        assert_eq!(info.version(), None);
+        assert!(info.is_from_setup());
+    }
+}
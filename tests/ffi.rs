@@ -0,0 +1,132 @@
+#![cfg(feature = "ffi")]
+
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use std::io::Cursor;
+use std::mem;
+use std::os::raw::{c_int, c_long};
+use std::slice;
+
+use vorbis::ffi::*;
+use vorbis::BitReader;
+
+fn packet(data: &mut [u8], packetno: usize) -> ogg_packet {
+    ogg_packet {
+        packet: data.as_mut_ptr(),
+        bytes: data.len() as c_long,
+        b_o_s: (packetno == 0) as c_long,
+        e_o_s: 0,
+        granulepos: -1,
+        packetno: packetno as i64,
+    }
+}
+
+#[test]
+fn ffi_matches_decoder() {
+    let mut packets = common::read_packets(common::ref_file("01_30s.ogg"));
+    let mut decoder = common::build_decoder(&packets);
+    let vendor = decoder.comments().unwrap().vendor_raw().to_vec();
+
+    unsafe {
+        let mut vi = mem::zeroed();
+        let mut vc = mem::zeroed();
+        vorbis_info_init(&mut vi);
+        vorbis_comment_init(&mut vc);
+        for i in 0..3 {
+            let mut op = packet(&mut packets[i], i);
+            assert_eq!(vorbis_synthesis_idheader(&mut op), (i == 0) as i32);
+            assert_eq!(vorbis_synthesis_headerin(&mut vi, &mut vc, &mut op), 0);
+        }
+        assert_eq!(vi.channels as usize, decoder.header().channel_count());
+        assert_eq!(vi.rate as u32, decoder.header().sample_rate());
+        assert_eq!(vc.comments as usize, decoder.comments().unwrap().len());
+        assert_eq!(slice::from_raw_parts(vc.vendor as *const u8, vendor.len()), &vendor[..]);
+
+        let mut vd = mem::zeroed();
+        let mut vb = mem::zeroed();
+        assert_eq!(vorbis_synthesis_init(&mut vd, &mut vi), 0);
+        assert_eq!(vorbis_block_init(&mut vd, &mut vb), 0);
+
+        for i in 3..packets.len() {
+            let expected = decoder.decode(&mut BitReader::new(Cursor::new(&packets[i][..]))).unwrap();
+
+            let mut op = packet(&mut packets[i], i);
+            let len = vorbis_packet_blocksize(&mut vi, &mut op);
+            assert!(len == 256 || len == 2048);
+            assert_eq!(vorbis_synthesis(&mut vb, &mut op), 0);
+            assert_eq!(vorbis_synthesis_blockin(&mut vd, &mut vb), 0);
+
+            let mut pcm = mem::zeroed();
+            let n = vorbis_synthesis_pcmout(&mut vd, &mut pcm) as usize;
+            assert_eq!(n, expected.len());
+            if n == 0 {
+                continue;
+            }
+            for (c, channel) in expected.channels().enumerate() {
+                let actual = slice::from_raw_parts(*pcm.offset(c as isize), n);
+                assert!(actual.iter().zip(channel).all(|(&a, &e)| a == e as f32));
+            }
+            assert_eq!(vorbis_synthesis_read(&mut vd, n as i32), 0);
+            assert_eq!(vorbis_synthesis_read(&mut vd, 1), OV_EINVAL);
+        }
+
+        vorbis_block_clear(&mut vb);
+        vorbis_dsp_clear(&mut vd);
+        vorbis_comment_clear(&mut vc);
+        vorbis_info_clear(&mut vi);
+    }
+}
+
+#[test]
+fn ffi_bad_packet_keeps_pcm() {
+    let mut packets = common::read_first_packets(common::ref_file("01_30s.ogg"), 5);
+
+    unsafe {
+        let mut vi = mem::zeroed();
+        let mut vc = mem::zeroed();
+        vorbis_info_init(&mut vi);
+        vorbis_comment_init(&mut vc);
+        for i in 0..3 {
+            let mut op = packet(&mut packets[i], i);
+            assert_eq!(vorbis_synthesis_headerin(&mut vi, &mut vc, &mut op), 0);
+        }
+        // The decoder uses the channel count of the stream, not the caller-writable one.
+        let channel_count = vi.channels;
+        vi.channels = 1;
+        let mut vd = mem::zeroed();
+        let mut vb = mem::zeroed();
+        assert_eq!(vorbis_synthesis_init(&mut vd, &mut vi), 0);
+        vi.channels = channel_count;
+        assert_eq!(vorbis_block_init(&mut vd, &mut vb), 0);
+        for i in 3..5 {
+            let mut op = packet(&mut packets[i], i);
+            assert_eq!(vorbis_synthesis(&mut vb, &mut op), 0);
+            assert_eq!(vorbis_synthesis_blockin(&mut vd, &mut vb), 0);
+        }
+        let mut pcm = mem::zeroed();
+        let n = vorbis_synthesis_pcmout(&mut vd, &mut pcm);
+        assert!(n > 1);
+        assert!((*(*pcm.offset(channel_count as isize - 1)).offset(n as isize - 1)).is_finite());
+        assert_eq!(vorbis_synthesis_read(&mut vd, -1), OV_EINVAL);
+        assert_eq!(vorbis_synthesis_read(&mut vd, n + 1), OV_EINVAL);
+        assert_eq!(vorbis_synthesis_read(&mut vd, c_int::max_value()), OV_EINVAL);
+        assert_eq!(vorbis_synthesis_read(&mut vd, 1), 0);
+        assert_eq!(vorbis_synthesis_read(&mut vd, n), OV_EINVAL);
+
+        // The consumed samples are dropped even if the packet fails to decode.
+        let mut bad = [4, 0];
+        let mut op = packet(&mut bad, 5);
+        assert_eq!(vorbis_synthesis(&mut vb, &mut op), 0);
+        assert_eq!(vorbis_synthesis_blockin(&mut vd, &mut vb), OV_EBADPACKET);
+        assert_eq!(vorbis_synthesis_pcmout(&mut vd, &mut pcm), n - 1);
+        assert_eq!(vd.pcm_returned, 0);
+
+        vorbis_block_clear(&mut vb);
+        vorbis_dsp_clear(&mut vd);
+        vorbis_comment_clear(&mut vc);
+        vorbis_info_clear(&mut vi);
+    }
+}
@@ -0,0 +1,47 @@
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use std::io::Cursor;
+
+use vorbis::{BitReader, Decoder};
+
+fn build_decoder(packets: &[Vec<u8>], floor_only: bool) -> Decoder {
+    let mut decoder_builder = Decoder::builder();
+    decoder_builder.floor_only(floor_only);
+    common::read_headers(&mut decoder_builder, packets);
+    decoder_builder.build()
+}
+
+// Returns the loudness (RMS in dB) of each block of `block_len` samples of the channel.
+fn loudness(decoder: &mut Decoder, packets: &[Vec<u8>], channel: usize, block_len: usize) -> Vec<f64> {
+    let mut samples = Vec::new();
+    for packet in packets {
+        let s = decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+        samples.extend(s.channel(channel).iter().map(|&s| s as f64));
+    }
+    samples.chunks(block_len)
+        .map(|b| 10.0 * (b.iter().fold(1e-10, |a, &s| a + s * s) / b.len() as f64).log10())
+        .collect()
+}
+
+#[test]
+fn floor_only() {
+    let packets = common::read_packets(common::ref_file("01_30s.ogg"));
+    let mut decoder = build_decoder(&packets, true);
+    let channel_count = decoder.header().channel_count();
+    let block_len = decoder.header().sample_rate() as usize / 10;
+    for channel in 0..channel_count {
+        let expected = loudness(&mut build_decoder(&packets, false), &packets[3..], channel, block_len);
+        decoder.reset();
+        let actual = loudness(&mut decoder, &packets[3..], channel, block_len);
+        assert_eq!(actual.len(), expected.len());
+        // The average difference of the loudness of 100 ms blocks in dB.
+        let diff = actual.iter().zip(&expected).fold(0.0, |a, (&a1, &e)| a + (a1 - e).abs())
+            / actual.len() as f64;
+        assert!(diff < 3.0, "{}", diff);
+    }
+    let stats = decoder.packet_stats().unwrap();
+    assert_eq!(stats.residue_bits(), 0);
+}
@@ -0,0 +1,119 @@
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use std::io::Cursor;
+
+use vorbis::{BitReader, DecodeLimits, Decoder, Error, ErrorKind, Result, Warning};
+
+fn build_decoder(packets: &[Vec<u8>], limits: &DecodeLimits, lenient: bool) -> Decoder {
+    let mut decoder_builder = Decoder::builder();
+    decoder_builder.decode_limits(limits).lenient(lenient);
+    common::read_headers(&mut decoder_builder, packets);
+    decoder_builder.build()
+}
+
+// Decodes the audio packets returning the first error.
+fn decode(decoder: &mut Decoder, packets: &[Vec<u8>]) -> Result<()> {
+    for packet in packets {
+        try!(decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))));
+    }
+    Ok(())
+}
+
+fn limit_exceeded(r: Result<()>) -> &'static str {
+    match r {
+        Err(e) => match e.inner() {
+            &Error::DecodeLimitExceeded(s) => s,
+            e => panic!("{:?}", e),
+        },
+        Ok(()) => panic!("Limit not exceeded"),
+    }
+}
+
+#[test]
+fn decode_limits() {
+    let packets = common::read_packets(common::ref_file("01_30s.ogg"));
+    let audio = &packets[3..200];
+
+    decode(&mut build_decoder(&packets, &DecodeLimits::new(), false), audio).unwrap();
+    decode(&mut build_decoder(&packets, DecodeLimits::new()
+        .max_vq_reads(1 << 20)
+        .max_floor_segments(1024), false), audio).unwrap();
+
+    assert_eq!(limit_exceeded(decode(&mut build_decoder(&packets,
+        DecodeLimits::new().max_vq_reads(10), false), audio)), "Too many VQ reads");
+    assert_eq!(limit_exceeded(decode(&mut build_decoder(&packets,
+        DecodeLimits::new().max_floor_segments(10), false), audio)), "Too many floor segments");
+    assert_eq!(limit_exceeded(decode(&mut build_decoder(&packets,
+        DecodeLimits::new().max_residue_passes(1), false), audio)), "Too many residue passes");
+
+    // Lenient mode skips the passes.
+    let mut decoder = build_decoder(&packets, DecodeLimits::new().max_residue_passes(1), true);
+    decode(&mut decoder, audio).unwrap();
+    let warnings = decoder.take_warnings();
+    assert!(!warnings.is_empty());
+    assert!(warnings.iter().all(|w| match w {
+        &Warning::ResiduePassesSkipped(n) => n > 0 && n < 8,
+        _ => false,
+    }));
+}
+
+#[test]
+fn error_packet_position() {
+    let packets = common::read_packets(common::ref_file("01_30s.ogg"));
+    let audio = &packets[3..13];
+
+    let mut decoder = build_decoder(&packets, &DecodeLimits::new(), false);
+    decode(&mut decoder, audio).unwrap();
+    let pos = decoder.pos();
+    let err = decoder.decode(&mut BitReader::new(Cursor::new(&[][..]))).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    let loc = decoder.error_location().unwrap();
+    assert_eq!((loc.packet(), loc.sample_pos()), (10, pos));
+    assert_eq!(decoder.packet_count(), 11);
+
+    // The reset decoder continues counting.
+    decoder.reset();
+    decode(&mut decoder, audio).unwrap();
+    assert_eq!(decoder.packet_count(), 21);
+    assert_eq!(decoder.error_location(), None);
+
+    let mut decoder = build_decoder(&packets, DecodeLimits::new().max_vq_reads(10), false);
+    let err = decode(&mut decoder, audio).err().unwrap();
+    let loc = decoder.error_location().unwrap();
+    assert_eq!((loc.packet(), loc.sample_pos()), (0, 0));
+    assert!(loc.bit_pos().is_some() && loc.stage().is_some());
+    match err {
+        Error::DecodeLimitExceeded(_) => {}
+        e => panic!("{:?}", e),
+    }
+}
+
+#[test]
+fn decode_outcome() {
+    let packets = common::read_packets(common::ref_file("01_30s.ogg"));
+    let audio = &packets[3..13];
+
+    for &(max_residue_passes, recovered) in &[(8, false), (1, true)] {
+        let mut decoder = build_decoder(&packets,
+            DecodeLimits::new().max_residue_passes(max_residue_passes), true);
+        let mut expected = build_decoder(&packets,
+            DecodeLimits::new().max_residue_passes(max_residue_passes), true);
+        for (i, packet) in audio.iter().enumerate() {
+            if i == 5 {
+                decoder.reset();
+                expected.reset();
+            }
+            let outcome = decoder.decode_outcome(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+            let expected = expected.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+            assert_eq!(outcome.is_primed(), i == 0 || i == 5);
+            assert_eq!(outcome.is_recovered(), recovered && !outcome.is_primed());
+            match outcome.samples() {
+                Some(samples) => assert!(samples.interleave().eq(expected.interleave())),
+                None => assert!(expected.is_empty()),
+            }
+        }
+    }
+}
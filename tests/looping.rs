@@ -0,0 +1,64 @@
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use std::ops::Range;
+
+use vorbis::LoopingDecoder;
+
+// Decodes until `len` samples (per channel) are produced or the end of the stream.
+fn decode_looping(decoder: &mut LoopingDecoder, len: usize) -> Vec<f32> {
+    let channel_count = decoder.decoder().header().channel_count();
+    let mut r = Vec::new();
+    while r.len() < len * channel_count {
+        match decoder.decode().unwrap() {
+            Some(samples) => r.extend(samples.interleave().map(|s| s as f32)),
+            None => break,
+        }
+    }
+    r
+}
+
+#[test]
+fn looping() {
+    let packets = common::read_packets(common::ref_file("01_30s.ogg"));
+    let mut decoder = common::build_decoder(&packets);
+    let channel_count = decoder.header().channel_count();
+    let full = common::decode(&mut decoder, &packets[3..]);
+    let full_len = full.len() / channel_count;
+    let samples = |range: Range<usize>| &full[range.start * channel_count..range.end * channel_count];
+
+    for &(start, end) in &[(12345, Some(100000)), (0, Some(50000)), (700000, None)] {
+        let end_pos = end.unwrap_or(full_len as u64) as usize;
+        let mut looping = LoopingDecoder::new(common::build_decoder(&packets), packets[3..].to_vec(),
+            start, end).unwrap();
+        assert_eq!(looping.loop_end(), end_pos as u64);
+        let start = start as usize;
+        let loop_len = end_pos - start;
+        let output = decode_looping(&mut looping, end_pos + loop_len * 2);
+        assert_eq!(looping.loop_count(), 2);
+        assert_eq!(output.len(), (end_pos + loop_len * 2) * channel_count);
+        assert!(&output[..end_pos * channel_count] == samples(0..end_pos));
+        for l in 0..2 {
+            let o = &output[(end_pos + loop_len * l) * channel_count..][..loop_len * channel_count];
+            assert!(o == samples(start..end_pos), "{}..{} loop {}", start, end_pos, l);
+        }
+
+        // Play the outro after the next pass.
+        let output = decode_looping(&mut looping, loop_len / 2);
+        assert!(&output[..] == &samples(start..end_pos)[..output.len()]);
+        let pos = looping.pos() as usize;
+        looping.set_looping(false);
+        let output = decode_looping(&mut looping, full_len);
+        assert!(&output[..] == samples(pos..full_len));
+        assert!(looping.decode().unwrap().is_none());
+        assert_eq!(looping.loop_count(), 3);
+    }
+
+    // The loop start is beyond the end.
+    let mut looping = LoopingDecoder::new(common::build_decoder(&packets), packets[3..].to_vec(),
+        full_len as u64, None).unwrap();
+    assert!(decode_looping(&mut looping, full_len * 2) == full);
+    assert_eq!(looping.loop_count(), 0);
+}
@@ -0,0 +1,32 @@
+#![cfg(feature = "memmap")]
+
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use std::io::Cursor;
+
+use vorbis::{BitReader, MmapDecoder};
+
+#[test]
+fn mmap_decode() {
+    for file in &["01_30s.ogg", "02_1m_6ch.ogg"] {
+        let path = common::ref_file(file);
+        let packets = common::read_packets(&path);
+        let mut expected_decoder = common::build_decoder(&packets);
+
+        let mut decoder = MmapDecoder::open(&path).unwrap();
+        assert_eq!(decoder.decoder().header().channel_count(),
+            expected_decoder.header().channel_count());
+        assert_eq!(decoder.bytes().len() as u64, path.metadata().unwrap().len());
+        for packet in &packets[3..] {
+            let expected = expected_decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+            let actual = decoder.decode().unwrap().unwrap();
+            assert!(actual.interleave().eq(expected.interleave()), "{}", file);
+        }
+        assert!(decoder.decode().unwrap().is_none());
+        assert_eq!(decoder.decoder().pos(), expected_decoder.pos());
+        assert!(decoder.granule_pos().samples().unwrap() <= decoder.decoder().pos());
+    }
+}
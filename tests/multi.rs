@@ -0,0 +1,50 @@
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use vorbis::MultiDecoder;
+
+#[test]
+fn multiplexed_streams() {
+    let streams = [(10, common::read_packets(common::ref_file("01_30s.ogg"))),
+        (20, common::read_packets(common::ref_file("01_30s_mono.ogg"))),
+        (30, vec![b"\x80theora".to_vec(); 10])];
+    let mut decoders = MultiDecoder::new();
+    let mut output = vec![Vec::new(); streams.len()];
+    let max_len = streams.iter().map(|s| s.1.len()).max().unwrap();
+    for i in 0..max_len {
+        for (&(serial, ref packets), output) in streams.iter().zip(output.iter_mut()) {
+            if let Some(packet) = packets.get(i) {
+                let samples = decoders.push(serial, packet).unwrap();
+                assert_eq!(samples.is_some(), serial != 30 && i >= 3);
+                if let Some(samples) = samples {
+                    output.extend(samples.interleave().map(|s| s as f32));
+                }
+            }
+        }
+        if i == 1 {
+            assert!(decoders.serials().is_empty());
+        }
+    }
+
+    assert_eq!(decoders.serials(), &[10, 20]);
+    assert!(decoders.is_ignored(30));
+    assert!(!decoders.is_ignored(10));
+    assert_eq!(decoders.decoder(20).unwrap().header().channel_count(), 1);
+    assert!(decoders.decoder(30).is_none());
+    for (&(_, ref packets), output) in streams[..2].iter().zip(&output) {
+        assert!(output == &common::decode(&mut common::build_decoder(packets), &packets[3..]));
+    }
+
+    // Broken headers.
+    let packets = &streams[0].1;
+    assert!(decoders.push(40, &packets[0]).unwrap().is_none());
+    assert!(decoders.push(40, &packets[2]).is_err());
+    assert!(decoders.is_ignored(40));
+    assert!(decoders.push(40, &packets[1]).unwrap().is_none());
+
+    assert!(decoders.remove(10).is_some());
+    assert!(decoders.remove(30).is_none());
+    assert_eq!(decoders.serials(), &[20]);
+}
@@ -0,0 +1,77 @@
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use vorbis::{BitReader, FrameKind};
+
+#[test]
+fn pipelined_matches_sequential() {
+    for entry in fs::read_dir(common::ref_dir()).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_file() && path.to_string_lossy().ends_with(".ogg") {
+            println!("> Checking pipelined decoding: {}", path.file_name().unwrap().to_string_lossy());
+            check_pipelined(path);
+        }
+    }
+}
+
+fn check_pipelined<P: AsRef<Path>>(path: P) {
+    let packets = common::read_packets(path);
+
+    let mut decoder = common::build_decoder(&packets);
+    let mut expected = Vec::new();
+    let mut expected_stats = Vec::new();
+    let mut expected_info = Vec::new();
+    for packet in &packets[3..] {
+        expected.push(decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap()
+            .interleave().collect::<Vec<_>>());
+        let stats = decoder.packet_stats().unwrap();
+        // Only the padding of the last byte is left.
+        let packet_bits = packet.len() as u64 * 8;
+        assert!(stats.total_bits() <= packet_bits && stats.total_bits() + 8 > packet_bits);
+        expected_stats.push(stats);
+        let info = decoder.packet_info().unwrap();
+        let mut reader = BitReader::new(Cursor::new(&packet[..]));
+        assert_eq!(info, decoder.setup().packet_info(&mut reader).unwrap());
+        expected_info.push(info);
+    }
+    // The window flags of the long frames match the neighbour frames.
+    for (i, info) in expected_info.iter().enumerate() {
+        let is_long = |i: usize| expected_info[i].frame_kind() == FrameKind::Long;
+        if i > 0 && info.prev_window_long().is_some() {
+            assert_eq!(info.prev_window_long(), Some(is_long(i - 1)));
+        }
+        if i + 1 < expected_info.len() && info.next_window_long().is_some() {
+            assert_eq!(info.next_window_long(), Some(is_long(i + 1)));
+        }
+    }
+
+    let mut decoder = common::build_decoder(&packets).into_pipelined();
+    let mut actual = Vec::new();
+    let mut actual_stats = Vec::new();
+    let mut actual_info = Vec::new();
+    for packet in &packets[3..] {
+        decoder.push(packet.clone());
+        // Keep one packet queued ahead.
+        if decoder.queued() > 1 {
+            actual.push(decoder.next().unwrap().unwrap().interleave().collect::<Vec<_>>());
+            actual_stats.push(decoder.packet_stats().unwrap());
+            actual_info.push(decoder.packet_info().unwrap());
+        }
+    }
+    while let Some(samples) = decoder.next() {
+        actual.push(samples.unwrap().interleave().collect::<Vec<_>>());
+        actual_stats.push(decoder.packet_stats().unwrap());
+        actual_info.push(decoder.packet_info().unwrap());
+    }
+
+    assert_eq!(decoder.queued(), 0);
+    assert!(actual == expected);
+    assert_eq!(actual_stats, expected_stats);
+    assert_eq!(actual_info, expected_info);
+}
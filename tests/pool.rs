@@ -0,0 +1,47 @@
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use std::io::Cursor;
+
+use vorbis::{BitReader, Decoder, DecoderBuilder, DecoderPool};
+
+fn read_headers(mut builder: DecoderBuilder, packets: &[Vec<u8>]) -> DecoderBuilder {
+    common::read_headers(&mut builder, packets);
+    builder
+}
+
+fn decode_all(decoder: &mut Decoder, packets: &[Vec<u8>]) -> Vec<Vec<f32>> {
+    packets[3..].iter()
+        .map(|p| decoder.decode(&mut BitReader::new(Cursor::new(&p[..]))).unwrap()
+            .interleave().map(|s| s as f32).collect())
+        .collect()
+}
+
+#[test]
+fn recycled_decoder_matches_fresh() {
+    let dir = common::ref_dir();
+    let stereo = common::read_packets(dir.join("01_30s.ogg"));
+    let mono = common::read_packets(dir.join("01_30s_mono.ogg"));
+
+    let expected = decode_all(&mut read_headers(Decoder::builder(), &stereo).build(), &stereo);
+
+    let mut pool = DecoderPool::new();
+    assert!(pool.is_empty());
+
+    let mut decoder = pool.build(read_headers(pool.builder(), &stereo));
+    assert!(decode_all(&mut decoder, &stereo) == expected);
+    pool.recycle(decoder);
+    assert_eq!(pool.len(), 1);
+
+    // Different channel count, the buffers can't be reused.
+    let decoder = pool.build(read_headers(pool.builder(), &mono));
+    assert_eq!(pool.len(), 1);
+    pool.recycle(decoder);
+    assert_eq!(pool.len(), 2);
+
+    let mut decoder = pool.build(read_headers(pool.builder(), &stereo));
+    assert_eq!(pool.len(), 1);
+    assert!(decode_all(&mut decoder, &stereo) == expected);
+}
@@ -0,0 +1,57 @@
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use std::io::Cursor;
+
+use vorbis::{BitReader, Decoder};
+
+fn build_decoder(packets: &[Vec<u8>], rate_divisor: usize) -> Decoder {
+    let mut decoder_builder = Decoder::builder();
+    decoder_builder.rate_divisor(rate_divisor);
+    common::read_headers(&mut decoder_builder, packets);
+    decoder_builder.build()
+}
+
+// Returns the decoded samples of each channel.
+fn decode(decoder: &mut Decoder, packets: &[Vec<u8>]) -> Vec<Vec<f32>> {
+    let mut r = vec![Vec::new(); decoder.header().channel_count()];
+    for packet in packets {
+        let samples = decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+        for (r, channel) in r.iter_mut().zip(samples.channels()) {
+            r.extend(channel.iter().map(|&s| s as f32));
+        }
+    }
+    r
+}
+
+#[test]
+fn reduced_rate() {
+    for name in &["01_30s.ogg", "02_1m_6ch.ogg"] {
+        let packets = common::read_packets(common::ref_file(name));
+        let full = decode(&mut build_decoder(&packets, 1), &packets[3..]);
+        for &divisor in &[2, 4] {
+            let mut decoder = build_decoder(&packets, divisor);
+            assert_eq!(decoder.rate_divisor(), divisor);
+            assert_eq!(decoder.sample_rate(), decoder.header().sample_rate() / divisor as u32);
+            let reduced = decode(&mut decoder, &packets[3..]);
+            assert_eq!(decoder.pos() as usize, reduced[0].len());
+
+            for (full, reduced) in full.iter().zip(&reduced) {
+                assert_eq!(reduced.len(), full.len() / divisor);
+                // The reduced rate sample is roughly the average of the full rate ones it
+                // replaces, the difference is mostly the dropped high frequencies.
+                let mut signal = 0.0;
+                let mut noise = 0.0;
+                for (chunk, &r) in full.chunks(divisor).zip(reduced) {
+                    let avg = chunk.iter().fold(0.0, |a, &s| a + s as f64) / divisor as f64;
+                    signal += avg * avg;
+                    noise += (avg - r as f64) * (avg - r as f64);
+                }
+                let snr = 10.0 * (signal / noise).log10();
+                assert!(snr > 15.0, "{} /{}: {} dB", name, divisor, snr);
+            }
+        }
+    }
+}
@@ -1,15 +1,14 @@
+#![cfg(feature = "reftest")]
+
 extern crate num_cpus;
-extern crate ogg_vorbis_ref;
 extern crate scoped_pool;
 extern crate vorbis;
 
 use scoped_pool::Pool;
 use std::fs::{self, File};
-use std::io::Cursor;
 use std::path::{Path, PathBuf};
 
-use ogg_vorbis_ref::{OggRefDecoder, VorbisRefDecoder};
-use vorbis::{Decoder, BitReader};
+use vorbis::RefTest;
 
 #[test] #[ignore]
 fn ref_test() {
@@ -29,62 +28,41 @@ fn ref_test() {
     thread_pool.shutdown();
 }
 
-fn do_ref_test<P: AsRef<Path>>(path: P) {
-    let file = File::open(path).unwrap();
-
-    let mut ogg = OggRefDecoder::new(file, 4096);
-
-    let mut ref_decoder = VorbisRefDecoder::new();
-    let mut decoder_builder = Decoder::builder();
-
-    ogg.next_packet().unwrap();
-    ref_decoder.decode_header(ogg.raw_packet_mut()).unwrap();
-    decoder_builder.read_ident_packet(&mut BitReader::new(Cursor::new(ogg.packet_data()))).unwrap();
-
-    ogg.next_packet().unwrap();
-    ref_decoder.decode_header(ogg.raw_packet_mut()).unwrap();
-    decoder_builder.read_comment_packet(&mut BitReader::new(Cursor::new(ogg.packet_data()))).unwrap();
+// Also compares with stb_vorbis which doesn't match libvorbis as closely, so the tolerance is
+// about one 16-bit LSB. Only the streams the references agree on must match.
+#[cfg(feature = "reftest-stb")]
+#[test] #[ignore]
+fn stb_ref_test() {
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("tests/data/ref");
 
-    {
-        let actual = decoder_builder.comments().unwrap();
-        assert_eq!(actual.vendor(), ref_decoder.comment_vendor());
-        assert_eq!(actual.len(), ref_decoder.comment_count());
-        for i in 0..ref_decoder.comment_count() {
-            assert_eq!(actual.raw()[i], ref_decoder.comment(i).unwrap());
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if !path.is_file() || !path.to_string_lossy().ends_with(".ogg") {
+            continue;
+        }
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let report = RefTest::new()
+            .max_diff(1e-4)
+            .stb_vorbis(true)
+            .run(File::open(&path).unwrap())
+            .unwrap();
+        assert!(report.is_ok(), "{}: {:?}", name, report);
+        let stb = report.stb().unwrap();
+        if stb.references_agree() {
+            assert!(stb.is_ok(), "{}: {:?}", name, stb);
+        } else {
+            println!("> References disagree on {}: max diff {}, {} mismatches, first {:?}, \
+                length mismatch {}", name, stb.ref_max_diff(), stb.ref_mismatch_count(),
+                stb.ref_first_mismatch(), stb.len_mismatch());
         }
     }
+}
 
-    ogg.next_packet().unwrap();
-    ref_decoder.decode_header(ogg.raw_packet_mut()).unwrap();
-    decoder_builder.read_setup_packet(&mut BitReader::new(Cursor::new(ogg.packet_data()))).unwrap();
-
-    let mut decoder = decoder_builder.build();
-
-    assert_eq!(decoder.header().channel_count(), ref_decoder.channel_count());
-
-    while ogg.next_packet().unwrap() {
-        ref_decoder.decode(ogg.raw_packet_mut()).unwrap();
-
-        let actual = decoder.decode(&mut BitReader::new(Cursor::new(ogg.packet_data()))).unwrap();
-
-        for ch in 0..ref_decoder.channel_count() {
-            let expected = ref_decoder.pcm(ch);
-
-            let actual = actual.channel(ch);
-            let actual = if ogg.is_eos() {
-                &actual[..expected.len()]
-            } else {
-                actual
-            };
-            assert!(expected.len() == actual.len());
-
-            for (&e, &a) in expected.iter().zip(actual.iter()) {
-                let eq = (e - a).abs() < 1e-6;
-                if !eq {
-                    println!("actual {} != expected {}", a, e);
-                }
-                assert!(eq);
-            }
-        }
+fn do_ref_test<P: AsRef<Path>>(path: P) {
+    let report = RefTest::new().run(File::open(path).unwrap()).unwrap();
+    if let Some(m) = report.first_mismatch() {
+        println!("actual {} != expected {}", m.actual(), m.expected());
     }
-}
\ No newline at end of file
+    assert!(report.is_ok(), "{:?}", report);
+}
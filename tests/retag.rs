@@ -0,0 +1,65 @@
+#![cfg(all(feature = "ogg", feature = "tags"))]
+
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use std::fs::File;
+use std::io::{Cursor, Read};
+
+use vorbis::{retag, BitReader, CommentTag, Comments};
+use vorbis::prelude::*;
+
+#[test]
+fn retag_keeps_audio() {
+    let mut input = Vec::new();
+    File::open(common::ref_file("01_30s.ogg")).unwrap().read_to_end(&mut input).unwrap();
+
+    // Big enough to span several pages.
+    let long_value: String = (0..100000).map(|i| (b'a' + (i % 26) as u8) as char).collect();
+    let comments = Comments::builder()
+        .vendor("retag test")
+        .add(CommentTag::Title, "New title")
+        .add(CommentTag::Description, &long_value)
+        .build();
+    let mut output = Vec::new();
+    retag(&input[..], &mut output, &comments).unwrap();
+    // The page CRCs are checked on reading.
+    retag(&output[..], &mut Vec::new(), &comments).unwrap();
+
+    let old_packets = common::read_packets_from(Cursor::new(input));
+    let new_packets = common::read_packets_from(Cursor::new(output));
+    assert_eq!(new_packets.len(), old_packets.len());
+    assert_eq!(new_packets[0], old_packets[0]);
+    assert_eq!(new_packets[2..], old_packets[2..]);
+
+    // Skip the packet kind and the "vorbis" signature.
+    let new_comments = Comments::read(&mut BitReader::new(Cursor::new(&new_packets[1][7..]))).unwrap();
+    assert_eq!(new_comments.vendor(), Some("retag test"));
+    assert_eq!(new_comments.first(CommentTag::Title), Some("New title"));
+    assert_eq!(new_comments.first(CommentTag::Description), Some(&long_value[..]));
+}
+
+#[test]
+fn decode_all_trims() {
+    let mut input = Vec::new();
+    File::open(common::ref_file("02_1m_6ch.ogg")).unwrap().read_to_end(&mut input).unwrap();
+    let packets = common::read_packets_from(Cursor::new(&input[..]));
+
+    let mut decoder = common::build_decoder(&packets);
+    let mut expected = Vec::new();
+    for packet in &packets[3..] {
+        expected.extend(decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap().interleave());
+    }
+
+    let (header, comments, samples) = decode_all(&input[..]).unwrap();
+    assert_eq!(header.channel_count(), 6);
+    assert_eq!(comments.raw(), decoder.comments().unwrap().raw());
+    // The stream starts at zero, only the padding at the end is trimmed.
+    assert!(samples.len() < expected.len());
+    assert!(expected.len() - samples.len() < header.frame_lens().long() * 6);
+    assert!(samples[..] == expected[..samples.len()]);
+
+    assert!(decode_all(&[][..]).is_err());
+}
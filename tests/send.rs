@@ -0,0 +1,33 @@
+// Compile-time checks that the decoders can be moved to and shared between threads.
+
+extern crate vorbis;
+
+use vorbis::{Decoder, DecoderBuilder, DecoderPool, DecoderState, LoopingDecoder, PipelinedDecoder,
+    Samples, Setup, TableCache};
+
+fn assert_send<T: Send>() {}
+
+fn assert_sync<T: Sync>() {}
+
+#[test]
+fn decoder_send_sync() {
+    assert_send::<Decoder>();
+    assert_sync::<Decoder>();
+    assert_send::<DecoderBuilder>();
+    assert_sync::<DecoderBuilder>();
+    assert_send::<Setup>();
+    assert_sync::<Setup>();
+    assert_send::<DecoderState>();
+    assert_send::<DecoderPool>();
+    assert_send::<TableCache>();
+    assert_sync::<TableCache>();
+    assert_send::<Samples>();
+    assert_send::<LoopingDecoder>();
+    assert_send::<PipelinedDecoder>();
+}
+
+#[cfg(feature = "memmap")]
+#[test]
+fn mmap_decoder_send() {
+    assert_send::<vorbis::MmapDecoder>();
+}
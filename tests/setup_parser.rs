@@ -0,0 +1,93 @@
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use std::io::Cursor;
+
+use vorbis::{BitReader, Decoder, DecoderBuilder, DecodeStage, Error, SetupParser};
+
+fn builder(packets: &[Vec<u8>]) -> DecoderBuilder {
+    let mut decoder_builder = Decoder::builder();
+    decoder_builder.read_ident_packet(&mut BitReader::new(Cursor::new(&packets[0][..]))).unwrap();
+    decoder_builder.read_comment_packet(&mut BitReader::new(Cursor::new(&packets[1][..]))).unwrap();
+    decoder_builder
+}
+
+#[test]
+fn parse_in_parts() {
+    let packets = common::read_packets(common::ref_file("02_1m_6ch.ogg"));
+    let setup_packet = &packets[2];
+    let audio = &packets[3..100];
+
+    let mut decoder_builder = builder(&packets);
+    decoder_builder.read_setup_packet(&mut BitReader::new(Cursor::new(&setup_packet[..]))).unwrap();
+    let codebook_count = decoder_builder.setup().unwrap().codebooks().len();
+    let expected = common::decode(&mut decoder_builder.build(), audio);
+
+    for &part_len in &[1, 7, 100, setup_packet.len()] {
+        let mut decoder_builder = builder(&packets);
+        let mut parser = decoder_builder.setup_parser();
+        let mut prev_read = 0;
+        for part in setup_packet.chunks(part_len) {
+            parser.push(part).unwrap();
+            assert!(parser.codebooks_read() >= prev_read);
+            prev_read = parser.codebooks_read();
+        }
+        assert_eq!(parser.codebooks_read(), codebook_count);
+        decoder_builder.finish_setup(parser).unwrap();
+        assert!(common::decode(&mut decoder_builder.build(), audio) == expected);
+    }
+
+    // Truncated packet.
+    let header = builder(&packets).header().unwrap().clone();
+    let mut parser = SetupParser::new(&header);
+    parser.push(&setup_packet[..setup_packet.len() / 2]).unwrap();
+    assert!(parser.codebooks_read() < codebook_count);
+    assert!(parser.finish().is_err());
+}
+
+#[test]
+fn invalid_codebook() {
+    let packets = common::read_packets(common::ref_file("01_30s.ogg"));
+    let mut setup_packet = packets[2].clone();
+    // The sync pattern of the first codebook.
+    setup_packet[8] = 0;
+
+    let mut parser = builder(&packets).setup_parser();
+    parser.push(&setup_packet[..4]).unwrap();
+    let err = parser.push(&setup_packet[4..20]).err().unwrap();
+    assert_eq!(err.stage(), Some(DecodeStage::Codebook(0)));
+    match err.inner() {
+        &Error::InvalidCodebookSync => {}
+        e => panic!("{:?}", e),
+    }
+}
+
+#[test]
+fn debug_summary() {
+    let packets = common::read_packets(common::ref_file("02_1m_6ch.ogg"));
+
+    let mut builder = builder(&packets);
+    let s = format!("{:?}", builder);
+    assert!(s.starts_with("DecoderBuilder { header: Some(Header {"), "{}", s);
+    assert!(s.contains("channel_count: 6"), "{}", s);
+    assert!(s.contains("setup: None"), "{}", s);
+
+    builder.read_setup_packet(&mut BitReader::new(Cursor::new(&packets[2][..]))).unwrap();
+    let setup = format!("{:?}", builder.setup().unwrap());
+    assert!(setup.starts_with("Setup { codebooks: "), "{}", setup);
+    assert!(setup.contains("modes: [Short, Long]"), "{}", setup);
+    // The components are only counted.
+    assert!(setup.len() < 200, "{}", setup);
+
+    let mut decoder = builder.build();
+    for packet in &packets[3..6] {
+        decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+    }
+    let s = format!("{:?}", decoder);
+    assert!(s.starts_with("Decoder { header: Header {"), "{}", s);
+    assert!(s.contains(&setup), "{}", s);
+    assert!(s.contains(&format!("pos: {}, packet_count: 3", decoder.pos())), "{}", s);
+    assert!(s.len() < 1000, "{}", s);
+}
@@ -0,0 +1,47 @@
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use std::io::Cursor;
+
+use vorbis::{BitReader, Decoder};
+
+#[test]
+fn decoded_spectrum() {
+    let packets = common::read_packets(common::ref_file("01_30s.ogg"));
+    let mut builder = Decoder::builder();
+    builder.spectrum_analysis(true);
+    common::read_headers(&mut builder, &packets);
+    let mut decoder = builder.build();
+    assert!(decoder.spectrum().is_none());
+
+    let sample_rate = decoder.header().sample_rate() as f32;
+    let frame_lens = decoder.header().frame_lens();
+    // Average magnitudes of the lowest and highest eighth of the long frame bins.
+    let (mut low, mut high, mut long_count) = (0.0, 0.0, 0);
+    for packet in &packets[3..] {
+        decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+        let frame_kind = decoder.packet_info().unwrap().frame_kind();
+        let spectrum = decoder.spectrum().unwrap();
+        assert_eq!(spectrum.len(), frame_lens.get(frame_kind) / 2);
+        assert_eq!(spectrum.channel_count(), 2);
+        assert!(spectrum.frequency(0) > 0.0);
+        assert!((spectrum.frequency(spectrum.len() - 1) - sample_rate / 2.0).abs() < sample_rate / 100.0);
+        for channel in spectrum.channels() {
+            assert!(channel.iter().all(|&v| v >= -140.0 && v < 20.0));
+        }
+        if spectrum.len() == frame_lens.long() / 2 {
+            let n = spectrum.len() / 8;
+            let channel = spectrum.channel(0);
+            low += channel[..n].iter().map(|&v| v as f64).sum::<f64>() / n as f64;
+            high += channel[channel.len() - n..].iter().map(|&v| v as f64).sum::<f64>() / n as f64;
+            long_count += 1;
+        }
+    }
+    // Music has most of its energy in the low frequencies.
+    assert!(long_count > 0 && low > high, "{} {}", low, high);
+
+    decoder.reset();
+    assert!(decoder.spectrum().is_none());
+}
@@ -0,0 +1,97 @@
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use std::io::Cursor;
+
+use vorbis::{BitReader, DecoderState, ErrorKind};
+
+#[test]
+fn restored_state_continues_decoding() {
+    let packets = common::read_packets(common::ref_file("01_30s.ogg"));
+    let audio = &packets[3..];
+    let expected = common::decode(&mut common::build_decoder(&packets), audio);
+
+    for &split in &[0, 1, 100, audio.len() - 1] {
+        let mut decoder = common::build_decoder(&packets);
+        let mut actual = common::decode(&mut decoder, &audio[..split]);
+        let bytes = decoder.state().to_bytes();
+        let state = DecoderState::from_bytes(&bytes).unwrap();
+        assert_eq!(state, decoder.state());
+
+        let mut decoder = common::build_decoder(&packets);
+        decoder.restore_state(&state).unwrap();
+        assert!(decoder.samples().is_empty());
+        assert_eq!(decoder.pos(), state.pos());
+        actual.extend(common::decode(&mut decoder, &audio[split..]));
+        assert!(actual == expected);
+    }
+}
+
+#[test]
+fn invalid_state() {
+    let dir = common::ref_dir();
+    let stereo = common::read_packets(dir.join("01_30s.ogg"));
+    let mono = common::read_packets(dir.join("01_30s_mono.ogg"));
+
+    let mut decoder = common::build_decoder(&mono);
+    common::decode(&mut decoder, &mono[3..5]);
+    let state = decoder.state();
+    let e = common::build_decoder(&stereo).restore_state(&state).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Undecodable);
+
+    let mut bytes = state.to_bytes();
+    bytes.pop();
+    assert!(DecoderState::from_bytes(&bytes).is_err());
+    assert!(DecoderState::from_bytes(b"VbDS").is_err());
+}
+
+#[test]
+fn prime_matches_linear_decoding() {
+    let packets = common::read_packets(common::ref_file("02_1m_6ch.ogg"));
+    let audio = &packets[3..];
+    let mut decoder = common::build_decoder(&packets);
+    let expected: Vec<_> = audio.iter().map(|p| common::decode(&mut decoder, &[p.clone()])).collect();
+
+    // Decoding some packets before priming checks the previous state is discarded.
+    let mut decoder = common::build_decoder(&packets);
+    common::decode(&mut decoder, &audio[..10]);
+    for &seek in &[1, 50, 3, audio.len() - 1] {
+        decoder.prime(&mut BitReader::new(Cursor::new(&audio[seek - 1][..]))).unwrap();
+        assert!(decoder.samples().is_empty());
+        assert_eq!(decoder.pos(), 0);
+        for (packet, expected) in audio[seek..].iter().zip(&expected[seek..]).take(20) {
+            assert!(&common::decode(&mut decoder, &[packet.clone()]) == expected);
+        }
+    }
+}
+
+#[test]
+fn latency() {
+    let packets = common::read_packets(common::ref_file("01_30s.ogg"));
+    let mut decoder = common::build_decoder(&packets);
+    assert_eq!(decoder.latency_samples(), 0);
+    let frame_lens = decoder.header().frame_lens();
+    let mut short_seen = false;
+    for packet in &packets[3..100] {
+        decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+        let frame_kind = decoder.packet_info().unwrap().frame_kind();
+        short_seen |= frame_kind == vorbis::FrameKind::Short;
+        assert_eq!(decoder.latency_samples(), frame_lens.get(frame_kind) / 2);
+    }
+    assert!(short_seen);
+
+    // Failed decode keeps the last frame.
+    let latency = decoder.latency_samples();
+    assert!(decoder.decode(&mut BitReader::new(Cursor::new(&[1_u8][..]))).is_err());
+    assert_eq!(decoder.latency_samples(), latency);
+
+    let state = decoder.state();
+    let mut restored = common::build_decoder(&packets);
+    restored.restore_state(&state).unwrap();
+    assert_eq!(restored.latency_samples(), latency);
+
+    decoder.reset();
+    assert_eq!(decoder.latency_samples(), 0);
+}
@@ -0,0 +1,62 @@
+extern crate ogg_vorbis_ref;
+extern crate vorbis;
+
+mod common;
+
+use std::io::Cursor;
+
+use vorbis::{validate, BitReader, IssueKind, Recovery, Severity};
+
+#[test]
+fn valid() {
+    let packets = common::read_packets(common::ref_file("01_30s.ogg"));
+    let report = validate(&packets);
+    assert!(report.is_valid());
+    assert!(report.issues().is_empty(), "{:?}", report.issues());
+    assert_eq!(report.packet_count(), packets.len());
+
+    let mut decoder = common::build_decoder(&packets);
+    for packet in &packets[3..] {
+        decoder.decode(&mut BitReader::new(Cursor::new(&packet[..]))).unwrap();
+    }
+    assert_eq!(report.sample_count(), decoder.pos());
+}
+
+#[test]
+fn issues() {
+    let mut packets = common::read_packets(common::ref_file("01_30s.ogg"));
+    let decoder = common::build_decoder(&packets);
+    assert_eq!(decoder.setup().modes().len(), 2);
+
+    // Flip the previous window flag of a long frame following a decoded one (the mode takes
+    // one bit).
+    let long = (4..packets.len())
+        .find(|&i| decoder.setup().packet_info(&mut BitReader::new(Cursor::new(&packets[i][..])))
+            .unwrap().prev_window_long().is_some())
+        .unwrap();
+    packets[long][0] ^= 1 << 2;
+    // Invalid packet kind.
+    packets[long + 2][0] |= 1;
+    packets.insert(long + 4, Vec::new());
+
+    let report = validate(&packets);
+    assert!(!report.is_valid());
+    let issues: Vec<_> = report.issues().iter()
+        .map(|i| (i.packet(), i.bit_pos(), i.severity(), i.recovery()))
+        .collect();
+    assert_eq!(issues, &[
+        (long, Some(2), Severity::Suspicious, Recovery::Continued),
+        (long + 2, None, Severity::Error, Recovery::PacketSkipped),
+        (long + 4, None, Severity::Suspicious, Recovery::PacketSkipped),
+    ]);
+    match report.issues()[0].kind() {
+        &IssueKind::PrevWindowFlagMismatch => {}
+        k => panic!("{:?}", k),
+    }
+    match report.issues()[2].kind() {
+        &IssueKind::EmptyPacket => {}
+        k => panic!("{:?}", k),
+    }
+    assert_eq!(report.issues()[0].to_string(),
+        format!("packet {} bit 2: Suspicious: PrevWindowFlagMismatch", long));
+}
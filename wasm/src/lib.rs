@@ -0,0 +1,107 @@
+//! JavaScript-friendly wrapper of the decoder: push packets in, pull interleaved samples out.
+
+extern crate vorbis;
+extern crate wasm_bindgen;
+
+use std::io::Cursor;
+use std::mem;
+use vorbis::{BitReader, Decoder, DecoderBuilder};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct VorbisDecoder {
+    builder: Option<DecoderBuilder>,
+    decoder: Option<Decoder>,
+    header_count: usize,
+    samples: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl VorbisDecoder {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> VorbisDecoder {
+        VorbisDecoder {
+            builder: Some(Decoder::builder()),
+            decoder: None,
+            header_count: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Decodes the next packet of the stream. The first three packets must be the header ones.
+    /// The decoded samples are appended to the ones returned by `pullSamples()`.
+    #[wasm_bindgen(js_name = pushPacket)]
+    pub fn push_packet(&mut self, packet: &[u8]) -> Result<(), JsValue> {
+        let reader = &mut BitReader::new(Cursor::new(packet));
+        if let Some(decoder) = self.decoder.as_mut() {
+            let samples = try!(decoder.decode(reader).map_err(to_js));
+            self.samples.extend(samples.interleave().map(|s| s as f32));
+            return Ok(());
+        }
+        {
+            let builder = self.builder.as_mut().unwrap();
+            try!(match self.header_count {
+                0 => builder.read_ident_packet(reader),
+                1 => builder.read_comment_packet(reader),
+                _ => builder.read_setup_packet(reader),
+            }.map_err(to_js));
+        }
+        self.header_count += 1;
+        if self.header_count == 3 {
+            self.decoder = Some(self.builder.take().unwrap().build());
+        }
+        Ok(())
+    }
+
+    /// Returns the interleaved samples decoded since the last call.
+    #[wasm_bindgen(js_name = pullSamples)]
+    pub fn pull_samples(&mut self) -> Vec<f32> {
+        mem::replace(&mut self.samples, Vec::new())
+    }
+
+    /// Returns `true` if all the header packets were pushed.
+    #[wasm_bindgen(getter, js_name = isReady)]
+    pub fn is_ready(&self) -> bool {
+        self.decoder.is_some()
+    }
+
+    /// Returns the channel count or 0 if the identification header wasn't pushed yet.
+    #[wasm_bindgen(getter, js_name = channelCount)]
+    pub fn channel_count(&self) -> u32 {
+        self.header().map(|h| h.channel_count() as u32).unwrap_or(0)
+    }
+
+    /// Returns the sample rate or 0 if the identification header wasn't pushed yet.
+    #[wasm_bindgen(getter, js_name = sampleRate)]
+    pub fn sample_rate(&self) -> u32 {
+        self.header().map(|h| h.sample_rate()).unwrap_or(0)
+    }
+
+    /// Forgets the decoded state, e.g. after seeking. Call this before pushing a packet from
+    /// another position of the stream.
+    pub fn reset(&mut self) {
+        if let Some(decoder) = self.decoder.as_mut() {
+            decoder.reset();
+        }
+        self.samples.clear();
+    }
+}
+
+impl Default for VorbisDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VorbisDecoder {
+    fn header(&self) -> Option<&vorbis::Header> {
+        match self.decoder {
+            Some(ref decoder) => Some(decoder.header()),
+            None => self.builder.as_ref().and_then(|b| b.header()),
+        }
+    }
+}
+
+fn to_js(e: vorbis::Error) -> JsValue {
+    JsValue::from_str(&format!("{:?}", e))
+}